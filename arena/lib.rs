@@ -0,0 +1,734 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A DOM whose nodes live in an arena.
+//!
+//! Nodes are allocated in a [`typed_arena::Arena`] that the caller owns, and refer to their
+//! parent, siblings and children with plain references, so building and walking the tree does no
+//! reference counting. Every node lives as long as the arena, and the whole document is freed at
+//! once when the arena is dropped. `ArenaDom` implements `TreeNavigator` from
+//! `markup5ever::traverse`, with `Ref`s as handles.
+//!
+//! ```text
+//! let arena = Arena::new();
+//! let dom = parse_document(ArenaDom::new(&arena), Default::default()).one(html);
+//! for node in dom.document.descendants() {
+//!     ...
+//! }
+//! ```
+//!
+//! [`typed_arena::Arena`]: https://docs.rs/typed-arena
+
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::io;
+use std::iter;
+use std::ptr;
+
+use markup5ever::interface::tree_builder::XmlDeclaration;
+use markup5ever::interface::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use markup5ever::serialize::TraversalScope::{ChildrenOnly, IncludeNode};
+use markup5ever::serialize::{Serialize, Serializer, TraversalScope};
+use markup5ever::traverse::{self, TreeNavigator};
+use markup5ever::{namespace_url, ns, Attribute, ExpandedName, LocalName, Namespace, QualName};
+use tendril::StrTendril;
+
+pub use typed_arena::Arena;
+
+/// Reference to a DOM node.
+pub type Ref<'arena> = &'arena Node<'arena>;
+
+type Link<'arena> = Cell<Option<Ref<'arena>>>;
+
+/// The different kinds of nodes in the DOM.
+#[derive(Debug)]
+pub enum NodeData<'arena> {
+    /// The `Document` itself, or the contents of a template.
+    Document,
+
+    /// A `DOCTYPE` with name, public id, and system id.
+    Doctype {
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    },
+
+    /// A text node.
+    Text { contents: RefCell<StrTendril> },
+
+    /// A comment.
+    Comment { contents: StrTendril },
+
+    /// A CDATA section.
+    CData { contents: StrTendril },
+
+    /// An element with attributes.
+    Element {
+        name: QualName,
+        attrs: RefCell<Vec<Attribute>>,
+
+        /// For HTML \<template\> elements, the [template contents].
+        ///
+        /// [template contents]: https://html.spec.whatwg.org/multipage/#template-contents
+        template_contents: Option<Ref<'arena>>,
+
+        /// Whether the node is a [HTML integration point].
+        ///
+        /// [HTML integration point]: https://html.spec.whatwg.org/multipage/#html-integration-point
+        mathml_annotation_xml_integration_point: bool,
+    },
+
+    /// A processing instruction.
+    ProcessingInstruction {
+        target: StrTendril,
+        contents: StrTendril,
+    },
+}
+
+/// A DOM node.
+pub struct Node<'arena> {
+    parent: Link<'arena>,
+    next_sibling: Link<'arena>,
+    previous_sibling: Link<'arena>,
+    first_child: Link<'arena>,
+    last_child: Link<'arena>,
+    /// Represents this node's data.
+    pub data: NodeData<'arena>,
+}
+
+impl<'arena> Node<'arena> {
+    /// Create a new node from its contents. Allocate it with `Arena::alloc` to add it to a tree.
+    pub fn new(data: NodeData<'arena>) -> Self {
+        Node {
+            parent: Cell::new(None),
+            previous_sibling: Cell::new(None),
+            next_sibling: Cell::new(None),
+            first_child: Cell::new(None),
+            last_child: Cell::new(None),
+            data,
+        }
+    }
+
+    pub fn parent(&self) -> Option<Ref<'arena>> {
+        self.parent.get()
+    }
+
+    pub fn previous_sibling(&self) -> Option<Ref<'arena>> {
+        self.previous_sibling.get()
+    }
+
+    pub fn next_sibling(&self) -> Option<Ref<'arena>> {
+        self.next_sibling.get()
+    }
+
+    pub fn first_child(&self) -> Option<Ref<'arena>> {
+        self.first_child.get()
+    }
+
+    pub fn last_child(&self) -> Option<Ref<'arena>> {
+        self.last_child.get()
+    }
+
+    /// For a \<template\> element, the `Document` node that holds its contents.
+    pub fn template_contents(&self) -> Option<Ref<'arena>> {
+        match self.data {
+            NodeData::Element {
+                template_contents, ..
+            } => template_contents,
+            _ => None,
+        }
+    }
+
+    /// The node's children, in order.
+    pub fn children(&self) -> Siblings<'arena> {
+        Siblings {
+            next: self.first_child(),
+            forward: true,
+        }
+    }
+
+    /// The siblings after this node, nearest first.
+    pub fn following_siblings(&self) -> Siblings<'arena> {
+        Siblings {
+            next: self.next_sibling(),
+            forward: true,
+        }
+    }
+
+    /// The siblings before this node, nearest first.
+    pub fn preceding_siblings(&self) -> Siblings<'arena> {
+        Siblings {
+            next: self.previous_sibling(),
+            forward: false,
+        }
+    }
+
+    /// The node's parent, its parent's parent, and so on.
+    pub fn ancestors(&self) -> Ancestors<'arena> {
+        Ancestors {
+            next: self.parent(),
+        }
+    }
+
+    /// The nodes below this one, in document order. Template contents are not included.
+    pub fn descendants(&'arena self) -> Descendants<'arena> {
+        Descendants {
+            root: self,
+            next: self.first_child(),
+        }
+    }
+
+    /// Remove this node from its parent, if it has one.
+    pub fn detach(&self) {
+        let parent = self.parent.take();
+        let previous_sibling = self.previous_sibling.take();
+        let next_sibling = self.next_sibling.take();
+
+        if let Some(next_sibling) = next_sibling {
+            next_sibling.previous_sibling.set(previous_sibling);
+        } else if let Some(parent) = parent {
+            parent.last_child.set(previous_sibling);
+        }
+
+        if let Some(previous_sibling) = previous_sibling {
+            previous_sibling.next_sibling.set(next_sibling);
+        } else if let Some(parent) = parent {
+            parent.first_child.set(next_sibling);
+        }
+    }
+
+    /// Append a node to this node's children, first removing it from its current parent.
+    ///
+    /// Panics if `new_child` is this node or one of its ancestors.
+    pub fn append(&'arena self, new_child: &'arena Self) {
+        self.check_can_insert(new_child);
+        self.append_unchecked(new_child);
+    }
+
+    /// Insert a node before this one in its parent's children, first removing it from its
+    /// current parent.
+    ///
+    /// Panics if `new_sibling` is this node or one of its ancestors.
+    pub fn insert_before(&'arena self, new_sibling: &'arena Self) {
+        self.check_can_insert(new_sibling);
+        self.insert_before_unchecked(new_sibling);
+    }
+
+    fn check_can_insert(&'arena self, node: &'arena Self) {
+        if iter::once(self)
+            .chain(self.ancestors())
+            .any(|ancestor| ptr::eq(ancestor, node))
+        {
+            panic!("cannot insert a node into itself or its descendants!");
+        }
+    }
+
+    /// `append`, without checking for cycles, for the tree builder.
+    fn append_unchecked(&'arena self, new_child: &'arena Self) {
+        new_child.detach();
+        new_child.parent.set(Some(self));
+        if let Some(last_child) = self.last_child.take() {
+            new_child.previous_sibling.set(Some(last_child));
+            debug_assert!(last_child.next_sibling.get().is_none());
+            last_child.next_sibling.set(Some(new_child));
+        } else {
+            debug_assert!(self.first_child.get().is_none());
+            self.first_child.set(Some(new_child));
+        }
+        self.last_child.set(Some(new_child));
+    }
+
+    /// `insert_before`, without checking for cycles, for the tree builder.
+    fn insert_before_unchecked(&'arena self, new_sibling: &'arena Self) {
+        new_sibling.detach();
+        new_sibling.parent.set(self.parent.get());
+        new_sibling.next_sibling.set(Some(self));
+        if let Some(previous_sibling) = self.previous_sibling.take() {
+            new_sibling.previous_sibling.set(Some(previous_sibling));
+            debug_assert!(ptr::eq::<Node>(
+                previous_sibling.next_sibling.get().unwrap(),
+                self
+            ));
+            previous_sibling.next_sibling.set(Some(new_sibling));
+        } else if let Some(parent) = self.parent.get() {
+            debug_assert!(ptr::eq::<Node>(parent.first_child.get().unwrap(), self));
+            parent.first_child.set(Some(new_sibling));
+        }
+        self.previous_sibling.set(Some(new_sibling));
+    }
+
+    /// The children to serialize: the template contents' children for a `<template>`, and the
+    /// node's own children otherwise.
+    fn serialized_children(&self) -> Siblings<'arena> {
+        match self.template_contents() {
+            Some(contents) => contents.children(),
+            None => self.children(),
+        }
+    }
+}
+
+impl<'arena> fmt::Debug for Node<'arena> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Node")
+            .field("data", &self.data)
+            .field("children", &self.children().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// An iterator over some of a node's siblings, or over its children.
+pub struct Siblings<'arena> {
+    next: Option<Ref<'arena>>,
+    forward: bool,
+}
+
+impl<'arena> Iterator for Siblings<'arena> {
+    type Item = Ref<'arena>;
+
+    fn next(&mut self) -> Option<Ref<'arena>> {
+        let node = self.next?;
+        self.next = if self.forward {
+            node.next_sibling()
+        } else {
+            node.previous_sibling()
+        };
+        Some(node)
+    }
+}
+
+/// An iterator over a node's ancestors.
+pub struct Ancestors<'arena> {
+    next: Option<Ref<'arena>>,
+}
+
+impl<'arena> Iterator for Ancestors<'arena> {
+    type Item = Ref<'arena>;
+
+    fn next(&mut self) -> Option<Ref<'arena>> {
+        let node = self.next?;
+        self.next = node.parent();
+        Some(node)
+    }
+}
+
+/// An iterator over a node's descendants, in document order.
+pub struct Descendants<'arena> {
+    root: Ref<'arena>,
+    next: Option<Ref<'arena>>,
+}
+
+impl<'arena> Iterator for Descendants<'arena> {
+    type Item = Ref<'arena>;
+
+    fn next(&mut self) -> Option<Ref<'arena>> {
+        let node = self.next?;
+        self.next = node.first_child().or_else(|| {
+            let mut ancestor = node;
+            loop {
+                if ptr::eq(ancestor, self.root) {
+                    return None;
+                }
+                if let Some(sibling) = ancestor.next_sibling() {
+                    return Some(sibling);
+                }
+                ancestor = ancestor.parent()?;
+            }
+        });
+        Some(node)
+    }
+}
+
+/// The DOM itself; the result of parsing.
+pub struct ArenaDom<'arena> {
+    arena: &'arena Arena<Node<'arena>>,
+
+    /// The `Document` itself.
+    pub document: Ref<'arena>,
+
+    /// Errors that occurred during parsing.
+    pub errors: Vec<Cow<'static, str>>,
+
+    /// The document's quirks mode.
+    pub quirks_mode: QuirksMode,
+
+    /// The document's XML declaration, if it has one.
+    pub xml_declaration: Option<XmlDeclaration>,
+}
+
+impl<'arena> ArenaDom<'arena> {
+    /// A new, empty document whose nodes will be allocated in `arena`.
+    pub fn new(arena: &'arena Arena<Node<'arena>>) -> ArenaDom<'arena> {
+        ArenaDom {
+            arena,
+            document: arena.alloc(Node::new(NodeData::Document)),
+            errors: vec![],
+            quirks_mode: QuirksMode::NoQuirks,
+            xml_declaration: None,
+        }
+    }
+
+    fn new_node(&self, data: NodeData<'arena>) -> Ref<'arena> {
+        self.arena.alloc(Node::new(data))
+    }
+
+    fn append_common<P, A>(&self, child: NodeOrText<Ref<'arena>>, previous: P, append: A)
+    where
+        P: FnOnce() -> Option<Ref<'arena>>,
+        A: FnOnce(Ref<'arena>),
+    {
+        let new_node = match child {
+            NodeOrText::AppendText(text) => {
+                // Append to an existing Text node if we have one.
+                if let Some(&Node {
+                    data: NodeData::Text { ref contents },
+                    ..
+                }) = previous()
+                {
+                    contents.borrow_mut().push_tendril(&text);
+                    return;
+                }
+                self.new_node(NodeData::Text {
+                    contents: RefCell::new(text),
+                })
+            },
+            NodeOrText::AppendNode(node) => node,
+        };
+
+        append(new_node)
+    }
+}
+
+impl<'arena> TreeSink for ArenaDom<'arena> {
+    type Handle = Ref<'arena>;
+    type Output = Self;
+
+    fn finish(self) -> Self {
+        self
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.errors.push(msg);
+    }
+
+    fn get_document(&mut self) -> Ref<'arena> {
+        self.document
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.quirks_mode = mode;
+    }
+
+    fn set_xml_declaration(&mut self, declaration: XmlDeclaration) {
+        self.xml_declaration = Some(declaration);
+    }
+
+    fn same_node(&self, x: &Ref<'arena>, y: &Ref<'arena>) -> bool {
+        ptr::eq::<Node>(*x, *y)
+    }
+
+    fn elem_name<'a>(&self, target: &'a Ref<'arena>) -> ExpandedName<'a> {
+        match target.data {
+            NodeData::Element { ref name, .. } => name.expanded(),
+            _ => panic!("not an element!"),
+        }
+    }
+
+    fn get_template_contents(&mut self, target: &Ref<'arena>) -> Ref<'arena> {
+        target.template_contents().expect("not a template element!")
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, target: &Ref<'arena>) -> bool {
+        if let NodeData::Element {
+            mathml_annotation_xml_integration_point,
+            ..
+        } = target.data
+        {
+            mathml_annotation_xml_integration_point
+        } else {
+            panic!("not an element!")
+        }
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Ref<'arena> {
+        self.new_node(NodeData::Element {
+            name,
+            attrs: RefCell::new(attrs),
+            template_contents: if flags.template {
+                Some(self.new_node(NodeData::Document))
+            } else {
+                None
+            },
+            mathml_annotation_xml_integration_point: flags.mathml_annotation_xml_integration_point,
+        })
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Ref<'arena> {
+        self.new_node(NodeData::Comment { contents: text })
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Ref<'arena> {
+        self.new_node(NodeData::ProcessingInstruction {
+            target,
+            contents: data,
+        })
+    }
+
+    fn append(&mut self, parent: &Ref<'arena>, child: NodeOrText<Ref<'arena>>) {
+        self.append_common(
+            child, ||
+            parent.last_child.get(),
+            |new_node| parent.append_unchecked(new_node),
+        )
+    }
+
+    fn append_cdata(&mut self, parent: &Ref<'arena>, text: StrTendril) {
+        parent.append_unchecked(self.new_node(NodeData::CData { contents: text }))
+    }
+
+    fn append_before_sibling(&mut self, sibling: &Ref<'arena>, child: NodeOrText<Ref<'arena>>) {
+        self.append_common(
+            child, ||
+            sibling.previous_sibling.get(),
+            |new_node| sibling.insert_before_unchecked(new_node),
+        )
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Ref<'arena>,
+        prev_element: &Ref<'arena>,
+        child: NodeOrText<Ref<'arena>>,
+    ) {
+        if element.parent.get().is_some() {
+            self.append_before_sibling(element, child)
+        } else {
+            self.append(prev_element, child)
+        }
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.document
+            .append_unchecked(self.new_node(NodeData::Doctype {
+                name,
+                public_id,
+                system_id,
+            }))
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Ref<'arena>, attrs: Vec<Attribute>) {
+        let mut existing = if let NodeData::Element { ref attrs, .. } = target.data {
+            attrs.borrow_mut()
+        } else {
+            panic!("not an element")
+        };
+
+        let existing_names = existing
+            .iter()
+            .map(|e| e.name.clone())
+            .collect::<HashSet<_>>();
+        existing.extend(
+            attrs
+                .into_iter()
+                .filter(|attr| !existing_names.contains(&attr.name)),
+        );
+    }
+
+    fn remove_from_parent(&mut self, target: &Ref<'arena>) {
+        target.detach()
+    }
+
+    fn reparent_children(&mut self, node: &Ref<'arena>, new_parent: &Ref<'arena>) {
+        let mut next_child = node.first_child.get();
+        while let Some(child) = next_child {
+            debug_assert!(ptr::eq::<Node>(child.parent.get().unwrap(), *node));
+            next_child = child.next_sibling.get();
+            new_parent.append_unchecked(child)
+        }
+    }
+}
+
+/// Lets code written against `TreeNavigator` walk the DOM. Template contents aren't children, as
+/// in the DOM.
+impl<'arena> TreeNavigator for ArenaDom<'arena> {
+    type Handle = Ref<'arena>;
+
+    fn kind(&self, node: &Ref<'arena>) -> traverse::NodeKind {
+        match node.data {
+            NodeData::Document => traverse::NodeKind::Document,
+            NodeData::Doctype { .. } => traverse::NodeKind::Doctype,
+            NodeData::Element { .. } => traverse::NodeKind::Element,
+            NodeData::Text { .. } => traverse::NodeKind::Text,
+            NodeData::Comment { .. } => traverse::NodeKind::Comment,
+            NodeData::CData { .. } => traverse::NodeKind::CData,
+            NodeData::ProcessingInstruction { .. } => traverse::NodeKind::ProcessingInstruction,
+        }
+    }
+
+    fn parent(&self, node: &Ref<'arena>) -> Option<Ref<'arena>> {
+        node.parent()
+    }
+
+    fn first_child(&self, node: &Ref<'arena>) -> Option<Ref<'arena>> {
+        node.first_child()
+    }
+
+    fn last_child(&self, node: &Ref<'arena>) -> Option<Ref<'arena>> {
+        node.last_child()
+    }
+
+    fn next_sibling(&self, node: &Ref<'arena>) -> Option<Ref<'arena>> {
+        node.next_sibling()
+    }
+
+    fn previous_sibling(&self, node: &Ref<'arena>) -> Option<Ref<'arena>> {
+        node.previous_sibling()
+    }
+
+    fn name(&self, node: &Ref<'arena>) -> Option<QualName> {
+        match node.data {
+            NodeData::Element { ref name, .. } => Some(name.clone()),
+            NodeData::ProcessingInstruction { ref target, .. } => {
+                Some(QualName::new(None, ns!(), LocalName::from(&**target)))
+            },
+            _ => None,
+        }
+    }
+
+    fn attributes(&self, node: &Ref<'arena>) -> Vec<Attribute> {
+        match node.data {
+            NodeData::Element { ref attrs, .. } => attrs.borrow().clone(),
+            _ => vec![],
+        }
+    }
+
+    fn attribute(
+        &self,
+        node: &Ref<'arena>,
+        ns: &Namespace,
+        local: &LocalName,
+    ) -> Option<StrTendril> {
+        match node.data {
+            NodeData::Element { ref attrs, .. } => attrs
+                .borrow()
+                .iter()
+                .find(|attr| attr.name.ns == *ns && attr.name.local == *local)
+                .map(|attr| attr.value.clone()),
+            _ => None,
+        }
+    }
+
+    fn text(&self, node: &Ref<'arena>) -> Option<StrTendril> {
+        match node.data {
+            NodeData::Text { ref contents } => Some(contents.borrow().clone()),
+            NodeData::Comment { ref contents } |
+            NodeData::CData { ref contents } |
+            NodeData::ProcessingInstruction { ref contents, .. } => Some(contents.clone()),
+            _ => None,
+        }
+    }
+
+    fn doctype(&self, node: &Ref<'arena>) -> Option<traverse::Doctype> {
+        match node.data {
+            NodeData::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+            } => Some(traverse::Doctype {
+                name: name.clone(),
+                public_id: public_id.clone(),
+                system_id: system_id.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn template_contents(&self, node: &Ref<'arena>) -> Option<Ref<'arena>> {
+        node.template_contents()
+    }
+
+    fn same_node(&self, x: &Ref<'arena>, y: &Ref<'arena>) -> bool {
+        ptr::eq::<Node>(*x, *y)
+    }
+}
+
+enum SerializeOp<'a, 'arena> {
+    Open(&'a Node<'arena>),
+    Close(QualName),
+}
+
+impl<'arena> Serialize for Node<'arena> {
+    fn serialize<S>(&self, serializer: &mut S, traversal_scope: TraversalScope) -> io::Result<()>
+    where
+        S: Serializer,
+    {
+        let mut ops = VecDeque::new();
+        match traversal_scope {
+            IncludeNode => ops.push_back(SerializeOp::Open(self)),
+            ChildrenOnly(_) => {
+                for child in self.serialized_children() {
+                    ops.push_back(SerializeOp::Open(child));
+                }
+            },
+        }
+
+        while let Some(op) = ops.pop_front() {
+            match op {
+                SerializeOp::Open(node) => match node.data {
+                    NodeData::Element {
+                        ref name,
+                        ref attrs,
+                        ..
+                    } => {
+                        serializer.start_elem(
+                            name.clone(),
+                            attrs.borrow().iter().map(|at| (&at.name, &at.value[..])),
+                        )?;
+
+                        ops.push_front(SerializeOp::Close(name.clone()));
+                        let children = node.serialized_children().collect::<Vec<_>>();
+                        for child in children.into_iter().rev() {
+                            ops.push_front(SerializeOp::Open(child));
+                        }
+                    },
+
+                    NodeData::Doctype { ref name, .. } => serializer.write_doctype(name)?,
+
+                    NodeData::Text { ref contents } => serializer.write_text(&contents.borrow())?,
+
+                    NodeData::Comment { ref contents } => serializer.write_comment(contents)?,
+
+                    NodeData::CData { ref contents } => serializer.write_cdata(contents)?,
+
+                    NodeData::ProcessingInstruction {
+                        ref target,
+                        ref contents,
+                    } => serializer.write_processing_instruction(target, contents)?,
+
+                    NodeData::Document => panic!("Can't serialize Document node itself"),
+                },
+
+                SerializeOp::Close(name) => serializer.end_elem(name)?,
+            }
+        }
+
+        Ok(())
+    }
+}
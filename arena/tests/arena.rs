@@ -0,0 +1,161 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cell::RefCell;
+
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::QualName;
+use html5ever::{local_name, namespace_url, ns, parse_document, parse_fragment, serialize};
+use markup5ever::interface::{NodeOrText, QuirksMode, TreeSink};
+use markup5ever::serialize::TraversalScope;
+use markup5ever::traverse::TreeNavigator;
+use markup5ever_arena::{Arena, ArenaDom, Node, NodeData, Ref};
+
+fn parse<'arena>(arena: &'arena Arena<Node<'arena>>, input: &str) -> ArenaDom<'arena> {
+    parse_document(ArenaDom::new(arena), Default::default()).one(input)
+}
+
+fn to_string(node: Ref, traversal_scope: TraversalScope) -> String {
+    let opts = html5ever::serialize::SerializeOpts {
+        traversal_scope,
+        ..Default::default()
+    };
+    let mut result = vec![];
+    serialize(&mut result, node, opts).unwrap();
+    String::from_utf8(result).unwrap()
+}
+
+fn children_html(node: Ref) -> String {
+    to_string(node, TraversalScope::ChildrenOnly(None))
+}
+
+fn html(node: Ref) -> String {
+    to_string(node, TraversalScope::IncludeNode)
+}
+
+fn names<'arena, I: Iterator<Item = Ref<'arena>>>(nodes: I) -> Vec<String> {
+    nodes
+        .map(|node| match node.data {
+            NodeData::Document => "#document".to_owned(),
+            NodeData::Element { ref name, .. } => name.local.to_string(),
+            NodeData::Text { ref contents } => format!("{:?}", &**contents.borrow()),
+            _ => "?".to_owned(),
+        })
+        .collect()
+}
+
+#[test]
+fn parse_and_serialize() {
+    let arena = Arena::new();
+    let dom = parse(
+        &arena,
+        "<!DOCTYPE html><title>Test</title>\
+         <table><tr><td>1</td>x<div>y</div></table><b>1<p>2</b>3</p><!--end-->",
+    );
+    assert_eq!(
+        children_html(dom.document),
+        "<!DOCTYPE html><html><head><title>Test</title></head><body>x<div>y</div>\
+         <table><tbody><tr><td>1</td></tr></tbody></table><b>1</b><p><b>2</b>3</p>\
+         <!--end--></body></html>"
+    );
+    assert_eq!(dom.quirks_mode, QuirksMode::NoQuirks);
+    assert!(!dom.errors.is_empty());
+
+    let dom = parse(&arena, "<p>No doctype");
+    assert_eq!(dom.quirks_mode, QuirksMode::Quirks);
+}
+
+#[test]
+fn templates() {
+    let arena = Arena::new();
+    let dom = parse(&arena, "<template><tr><td>cell</template>");
+    let head = dom.document.first_child().unwrap().first_child().unwrap();
+    let template = head.first_child().unwrap();
+    assert!(template.first_child().is_none());
+
+    let contents = template.template_contents().unwrap();
+    assert!(contents.parent().is_none());
+    assert_eq!(names(contents.descendants()), ["tr", "td", "\"cell\""]);
+    assert_eq!(
+        html(template),
+        "<template><tr><td>cell</td></tr></template>"
+    );
+    assert!(head.template_contents().is_none());
+}
+
+#[test]
+fn fragment() {
+    let arena = Arena::new();
+    let context = QualName::new(None, ns!(html), local_name!("tr"));
+    let dom = parse_fragment(ArenaDom::new(&arena), Default::default(), context, vec![])
+        .one("<td>1<td>2");
+    let root = dom.document.first_child().unwrap();
+    assert_eq!(children_html(root), "<td>1</td><td>2</td>");
+}
+
+#[test]
+fn iterators() {
+    let arena = Arena::new();
+    let dom = parse(&arena, "<ul><li>1<li>2<b>3</b><li>4</ul><p>5");
+    let body = dom.document.last_child().unwrap().last_child().unwrap();
+    let list = body.first_child().unwrap();
+    let items = list.children().collect::<Vec<_>>();
+    let bold = items[1].last_child().unwrap();
+
+    assert_eq!(names(list.children()), ["li", "li", "li"]);
+    assert_eq!(
+        names(body.descendants()),
+        ["ul", "li", "\"1\"", "li", "\"2\"", "b", "\"3\"", "li", "\"4\"", "p", "\"5\""]
+    );
+    assert_eq!(names(items[1].descendants()), ["\"2\"", "b", "\"3\""]);
+    assert_eq!(names(bold.first_child().unwrap().descendants()).len(), 0);
+    assert_eq!(
+        names(bold.ancestors()),
+        ["li", "ul", "body", "html", "#document"]
+    );
+    assert_eq!(names(items[0].following_siblings()), ["li", "li"]);
+    assert_eq!(names(items[2].preceding_siblings()), ["li", "li"]);
+    assert_eq!(names(items[0].preceding_siblings()).len(), 0);
+    assert_eq!(names(dom.document.descendants()).len(), 14);
+
+    // The same tree through `TreeNavigator`.
+    assert_eq!(names(dom.descendants(&body)), names(body.descendants()));
+    assert_eq!(names(dom.ancestors(&bold)), names(bold.ancestors()));
+    assert_eq!(names(dom.preceding(&items[2]).take(2)), ["\"3\"", "b"]);
+}
+
+#[test]
+fn mutation() {
+    let arena = Arena::new();
+    let dom = parse(&arena, "<p>1</p><p>2</p><p>3</p>");
+    let body = dom.document.last_child().unwrap().last_child().unwrap();
+    let paragraphs = body.children().collect::<Vec<_>>();
+
+    body.append(paragraphs[0]);
+    paragraphs[1].insert_before(paragraphs[2]);
+    paragraphs[1].detach();
+    assert_eq!(children_html(body), "<p>3</p><p>1</p>");
+    assert!(paragraphs[1].parent().is_none());
+
+    let mut dom = dom;
+    let text = arena.alloc(Node::new(NodeData::Text {
+        contents: RefCell::new(StrTendril::from("4")),
+    }));
+    dom.append(&paragraphs[0], NodeOrText::AppendNode(text));
+    assert_eq!(children_html(body), "<p>3</p><p>14</p>");
+}
+
+#[test]
+#[should_panic(expected = "cannot insert a node into itself or its descendants!")]
+fn append_ancestor() {
+    let arena = Arena::new();
+    let dom = parse(&arena, "<p>1</p>");
+    let html = dom.document.first_child().unwrap();
+    html.last_child().unwrap().append(html);
+}
@@ -9,6 +9,10 @@
 
 use log::warn;
 pub use markup5ever::serialize::{AttrRef, Serialize, Serializer, TraversalScope};
+pub use markup5ever::serialize::{CharRefStyle, EncodingWriter, EscapePolicy, SingleQuote};
+pub use markup5ever::serialize::{ShadowRootInit, ShadowRootMode};
+use markup5ever::serialize::check_encodable;
+use markup5ever::encoding_rs::{Encoding, UTF_8};
 use markup5ever::{local_name, namespace_url, ns};
 use std::default::Default;
 use std::io::{self, Write};
@@ -20,8 +24,14 @@ where
     Wr: Write,
     T: Serialize,
 {
+    if opts.encoding.output_encoding() == UTF_8 {
+        let mut ser = HtmlSerializer::new(writer, opts.clone());
+        return node.serialize(&mut ser, opts.traversal_scope);
+    }
+    let writer = EncodingWriter::new(writer, opts.encoding);
     let mut ser = HtmlSerializer::new(writer, opts.clone());
-    node.serialize(&mut ser, opts.traversal_scope)
+    node.serialize(&mut ser, opts.traversal_scope)?;
+    ser.writer.finish().map(|_| ())
 }
 
 #[derive(Clone)]
@@ -38,6 +48,25 @@ pub struct SerializeOpts {
     /// creating a default parent on the element stack. No extra start elem will
     /// actually be written. Default: false
     pub create_missing_parent: bool,
+
+    /// Which characters are written as character references in text and
+    /// attribute values. Default: `EscapePolicy::html()`
+    pub escape_policy: EscapePolicy,
+
+    /// The encoding `serialize` writes. Characters it can't represent are
+    /// written as decimal character references in text and attribute
+    /// values; anywhere else, such as in a comment or the raw text of a
+    /// `<script>`, they are an `InvalidData` error. `HtmlSerializer` itself
+    /// always writes UTF-8; wrap its writer in an `EncodingWriter` to
+    /// encode. Default: UTF-8
+    pub encoding: &'static Encoding,
+
+    /// Declare `encoding` in the output: write `<meta charset>` at the start
+    /// of `<head>`, and leave out any `<meta>` that declares another
+    /// character encoding. The declaration is only written when a `<head>`
+    /// start tag is serialized, so it is missing when serializing a fragment
+    /// or a tree without a `<head>` element. Default: false
+    pub declare_encoding: bool,
 }
 
 impl Default for SerializeOpts {
@@ -46,6 +75,9 @@ impl Default for SerializeOpts {
             scripting_enabled: true,
             traversal_scope: TraversalScope::ChildrenOnly(None),
             create_missing_parent: false,
+            escape_policy: EscapePolicy::html(),
+            encoding: UTF_8,
+            declare_encoding: false,
         }
     }
 }
@@ -74,6 +106,18 @@ fn tagname(name: &QualName) -> LocalName {
     name.local.clone()
 }
 
+/// Whether the attributes of a `<meta>` element make it a [character encoding declaration].
+///
+/// [character encoding declaration]: https://html.spec.whatwg.org/multipage/#character-encoding-declaration
+fn declares_encoding(attrs: &[AttrRef]) -> bool {
+    attrs.iter().any(|&(name, value)| {
+        name.ns == ns!() &&
+            (name.local == local_name!("charset") ||
+                (name.local == local_name!("http-equiv") &&
+                    value.eq_ignore_ascii_case("content-type")))
+    })
+}
+
 impl<Wr: Write> HtmlSerializer<Wr> {
     pub fn new(writer: Wr, opts: SerializeOpts) -> Self {
         let html_name = match opts.traversal_scope {
@@ -103,40 +147,19 @@ impl<Wr: Write> HtmlSerializer<Wr> {
     }
 
     fn write_escaped(&mut self, text: &str, attr_mode: bool) -> io::Result<()> {
-        for c in text.chars() {
-            match c {
-                '&' => self.writer.write_all(b"&amp;"),
-                '\u{00A0}' => self.writer.write_all(b"&nbsp;"),
-                '"' if attr_mode => self.writer.write_all(b"&quot;"),
-                '<' if !attr_mode => self.writer.write_all(b"&lt;"),
-                '>' if !attr_mode => self.writer.write_all(b"&gt;"),
-                c => self.writer.write_fmt(format_args!("{}", c)),
-            }?;
-        }
-        Ok(())
+        self.opts
+            .escape_policy
+            .write_escaped(&mut self.writer, text, attr_mode)
     }
-}
 
-impl<Wr: Write> Serializer for HtmlSerializer<Wr> {
-    fn start_elem<'a, AttrIter>(&mut self, name: QualName, attrs: AttrIter) -> io::Result<()>
+    fn write_start_tag<'a, AttrIter>(&mut self, name: &QualName, attrs: AttrIter) -> io::Result<()>
     where
         AttrIter: Iterator<Item = AttrRef<'a>>,
     {
-        let html_name = match name.ns {
-            ns!(html) => Some(name.local.clone()),
-            _ => None,
-        };
-
-        if self.parent().ignore_children {
-            self.stack.push(ElemInfo {
-                html_name,
-                ignore_children: true,
-            });
-            return Ok(());
-        }
-
         self.writer.write_all(b"<")?;
-        self.writer.write_all(tagname(&name).as_bytes())?;
+        let tagname = tagname(name);
+        self.check_encodable(&tagname, "an element name")?;
+        self.writer.write_all(tagname.as_bytes())?;
         for (name, value) in attrs {
             self.writer.write_all(b" ")?;
 
@@ -156,12 +179,59 @@ impl<Wr: Write> Serializer for HtmlSerializer<Wr> {
                 },
             }
 
+            self.check_encodable(&name.local, "an attribute name")?;
             self.writer.write_all(name.local.as_bytes())?;
             self.writer.write_all(b"=\"")?;
             self.write_escaped(value, true)?;
             self.writer.write_all(b"\"")?;
         }
-        self.writer.write_all(b">")?;
+        self.writer.write_all(b">")
+    }
+
+    fn check_encodable(&self, text: &str, what: &str) -> io::Result<()> {
+        check_encodable(self.opts.encoding, text, what)
+    }
+}
+
+impl<Wr: Write> Serializer for HtmlSerializer<Wr> {
+    fn start_elem<'a, AttrIter>(&mut self, name: QualName, attrs: AttrIter) -> io::Result<()>
+    where
+        AttrIter: Iterator<Item = AttrRef<'a>>,
+    {
+        let html_name = match name.ns {
+            ns!(html) => Some(name.local.clone()),
+            _ => None,
+        };
+
+        if self.parent().ignore_children {
+            self.stack.push(ElemInfo {
+                html_name,
+                ignore_children: true,
+            });
+            return Ok(());
+        }
+
+        // Only `<meta>` attributes are buffered, to look for an encoding declaration.
+        if self.opts.declare_encoding && html_name == Some(local_name!("meta")) {
+            let attrs: Vec<AttrRef> = attrs.collect();
+            if declares_encoding(&attrs) {
+                self.stack.push(ElemInfo {
+                    html_name,
+                    ignore_children: true,
+                });
+                return Ok(());
+            }
+            self.write_start_tag(&name, attrs.into_iter())?;
+        } else {
+            self.write_start_tag(&name, attrs)?;
+        }
+
+        if self.opts.declare_encoding && html_name == Some(local_name!("head")) {
+            self.writer.write_all(b"<meta charset=\"")?;
+            self.writer
+                .write_all(self.opts.encoding.output_encoding().name().as_bytes())?;
+            self.writer.write_all(b"\">")?;
+        }
 
         let ignore_children = name.ns == ns!(html) &&
             match name.local {
@@ -230,23 +300,28 @@ impl<Wr: Write> Serializer for HtmlSerializer<Wr> {
         if escape {
             self.write_escaped(text, false)
         } else {
+            self.check_encodable(text, "raw text")?;
             self.writer.write_all(text.as_bytes())
         }
     }
 
     fn write_comment(&mut self, text: &str) -> io::Result<()> {
+        self.check_encodable(text, "a comment")?;
         self.writer.write_all(b"<!--")?;
         self.writer.write_all(text.as_bytes())?;
         self.writer.write_all(b"-->")
     }
 
     fn write_doctype(&mut self, name: &str) -> io::Result<()> {
+        self.check_encodable(name, "a doctype")?;
         self.writer.write_all(b"<!DOCTYPE ")?;
         self.writer.write_all(name.as_bytes())?;
         self.writer.write_all(b">")
     }
 
     fn write_processing_instruction(&mut self, target: &str, data: &str) -> io::Result<()> {
+        self.check_encodable(target, "a processing instruction")?;
+        self.check_encodable(data, "a processing instruction")?;
         self.writer.write_all(b"<?")?;
         self.writer.write_all(target.as_bytes())?;
         self.writer.write_all(b" ")?;
@@ -526,6 +526,7 @@ where
     fn end(&mut self) {
         for elem in self.open_elems.drain(..).rev() {
             self.sink.pop(&elem);
+            self.sink.element_closed(&elem);
         }
     }
 
@@ -735,7 +736,7 @@ where
                     .map(|(i, h)| (i, h.clone())),
                 // 10.
                 {
-                    self.open_elems.truncate(fmt_elem_stack_index);
+                    self.truncate_open_elems(fmt_elem_stack_index);
                     self.active_formatting.remove(fmt_elem_index);
                 }
             );
@@ -771,6 +772,7 @@ where
                     self.position_in_active_formatting(&node)
                         .map(|position| self.active_formatting.remove(position));
                     self.open_elems.remove(node_index);
+                    self.sink.element_closed(&node);
                     continue;
                 }
 
@@ -779,6 +781,7 @@ where
                     // 13.6.
                     {
                         self.open_elems.remove(node_index);
+                        self.sink.element_closed(&node);
                         continue;
                     }
                 );
@@ -800,6 +803,7 @@ where
                 );
                 self.open_elems[node_index] = new_element.clone();
                 self.active_formatting[node_formatting_index] = Element(new_element.clone(), tag);
+                self.sink.element_closed(&node);
                 node = new_element;
 
                 // 13.8.
@@ -882,9 +886,18 @@ where
     fn pop(&mut self) -> Handle {
         let elem = self.open_elems.pop().expect("no current element");
         self.sink.pop(&elem);
+        self.sink.element_closed(&elem);
         elem
     }
 
+    // Remove elements from the stack until only `len` are left.
+    fn truncate_open_elems(&mut self, len: usize) {
+        while self.open_elems.len() > len {
+            let elem = self.open_elems.pop().expect("no current element");
+            self.sink.element_closed(&elem);
+        }
+    }
+
     fn remove_from_stack(&mut self, elem: &Handle) {
         let sink = &mut self.sink;
         let position = self
@@ -894,6 +907,7 @@ where
         if let Some(position) = position {
             self.open_elems.remove(position);
             sink.pop(elem);
+            sink.element_closed(elem);
         }
     }
 
@@ -1079,7 +1093,9 @@ where
             if self.current_node_in(|x| pred(x)) {
                 break;
             }
-            self.open_elems.pop();
+            if let Some(elem) = self.open_elems.pop() {
+                self.sink.element_closed(&elem);
+            }
         }
     }
 
@@ -1095,6 +1111,7 @@ where
             match self.open_elems.pop() {
                 None => break,
                 Some(elem) => {
+                    self.sink.element_closed(&elem);
                     if pred(self.sink.elem_name(&elem)) {
                         break;
                     }
@@ -1320,7 +1337,7 @@ where
 
         match push {
             Push => self.push(&elem),
-            NoPush => (),
+            NoPush => self.sink.element_closed(&elem),
         }
         // FIXME: Remove from the stack if we can't append?
         elem
@@ -1402,7 +1419,7 @@ where
             // mis-nested tags
             self.unexpected(&tag);
         }
-        self.open_elems.truncate(match_idx);
+        self.truncate_open_elems(match_idx);
     }
 
     fn handle_misnested_a_tags(&mut self, tag: &Tag) {
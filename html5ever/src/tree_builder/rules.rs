@@ -300,7 +300,7 @@ where
 
                     // FIXME: can we get here in the fragment case?
                     // What to do with the first element then?
-                    self.open_elems.truncate(1);
+                    self.truncate_open_elems(1);
                     self.insert_element_for(tag);
                     self.mode = InFrameset;
                     Done
@@ -1429,7 +1429,7 @@ where
                     }
 
                     if eq {
-                        self.open_elems.truncate(stack_idx);
+                        self.truncate_open_elems(stack_idx);
                         return Done;
                     }
 
@@ -106,6 +106,7 @@ fn named_entities_to_phf(to: &Path) {
     }
 
     let mut file = File::create(to).unwrap();
+    write_entity_names(&mut file);
     writeln!(
         &mut file,
         r#"
@@ -129,3 +130,52 @@ fn named_entities_to_phf(to: &Path) {
     )
     .unwrap();
 }
+
+/// Write the reverse map, from a single codepoint to the name a serializer should use for it.
+fn write_entity_names(file: &mut File) {
+    let mut names: HashMap<u32, &str> = HashMap::new();
+    for &(name, cp1, cp2) in entities::NAMED_ENTITIES.iter() {
+        if cp2 != 0 || !name.ends_with(';') {
+            continue;
+        }
+        let name = &name[1..];
+        // Prefer the shortest name, then the one with the fewest capitals (`copy;` over `COPY;`).
+        let key = |name: &str| {
+            let capitals = name.bytes().filter(u8::is_ascii_uppercase).count();
+            (name.len(), capitals, name.to_owned())
+        };
+        let best = names.entry(cp1).or_insert(name);
+        if key(name) < key(best) {
+            *best = name;
+        }
+    }
+
+    let mut phf_map = phf_codegen::Map::new();
+    for (codepoint, name) in names {
+        phf_map.entry(codepoint, &format!("{:?}", name));
+    }
+
+    writeln!(
+        file,
+        r#"
+/// A map of codepoints to the name of a character reference for them, including the trailing
+/// ';' but without the preceding '&'. Only characters with a named reference that expands to a
+/// single codepoint are present.
+///
+/// # Examples
+///
+/// ```
+/// use markup5ever::data::ENTITY_NAMES;
+///
+/// assert_eq!(ENTITY_NAMES.get(&0xA9).unwrap(), &"copy;");
+/// ```
+"#
+    )
+    .unwrap();
+    writeln!(
+        file,
+        "pub static ENTITY_NAMES: Map<u32, &'static str> = {};",
+        phf_map.build(),
+    )
+    .unwrap();
+}
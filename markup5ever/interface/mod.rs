@@ -13,7 +13,7 @@ use tendril::StrTendril;
 
 pub use self::tree_builder::{create_element, AppendNode, AppendText, ElementFlags, NodeOrText};
 pub use self::tree_builder::{LimitedQuirks, NoQuirks, Quirks, QuirksMode};
-pub use self::tree_builder::{NextParserState, Tracer, TreeSink};
+pub use self::tree_builder::{NextParserState, Tracer, TreeSink, XmlDeclaration, XmlScope};
 use super::{LocalName, Namespace, Prefix};
 
 /// An [expanded name], containing the tag and the namespace.
@@ -144,6 +144,7 @@ pub mod tree_builder;
 ///
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
 #[cfg_attr(feature = "heap_size", derive(HeapSizeOf))]
+#[cfg_attr(feature = "serde_support", derive(serde::Serialize, serde::Deserialize))]
 pub struct QualName {
     /// The prefix of qualified (e.g. `furn` in `<furn:table>` above).
     /// Optional (since some namespaces can be empty or inferred), and
@@ -346,10 +347,12 @@ impl QualName {
 /// builder will adjust certain attribute names inside foreign
 /// content (MathML, SVG).
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+#[cfg_attr(feature = "serde_support", derive(serde::Serialize, serde::Deserialize))]
 pub struct Attribute {
     /// The name of the attribute (e.g. the `class` in `<div class="test">`)
     pub name: QualName,
     /// The value of the attribute (e.g. the `"test"` in `<div class="test">`)
+    #[cfg_attr(feature = "serde_support", serde(with = "crate::serde_tendril"))]
     pub value: StrTendril,
 }
 
@@ -12,6 +12,7 @@
 //! It can be used by a parser to create the DOM graph structure in memory.
 
 use crate::interface::{Attribute, ExpandedName, QualName};
+use crate::{Namespace, Prefix};
 use std::borrow::Cow;
 use tendril::StrTendril;
 
@@ -32,6 +33,7 @@ pub enum NodeOrText<Handle> {
 ///
 /// [quirks mode on wikipedia]: https://en.wikipedia.org/wiki/Quirks_mode
 #[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+#[cfg_attr(feature = "serde_support", derive(serde::Serialize, serde::Deserialize))]
 pub enum QuirksMode {
     /// Full quirks mode
     Quirks,
@@ -52,6 +54,34 @@ pub enum NextParserState {
     Continue,
 }
 
+/// The XML declaration at the start of a document, e.g.
+/// `<?xml version="1.0" encoding="UTF-8" standalone="yes"?>`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde_support", derive(serde::Serialize, serde::Deserialize))]
+pub struct XmlDeclaration {
+    /// The `version` pseudo-attribute.
+    #[cfg_attr(feature = "serde_support", serde(with = "crate::serde_tendril"))]
+    pub version: StrTendril,
+    /// The `encoding` pseudo-attribute, if present.
+    #[cfg_attr(feature = "serde_support", serde(with = "crate::serde_tendril::option"))]
+    pub encoding: Option<StrTendril>,
+    /// The `standalone` pseudo-attribute, if present.
+    pub standalone: Option<bool>,
+}
+
+/// The values of the `xml:space`, `xml:lang` and `xml:base` attributes in scope for an element:
+/// its own, or else the nearest ancestor's.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct XmlScope {
+    /// Is `xml:space="preserve"` in scope?
+    pub preserve_space: bool,
+    /// The language, if any. An empty value means the language is unknown.
+    pub lang: Option<StrTendril>,
+    /// The `xml:base` of the element and its ancestors, each resolved against the one above.
+    /// This stays relative unless one of them is an absolute URI.
+    pub base: Option<StrTendril>,
+}
+
 /// Special properties of an element, useful for tagging elements with this information.
 #[derive(Default)]
 pub struct ElementFlags {
@@ -163,6 +193,12 @@ pub trait TreeSink {
     /// The child node will not already have a parent.
     fn append(&mut self, parent: &Self::Handle, child: NodeOrText<Self::Handle>);
 
+    /// Append a CDATA section as the last child of the given node. By
+    /// default it is appended as text.
+    fn append_cdata(&mut self, parent: &Self::Handle, text: StrTendril) {
+        self.append(parent, AppendText(text))
+    }
+
     /// When the insertion point is decided by the existence of a parent node of the
     /// element, we consider both possibilities and send the element which will be used
     /// if a parent node exists, along with the element to be used if there isn't one.
@@ -187,6 +223,16 @@ pub trait TreeSink {
     /// Indicate that a node was popped off the stack of open elements.
     fn pop(&mut self, _node: &Self::Handle) {}
 
+    /// Indicate that the tree builder is done with an element: it left the
+    /// stack of open elements by any route (including being removed or
+    /// replaced during the adoption agency algorithm), or it was a void or
+    /// self-closing element that was never pushed.
+    ///
+    /// Unlike `pop`, this is called for every element the tree builder
+    /// creates, so sinks that write output as elements complete should use
+    /// it.
+    fn element_closed(&mut self, _node: &Self::Handle) {}
+
     /// Get a handle to a template's template contents. The tree builder
     /// promises this will never be called with something else than
     /// a template element.
@@ -237,6 +283,23 @@ pub trait TreeSink {
     /// Called whenever the line number changes.
     fn set_current_line(&mut self, _line_number: u64) {}
 
+    /// Called by the XML tree builder before `create_element` when the
+    /// element's start tag declares namespaces. Each declaration maps a
+    /// prefix (`None` for the default namespace) to a namespace (`None` if
+    /// it is undeclared), ordered by prefix. Declarations aren't passed to
+    /// `create_element` as attributes.
+    fn declare_namespaces(&mut self, _declarations: Vec<(Option<Prefix>, Option<Namespace>)>) {}
+
+    /// Set the document's XML declaration. Called at most once, just before
+    /// the declaration is appended to the document as a processing
+    /// instruction, which serializers replace with the declaration they write.
+    fn set_xml_declaration(&mut self, _declaration: XmlDeclaration) {}
+
+    /// Called by the XML tree builder after `create_element` with the
+    /// `xml:space`, `xml:lang` and `xml:base` values in scope for the
+    /// element.
+    fn set_xml_scope(&mut self, _target: &Self::Handle, _scope: &XmlScope) {}
+
     /// Indicate that a `script` element is complete.
     fn complete_script(&mut self, _node: &Self::Handle) -> NextParserState {
         NextParserState::Continue
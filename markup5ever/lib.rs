@@ -7,6 +7,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+pub use encoding_rs;
 pub use tendril;
 
 /// Create a [`SmallCharSet`], with each space-separated number stored in the set.
@@ -35,7 +36,12 @@ include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 pub mod data;
 #[macro_use]
 pub mod interface;
+pub mod select;
+#[cfg(feature = "serde_support")]
+pub mod serde_tendril;
 pub mod serialize;
+pub mod traverse;
+pub mod xpath;
 mod util {
     pub mod buffer_queue;
     pub mod smallcharset;
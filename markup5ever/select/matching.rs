@@ -0,0 +1,679 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Matching selectors against elements, from right to left.
+
+use super::parser::{AttributeOp, AttributeTest, Combinator, Complex, NamespaceConstraint, Simple};
+use crate::traverse::{NodeKind, TreeNavigator};
+use crate::{LocalName, Namespace, QualName};
+use mac::{_tt_as_expr_hack, matches};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Attributes of HTML elements whose values are compared ignoring ASCII case, from
+/// https://html.spec.whatwg.org/multipage/#case-sensitivity-of-selectors
+static CASE_INSENSITIVE_ATTRIBUTES: &[&str] = &[
+    "accept",
+    "accept-charset",
+    "align",
+    "alink",
+    "axis",
+    "bgcolor",
+    "charset",
+    "checked",
+    "clear",
+    "codetype",
+    "color",
+    "compact",
+    "declare",
+    "defer",
+    "dir",
+    "direction",
+    "disabled",
+    "enctype",
+    "face",
+    "frame",
+    "hreflang",
+    "http-equiv",
+    "lang",
+    "language",
+    "link",
+    "media",
+    "method",
+    "multiple",
+    "nohref",
+    "noresize",
+    "noshade",
+    "nowrap",
+    "readonly",
+    "rel",
+    "rev",
+    "rules",
+    "scope",
+    "scrolling",
+    "selected",
+    "shape",
+    "target",
+    "text",
+    "type",
+    "valign",
+    "valuetype",
+    "vlink",
+];
+
+/// How many parents' child elements `Matcher` remembers for `:nth-*` selectors.
+const CACHED_PARENTS: usize = 32;
+
+struct Matcher<'a, N: TreeNavigator> {
+    navigator: &'a N,
+    /// The node that `:scope` matches, or `None` for the document element.
+    scope: Option<&'a N::Handle>,
+    /// The child elements of recently seen parents, most recent last, so that `:nth-*`
+    /// selectors don't count siblings again for each element.
+    siblings: RefCell<Vec<Siblings<N::Handle>>>,
+}
+
+/// Which siblings an `:nth-*` selector counts, besides all of them.
+#[derive(Clone, Copy, PartialEq)]
+enum Counted {
+    /// Siblings with the same name, for `:nth-of-type()`.
+    Type,
+    /// Siblings matching the `of S` selector list at this address.
+    Matching(*const Complex),
+}
+
+/// The child elements of one parent.
+struct Siblings<H> {
+    parent: H,
+    elements: Vec<H>,
+    /// Where the last element was found in `elements`. Elements are mostly looked up in
+    /// document order, so the search starts here.
+    cursor: usize,
+    /// The positions of the elements among the siblings that count, from the start and from the
+    /// end, or zero for those that don't count.
+    positions: Vec<(Counted, Vec<(i64, i64)>)>,
+}
+
+/// Moves from an element to the one a combinator relates it to.
+type Step<N> = fn(&N, &<N as TreeNavigator>::Handle) -> Option<<N as TreeNavigator>::Handle>;
+
+pub(super) fn matches_any<N: TreeNavigator>(
+    navigator: &N,
+    selectors: &[Complex],
+    element: &N::Handle,
+    scope: Option<&N::Handle>,
+) -> bool {
+    Matcher::new(navigator, scope).matches_any(selectors, element, None)
+}
+
+/// Calls `found` with each descendant element of `node` that matches, until it returns false.
+pub(super) fn select<N: TreeNavigator>(
+    navigator: &N,
+    selectors: &[Complex],
+    node: &N::Handle,
+    found: &mut dyn FnMut(N::Handle) -> bool,
+) {
+    let matcher = Matcher::new(navigator, Some(node));
+    matcher.descendants(node, &mut |element| {
+        !matcher.matches_any(selectors, element, None) || found(element.clone())
+    });
+}
+
+fn is_element<N: TreeNavigator>(navigator: &N, node: &N::Handle) -> bool {
+    navigator.kind(node) == NodeKind::Element
+}
+
+fn element_name<N: TreeNavigator>(navigator: &N, element: &N::Handle) -> QualName {
+    navigator.name(element).expect("not an element")
+}
+
+/// The parent of an element, if that is an element.
+fn parent_element<N: TreeNavigator>(navigator: &N, element: &N::Handle) -> Option<N::Handle> {
+    navigator
+        .parent(element)
+        .filter(|parent| is_element(navigator, parent))
+}
+
+/// The nearest element before this one with the same parent.
+fn previous_sibling_element<N: TreeNavigator>(
+    navigator: &N,
+    element: &N::Handle,
+) -> Option<N::Handle> {
+    let mut sibling = navigator.previous_sibling(element);
+    while let Some(node) = sibling {
+        if is_element(navigator, &node) {
+            return Some(node);
+        }
+        sibling = navigator.previous_sibling(&node);
+    }
+    None
+}
+
+/// The nearest element after this one with the same parent.
+fn next_sibling_element<N: TreeNavigator>(navigator: &N, element: &N::Handle) -> Option<N::Handle> {
+    let mut sibling = navigator.next_sibling(element);
+    while let Some(node) = sibling {
+        if is_element(navigator, &node) {
+            return Some(node);
+        }
+        sibling = navigator.next_sibling(&node);
+    }
+    None
+}
+
+/// The child elements of a document or element, in document order.
+fn child_elements<N: TreeNavigator>(navigator: &N, node: &N::Handle) -> Vec<N::Handle> {
+    navigator
+        .children(node)
+        .filter(|child| is_element(navigator, child))
+        .collect()
+}
+
+/// Is this the document element, whose parent is the document?
+fn is_root<N: TreeNavigator>(navigator: &N, element: &N::Handle) -> bool {
+    match navigator.parent(element) {
+        Some(parent) => navigator.kind(&parent) == NodeKind::Document,
+        None => false,
+    }
+}
+
+/// Does the element have no children other than comments and processing instructions? Empty
+/// text nodes don't count either.
+fn is_empty<N: TreeNavigator>(navigator: &N, element: &N::Handle) -> bool {
+    navigator
+        .children(element)
+        .all(|child| match navigator.kind(&child) {
+            NodeKind::Text | NodeKind::CData => match navigator.text(&child) {
+                Some(text) => text.is_empty(),
+                None => true,
+            },
+            NodeKind::Comment | NodeKind::ProcessingInstruction => true,
+            _ => false,
+        })
+}
+
+fn is_html(name: &QualName) -> bool {
+    name.ns == ns!(html)
+}
+
+fn namespace_matches(constraint: &NamespaceConstraint, ns: &Namespace) -> bool {
+    match *constraint {
+        NamespaceConstraint::Any => true,
+        NamespaceConstraint::Namespace(ref expected) => expected == ns,
+    }
+}
+
+fn nth_matches(a: i64, b: i64, position: i64) -> bool {
+    if a == 0 {
+        position == b
+    } else {
+        let n = position - b;
+        n % a == 0 && n / a >= 0
+    }
+}
+
+fn attribute_matches(test: &AttributeTest, value: &str, case_insensitive: bool) -> bool {
+    let (value, expected) = if case_insensitive {
+        (value.to_ascii_lowercase(), test.value.to_ascii_lowercase())
+    } else {
+        (value.to_owned(), test.value.clone())
+    };
+    match test.op {
+        AttributeOp::Equal => value == expected,
+        AttributeOp::Includes => {
+            !expected.is_empty() &&
+                !expected.contains(|c: char| c.is_ascii_whitespace()) &&
+                value.split_ascii_whitespace().any(|word| word == expected)
+        },
+        AttributeOp::DashMatch => {
+            value == expected ||
+                (value.starts_with(&*expected) && value[expected.len()..].starts_with('-'))
+        },
+        AttributeOp::Prefix => !expected.is_empty() && value.starts_with(&*expected),
+        AttributeOp::Suffix => !expected.is_empty() && value.ends_with(&*expected),
+        AttributeOp::Substring => !expected.is_empty() && value.contains(&*expected),
+    }
+}
+
+impl<'a, N: TreeNavigator> Matcher<'a, N> {
+    fn new(navigator: &'a N, scope: Option<&'a N::Handle>) -> Matcher<'a, N> {
+        Matcher {
+            navigator,
+            scope,
+            siblings: RefCell::new(vec![]),
+        }
+    }
+
+    /// Calls `f` with each descendant element of `node` in document order, until it returns
+    /// false. Returns whether it never did.
+    fn descendants(&self, node: &N::Handle, f: &mut dyn FnMut(&N::Handle) -> bool) -> bool {
+        for child in child_elements(self.navigator, node) {
+            if !f(&child) || !self.descendants(&child, f) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// `anchor` is the element that relative selectors are relative to.
+    fn matches_any(
+        &self,
+        selectors: &[Complex],
+        element: &N::Handle,
+        anchor: Option<&N::Handle>,
+    ) -> bool {
+        selectors.iter().any(|complex| {
+            !complex.pseudo_element &&
+                self.matches_from(complex, complex.compounds.len() - 1, element, anchor)
+        })
+    }
+
+    /// Does the element match the compound selector `index` of `complex` and, through the
+    /// combinators, those to the left of it?
+    fn matches_from(
+        &self,
+        complex: &Complex,
+        index: usize,
+        element: &N::Handle,
+        anchor: Option<&N::Handle>,
+    ) -> bool {
+        let matches_compound = complex.compounds[index]
+            .iter()
+            .all(|simple| self.matches_simple(simple, element, anchor));
+        if !matches_compound {
+            return false;
+        }
+        if index == 0 {
+            return true;
+        }
+        let navigator = self.navigator;
+        let (next, repeat): (Step<N>, bool) = match complex.combinators[index - 1] {
+            Combinator::Descendant => (parent_element, true),
+            Combinator::Child => (parent_element, false),
+            Combinator::NextSibling => (previous_sibling_element, false),
+            Combinator::SubsequentSibling => (previous_sibling_element, true),
+        };
+        let mut candidate = next(navigator, element);
+        while let Some(element) = candidate {
+            if self.matches_from(complex, index - 1, &element, anchor) {
+                return true;
+            }
+            if !repeat {
+                break;
+            }
+            candidate = next(navigator, &element);
+        }
+        false
+    }
+
+    fn matches_simple(
+        &self,
+        simple: &Simple,
+        element: &N::Handle,
+        anchor: Option<&N::Handle>,
+    ) -> bool {
+        let navigator = self.navigator;
+        match *simple {
+            Simple::Type {
+                ref ns,
+                ref local,
+                ref lower,
+            } => {
+                let name = element_name(navigator, element);
+                namespace_matches(ns, &name.ns) &&
+                    name.local == *if is_html(&name) { lower } else { local }
+            },
+            Simple::Universal(ref ns) => {
+                namespace_matches(ns, &element_name(navigator, element).ns)
+            },
+            Simple::Id(ref id) => match navigator.attribute(element, &ns!(), &local_name!("id")) {
+                Some(value) => *value == **id,
+                None => false,
+            },
+            Simple::Class(ref class) => {
+                match navigator.attribute(element, &ns!(), &local_name!("class")) {
+                    Some(value) => value.split_ascii_whitespace().any(|c| c == class),
+                    None => false,
+                }
+            },
+            Simple::Attribute {
+                ref ns,
+                ref local,
+                ref lower,
+                ref test,
+            } => self.matches_attribute(element, ns, local, lower, test.as_ref()),
+            Simple::Root => is_root(navigator, element),
+            Simple::Empty => is_empty(navigator, element),
+            Simple::Scope => match self.scope {
+                Some(scope) => {
+                    navigator.same_node(element, scope) ||
+                        (is_root(navigator, element) &&
+                            child_elements(navigator, scope)
+                                .iter()
+                                .any(|child| navigator.same_node(child, element)))
+                },
+                None => is_root(navigator, element),
+            },
+            Simple::Relative => match anchor {
+                Some(anchor) => navigator.same_node(element, anchor),
+                None => false,
+            },
+            Simple::Link => {
+                let name = element_name(navigator, element);
+                is_html(&name) &&
+                    matches!(
+                        name.local,
+                        local_name!("a") | local_name!("area") | local_name!("link")
+                    ) &&
+                    self.has_attribute(element, local_name!("href"))
+            },
+            Simple::Checked => {
+                let name = element_name(navigator, element);
+                match name.local {
+                    local_name!("input") if is_html(&name) => {
+                        self.has_attribute(element, local_name!("checked")) &&
+                            match navigator.attribute(element, &ns!(), &local_name!("type")) {
+                                Some(t) => {
+                                    t.eq_ignore_ascii_case("checkbox") ||
+                                        t.eq_ignore_ascii_case("radio")
+                                },
+                                None => false,
+                            }
+                    },
+                    local_name!("option") if is_html(&name) => {
+                        self.has_attribute(element, local_name!("selected"))
+                    },
+                    _ => false,
+                }
+            },
+            Simple::Enabled => {
+                self.is_form_control(element) &&
+                    !self.has_attribute(element, local_name!("disabled"))
+            },
+            Simple::Disabled => {
+                self.is_form_control(element) &&
+                    self.has_attribute(element, local_name!("disabled"))
+            },
+            Simple::Never => false,
+            Simple::Lang(ref lang) => self.matches_lang(element, lang),
+            Simple::Nth {
+                a,
+                b,
+                of_type,
+                from_end,
+                ref of,
+            } => {
+                if !of.is_empty() && !self.matches_any(of, element, None) {
+                    return false;
+                }
+                let position = self.nth_position(element, of_type, of, from_end);
+                nth_matches(a, b, position)
+            },
+            Simple::Not(ref selectors) => !self.matches_any(selectors, element, None),
+            Simple::Is(ref selectors) => self.matches_any(selectors, element, None),
+            Simple::Has(ref selectors) => selectors.iter().any(|complex| {
+                let relative = |candidate: &N::Handle| {
+                    self.matches_any(std::slice::from_ref(complex), candidate, Some(element))
+                };
+                match complex.combinators[0] {
+                    Combinator::Descendant | Combinator::Child => {
+                        !self.descendants(element, &mut |candidate| !relative(candidate))
+                    },
+                    Combinator::NextSibling | Combinator::SubsequentSibling => {
+                        let mut sibling = next_sibling_element(navigator, element);
+                        while let Some(candidate) = sibling {
+                            if relative(&candidate) ||
+                                !self
+                                    .descendants(&candidate, &mut |candidate| !relative(candidate))
+                            {
+                                return true;
+                            }
+                            sibling = next_sibling_element(navigator, &candidate);
+                        }
+                        false
+                    },
+                }
+            }),
+        }
+    }
+
+    /// The position of an element among its siblings, counting from the end if `from_end`.
+    /// Only siblings of the same type count if `of_type`, and only those matching `of` if that
+    /// is not empty.
+    fn nth_position(
+        &self,
+        element: &N::Handle,
+        of_type: bool,
+        of: &[Complex],
+        from_end: bool,
+    ) -> i64 {
+        let parent = match parent_element(self.navigator, element) {
+            Some(parent) => parent,
+            None => return self.count_siblings(element, of_type, of, from_end),
+        };
+        let counted = if of_type {
+            Counted::Type
+        } else if !of.is_empty() {
+            Counted::Matching(of.as_ptr())
+        } else {
+            let mut cache = self.siblings.borrow_mut();
+            let siblings = self.siblings_of(&mut cache, &parent);
+            let index = self.index_of(siblings, element) as i64;
+            let len = siblings.elements.len() as i64;
+            return if from_end { len - index } else { index + 1 };
+        };
+        let (index, elements) = {
+            let mut cache = self.siblings.borrow_mut();
+            let siblings = self.siblings_of(&mut cache, &parent);
+            let index = self.index_of(siblings, element);
+            let known = siblings.positions.iter().find(|&&(c, _)| c == counted);
+            if let Some((_, positions)) = known {
+                let (start, end) = positions[index];
+                return if from_end { end } else { start };
+            }
+            (index, siblings.elements.clone())
+        };
+        // Matching `of` may look at other parents' siblings, so the cache isn't borrowed here.
+        let positions = self.positions(&elements, of_type, of);
+        let (start, end) = positions[index];
+        let mut cache = self.siblings.borrow_mut();
+        self.siblings_of(&mut cache, &parent)
+            .positions
+            .push((counted, positions));
+        if from_end { end } else { start }
+    }
+
+    /// `nth_position` for an element without a parent element, found by counting siblings.
+    fn count_siblings(
+        &self,
+        element: &N::Handle,
+        of_type: bool,
+        of: &[Complex],
+        from_end: bool,
+    ) -> i64 {
+        let navigator = self.navigator;
+        let name = if of_type {
+            Some(element_name(navigator, element))
+        } else {
+            None
+        };
+        let next: Step<N> = if from_end {
+            next_sibling_element
+        } else {
+            previous_sibling_element
+        };
+        let mut position = 1;
+        let mut sibling = next(navigator, element);
+        while let Some(element) = sibling {
+            let counts = match name {
+                Some(ref name) => {
+                    let sibling_name = element_name(navigator, &element);
+                    sibling_name.ns == name.ns && sibling_name.local == name.local
+                },
+                None => of.is_empty() || self.matches_any(of, &element, None),
+            };
+            if counts {
+                position += 1;
+            }
+            sibling = next(navigator, &element);
+        }
+        position
+    }
+
+    /// The positions of each of `elements` among those that count, as `Siblings::positions`
+    /// holds them.
+    fn positions(&self, elements: &[N::Handle], of_type: bool, of: &[Complex]) -> Vec<(i64, i64)> {
+        let mut positions = vec![(0, 0); elements.len()];
+        if of_type {
+            let names: Vec<_> = elements
+                .iter()
+                .map(|element| {
+                    let name = element_name(self.navigator, element);
+                    (name.ns, name.local)
+                })
+                .collect();
+            let mut counts = HashMap::new();
+            for (i, name) in names.iter().enumerate() {
+                let count = counts.entry(name).or_insert(0);
+                *count += 1;
+                positions[i].0 = *count;
+            }
+            for (i, name) in names.iter().enumerate() {
+                positions[i].1 = counts[name] - positions[i].0 + 1;
+            }
+        } else {
+            let matching: Vec<bool> = elements
+                .iter()
+                .map(|element| self.matches_any(of, element, None))
+                .collect();
+            let total = matching.iter().filter(|&&m| m).count() as i64;
+            let mut count = 0;
+            for (i, &m) in matching.iter().enumerate() {
+                if m {
+                    count += 1;
+                    positions[i] = (count, total - count + 1);
+                }
+            }
+        }
+        positions
+    }
+
+    /// The cached child elements of `parent`, which are looked up and added to the cache if
+    /// they aren't there.
+    fn siblings_of<'c>(
+        &self,
+        cache: &'c mut Vec<Siblings<N::Handle>>,
+        parent: &N::Handle,
+    ) -> &'c mut Siblings<N::Handle> {
+        let navigator = self.navigator;
+        let i = match cache
+            .iter()
+            .rposition(|siblings| navigator.same_node(&siblings.parent, parent))
+        {
+            Some(i) => i,
+            None => {
+                if cache.len() == CACHED_PARENTS {
+                    cache.remove(0);
+                }
+                cache.push(Siblings {
+                    parent: parent.clone(),
+                    elements: child_elements(navigator, parent),
+                    cursor: 0,
+                    positions: vec![],
+                });
+                cache.len() - 1
+            },
+        };
+        &mut cache[i]
+    }
+
+    /// Where `element` is in `siblings.elements`.
+    fn index_of(&self, siblings: &mut Siblings<N::Handle>, element: &N::Handle) -> usize {
+        let len = siblings.elements.len();
+        let index = (0..len)
+            .map(|i| (siblings.cursor + i) % len)
+            .find(|&i| self.navigator.same_node(&siblings.elements[i], element))
+            .expect("element is not one of its parent's children");
+        siblings.cursor = index;
+        index
+    }
+
+    fn has_attribute(&self, element: &N::Handle, local: LocalName) -> bool {
+        self.navigator.attribute(element, &ns!(), &local).is_some()
+    }
+
+    fn is_form_control(&self, element: &N::Handle) -> bool {
+        let name = element_name(self.navigator, element);
+        is_html(&name) &&
+            matches!(
+                name.local,
+                local_name!("button") |
+                    local_name!("input") |
+                    local_name!("select") |
+                    local_name!("textarea") |
+                    local_name!("optgroup") |
+                    local_name!("option") |
+                    local_name!("fieldset")
+            )
+    }
+
+    fn matches_attribute(
+        &self,
+        element: &N::Handle,
+        ns: &NamespaceConstraint,
+        local: &LocalName,
+        lower: &LocalName,
+        test: Option<&AttributeTest>,
+    ) -> bool {
+        let html = is_html(&element_name(self.navigator, element));
+        let local = if html { lower } else { local };
+        let case_insensitive = |attr_ns: &Namespace| match test.and_then(|t| t.case_insensitive) {
+            Some(case_insensitive) => case_insensitive,
+            None => html && *attr_ns == ns!() && CASE_INSENSITIVE_ATTRIBUTES.contains(&&**local),
+        };
+        let value_matches = |attr_ns: &Namespace, value: &str| match test {
+            Some(test) => attribute_matches(test, value, case_insensitive(attr_ns)),
+            None => true,
+        };
+        match *ns {
+            NamespaceConstraint::Namespace(ref ns) => {
+                match self.navigator.attribute(element, ns, local) {
+                    Some(value) => value_matches(ns, &value),
+                    None => false,
+                }
+            },
+            NamespaceConstraint::Any => {
+                self.navigator.attributes(element).iter().any(|attr| {
+                    attr.name.local == *local && value_matches(&attr.name.ns, &attr.value)
+                })
+            },
+        }
+    }
+
+    /// Is the element's language, from the nearest `xml:lang` or `lang` attribute, `lang` or a
+    /// subtag of it?
+    fn matches_lang(&self, element: &N::Handle, lang: &str) -> bool {
+        let navigator = self.navigator;
+        let mut node = Some(element.clone());
+        while let Some(element) = node {
+            let value = navigator
+                .attribute(&element, &ns!(xml), &local_name!("lang"))
+                .or_else(|| navigator.attribute(&element, &ns!(), &local_name!("lang")));
+            if let Some(value) = value {
+                let value = value.as_bytes();
+                let lang = lang.as_bytes();
+                return value.len() >= lang.len() &&
+                    value[..lang.len()].eq_ignore_ascii_case(lang) &&
+                    (value.len() == lang.len() || value[lang.len()] == b'-');
+            }
+            node = parent_element(navigator, &element);
+        }
+        false
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! CSS selector matching over any tree that implements `TreeNavigator`.
+//!
+//! ```text
+//! let selector = Selector::parse("div.article > a[href^=http]")?;
+//! let links = selector.select(&navigator, &document);
+//! ```
+//!
+//! [Selectors Level 3] is supported, along with `:is()`, `:where()`, `:has()`, `:scope`, the
+//! selector list argument of `:not()`, the `of S` argument of `:nth-child()`, and the `i` and
+//! `s` attribute selector flags from [Selectors Level 4]. Dynamic pseudo-classes such as
+//! `:hover` never match, and neither do selectors with a pseudo-element.
+//!
+//! As in an HTML document, type selectors and attribute names match HTML elements ignoring ASCII
+//! case, and elements in other namespaces case-sensitively. An unprefixed type selector matches
+//! elements in any namespace, and an unprefixed attribute name matches attributes in no
+//! namespace.
+//!
+//! [Selectors Level 3]: https://www.w3.org/TR/selectors-3/
+//! [Selectors Level 4]: https://www.w3.org/TR/selectors-4/
+
+use crate::traverse::TreeNavigator;
+use crate::{Namespace, Prefix};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+mod matching;
+mod parser;
+
+/// Failure to parse a selector. `position` is the byte offset where parsing failed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub position: usize,
+    pub message: Cow<'static, str>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A parsed selector list, such as `h1, h2.title`.
+#[derive(Clone, Debug)]
+pub struct Selector {
+    selectors: Vec<parser::Complex>,
+}
+
+impl Selector {
+    /// Parses a selector list. Namespace prefixes other than `*` are errors.
+    pub fn parse(source: &str) -> Result<Selector, Error> {
+        Selector::parse_with_namespaces(source, &HashMap::new())
+    }
+
+    /// Parses a selector list whose type and attribute selectors may have the given namespace
+    /// prefixes, as declared by `@namespace` rules in a style sheet.
+    pub fn parse_with_namespaces(
+        source: &str,
+        namespaces: &HashMap<Prefix, Namespace>,
+    ) -> Result<Selector, Error> {
+        Ok(Selector {
+            selectors: parser::parse(source, namespaces)?,
+        })
+    }
+
+    /// Does the element match the selector? `:scope` matches the document element.
+    pub fn matches<N>(&self, navigator: &N, element: &N::Handle) -> bool
+    where
+        N: TreeNavigator,
+    {
+        matching::matches_any(navigator, &self.selectors, element, None)
+    }
+
+    /// The descendant elements of a document or element that match the selector, in document
+    /// order, as `querySelectorAll()` finds them. `:scope` matches `node`, or the document element
+    /// if `node` is the document.
+    pub fn select<N>(&self, navigator: &N, node: &N::Handle) -> Vec<N::Handle>
+    where
+        N: TreeNavigator,
+    {
+        let mut found = vec![];
+        matching::select(navigator, &self.selectors, node, &mut |element| {
+            found.push(element);
+            true
+        });
+        found
+    }
+
+    /// The first element that `select` would find.
+    pub fn select_first<N>(&self, navigator: &N, node: &N::Handle) -> Option<N::Handle>
+    where
+        N: TreeNavigator,
+    {
+        let mut found = None;
+        matching::select(navigator, &self.selectors, node, &mut |element| {
+            found = Some(element);
+            false
+        });
+        found
+    }
+}
@@ -0,0 +1,772 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Recursive descent parser for selector lists.
+
+use super::Error;
+use crate::{LocalName, Namespace, Prefix};
+use mac::{_tt_as_expr_hack, matches};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// A complex selector, such as `div > p.note`.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Complex {
+    /// The compound selectors, from left to right.
+    pub(super) compounds: Vec<Vec<Simple>>,
+    /// `combinators[i]` joins `compounds[i]` and `compounds[i + 1]`.
+    pub(super) combinators: Vec<Combinator>,
+    /// Does the selector end with a pseudo-element? It then matches no elements.
+    pub(super) pseudo_element: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(super) enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum NamespaceConstraint {
+    Any,
+    /// A namespace, or `ns!()` for names in no namespace.
+    Namespace(Namespace),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Simple {
+    /// A type selector. `lower` is `local` in ASCII lowercase, for matching HTML elements.
+    Type {
+        ns: NamespaceConstraint,
+        local: LocalName,
+        lower: LocalName,
+    },
+    /// `ns|*`. A plain `*` is left out, as it matches everything.
+    Universal(NamespaceConstraint),
+    Id(String),
+    Class(String),
+    Attribute {
+        ns: NamespaceConstraint,
+        local: LocalName,
+        lower: LocalName,
+        test: Option<AttributeTest>,
+    },
+    Root,
+    Empty,
+    Scope,
+    /// The element that a relative selector in `:has()` is relative to.
+    Relative,
+    Link,
+    Checked,
+    Enabled,
+    Disabled,
+    /// A dynamic pseudo-class, such as `:hover`, which never matches a static tree.
+    Never,
+    Lang(String),
+    Nth {
+        a: i64,
+        b: i64,
+        of_type: bool,
+        from_end: bool,
+        /// The selectors that siblings must match to be counted, from `:nth-child(An+B of S)`.
+        of: Vec<Complex>,
+    },
+    Not(Vec<Complex>),
+    Is(Vec<Complex>),
+    Has(Vec<Complex>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct AttributeTest {
+    pub(super) op: AttributeOp,
+    pub(super) value: String,
+    /// `Some(true)` for the `i` flag, `Some(false)` for `s`, or `None` to follow the HTML rules.
+    pub(super) case_insensitive: Option<bool>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(super) enum AttributeOp {
+    /// `=`
+    Equal,
+    /// `~=`
+    Includes,
+    /// `|=`
+    DashMatch,
+    /// `^=`
+    Prefix,
+    /// `$=`
+    Suffix,
+    /// `*=`
+    Substring,
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0C')
+}
+
+fn is_name_start_char(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn syntax_error(position: usize, message: impl Into<Cow<'static, str>>) -> Error {
+    Error {
+        position,
+        message: message.into(),
+    }
+}
+
+/// A namespace constraint, `None` if there was no prefix, and a local name, `None` for `*`.
+type QualifiedName = (Option<NamespaceConstraint>, Option<String>);
+
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+    namespaces: &'a HashMap<Prefix, Namespace>,
+}
+
+pub(super) fn parse(
+    source: &str,
+    namespaces: &HashMap<Prefix, Namespace>,
+) -> Result<Vec<Complex>, Error> {
+    let mut parser = Parser {
+        source,
+        position: 0,
+        namespaces,
+    };
+    let selectors = parser.selector_list(false)?;
+    if parser.peek().is_some() {
+        return Err(parser.error("Unexpected character"));
+    }
+    Ok(selectors)
+}
+
+/// The first of the prefix and local name of a qualified name.
+enum NamePart {
+    Star,
+    Name(String),
+    /// Nothing before the `|` of `|name`.
+    Empty,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.source[self.position..].chars().nth(offset)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: impl Into<Cow<'static, str>>) -> Error {
+        syntax_error(self.position, message)
+    }
+
+    fn expect(&mut self, c: char, message: &'static str) -> Result<(), Error> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    /// Skips whitespace and comments, and returns whether there were any.
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.position;
+        loop {
+            let rest = &self.source[self.position..];
+            if rest.starts_with(is_whitespace) {
+                self.bump();
+            } else if rest.starts_with("/*") {
+                self.position += 2;
+                let comment = &self.source[self.position..];
+                self.position += comment.find("*/").map_or(comment.len(), |end| end + 2);
+            } else {
+                return self.position != start;
+            }
+        }
+    }
+
+    fn starts_escape(&self, offset: usize) -> bool {
+        self.peek_at(offset) == Some('\\') && !matches!(self.peek_at(offset + 1), None | Some('\n'))
+    }
+
+    fn starts_ident(&self) -> bool {
+        match self.peek() {
+            Some('-') => {
+                matches!(self.peek_at(1), Some(c) if is_name_start_char(c) || c == '-') ||
+                    self.starts_escape(1)
+            },
+            Some(c) if is_name_start_char(c) => true,
+            _ => self.starts_escape(0),
+        }
+    }
+
+    // Consumes the escape sequence after a backslash.
+    fn escape(&mut self) -> char {
+        let hex_len = self.source[self.position..]
+            .chars()
+            .take(6)
+            .take_while(|c| c.is_ascii_hexdigit())
+            .count();
+        if hex_len == 0 {
+            return self.bump().unwrap_or('\u{FFFD}');
+        }
+        let hex = &self.source[self.position..self.position + hex_len];
+        self.position += hex_len;
+        if matches!(self.peek(), Some(c) if is_whitespace(c)) {
+            self.bump();
+        }
+        match u32::from_str_radix(hex, 16).ok().and_then(std::char::from_u32) {
+            Some('\0') | None => '\u{FFFD}',
+            Some(c) => c,
+        }
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        if !self.starts_ident() {
+            return None;
+        }
+        let mut ident = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') if self.starts_escape(0) => {
+                    self.bump();
+                    ident.push(self.escape());
+                },
+                Some(c) if is_name_char(c) => {
+                    self.bump();
+                    ident.push(c);
+                },
+                _ => return Some(ident),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<Option<String>, Error> {
+        let quote = match self.peek() {
+            Some(c @ '"') | Some(c @ '\'') => c,
+            _ => return Ok(None),
+        };
+        let start = self.position;
+        self.bump();
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => return Ok(Some(string)),
+                Some('\\') => match self.peek() {
+                    Some('\n') => {
+                        self.bump();
+                    },
+                    Some(_) => string.push(self.escape()),
+                    None => (),
+                },
+                Some('\n') | None => return Err(syntax_error(start, "Unterminated string")),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn selector_list(&mut self, relative: bool) -> Result<Vec<Complex>, Error> {
+        let mut selectors = vec![];
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.complex(relative)?);
+            if !self.eat(',') {
+                return Ok(selectors);
+            }
+        }
+    }
+
+    fn combinator(&mut self) -> Option<Combinator> {
+        let combinator = match self.peek()? {
+            '>' => Combinator::Child,
+            '+' => Combinator::NextSibling,
+            '~' => Combinator::SubsequentSibling,
+            _ => return None,
+        };
+        self.bump();
+        Some(combinator)
+    }
+
+    fn complex(&mut self, relative: bool) -> Result<Complex, Error> {
+        let mut complex = Complex {
+            compounds: vec![],
+            combinators: vec![],
+            pseudo_element: false,
+        };
+        if relative {
+            complex.compounds.push(vec![Simple::Relative]);
+            complex
+                .combinators
+                .push(self.combinator().unwrap_or(Combinator::Descendant));
+            self.skip_whitespace();
+        }
+        loop {
+            let start = self.position;
+            let compound = self.compound(&mut complex.pseudo_element)?;
+            if compound.is_empty() && self.position == start {
+                return Err(self.error("Expected a selector"));
+            }
+            complex.compounds.push(compound);
+            let whitespace = self.skip_whitespace();
+            if complex.pseudo_element {
+                return Ok(complex);
+            }
+            if let Some(combinator) = self.combinator() {
+                self.skip_whitespace();
+                complex.combinators.push(combinator);
+            } else if whitespace && !matches!(self.peek(), None | Some(',') | Some(')')) {
+                complex.combinators.push(Combinator::Descendant);
+            } else {
+                return Ok(complex);
+            }
+        }
+    }
+
+    fn name_part(&mut self) -> Option<NamePart> {
+        if self.eat('*') {
+            Some(NamePart::Star)
+        } else if let Some(name) = self.ident() {
+            Some(NamePart::Name(name))
+        } else if self.peek() == Some('|') {
+            Some(NamePart::Empty)
+        } else {
+            None
+        }
+    }
+
+    /// Parses `prefix|name`, `*|name`, `|name` or `name`, where `name` may be `*` unless this
+    /// is an attribute name. `None` as the constraint means there was no prefix.
+    fn qualified_name(&mut self, attribute: bool) -> Result<Option<QualifiedName>, Error> {
+        let start = self.position;
+        let first = match self.name_part() {
+            Some(first) => first,
+            None => return Ok(None),
+        };
+        let has_prefix =
+            self.peek() == Some('|') && self.peek_at(1) != Some('=') && self.peek_at(1).is_some();
+        let (ns, local) = if has_prefix {
+            self.bump();
+            let ns = match first {
+                NamePart::Star => NamespaceConstraint::Any,
+                NamePart::Empty => NamespaceConstraint::Namespace(ns!()),
+                NamePart::Name(prefix) => match self.namespaces.get(&Prefix::from(&*prefix)) {
+                    Some(ns) => NamespaceConstraint::Namespace(ns.clone()),
+                    None => {
+                        let message = format!("Unknown namespace prefix {}", prefix);
+                        return Err(syntax_error(start, message));
+                    },
+                },
+            };
+            let local = match self.name_part() {
+                Some(NamePart::Star) => None,
+                Some(NamePart::Name(local)) => Some(local),
+                _ => return Err(self.error("Expected a name")),
+            };
+            (Some(ns), local)
+        } else {
+            match first {
+                NamePart::Star => (None, None),
+                NamePart::Name(local) => (None, Some(local)),
+                NamePart::Empty => return Err(self.error("Expected a name")),
+            }
+        };
+        if attribute && local.is_none() {
+            return Err(syntax_error(start, "Expected an attribute name"));
+        }
+        Ok(Some((ns, local)))
+    }
+
+    fn compound(&mut self, pseudo_element: &mut bool) -> Result<Vec<Simple>, Error> {
+        let mut compound = vec![];
+        match self.qualified_name(false)? {
+            Some((ns, Some(local))) => compound.push(Simple::Type {
+                ns: ns.unwrap_or(NamespaceConstraint::Any),
+                lower: LocalName::from(local.to_ascii_lowercase()),
+                local: LocalName::from(local),
+            }),
+            Some((Some(NamespaceConstraint::Namespace(ns)), None)) => {
+                compound.push(Simple::Universal(NamespaceConstraint::Namespace(ns)))
+            },
+            Some(_) | None => (),
+        }
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.bump();
+                    match self.ident() {
+                        Some(id) => compound.push(Simple::Id(id)),
+                        None => return Err(self.error("Expected an ID")),
+                    }
+                },
+                Some('.') => {
+                    self.bump();
+                    match self.ident() {
+                        Some(class) => compound.push(Simple::Class(class)),
+                        None => return Err(self.error("Expected a class name")),
+                    }
+                },
+                Some('[') => {
+                    self.bump();
+                    compound.push(self.attribute()?);
+                },
+                Some(':') if self.peek_at(1) == Some(':') => {
+                    self.position += 2;
+                    self.pseudo_element()?;
+                    *pseudo_element = true;
+                    return Ok(compound);
+                },
+                Some(':') => {
+                    self.bump();
+                    match self.pseudo_class()? {
+                        Some(simple) => compound.push(simple),
+                        None => {
+                            *pseudo_element = true;
+                            return Ok(compound);
+                        },
+                    }
+                },
+                _ => return Ok(compound),
+            }
+        }
+    }
+
+    fn attribute(&mut self) -> Result<Simple, Error> {
+        self.skip_whitespace();
+        let (ns, local) = match self.qualified_name(true)? {
+            Some((ns, Some(local))) => (ns, local),
+            _ => return Err(self.error("Expected an attribute name")),
+        };
+        let ns = ns.unwrap_or(NamespaceConstraint::Namespace(ns!()));
+        self.skip_whitespace();
+        let test = if self.eat(']') {
+            None
+        } else {
+            let op = match (self.peek(), self.peek_at(1)) {
+                (Some('='), _) => AttributeOp::Equal,
+                (Some('~'), Some('=')) => AttributeOp::Includes,
+                (Some('|'), Some('=')) => AttributeOp::DashMatch,
+                (Some('^'), Some('=')) => AttributeOp::Prefix,
+                (Some('$'), Some('=')) => AttributeOp::Suffix,
+                (Some('*'), Some('=')) => AttributeOp::Substring,
+                _ => return Err(self.error("Expected an attribute selector operator")),
+            };
+            self.position += if op == AttributeOp::Equal { 1 } else { 2 };
+            self.skip_whitespace();
+            let value = match self.string()? {
+                Some(value) => value,
+                None => match self.ident() {
+                    Some(value) => value,
+                    None => return Err(self.error("Expected an attribute value")),
+                },
+            };
+            self.skip_whitespace();
+            let flag_start = self.position;
+            let case_insensitive = match self.ident() {
+                Some(ref flag) if flag.eq_ignore_ascii_case("i") => Some(true),
+                Some(ref flag) if flag.eq_ignore_ascii_case("s") => Some(false),
+                Some(_) => return Err(syntax_error(flag_start, "Unknown attribute selector flag")),
+                None => None,
+            };
+            self.skip_whitespace();
+            self.expect(']', "Expected ]")?;
+            Some(AttributeTest {
+                op,
+                value,
+                case_insensitive,
+            })
+        };
+        Ok(Simple::Attribute {
+            ns,
+            lower: LocalName::from(local.to_ascii_lowercase()),
+            local: LocalName::from(local),
+            test,
+        })
+    }
+
+    fn pseudo_element(&mut self) -> Result<(), Error> {
+        let start = self.position;
+        let name = self.ident().map(|name| name.to_ascii_lowercase());
+        match name.as_deref() {
+            Some("before") | Some("after") | Some("first-line") | Some("first-letter") |
+            Some("marker") | Some("placeholder") | Some("selection") | Some("backdrop") => Ok(()),
+            Some(name) => Err(syntax_error(
+                start,
+                format!("Unknown pseudo-element ::{}", name),
+            )),
+            None => Err(self.error("Expected a pseudo-element")),
+        }
+    }
+
+    /// Parses a pseudo-class, or returns `None` for a pseudo-element with the legacy
+    /// single-colon syntax.
+    fn pseudo_class(&mut self) -> Result<Option<Simple>, Error> {
+        let start = self.position;
+        let name = match self.ident() {
+            Some(name) => name.to_ascii_lowercase(),
+            None => return Err(self.error("Expected a pseudo-class")),
+        };
+        if !self.eat('(') {
+            let nth = |a, b, of_type, from_end| Simple::Nth {
+                a,
+                b,
+                of_type,
+                from_end,
+                of: vec![],
+            };
+            return Ok(Some(match &*name {
+                "root" => Simple::Root,
+                "empty" => Simple::Empty,
+                "scope" => Simple::Scope,
+                "first-child" => nth(0, 1, false, false),
+                "last-child" => nth(0, 1, false, true),
+                "only-child" => Simple::Is(vec![Complex {
+                    compounds: vec![vec![nth(0, 1, false, false), nth(0, 1, false, true)]],
+                    combinators: vec![],
+                    pseudo_element: false,
+                }]),
+                "first-of-type" => nth(0, 1, true, false),
+                "last-of-type" => nth(0, 1, true, true),
+                "only-of-type" => Simple::Is(vec![Complex {
+                    compounds: vec![vec![nth(0, 1, true, false), nth(0, 1, true, true)]],
+                    combinators: vec![],
+                    pseudo_element: false,
+                }]),
+                "link" | "any-link" => Simple::Link,
+                "checked" => Simple::Checked,
+                "enabled" => Simple::Enabled,
+                "disabled" => Simple::Disabled,
+                "visited" | "hover" | "active" | "focus" | "focus-within" | "focus-visible" |
+                "target" | "target-within" => Simple::Never,
+                "before" | "after" | "first-line" | "first-letter" => return Ok(None),
+                _ => {
+                    let message = format!("Unknown pseudo-class :{}", name);
+                    return Err(syntax_error(start, message));
+                },
+            }));
+        }
+
+        self.skip_whitespace();
+        let simple = match &*name {
+            "not" => Simple::Not(self.nested_list(false)?),
+            "is" | "where" => Simple::Is(self.nested_list(false)?),
+            "has" => Simple::Has(self.nested_list(true)?),
+            "nth-child" | "nth-last-child" | "nth-of-type" | "nth-last-of-type" => {
+                let (a, b) = self.an_plus_b()?;
+                let of_type = name.ends_with("of-type");
+                let whitespace = self.skip_whitespace();
+                let mut of = vec![];
+                if !of_type && whitespace && self.starts_ident() {
+                    let keyword_start = self.position;
+                    match self.ident() {
+                        Some(ref keyword) if keyword.eq_ignore_ascii_case("of") => {
+                            of = self.nested_list(false)?;
+                        },
+                        _ => return Err(syntax_error(keyword_start, "Expected )")),
+                    }
+                }
+                Simple::Nth {
+                    a,
+                    b,
+                    of_type,
+                    from_end: name.starts_with("nth-last"),
+                    of,
+                }
+            },
+            "lang" => {
+                let lang = match self.string()? {
+                    Some(lang) => lang,
+                    None => match self.ident() {
+                        Some(lang) => lang,
+                        None => return Err(self.error("Expected a language")),
+                    },
+                };
+                self.skip_whitespace();
+                Simple::Lang(lang)
+            },
+            _ => {
+                let message = format!("Unknown pseudo-class :{}()", name);
+                return Err(syntax_error(start, message));
+            },
+        };
+        self.expect(')', "Expected )")?;
+        Ok(Some(simple))
+    }
+
+    // The selector list argument of a pseudo-class, which can't contain pseudo-elements.
+    fn nested_list(&mut self, relative: bool) -> Result<Vec<Complex>, Error> {
+        let start = self.position;
+        let selectors = self.selector_list(relative)?;
+        if selectors.iter().any(|complex| complex.pseudo_element) {
+            return Err(syntax_error(start, "Pseudo-elements are not allowed here"));
+        }
+        Ok(selectors)
+    }
+
+    fn integer(&mut self) -> Option<i64> {
+        let digits = self.source[self.position..]
+            .bytes()
+            .take_while(u8::is_ascii_digit)
+            .count();
+        if digits == 0 {
+            return None;
+        }
+        let number = self.source[self.position..self.position + digits]
+            .bytes()
+            .fold(0i64, |n, digit| n.saturating_mul(10).saturating_add(i64::from(digit - b'0')));
+        self.position += digits;
+        Some(number)
+    }
+
+    fn an_plus_b(&mut self) -> Result<(i64, i64), Error> {
+        let start = self.position;
+        let error = || syntax_error(start, "Expected An+B");
+        if self.starts_ident() && !matches!(self.peek(), Some('n') | Some('N') | Some('-')) {
+            return match self.ident() {
+                Some(ref keyword) if keyword.eq_ignore_ascii_case("odd") => Ok((2, 1)),
+                Some(ref keyword) if keyword.eq_ignore_ascii_case("even") => Ok((2, 0)),
+                _ => Err(error()),
+            };
+        }
+        let sign = if self.eat('-') {
+            -1
+        } else {
+            self.eat('+');
+            1
+        };
+        let number = self.integer();
+        if !self.eat('n') && !self.eat('N') {
+            return number.map(|b| (0, sign * b)).ok_or_else(error);
+        }
+        let a = sign * number.unwrap_or(1);
+        let after_n = self.position;
+        self.skip_whitespace();
+        let sign = if self.eat('-') {
+            -1
+        } else if self.eat('+') {
+            1
+        } else {
+            self.position = after_n;
+            return Ok((a, 0));
+        };
+        self.skip_whitespace();
+        match self.integer() {
+            Some(b) => Ok((a, sign * b)),
+            None => Err(error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Vec<Complex>, Error> {
+        super::parse(source, &HashMap::new())
+    }
+
+    fn nth(source: &str) -> (i64, i64) {
+        match parse(&format!(":nth-child({})", source)).unwrap()[0].compounds[0][0] {
+            Simple::Nth { a, b, .. } => (a, b),
+            ref other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_plus_b() {
+        assert_eq!(nth("odd"), (2, 1));
+        assert_eq!(nth(" EVEN "), (2, 0));
+        assert_eq!(nth("7"), (0, 7));
+        assert_eq!(nth("-3"), (0, -3));
+        assert_eq!(nth("n"), (1, 0));
+        assert_eq!(nth("-n+3"), (-1, 3));
+        assert_eq!(nth("+2n - 1"), (2, -1));
+        assert_eq!(nth("10N+ 5"), (10, 5));
+        assert!(parse(":nth-child(n+)").is_err());
+        assert!(parse(":nth-child(odd of)").is_err());
+        assert!(parse(":nth-of-type(2n of p)").is_err());
+    }
+
+    #[test]
+    fn combinators() {
+        let complex = &parse("a b>c + d~e").unwrap()[0];
+        assert_eq!(
+            complex.combinators,
+            [
+                Combinator::Descendant,
+                Combinator::Child,
+                Combinator::NextSibling,
+                Combinator::SubsequentSibling,
+            ]
+        );
+        assert_eq!(complex.compounds.len(), 5);
+        let list = parse(" a /* comment */ , b ").unwrap();
+        assert_eq!(list.len(), 2);
+        assert!(list.iter().all(|complex| complex.combinators.is_empty()));
+    }
+
+    #[test]
+    fn escapes() {
+        let complex = &parse(r#"#\31 23.a\.b[data-x="\"\41 "]"#).unwrap()[0];
+        assert_eq!(
+            complex.compounds[0],
+            [
+                Simple::Id("123".to_owned()),
+                Simple::Class("a.b".to_owned()),
+                Simple::Attribute {
+                    ns: NamespaceConstraint::Namespace(ns!()),
+                    local: LocalName::from("data-x"),
+                    lower: LocalName::from("data-x"),
+                    test: Some(AttributeTest {
+                        op: AttributeOp::Equal,
+                        value: "\"A".to_owned(),
+                        case_insensitive: None,
+                    }),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let position = |source| parse(source).unwrap_err().position;
+        assert_eq!(position(""), 0);
+        assert_eq!(position("a,"), 2);
+        assert_eq!(position("a >"), 3);
+        assert_eq!(position("a[href"), 6);
+        assert_eq!(position("a[href=]"), 7);
+        assert_eq!(position("a[href='x]"), 7);
+        assert_eq!(position("a:hovr"), 2);
+        assert_eq!(position("p:not(::before)"), 6);
+        assert_eq!(position("p::before span"), 10);
+        assert_eq!(position("svg|rect"), 0);
+        assert_eq!(position("a)"), 1);
+    }
+}
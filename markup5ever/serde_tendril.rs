@@ -0,0 +1,54 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Serde support for `StrTendril`, which is serialized as a string.
+//!
+//! `StrTendril` belongs to the `tendril` crate, so it can't implement `Serialize` and
+//! `Deserialize` here. Use this module with `#[serde(with = "markup5ever::serde_tendril")]` on a
+//! `StrTendril` field, or `serde_tendril::option` on an `Option<StrTendril>` field.
+//!
+//! `LocalName`, `Namespace` and `Prefix` are serialized as strings by `string_cache`, which the
+//! `serde_support` feature turns on.
+
+use serde::{Deserialize, Deserializer, Serializer};
+use tendril::StrTendril;
+
+/// Serialize a `StrTendril` as a string.
+pub fn serialize<S: Serializer>(tendril: &StrTendril, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(tendril)
+}
+
+/// Deserialize a `StrTendril` from a string.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<StrTendril, D::Error> {
+    String::deserialize(deserializer).map(StrTendril::from)
+}
+
+/// Serde support for `Option<StrTendril>`.
+pub mod option {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use tendril::StrTendril;
+
+    /// Serialize an `Option<StrTendril>` as an optional string.
+    pub fn serialize<S: Serializer>(
+        tendril: &Option<StrTendril>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match *tendril {
+            Some(ref tendril) => serializer.serialize_some(&**tendril),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// Deserialize an `Option<StrTendril>` from an optional string.
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<StrTendril>, D::Error> {
+        Option::<String>::deserialize(deserializer).map(|string| string.map(StrTendril::from))
+    }
+}
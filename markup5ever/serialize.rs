@@ -13,8 +13,11 @@
 //!
 //! [processing instructions]: https://en.wikipedia.org/wiki/Processing_Instruction
 
-use crate::QualName;
-use std::io;
+use crate::data::ENTITY_NAMES;
+use crate::{LocalName, QualName};
+use encoding_rs::{CoderResult, Encoder, EncoderResult, Encoding};
+use std::io::{self, Write};
+use std::{mem, str};
 
 //§ serializing-html-fragments
 /// Used as a parameter to `serialize`, telling it if we want to skip the parent.
@@ -63,6 +66,109 @@ pub trait Serializer {
     /// Serialize a processing instruction node, for example
     /// `<?xml-stylesheet type="text/xsl" href="style.xsl"?>`.
     fn write_processing_instruction(&mut self, target: &str, data: &str) -> io::Result<()>;
+
+    /// Serialize a CDATA section, for example `<![CDATA[a < b]]>`.
+    ///
+    /// The default implementation writes its contents through `write_text`.
+    fn write_cdata(&mut self, text: &str) -> io::Result<()> {
+        self.write_text(text)
+    }
+
+    /// Serialize the start of a shadow root attached to the element that was just started, as a
+    /// [declarative shadow root] `<template shadowrootmode="open">`. The shadow root's children
+    /// follow, then a matching call to `end_shadow_root`, and only then the host's own children.
+    ///
+    /// The default implementation writes a `template` element in the HTML namespace through
+    /// `start_elem`.
+    ///
+    /// [declarative shadow root]: https://html.spec.whatwg.org/multipage/#attr-template-shadowrootmode
+    fn start_shadow_root(&mut self, init: ShadowRootInit) -> io::Result<()> {
+        let attrs = init.template_attrs();
+        self.start_elem(
+            template_name(),
+            attrs.iter().map(|&(ref name, value)| (name, value)),
+        )
+    }
+
+    /// Serialize the end of a shadow root started with `start_shadow_root`.
+    fn end_shadow_root(&mut self) -> io::Result<()> {
+        self.end_elem(template_name())
+    }
+}
+
+/// The [mode] of a shadow root.
+///
+/// [mode]: https://dom.spec.whatwg.org/#shadowroot-mode
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ShadowRootMode {
+    /// `shadowrootmode="open"`
+    Open,
+    /// `shadowrootmode="closed"`
+    Closed,
+}
+
+/// The properties of a shadow root that survive serialization, as in the DOM's
+/// [`ShadowRootInit`] dictionary.
+///
+/// [`ShadowRootInit`]: https://dom.spec.whatwg.org/#dictdef-shadowrootinit
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ShadowRootInit {
+    /// Whether the shadow root is open or closed.
+    pub mode: ShadowRootMode,
+    /// The shadow root's [delegates focus] flag.
+    ///
+    /// [delegates focus]: https://dom.spec.whatwg.org/#shadowroot-delegates-focus
+    pub delegates_focus: bool,
+    /// The shadow root's [clonable] flag.
+    ///
+    /// [clonable]: https://dom.spec.whatwg.org/#shadowroot-clonable
+    pub clonable: bool,
+    /// The shadow root's [serializable] flag, consulted by `getHTML()` when
+    /// `serializableShadowRoots` is set.
+    ///
+    /// [serializable]: https://dom.spec.whatwg.org/#shadowroot-serializable
+    pub serializable: bool,
+}
+
+impl ShadowRootInit {
+    /// A shadow root in the given mode, with every flag unset.
+    pub fn new(mode: ShadowRootMode) -> ShadowRootInit {
+        ShadowRootInit {
+            mode,
+            delegates_focus: false,
+            clonable: false,
+            serializable: false,
+        }
+    }
+
+    /// The attributes of the `<template>` that declares this shadow root, in the order the
+    /// [HTML fragment serialization algorithm] writes them.
+    ///
+    /// [HTML fragment serialization algorithm]: https://html.spec.whatwg.org/multipage/#serialising-html-fragments
+    pub fn template_attrs(&self) -> Vec<(QualName, &'static str)> {
+        let attr = |name: &str| QualName::new(None, ns!(), LocalName::from(name));
+        let mut attrs = vec![(
+            attr("shadowrootmode"),
+            match self.mode {
+                ShadowRootMode::Open => "open",
+                ShadowRootMode::Closed => "closed",
+            },
+        )];
+        if self.delegates_focus {
+            attrs.push((attr("shadowrootdelegatesfocus"), ""));
+        }
+        if self.serializable {
+            attrs.push((attr("shadowrootserializable"), ""));
+        }
+        if self.clonable {
+            attrs.push((attr("shadowrootclonable"), ""));
+        }
+        attrs
+    }
+}
+
+fn template_name() -> QualName {
+    QualName::new(None, ns!(html), local_name!("template"))
 }
 
 /// A type alias for an attribute name and value (e.g. the `class="test"` in `<div class="test">`
@@ -73,3 +179,298 @@ pub trait Serializer {
 ///
 /// [`Serializer::start_elem`]: trait.Serializer.html#tymethod.start_elem
 pub type AttrRef<'a> = (&'a QualName, &'a str);
+
+/// How characters outside ASCII are written when `EscapePolicy::ascii_only` is set.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum CharRefStyle {
+    /// Decimal references, for example `&#233;`.
+    Decimal,
+    /// Hexadecimal references, for example `&#xE9;`.
+    Hex,
+    /// Named references from `data::ENTITY_NAMES`, for example `&eacute;`, falling back to
+    /// decimal references for characters without a name. XML only predefines `amp`, `lt`, `gt`,
+    /// `quot` and `apos`, so this is only useful for HTML.
+    Named,
+}
+
+/// How `'` is written in attribute values.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SingleQuote {
+    /// As is. Attribute values are always quoted with `"`, so this is safe.
+    Literal,
+    /// As `&apos;`.
+    Apos,
+    /// As `&#39;`, which unlike `&apos;` is understood by HTML 4 user agents.
+    Numeric,
+}
+
+/// The characters a serializer replaces with character references in text and attribute
+/// values.
+///
+/// `&` is always escaped, as are `<` and `>` in text and `"` in attribute values. Everything
+/// else is controlled by the fields below. Content that can't contain character references, such
+/// as comments or the text of a `<script>` element, is written as is.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct EscapePolicy {
+    /// Write every character outside ASCII as a character reference.
+    pub ascii_only: bool,
+    /// The kind of reference used for characters outside ASCII when `ascii_only` is set.
+    pub char_refs: CharRefStyle,
+    /// How `'` is written in attribute values.
+    pub single_quote: SingleQuote,
+    /// Write U+00A0 NO-BREAK SPACE as `&nbsp;`.
+    pub nbsp: bool,
+}
+
+impl EscapePolicy {
+    /// The escaping described by the [HTML fragment serialization algorithm].
+    ///
+    /// [HTML fragment serialization algorithm]: https://html.spec.whatwg.org/multipage/#escapingString
+    pub fn html() -> EscapePolicy {
+        EscapePolicy {
+            ascii_only: false,
+            char_refs: CharRefStyle::Decimal,
+            single_quote: SingleQuote::Literal,
+            nbsp: true,
+        }
+    }
+
+    /// The escaping used by xml5ever's `XmlSerializer`, which only uses the references XML
+    /// predefines.
+    pub fn xml() -> EscapePolicy {
+        EscapePolicy {
+            ascii_only: false,
+            char_refs: CharRefStyle::Decimal,
+            single_quote: SingleQuote::Apos,
+            nbsp: false,
+        }
+    }
+
+    /// Write `text` to `writer`, escaped for an attribute value if `attr_mode` is set and for a
+    /// text node otherwise.
+    pub fn write_escaped<W: Write>(
+        &self,
+        writer: &mut W,
+        text: &str,
+        attr_mode: bool,
+    ) -> io::Result<()> {
+        let mut written = 0;
+        for (i, c) in text.char_indices() {
+            let replacement = match c {
+                '&' => Some("&amp;"),
+                '\u{00A0}' if self.nbsp => Some("&nbsp;"),
+                '"' if attr_mode => Some("&quot;"),
+                '\'' if attr_mode && self.single_quote == SingleQuote::Apos => Some("&apos;"),
+                '\'' if attr_mode && self.single_quote == SingleQuote::Numeric => Some("&#39;"),
+                '<' if !attr_mode => Some("&lt;"),
+                '>' if !attr_mode => Some("&gt;"),
+                c if self.ascii_only && !c.is_ascii() => None,
+                _ => continue,
+            };
+            writer.write_all(&text.as_bytes()[written..i])?;
+            match replacement {
+                Some(replacement) => writer.write_all(replacement.as_bytes())?,
+                None => self.write_char_ref(writer, c)?,
+            }
+            written = i + c.len_utf8();
+        }
+        writer.write_all(&text.as_bytes()[written..])
+    }
+
+    fn write_char_ref<W: Write>(&self, writer: &mut W, c: char) -> io::Result<()> {
+        match self.char_refs {
+            CharRefStyle::Named => match ENTITY_NAMES.get(&(c as u32)) {
+                Some(name) => write!(writer, "&{}", name),
+                None => write!(writer, "&#{};", c as u32),
+            },
+            CharRefStyle::Decimal => write!(writer, "&#{};", c as u32),
+            CharRefStyle::Hex => write!(writer, "&#x{:X};", c as u32),
+        }
+    }
+}
+
+/// A writer that encodes the UTF-8 written to it into another encoding, writing characters the
+/// encoding can't represent as decimal character references, like the [encode] step of form
+/// submission. References are only meaningful in text and attribute values, so serializers use
+/// `check_encodable` for everything else.
+///
+/// Stateful encodings such as ISO-2022-JP may need to write a final escape sequence, so
+/// `finish` must be called once everything has been written.
+///
+/// [encode]: https://encoding.spec.whatwg.org/#encode
+pub struct EncodingWriter<W: Write> {
+    writer: W,
+    encoder: Encoder,
+    /// The start of a character whose remaining bytes haven't been written yet.
+    pending: Vec<u8>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    /// Create a writer encoding to the [output encoding] of `encoding`: UTF-16 and the
+    /// replacement encoding are written as UTF-8.
+    ///
+    /// [output encoding]: https://encoding.spec.whatwg.org/#get-an-output-encoding
+    pub fn new(writer: W, encoding: &'static Encoding) -> EncodingWriter<W> {
+        EncodingWriter {
+            writer,
+            encoder: encoding.output_encoding().new_encoder(),
+            pending: vec![],
+            buffer: vec![],
+        }
+    }
+
+    /// The encoding being written.
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoder.encoding()
+    }
+
+    /// Finish encoding and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            return Err(invalid_utf8());
+        }
+        self.encode("", true)?;
+        Ok(self.writer)
+    }
+
+    fn encode(&mut self, mut text: &str, last: bool) -> io::Result<()> {
+        loop {
+            // Leave room for at least one character reference.
+            let capacity = self
+                .encoder
+                .max_buffer_length_from_utf8_if_no_unmappables(text.len())
+                .unwrap_or(text.len() * 4) +
+                16;
+            self.buffer.clear();
+            self.buffer.reserve(capacity);
+            let (result, read, _) =
+                self.encoder
+                    .encode_from_utf8_to_vec(text, &mut self.buffer, last);
+            self.writer.write_all(&self.buffer)?;
+            text = &text[read..];
+            match result {
+                CoderResult::InputEmpty => return Ok(()),
+                CoderResult::OutputFull => (),
+            }
+        }
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "input is not UTF-8")
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut input = mem::take(&mut self.pending);
+        input.extend_from_slice(buf);
+        let valid = match str::from_utf8(&input) {
+            Ok(text) => text.len(),
+            // The rest of a character split across writes is still to come.
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => {
+                input.truncate(input.len() - buf.len());
+                self.pending = input;
+                return Err(invalid_utf8());
+            },
+        };
+        let rest = input.split_off(valid);
+        self.encode(str::from_utf8(&input).unwrap(), false)?;
+        self.pending = rest;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Check that `encoding` can represent every character of `text`.
+///
+/// `EncodingWriter` falls back to character references, which are only decoded in text and
+/// attribute values. Serializers call this for raw text, comments and the like, where a
+/// reference would change the content, and fail with an `InvalidData` error naming `what`.
+pub fn check_encodable(encoding: &'static Encoding, text: &str, what: &str) -> io::Result<()> {
+    let encoding = encoding.output_encoding();
+    if encoding == encoding_rs::UTF_8 || text.is_ascii() {
+        return Ok(());
+    }
+    let mut encoder = encoding.new_encoder();
+    let mut buffer = Vec::with_capacity(text.len() * 4 + 16);
+    let mut text = text;
+    loop {
+        buffer.clear();
+        let (result, read) =
+            encoder.encode_from_utf8_to_vec_without_replacement(text, &mut buffer, false);
+        text = &text[read..];
+        match result {
+            EncoderResult::InputEmpty => return Ok(()),
+            EncoderResult::OutputFull => (),
+            EncoderResult::Unmappable(c) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} can't represent {:?} in {}", encoding.name(), c, what),
+                ));
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_encodable, EncodingWriter};
+    use encoding_rs::{ISO_2022_JP, UTF_16LE, UTF_8, WINDOWS_1252};
+    use std::io::Write;
+
+    fn encode(encoding: &'static encoding_rs::Encoding, chunks: &[&[u8]]) -> Vec<u8> {
+        let mut writer = EncodingWriter::new(vec![], encoding);
+        for chunk in chunks {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn unmappable_characters_become_references() {
+        assert_eq!(
+            encode(WINDOWS_1252, &["caf\u{e9} \u{20ac} \u{65e5}".as_bytes()]),
+            b"caf\xe9 \x80 &#26085;"
+        );
+    }
+
+    #[test]
+    fn characters_split_across_writes() {
+        let bytes = "\u{e9}\u{1F600}".as_bytes();
+        assert_eq!(
+            encode(WINDOWS_1252, &[&bytes[..1], &bytes[1..3], &bytes[3..]]),
+            b"\xe9&#128512;"
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error() {
+        let mut writer = EncodingWriter::new(vec![], WINDOWS_1252);
+        assert!(writer.write_all(b"a\xffb").is_err());
+        let mut writer = EncodingWriter::new(vec![], WINDOWS_1252);
+        writer.write_all(b"a\xc3").unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn encodable() {
+        assert!(check_encodable(WINDOWS_1252, "caf\u{e9} \u{20ac}", "a comment").is_ok());
+        assert!(check_encodable(UTF_16LE, "\u{65e5}", "a comment").is_ok());
+        let err = check_encodable(WINDOWS_1252, "caf\u{e9} \u{65e5}", "a comment").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "windows-1252 can't represent '\u{65e5}' in a comment");
+    }
+
+    #[test]
+    fn output_encoding() {
+        let writer = EncodingWriter::new(vec![], UTF_16LE);
+        assert_eq!(writer.encoding(), UTF_8);
+        // The final escape back to ASCII is only written by `finish`.
+        assert_eq!(encode(ISO_2022_JP, &["\u{65e5}".as_bytes()]), b"\x1b$BF|\x1b(B");
+    }
+}
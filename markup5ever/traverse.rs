@@ -0,0 +1,644 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Read-only traversal of any tree that implements `TreeNavigator`.
+//!
+//! ```text
+//! for node in dom.descendants(&dom.document) {
+//!     if let Some(name) = dom.name(&node) { ... }
+//! }
+//! ```
+//!
+//! The iterators follow the [XPath axes] of the same names: `ancestors`, `descendants`,
+//! `following` and `preceding` leave out the node they start from, and `preceding` goes
+//! backwards from it. `depth_first` and `breadth_first` include the node they start from.
+//! `TreeWalker` is the DOM's [`TreeWalker`], which moves around the nodes a filter accepts.
+//!
+//! None of them recurse or keep a list of nodes to visit, apart from `breadth_first`, so they
+//! work on trees of any depth.
+//!
+//! XPath evaluation in `xpath` and selector matching in `select` are written against the same
+//! trait.
+//!
+//! [XPath axes]: https://www.w3.org/TR/1999/REC-xpath-19991116/#axes
+//! [`TreeWalker`]: https://dom.spec.whatwg.org/#interface-treewalker
+
+use crate::{Attribute, LocalName, Namespace, QualName};
+use std::collections::VecDeque;
+use tendril::StrTendril;
+
+/// The kinds of node in a tree.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Document,
+    Doctype,
+    Element,
+    Text,
+    Comment,
+    /// Only found in trees built from XML.
+    CData,
+    ProcessingInstruction,
+}
+
+/// The contents of a doctype.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Doctype {
+    pub name: StrTendril,
+    pub public_id: StrTendril,
+    pub system_id: StrTendril,
+}
+
+/// Read-only access to a tree.
+///
+/// Like `TreeSink`, a navigator is the object that knows how to look at the tree's handles. Only
+/// the methods for moving around the tree have to be implemented efficiently: everything else
+/// is built from them.
+pub trait TreeNavigator {
+    /// `Handle` is a reference to a DOM node.
+    type Handle: Clone;
+
+    /// What kind of node this is.
+    fn kind(&self, node: &Self::Handle) -> NodeKind;
+
+    /// The parent of a node, or `None` for the root.
+    fn parent(&self, node: &Self::Handle) -> Option<Self::Handle>;
+
+    /// The first child of a node.
+    fn first_child(&self, node: &Self::Handle) -> Option<Self::Handle>;
+
+    /// The last child of a node.
+    fn last_child(&self, node: &Self::Handle) -> Option<Self::Handle>;
+
+    /// The node after this one with the same parent.
+    fn next_sibling(&self, node: &Self::Handle) -> Option<Self::Handle>;
+
+    /// The node before this one with the same parent.
+    fn previous_sibling(&self, node: &Self::Handle) -> Option<Self::Handle>;
+
+    /// The position of a node among its parent's children, or 0 for the root. XPath node-sets
+    /// are sorted into document order with this.
+    ///
+    /// The default counts the node's previous siblings. Trees that know where their nodes are
+    /// should override it, as sorting a node-set asks for the position of every node and its
+    /// ancestors.
+    fn position(&self, node: &Self::Handle) -> usize {
+        let mut position = 0;
+        let mut node = node.clone();
+        while let Some(sibling) = self.previous_sibling(&node) {
+            position += 1;
+            node = sibling;
+        }
+        position
+    }
+
+    /// The name of an element, or the target of a processing instruction as a local name.
+    fn name(&self, node: &Self::Handle) -> Option<QualName>;
+
+    /// The attributes of an element.
+    fn attributes(&self, node: &Self::Handle) -> Vec<Attribute>;
+
+    /// The contents of a text node, comment or CDATA section, or the data of a processing
+    /// instruction.
+    fn text(&self, node: &Self::Handle) -> Option<StrTendril>;
+
+    /// The name and ids of a doctype.
+    fn doctype(&self, node: &Self::Handle) -> Option<Doctype>;
+
+    /// For an HTML `<template>` element, the document fragment that holds its contents, which
+    /// aren't its children.
+    fn template_contents(&self, node: &Self::Handle) -> Option<Self::Handle>;
+
+    /// Do two handles refer to the same node?
+    fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool;
+
+    /// The value of an element's attribute. Selectors look up attributes one at a time, so this
+    /// shouldn't copy the others the way `attributes` does.
+    fn attribute(
+        &self,
+        node: &Self::Handle,
+        ns: &Namespace,
+        local: &LocalName,
+    ) -> Option<StrTendril>;
+
+    /// The children of a node, in document order.
+    fn children(&self, node: &Self::Handle) -> Children<'_, Self>
+    where
+        Self: Sized,
+    {
+        Children {
+            navigator: self,
+            next: self.first_child(node),
+        }
+    }
+
+    /// The parent of a node, its parent, and so on up to the root.
+    fn ancestors(&self, node: &Self::Handle) -> Ancestors<'_, Self>
+    where
+        Self: Sized,
+    {
+        Ancestors {
+            navigator: self,
+            next: self.parent(node),
+        }
+    }
+
+    /// The descendants of a node, in document order.
+    fn descendants(&self, node: &Self::Handle) -> Descendants<'_, Self>
+    where
+        Self: Sized,
+    {
+        Descendants {
+            navigator: self,
+            root: node.clone(),
+            next: self.first_child(node),
+        }
+    }
+
+    /// The nodes after a node in document order, other than its descendants.
+    fn following(&self, node: &Self::Handle) -> Following<'_, Self>
+    where
+        Self: Sized,
+    {
+        Following {
+            navigator: self,
+            next: next_outside(self, node, None),
+        }
+    }
+
+    /// The nodes before a node in document order, other than its ancestors, starting with the
+    /// nearest.
+    fn preceding(&self, node: &Self::Handle) -> Preceding<'_, Self>
+    where
+        Self: Sized,
+    {
+        Preceding {
+            navigator: self,
+            position: Some(node.clone()),
+            ancestor: self.parent(node),
+        }
+    }
+
+    /// A node and its descendants in document order, as the edges where each one starts and
+    /// ends. This is the order a serializer writes start and end tags in.
+    fn depth_first(&self, node: &Self::Handle) -> DepthFirst<'_, Self>
+    where
+        Self: Sized,
+    {
+        DepthFirst {
+            navigator: self,
+            root: node.clone(),
+            next: Some(Edge::Open(node.clone())),
+        }
+    }
+
+    /// A node and its descendants, each level of the tree in turn.
+    fn breadth_first(&self, node: &Self::Handle) -> BreadthFirst<'_, Self>
+    where
+        Self: Sized,
+    {
+        BreadthFirst {
+            navigator: self,
+            queue: vec![node.clone()].into(),
+        }
+    }
+}
+
+/// The node after `node` in document order, skipping its descendants, or `None` if that would
+/// leave `root`.
+fn next_outside<N: TreeNavigator>(
+    navigator: &N,
+    node: &N::Handle,
+    root: Option<&N::Handle>,
+) -> Option<N::Handle> {
+    let mut node = node.clone();
+    loop {
+        if let Some(root) = root {
+            if navigator.same_node(&node, root) {
+                return None;
+            }
+        }
+        if let Some(sibling) = navigator.next_sibling(&node) {
+            return Some(sibling);
+        }
+        node = navigator.parent(&node)?;
+    }
+}
+
+/// The last node in document order among a node and its descendants.
+fn last_descendant<N: TreeNavigator>(navigator: &N, node: N::Handle) -> N::Handle {
+    let mut node = node;
+    while let Some(child) = navigator.last_child(&node) {
+        node = child;
+    }
+    node
+}
+
+/// The iterator returned by `TreeNavigator::children`.
+pub struct Children<'a, N: TreeNavigator> {
+    navigator: &'a N,
+    next: Option<N::Handle>,
+}
+
+impl<N: TreeNavigator> Iterator for Children<'_, N> {
+    type Item = N::Handle;
+
+    fn next(&mut self) -> Option<N::Handle> {
+        let node = self.next.take()?;
+        self.next = self.navigator.next_sibling(&node);
+        Some(node)
+    }
+}
+
+/// The iterator returned by `TreeNavigator::ancestors`.
+pub struct Ancestors<'a, N: TreeNavigator> {
+    navigator: &'a N,
+    next: Option<N::Handle>,
+}
+
+impl<N: TreeNavigator> Iterator for Ancestors<'_, N> {
+    type Item = N::Handle;
+
+    fn next(&mut self) -> Option<N::Handle> {
+        let node = self.next.take()?;
+        self.next = self.navigator.parent(&node);
+        Some(node)
+    }
+}
+
+/// The iterator returned by `TreeNavigator::descendants`.
+pub struct Descendants<'a, N: TreeNavigator> {
+    navigator: &'a N,
+    root: N::Handle,
+    next: Option<N::Handle>,
+}
+
+impl<N: TreeNavigator> Iterator for Descendants<'_, N> {
+    type Item = N::Handle;
+
+    fn next(&mut self) -> Option<N::Handle> {
+        let node = self.next.take()?;
+        self.next = self
+            .navigator
+            .first_child(&node)
+            .or_else(|| next_outside(self.navigator, &node, Some(&self.root)));
+        Some(node)
+    }
+}
+
+/// The iterator returned by `TreeNavigator::following`.
+pub struct Following<'a, N: TreeNavigator> {
+    navigator: &'a N,
+    next: Option<N::Handle>,
+}
+
+impl<N: TreeNavigator> Iterator for Following<'_, N> {
+    type Item = N::Handle;
+
+    fn next(&mut self) -> Option<N::Handle> {
+        let node = self.next.take()?;
+        self.next = self
+            .navigator
+            .first_child(&node)
+            .or_else(|| next_outside(self.navigator, &node, None));
+        Some(node)
+    }
+}
+
+/// The iterator returned by `TreeNavigator::preceding`.
+pub struct Preceding<'a, N: TreeNavigator> {
+    navigator: &'a N,
+    /// The node last returned, or the node we started from.
+    position: Option<N::Handle>,
+    /// The nearest ancestor of the node we started from that hasn't been passed yet.
+    ancestor: Option<N::Handle>,
+}
+
+impl<N: TreeNavigator> Iterator for Preceding<'_, N> {
+    type Item = N::Handle;
+
+    fn next(&mut self) -> Option<N::Handle> {
+        loop {
+            let node = self.position.take()?;
+            if let Some(sibling) = self.navigator.previous_sibling(&node) {
+                let node = last_descendant(self.navigator, sibling);
+                self.position = Some(node.clone());
+                return Some(node);
+            }
+            let parent = self.navigator.parent(&node)?;
+            self.position = Some(parent.clone());
+            match self.ancestor {
+                Some(ref ancestor) if self.navigator.same_node(ancestor, &parent) => {
+                    self.ancestor = self.navigator.parent(&parent);
+                },
+                _ => return Some(parent),
+            }
+        }
+    }
+}
+
+/// Where `depth_first` is in the tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edge<Handle> {
+    /// The start of a node, before its descendants.
+    Open(Handle),
+    /// The end of a node, after its descendants.
+    Close(Handle),
+}
+
+/// The iterator returned by `TreeNavigator::depth_first`.
+pub struct DepthFirst<'a, N: TreeNavigator> {
+    navigator: &'a N,
+    root: N::Handle,
+    next: Option<Edge<N::Handle>>,
+}
+
+impl<N: TreeNavigator> Iterator for DepthFirst<'_, N> {
+    type Item = Edge<N::Handle>;
+
+    fn next(&mut self) -> Option<Edge<N::Handle>> {
+        let edge = self.next.take()?;
+        self.next = match edge {
+            Edge::Open(ref node) => Some(match self.navigator.first_child(node) {
+                Some(child) => Edge::Open(child),
+                None => Edge::Close(node.clone()),
+            }),
+            Edge::Close(ref node) if self.navigator.same_node(node, &self.root) => None,
+            Edge::Close(ref node) => match self.navigator.next_sibling(node) {
+                Some(sibling) => Some(Edge::Open(sibling)),
+                None => self.navigator.parent(node).map(Edge::Close),
+            },
+        };
+        Some(edge)
+    }
+}
+
+/// The iterator returned by `TreeNavigator::breadth_first`.
+pub struct BreadthFirst<'a, N: TreeNavigator> {
+    navigator: &'a N,
+    queue: VecDeque<N::Handle>,
+}
+
+impl<N: TreeNavigator> Iterator for BreadthFirst<'_, N> {
+    type Item = N::Handle;
+
+    fn next(&mut self) -> Option<N::Handle> {
+        let node = self.queue.pop_front()?;
+        self.queue.extend(self.navigator.children(&node));
+        Some(node)
+    }
+}
+
+/// What a `TreeWalker` filter says about a node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// The walker stops at the node.
+    Accept,
+    /// The walker doesn't stop at the node, but may stop at its descendants.
+    Skip,
+    /// The walker doesn't stop at the node or any of its descendants.
+    Reject,
+}
+
+/// Moves around the nodes under a root that a filter accepts, as if the other nodes weren't
+/// there, like the DOM's `TreeWalker`.
+///
+/// Each method moves to the node it returns. If there's no such node it returns `None` and the
+/// walker stays where it is. As an iterator, a walker returns the accepted nodes after the
+/// current one in document order, like calling `next_node` until it returns `None`.
+pub struct TreeWalker<'a, N: TreeNavigator, F> {
+    navigator: &'a N,
+    root: N::Handle,
+    current: N::Handle,
+    filter: F,
+}
+
+impl<'a, N, F> TreeWalker<'a, N, F>
+where
+    N: TreeNavigator,
+    F: FnMut(&N::Handle) -> Filter,
+{
+    /// A walker over `root` and its descendants that starts at `root`.
+    pub fn new(navigator: &'a N, root: N::Handle, filter: F) -> TreeWalker<'a, N, F> {
+        TreeWalker {
+            navigator,
+            current: root.clone(),
+            root,
+            filter,
+        }
+    }
+
+    pub fn root(&self) -> &N::Handle {
+        &self.root
+    }
+
+    /// The node the walker is at.
+    pub fn current(&self) -> &N::Handle {
+        &self.current
+    }
+
+    /// Move the walker to any node, whether or not it's under the root or accepted.
+    pub fn set_current(&mut self, node: N::Handle) {
+        self.current = node;
+    }
+
+    fn is_root(&self, node: &N::Handle) -> bool {
+        self.navigator.same_node(node, &self.root)
+    }
+
+    fn accept(&mut self, node: N::Handle) -> Option<N::Handle> {
+        self.current = node.clone();
+        Some(node)
+    }
+
+    /// Move to the nearest accepted ancestor of the current node, up to the root.
+    pub fn parent_node(&mut self) -> Option<N::Handle> {
+        let mut node = self.current.clone();
+        while !self.is_root(&node) {
+            node = self.navigator.parent(&node)?;
+            if (self.filter)(&node) == Filter::Accept {
+                return self.accept(node);
+            }
+        }
+        None
+    }
+
+    /// Move to the first accepted child of the current node, looking inside the children that
+    /// are skipped.
+    pub fn first_child(&mut self) -> Option<N::Handle> {
+        self.traverse_children(true)
+    }
+
+    /// Move to the last accepted child of the current node, looking inside the children that
+    /// are skipped.
+    pub fn last_child(&mut self) -> Option<N::Handle> {
+        self.traverse_children(false)
+    }
+
+    /// Move to the next accepted sibling of the current node, looking inside the siblings, and
+    /// the siblings of the ancestors, that are skipped.
+    pub fn next_sibling(&mut self) -> Option<N::Handle> {
+        self.traverse_siblings(true)
+    }
+
+    /// Move to the previous accepted sibling of the current node, looking inside the siblings,
+    /// and the siblings of the ancestors, that are skipped.
+    pub fn previous_sibling(&mut self) -> Option<N::Handle> {
+        self.traverse_siblings(false)
+    }
+
+    /// Move to the accepted node before the current one in document order.
+    pub fn previous_node(&mut self) -> Option<N::Handle> {
+        let mut node = self.current.clone();
+        while !self.is_root(&node) {
+            while let Some(sibling) = self.navigator.previous_sibling(&node) {
+                node = sibling;
+                let mut result = (self.filter)(&node);
+                while result != Filter::Reject {
+                    match self.navigator.last_child(&node) {
+                        Some(child) => {
+                            node = child;
+                            result = (self.filter)(&node);
+                        },
+                        None => break,
+                    }
+                }
+                if result == Filter::Accept {
+                    return self.accept(node);
+                }
+            }
+            node = self.navigator.parent(&node)?;
+            if (self.filter)(&node) == Filter::Accept {
+                return self.accept(node);
+            }
+        }
+        None
+    }
+
+    /// Move to the accepted node after the current one in document order.
+    pub fn next_node(&mut self) -> Option<N::Handle> {
+        let mut node = self.current.clone();
+        let mut result = Filter::Accept;
+        loop {
+            while result != Filter::Reject {
+                match self.navigator.first_child(&node) {
+                    Some(child) => {
+                        node = child;
+                        result = (self.filter)(&node);
+                        if result == Filter::Accept {
+                            return self.accept(node);
+                        }
+                    },
+                    None => break,
+                }
+            }
+            node = next_outside(self.navigator, &node, Some(&self.root))?;
+            result = (self.filter)(&node);
+            if result == Filter::Accept {
+                return self.accept(node);
+            }
+        }
+    }
+
+    fn traverse_children(&mut self, first: bool) -> Option<N::Handle> {
+        let navigator = self.navigator;
+        let child = |node: &N::Handle| {
+            if first {
+                navigator.first_child(node)
+            } else {
+                navigator.last_child(node)
+            }
+        };
+        let sibling = |node: &N::Handle| {
+            if first {
+                navigator.next_sibling(node)
+            } else {
+                navigator.previous_sibling(node)
+            }
+        };
+
+        let mut node = child(&self.current)?;
+        loop {
+            match (self.filter)(&node) {
+                Filter::Accept => return self.accept(node),
+                Filter::Skip => {
+                    if let Some(child) = child(&node) {
+                        node = child;
+                        continue;
+                    }
+                },
+                Filter::Reject => (),
+            }
+            loop {
+                if let Some(sibling) = sibling(&node) {
+                    node = sibling;
+                    break;
+                }
+                let parent = navigator.parent(&node)?;
+                if self.is_root(&parent) || navigator.same_node(&parent, &self.current) {
+                    return None;
+                }
+                node = parent;
+            }
+        }
+    }
+
+    fn traverse_siblings(&mut self, next: bool) -> Option<N::Handle> {
+        let navigator = self.navigator;
+        let child = |node: &N::Handle| {
+            if next {
+                navigator.first_child(node)
+            } else {
+                navigator.last_child(node)
+            }
+        };
+        let sibling = |node: &N::Handle| {
+            if next {
+                navigator.next_sibling(node)
+            } else {
+                navigator.previous_sibling(node)
+            }
+        };
+
+        let mut node = self.current.clone();
+        if self.is_root(&node) {
+            return None;
+        }
+        loop {
+            let mut candidate = sibling(&node);
+            while let Some(found) = candidate {
+                node = found;
+                let result = (self.filter)(&node);
+                if result == Filter::Accept {
+                    return self.accept(node);
+                }
+                candidate = match result {
+                    Filter::Reject => None,
+                    _ => child(&node),
+                }
+                .or_else(|| sibling(&node));
+            }
+            node = navigator.parent(&node)?;
+            if self.is_root(&node) || (self.filter)(&node) == Filter::Accept {
+                return None;
+            }
+        }
+    }
+}
+
+impl<N, F> Iterator for TreeWalker<'_, N, F>
+where
+    N: TreeNavigator,
+    F: FnMut(&N::Handle) -> Filter,
+{
+    type Item = N::Handle;
+
+    fn next(&mut self) -> Option<N::Handle> {
+        self.next_node()
+    }
+}
@@ -0,0 +1,877 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Evaluation of parsed expressions, and the core function library.
+
+use super::parser::{ArithmeticOp, Axis, CompareOp, Expr, NodeTest, PathStart, Step};
+use super::{expanded_name, Context, Error, Node, NodeKind, Value};
+use crate::traverse::{self, TreeNavigator};
+use crate::{Namespace, Prefix, QualName};
+use mac::{_tt_as_expr_hack, matches};
+use std::borrow::Cow;
+use std::{f64, usize};
+
+type Nodes<Handle> = Vec<Node<Handle>>;
+
+/// The context node, context position and context size.
+struct Focus<'a, Handle> {
+    node: &'a Node<Handle>,
+    position: usize,
+    size: usize,
+}
+
+struct Evaluator<'a, N: TreeNavigator> {
+    navigator: &'a N,
+    context: &'a Context<N::Handle>,
+}
+
+fn evaluation_error(message: String) -> Error {
+    Error::Evaluation(Cow::Owned(message))
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n')
+}
+
+pub(super) fn evaluate<N: TreeNavigator>(
+    navigator: &N,
+    context: &Context<N::Handle>,
+    expr: &Expr,
+    node: &N::Handle,
+) -> Result<Value<N::Handle>, Error> {
+    let evaluator = Evaluator { navigator, context };
+    let node = Node::Tree(node.clone());
+    evaluator.eval(
+        expr,
+        &Focus {
+            node: &node,
+            position: 1,
+            size: 1,
+        },
+    )
+}
+
+/// The kind of a tree node in the XPath data model.
+fn node_kind<N: TreeNavigator>(navigator: &N, node: &N::Handle) -> NodeKind {
+    match navigator.kind(node) {
+        // Doctypes are never children, so only show up as the context node.
+        traverse::NodeKind::Document | traverse::NodeKind::Doctype => NodeKind::Document,
+        traverse::NodeKind::Element => NodeKind::Element,
+        traverse::NodeKind::Text | traverse::NodeKind::CData => NodeKind::Text,
+        traverse::NodeKind::Comment => NodeKind::Comment,
+        traverse::NodeKind::ProcessingInstruction => NodeKind::ProcessingInstruction,
+    }
+}
+
+/// The children of a document or element, other than doctypes.
+fn child_nodes<N: TreeNavigator>(navigator: &N, node: &N::Handle) -> Vec<N::Handle> {
+    navigator
+        .children(node)
+        .filter(|child| navigator.kind(child) != traverse::NodeKind::Doctype)
+        .collect()
+}
+
+/// Appends the descendants of a node in document order.
+fn descendants<N: TreeNavigator>(navigator: &N, node: &N::Handle, out: &mut Vec<N::Handle>) {
+    let mut stack = vec![child_nodes(navigator, node).into_iter()];
+    while let Some(children) = stack.last_mut() {
+        match children.next() {
+            Some(child) => {
+                stack.push(child_nodes(navigator, &child).into_iter());
+                out.push(child);
+            },
+            None => {
+                stack.pop();
+            },
+        }
+    }
+}
+
+pub(super) fn string_value<N: TreeNavigator>(navigator: &N, node: &Node<N::Handle>) -> String {
+    let handle = match *node {
+        Node::Tree(ref handle) => handle,
+        Node::Attribute(_, ref attr) => return attr.value.to_string(),
+        Node::Namespace(_, _, ref ns) => return ns.to_string(),
+    };
+    match node_kind(navigator, handle) {
+        NodeKind::Document | NodeKind::Element => {
+            let mut nodes = vec![];
+            descendants(navigator, handle, &mut nodes);
+            let mut value = String::new();
+            for node in nodes {
+                if node_kind(navigator, &node) == NodeKind::Text {
+                    value.push_str(&navigator.text(&node).unwrap_or_default());
+                }
+            }
+            value
+        },
+        _ => navigator
+            .text(handle)
+            .map_or_else(String::new, |text| text.to_string()),
+    }
+}
+
+pub(super) fn number_to_string(number: f64) -> String {
+    if number.is_nan() {
+        "NaN".to_owned()
+    } else if number == f64::INFINITY {
+        "Infinity".to_owned()
+    } else if number == f64::NEG_INFINITY {
+        "-Infinity".to_owned()
+    } else if number == 0.0 {
+        "0".to_owned()
+    } else {
+        number.to_string()
+    }
+}
+
+pub(super) fn string_to_number(string: &str) -> f64 {
+    let string = string.trim_matches(is_whitespace);
+    // `parse` rejects a second `-`.
+    let digits = string.trim_start_matches('-');
+    let is_number = digits.chars().any(|c| c.is_ascii_digit()) &&
+        digits.chars().all(|c| c.is_ascii_digit() || c == '.') &&
+        digits.matches('.').count() <= 1;
+    if is_number {
+        string.parse().unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    }
+}
+
+fn round(number: f64) -> f64 {
+    if number.is_nan() || number.is_infinite() {
+        number
+    } else if (-0.5..0.0).contains(&number) {
+        -0.0
+    } else {
+        (number + 0.5).floor()
+    }
+}
+
+impl<'a, N: TreeNavigator> Evaluator<'a, N> {
+    fn eval(&self, expr: &Expr, focus: &Focus<N::Handle>) -> Result<Value<N::Handle>, Error> {
+        Ok(match *expr {
+            Expr::Or(ref left, ref right) => Value::Boolean(
+                self.eval(left, focus)?.boolean() || self.eval(right, focus)?.boolean(),
+            ),
+            Expr::And(ref left, ref right) => Value::Boolean(
+                self.eval(left, focus)?.boolean() && self.eval(right, focus)?.boolean(),
+            ),
+            Expr::Compare(op, ref left, ref right) => {
+                let left = self.eval(left, focus)?;
+                let right = self.eval(right, focus)?;
+                Value::Boolean(self.compare(op, &left, &right))
+            },
+            Expr::Arithmetic(op, ref left, ref right) => {
+                let left = self.eval(left, focus)?.number(self.navigator);
+                let right = self.eval(right, focus)?.number(self.navigator);
+                Value::Number(match op {
+                    ArithmeticOp::Add => left + right,
+                    ArithmeticOp::Subtract => left - right,
+                    ArithmeticOp::Multiply => left * right,
+                    ArithmeticOp::Divide => left / right,
+                    ArithmeticOp::Modulo => left % right,
+                })
+            },
+            Expr::Negate(ref expr) => {
+                Value::Number(-self.eval(expr, focus)?.number(self.navigator))
+            },
+            Expr::Union(ref left, ref right) => {
+                let mut nodes = self.node_set(left, focus)?;
+                nodes.extend(self.node_set(right, focus)?);
+                Value::Nodes(self.sort(nodes))
+            },
+            Expr::Path(ref start, ref steps) => {
+                let mut nodes = match *start {
+                    PathStart::Root => vec![Node::Tree(self.root(focus.node.handle()))],
+                    PathStart::Context => vec![focus.node.clone()],
+                    PathStart::Expr(ref expr) => self.node_set(expr, focus)?,
+                };
+                let mut steps = steps.iter().peekable();
+                while let Some(step) = steps.next() {
+                    // `//name` selects the same nodes as `descendant::name`, without having to
+                    // sort the children of every descendant into document order.
+                    if let Some(next) = steps.peek() {
+                        if step.axis == Axis::DescendantOrSelf &&
+                            step.test == NodeTest::Node &&
+                            step.predicates.is_empty() &&
+                            next.axis == Axis::Child &&
+                            next.predicates.is_empty()
+                        {
+                            nodes = self.step(Axis::Descendant, &next.test, &[], &nodes)?;
+                            steps.next();
+                            continue;
+                        }
+                    }
+                    let Step {
+                        axis,
+                        ref test,
+                        ref predicates,
+                    } = *step;
+                    nodes = self.step(axis, test, predicates, &nodes)?;
+                }
+                Value::Nodes(nodes)
+            },
+            Expr::Filter(ref expr, ref predicates) => {
+                let mut nodes = self.node_set(expr, focus)?;
+                for predicate in predicates {
+                    nodes = self.filter(nodes, predicate)?;
+                }
+                Value::Nodes(nodes)
+            },
+            Expr::Variable(ref name) => match self.context.variables.get(name) {
+                Some(value) => value.clone(),
+                None => return Err(evaluation_error(format!("Unknown variable ${}", name))),
+            },
+            Expr::Literal(ref literal) => Value::String(literal.clone()),
+            Expr::Number(number) => Value::Number(number),
+            Expr::Function(ref name, ref args) => self.function(name, args, focus)?,
+        })
+    }
+
+    fn node_set(&self, expr: &Expr, focus: &Focus<N::Handle>) -> Result<Nodes<N::Handle>, Error> {
+        match self.eval(expr, focus)? {
+            Value::Nodes(nodes) => Ok(nodes),
+            _ => Err(Error::Evaluation(Cow::Borrowed(
+                "Expected an expression that selects nodes",
+            ))),
+        }
+    }
+
+    fn step(
+        &self,
+        axis: Axis,
+        test: &NodeTest,
+        predicates: &[Expr],
+        nodes: &[Node<N::Handle>],
+    ) -> Result<Nodes<N::Handle>, Error> {
+        let mut result = vec![];
+        for node in nodes {
+            let mut selected = vec![];
+            for candidate in self.axis(axis, node) {
+                if self.matches(axis, test, &candidate)? {
+                    selected.push(candidate);
+                }
+            }
+            for predicate in predicates {
+                selected = self.filter(selected, predicate)?;
+            }
+            if axis.is_reverse() {
+                selected.reverse();
+            }
+            result.extend(selected);
+        }
+        if nodes.len() > 1 {
+            result = self.sort(result);
+        }
+        Ok(result)
+    }
+
+    /// Keeps the nodes that the predicate holds for. Positions follow the order of `nodes`.
+    fn filter(&self, nodes: Nodes<N::Handle>, predicate: &Expr) -> Result<Nodes<N::Handle>, Error> {
+        let size = nodes.len();
+        let mut kept = vec![];
+        for (i, node) in nodes.into_iter().enumerate() {
+            let focus = Focus {
+                node: &node,
+                position: i + 1,
+                size,
+            };
+            let keep = match self.eval(predicate, &focus)? {
+                Value::Number(number) => number == (i + 1) as f64,
+                value => value.boolean(),
+            };
+            if keep {
+                kept.push(node);
+            }
+        }
+        Ok(kept)
+    }
+
+    fn kind(&self, node: &Node<N::Handle>) -> NodeKind {
+        match *node {
+            Node::Tree(ref handle) => node_kind(self.navigator, handle),
+            Node::Attribute(..) => NodeKind::Attribute,
+            Node::Namespace(..) => NodeKind::Namespace,
+        }
+    }
+
+    fn name(&self, node: &Node<N::Handle>) -> Option<QualName> {
+        match *node {
+            Node::Tree(ref handle) => self.navigator.name(handle),
+            _ => expanded_name(node),
+        }
+    }
+
+    fn parent(&self, node: &Node<N::Handle>) -> Option<N::Handle> {
+        match *node {
+            Node::Tree(ref handle) => self.navigator.parent(handle),
+            Node::Attribute(ref owner, _) | Node::Namespace(ref owner, ..) => Some(owner.clone()),
+        }
+    }
+
+    fn root(&self, node: &N::Handle) -> N::Handle {
+        let mut root = node.clone();
+        while let Some(parent) = self.navigator.parent(&root) {
+            root = parent;
+        }
+        root
+    }
+
+    fn resolve(&self, prefix: &Prefix) -> Result<Namespace, Error> {
+        match self.context.namespaces.get(prefix) {
+            Some(ns) => Ok(ns.clone()),
+            None if *prefix == namespace_prefix!("xml") => Ok(ns!(xml)),
+            None => Err(evaluation_error(format!(
+                "No namespace bound to prefix {}",
+                prefix
+            ))),
+        }
+    }
+
+    fn matches(&self, axis: Axis, test: &NodeTest, node: &Node<N::Handle>) -> Result<bool, Error> {
+        let kind = self.kind(node);
+        let (prefix, local) = match *test {
+            NodeTest::Node => return Ok(true),
+            NodeTest::Text => return Ok(kind == NodeKind::Text),
+            NodeTest::Comment => return Ok(kind == NodeKind::Comment),
+            NodeTest::ProcessingInstruction(ref target) => {
+                return Ok(kind == NodeKind::ProcessingInstruction &&
+                    match *target {
+                        Some(ref target) => {
+                            self.name(node).map(|name| name.local).as_deref() == Some(&**target)
+                        },
+                        None => true,
+                    });
+            },
+            NodeTest::Name {
+                ref prefix,
+                ref local,
+            } => (prefix, local),
+        };
+        let principal = match axis {
+            Axis::Attribute => NodeKind::Attribute,
+            Axis::Namespace => NodeKind::Namespace,
+            _ => NodeKind::Element,
+        };
+        if kind != principal {
+            return Ok(false);
+        }
+        if prefix.is_none() && local.is_none() {
+            return Ok(true);
+        }
+        let name = match self.name(node) {
+            Some(name) => name,
+            None => return Ok(false),
+        };
+        // No local name is the `prefix:*` wildcard.
+        let local_matches = |ignore_case: bool| match *local {
+            Some(ref local) if ignore_case => local.eq_ignore_ascii_case(&name.local),
+            Some(ref local) => **local == *name.local,
+            None => true,
+        };
+        if principal == NodeKind::Namespace {
+            return Ok(prefix.is_none() && local_matches(false));
+        }
+        if principal == NodeKind::Element &&
+            prefix.is_none() &&
+            self.context.html &&
+            name.ns == ns!(html)
+        {
+            return Ok(local_matches(true));
+        }
+        let ns = match *prefix {
+            Some(ref prefix) => self.resolve(prefix)?,
+            None => ns!(),
+        };
+        Ok(name.ns == ns && local_matches(false))
+    }
+
+    /// The nodes on an axis, in proximity order.
+    fn axis(&self, axis: Axis, node: &Node<N::Handle>) -> Nodes<N::Handle> {
+        let navigator = self.navigator;
+        let mut nodes = vec![];
+        match axis {
+            Axis::SelfNode => nodes.push(node.clone()),
+            Axis::Child => {
+                if let Node::Tree(ref handle) = *node {
+                    nodes.extend(child_nodes(navigator, handle).into_iter().map(Node::Tree));
+                }
+            },
+            Axis::Descendant | Axis::DescendantOrSelf => {
+                if axis == Axis::DescendantOrSelf {
+                    nodes.push(node.clone());
+                }
+                if let Node::Tree(ref handle) = *node {
+                    let mut handles = vec![];
+                    descendants(navigator, handle, &mut handles);
+                    nodes.extend(handles.into_iter().map(Node::Tree));
+                }
+            },
+            Axis::Parent => nodes.extend(self.parent(node).map(Node::Tree)),
+            Axis::Ancestor | Axis::AncestorOrSelf => {
+                if axis == Axis::AncestorOrSelf {
+                    nodes.push(node.clone());
+                }
+                let mut ancestor = self.parent(node);
+                while let Some(handle) = ancestor {
+                    ancestor = navigator.parent(&handle);
+                    nodes.push(Node::Tree(handle));
+                }
+            },
+            Axis::FollowingSibling | Axis::PrecedingSibling => {
+                if let Node::Tree(ref handle) = *node {
+                    if let Some(parent) = navigator.parent(handle) {
+                        let mut siblings = child_nodes(navigator, &parent);
+                        let index = self.index_of(&siblings, handle);
+                        if axis == Axis::FollowingSibling {
+                            siblings.drain(..=index);
+                        } else {
+                            siblings.truncate(index);
+                            siblings.reverse();
+                        }
+                        nodes.extend(siblings.into_iter().map(Node::Tree));
+                    }
+                }
+            },
+            Axis::Following => {
+                let mut handles = vec![];
+                let mut current = node.handle().clone();
+                if !matches!(*node, Node::Tree(_)) {
+                    // The children of an element follow its attributes.
+                    descendants(navigator, &current, &mut handles);
+                }
+                while let Some(parent) = navigator.parent(&current) {
+                    let siblings = child_nodes(navigator, &parent);
+                    let index = self.index_of(&siblings, &current);
+                    for sibling in &siblings[index + 1..] {
+                        handles.push(sibling.clone());
+                        descendants(navigator, sibling, &mut handles);
+                    }
+                    current = parent;
+                }
+                nodes.extend(handles.into_iter().map(Node::Tree));
+            },
+            Axis::Preceding => {
+                let mut handles = vec![];
+                let mut current = node.handle().clone();
+                while let Some(parent) = navigator.parent(&current) {
+                    let siblings = child_nodes(navigator, &parent);
+                    let index = self.index_of(&siblings, &current);
+                    for sibling in siblings[..index].iter().rev() {
+                        let start = handles.len();
+                        handles.push(sibling.clone());
+                        descendants(navigator, sibling, &mut handles);
+                        handles[start..].reverse();
+                    }
+                    current = parent;
+                }
+                nodes.extend(handles.into_iter().map(Node::Tree));
+            },
+            Axis::Attribute => {
+                if let Node::Tree(ref handle) = *node {
+                    for attr in navigator.attributes(handle) {
+                        if attr.name.ns != ns!(xmlns) {
+                            nodes.push(Node::Attribute(handle.clone(), attr));
+                        }
+                    }
+                }
+            },
+            Axis::Namespace => {
+                if let Node::Tree(ref handle) = *node {
+                    if node_kind(navigator, handle) == NodeKind::Element {
+                        nodes = self.namespaces(handle);
+                    }
+                }
+            },
+        }
+        nodes
+    }
+
+    fn index_of(&self, siblings: &[N::Handle], node: &N::Handle) -> usize {
+        siblings
+            .iter()
+            .position(|sibling| self.navigator.same_node(sibling, node))
+            .expect("node not found among its parent's children")
+    }
+
+    /// The namespace nodes of an element, ordered by prefix.
+    fn namespaces(&self, element: &N::Handle) -> Nodes<N::Handle> {
+        let navigator = self.navigator;
+        let mut elements = vec![element.clone()];
+        while let Some(parent) = navigator.parent(elements.last().unwrap()) {
+            elements.push(parent);
+        }
+        let mut bindings: Vec<(Option<Prefix>, Namespace)> =
+            vec![(Some(namespace_prefix!("xml")), ns!(xml))];
+        let mut bind = |prefix: Option<Prefix>, ns: Namespace| {
+            bindings.retain(|(bound, _)| *bound != prefix);
+            bindings.push((prefix, ns));
+        };
+        for handle in elements.iter().rev() {
+            if node_kind(navigator, handle) != NodeKind::Element {
+                continue;
+            }
+            if let Some(name) = navigator.name(handle) {
+                if name.prefix.is_some() || !name.ns.is_empty() {
+                    bind(name.prefix, name.ns);
+                }
+            }
+            for attr in navigator.attributes(handle) {
+                let ns = Namespace::from(&*attr.value);
+                match attr.name.prefix {
+                    _ if attr.name.ns == ns!(xmlns) && attr.name.local == local_name!("xmlns") => {
+                        bind(None, ns)
+                    },
+                    _ if attr.name.ns == ns!(xmlns) => {
+                        bind(Some(Prefix::from(&*attr.name.local)), ns)
+                    },
+                    Some(prefix) if !attr.name.ns.is_empty() => bind(Some(prefix), attr.name.ns),
+                    _ => (),
+                }
+            }
+        }
+        bindings.retain(|(_, ns)| !ns.is_empty());
+        bindings.sort_by(|(x, _), (y, _)| x.as_deref().cmp(&y.as_deref()));
+        bindings
+            .into_iter()
+            .map(|(prefix, ns)| Node::Namespace(element.clone(), prefix, ns))
+            .collect()
+    }
+
+    /// The position of a node in document order, as a sequence of indices that compares
+    /// lexicographically. An element's namespaces and attributes come before its children.
+    fn order_key(&self, node: &Node<N::Handle>) -> Vec<usize> {
+        let mut key = vec![];
+        match *node {
+            Node::Tree(_) => {},
+            Node::Namespace(ref owner, ref prefix, _) => {
+                let namespaces = self.namespaces(owner);
+                let index = namespaces.iter().position(|namespace| match *namespace {
+                    Node::Namespace(_, ref other, _) => other == prefix,
+                    _ => false,
+                });
+                key.extend(&[index.unwrap_or(0), 0]);
+            },
+            Node::Attribute(ref owner, ref attr) => {
+                let attrs = self.navigator.attributes(owner);
+                let index = attrs.iter().position(|other| other.name == attr.name);
+                key.extend(&[index.unwrap_or(0), 1]);
+            },
+        }
+        let mut current = node.handle().clone();
+        while let Some(parent) = self.navigator.parent(&current) {
+            key.push(self.navigator.position(&current) + 2);
+            current = parent;
+        }
+        key.reverse();
+        key
+    }
+
+    /// Puts nodes into document order and removes duplicates.
+    fn sort(&self, nodes: Nodes<N::Handle>) -> Nodes<N::Handle> {
+        let mut keyed: Vec<_> = nodes
+            .into_iter()
+            .map(|node| (self.order_key(&node), node))
+            .collect();
+        keyed.sort_by(|(x, _), (y, _)| x.cmp(y));
+        keyed.dedup_by(|(x, _), (y, _)| x == y);
+        keyed.into_iter().map(|(_, node)| node).collect()
+    }
+
+    fn compare(&self, op: CompareOp, left: &Value<N::Handle>, right: &Value<N::Handle>) -> bool {
+        let string_value = |node| Value::String(string_value(self.navigator, node));
+        match (left, right) {
+            (Value::Nodes(left), Value::Nodes(right)) => {
+                let right: Vec<_> = right.iter().map(string_value).collect();
+                left.iter().any(|node| {
+                    let left = string_value(node);
+                    right
+                        .iter()
+                        .any(|right| self.compare_values(op, &left, right))
+                })
+            },
+            (Value::Nodes(nodes), Value::Boolean(_)) => {
+                self.compare_values(op, &Value::Boolean(!nodes.is_empty()), right)
+            },
+            (Value::Boolean(_), Value::Nodes(nodes)) => {
+                self.compare_values(op, left, &Value::Boolean(!nodes.is_empty()))
+            },
+            (Value::Nodes(nodes), _) => nodes
+                .iter()
+                .any(|node| self.compare_values(op, &string_value(node), right)),
+            (_, Value::Nodes(nodes)) => nodes
+                .iter()
+                .any(|node| self.compare_values(op, left, &string_value(node))),
+            _ => self.compare_values(op, left, right),
+        }
+    }
+
+    /// Compares two values that aren't node-sets.
+    fn compare_values(
+        &self,
+        op: CompareOp,
+        left: &Value<N::Handle>,
+        right: &Value<N::Handle>,
+    ) -> bool {
+        let navigator = self.navigator;
+        let equal = match (left, right) {
+            (&Value::Boolean(_), _) | (_, &Value::Boolean(_)) => left.boolean() == right.boolean(),
+            (&Value::Number(_), _) | (_, &Value::Number(_)) => {
+                left.number(navigator) == right.number(navigator)
+            },
+            _ => left.string(navigator) == right.string(navigator),
+        };
+        let (left, right) = (left.number(navigator), right.number(navigator));
+        match op {
+            CompareOp::Equal => equal,
+            CompareOp::NotEqual => !equal,
+            CompareOp::Less => left < right,
+            CompareOp::LessOrEqual => left <= right,
+            CompareOp::Greater => left > right,
+            CompareOp::GreaterOrEqual => left >= right,
+        }
+    }
+
+    fn string_arg(&self, arg: Option<&Expr>, focus: &Focus<N::Handle>) -> Result<String, Error> {
+        match arg {
+            Some(arg) => Ok(self.eval(arg, focus)?.string(self.navigator)),
+            None => Ok(string_value(self.navigator, focus.node)),
+        }
+    }
+
+    fn number_arg(&self, arg: &Expr, focus: &Focus<N::Handle>) -> Result<f64, Error> {
+        Ok(self.eval(arg, focus)?.number(self.navigator))
+    }
+
+    fn function(
+        &self,
+        name: &str,
+        args: &[Expr],
+        focus: &Focus<N::Handle>,
+    ) -> Result<Value<N::Handle>, Error> {
+        let (min, max) = match name {
+            "last" | "position" | "true" | "false" => (0, 0),
+            "local-name" | "namespace-uri" | "name" | "string" | "string-length" |
+            "normalize-space" | "number" => (0, 1),
+            "count" | "id" | "boolean" | "not" | "lang" | "sum" | "floor" | "ceiling" | "round" => {
+                (1, 1)
+            },
+            "starts-with" | "contains" | "substring-before" | "substring-after" => (2, 2),
+            "concat" => (2, usize::MAX),
+            "substring" => (2, 3),
+            "translate" => (3, 3),
+            _ => return Err(evaluation_error(format!("Unknown function {}()", name))),
+        };
+        if args.len() < min || args.len() > max {
+            return Err(evaluation_error(format!(
+                "Wrong number of arguments to {}()",
+                name
+            )));
+        }
+        let string = |i: usize| self.string_arg(args.get(i), focus);
+        Ok(match name {
+            "last" => Value::Number(focus.size as f64),
+            "position" => Value::Number(focus.position as f64),
+            "count" => Value::Number(self.node_set(&args[0], focus)?.len() as f64),
+            "id" => Value::Nodes(self.id(&args[0], focus)?),
+            "local-name" | "namespace-uri" | "name" => {
+                let node = match args.first() {
+                    Some(arg) => self.node_set(arg, focus)?.into_iter().next(),
+                    None => Some(focus.node.clone()),
+                };
+                let qual_name = match node.and_then(|node| self.name(&node)) {
+                    Some(qual_name) => qual_name,
+                    None => return Ok(Value::String(String::new())),
+                };
+                Value::String(match (name, qual_name.prefix) {
+                    ("namespace-uri", _) => qual_name.ns.to_string(),
+                    ("name", Some(prefix)) => format!("{}:{}", prefix, qual_name.local),
+                    _ => qual_name.local.to_string(),
+                })
+            },
+            "string" => Value::String(string(0)?),
+            "concat" => {
+                let mut result = String::new();
+                for i in 0..args.len() {
+                    result.push_str(&string(i)?);
+                }
+                Value::String(result)
+            },
+            "starts-with" => Value::Boolean(string(0)?.starts_with(&*string(1)?)),
+            "contains" => Value::Boolean(string(0)?.contains(&*string(1)?)),
+            "substring-before" => {
+                let (haystack, needle) = (string(0)?, string(1)?);
+                Value::String(match haystack.find(&*needle) {
+                    Some(index) => haystack[..index].to_owned(),
+                    None => String::new(),
+                })
+            },
+            "substring-after" => {
+                let (haystack, needle) = (string(0)?, string(1)?);
+                Value::String(match haystack.find(&*needle) {
+                    Some(index) => haystack[index + needle.len()..].to_owned(),
+                    None => String::new(),
+                })
+            },
+            "substring" => {
+                let text = string(0)?;
+                let start = round(self.number_arg(&args[1], focus)?);
+                let end = match args.get(2) {
+                    Some(length) => start + round(self.number_arg(length, focus)?),
+                    None => f64::INFINITY,
+                };
+                let chars = text.chars().enumerate().filter(|&(i, _)| {
+                    let position = (i + 1) as f64;
+                    position >= start && position < end
+                });
+                Value::String(chars.map(|(_, c)| c).collect())
+            },
+            "string-length" => Value::Number(string(0)?.chars().count() as f64),
+            "normalize-space" => {
+                let text = string(0)?;
+                let words: Vec<&str> = text
+                    .split(is_whitespace)
+                    .filter(|w| !w.is_empty())
+                    .collect();
+                Value::String(words.join(" "))
+            },
+            "translate" => {
+                let (text, from, to) = (string(0)?, string(1)?, string(2)?);
+                let from: Vec<char> = from.chars().collect();
+                let to: Vec<char> = to.chars().collect();
+                Value::String(
+                    text.chars()
+                        .filter_map(|c| match from.iter().position(|&f| f == c) {
+                            Some(index) => to.get(index).cloned(),
+                            None => Some(c),
+                        })
+                        .collect(),
+                )
+            },
+            "boolean" => Value::Boolean(self.eval(&args[0], focus)?.boolean()),
+            "not" => Value::Boolean(!self.eval(&args[0], focus)?.boolean()),
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            "lang" => Value::Boolean(self.lang(&string(0)?, focus.node)),
+            "number" => Value::Number(match args.first() {
+                Some(arg) => self.number_arg(arg, focus)?,
+                None => string_to_number(&string_value(self.navigator, focus.node)),
+            }),
+            "sum" => Value::Number(
+                self.node_set(&args[0], focus)?
+                    .iter()
+                    .map(|node| string_to_number(&string_value(self.navigator, node)))
+                    .sum(),
+            ),
+            "floor" => Value::Number(self.number_arg(&args[0], focus)?.floor()),
+            "ceiling" => Value::Number(self.number_arg(&args[0], focus)?.ceil()),
+            "round" => Value::Number(round(self.number_arg(&args[0], focus)?)),
+            _ => unreachable!(),
+        })
+    }
+
+    /// The elements with the IDs listed by the argument, using `id` and `xml:id` attributes.
+    fn id(&self, arg: &Expr, focus: &Focus<N::Handle>) -> Result<Nodes<N::Handle>, Error> {
+        let ids = match self.eval(arg, focus)? {
+            Value::Nodes(nodes) => nodes
+                .iter()
+                .map(|node| string_value(self.navigator, node))
+                .collect::<Vec<_>>()
+                .join(" "),
+            value => value.string(self.navigator),
+        };
+        let mut ids: Vec<&str> = ids
+            .split(is_whitespace)
+            .filter(|id| !id.is_empty())
+            .collect();
+        let mut elements = vec![];
+        let mut handles = vec![];
+        descendants(
+            self.navigator,
+            &self.root(focus.node.handle()),
+            &mut handles,
+        );
+        for handle in handles {
+            if ids.is_empty() {
+                break;
+            }
+            if node_kind(self.navigator, &handle) != NodeKind::Element {
+                continue;
+            }
+            let id = self.navigator.attributes(&handle).into_iter().find(|attr| {
+                attr.name.local == local_name!("id") &&
+                    (attr.name.ns.is_empty() || attr.name.ns == ns!(xml))
+            });
+            if let Some(id) = id {
+                let len = ids.len();
+                ids.retain(|&other| other != &*id.value);
+                if ids.len() != len {
+                    elements.push(Node::Tree(handle));
+                }
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Is the language of the node `lang` or one of its sublanguages?
+    fn lang(&self, lang: &str, node: &Node<N::Handle>) -> bool {
+        let mut current = match *node {
+            Node::Tree(ref handle) => Some(handle.clone()),
+            _ => self.parent(node),
+        };
+        while let Some(handle) = current {
+            if node_kind(self.navigator, &handle) == NodeKind::Element {
+                let html = self.context.html;
+                let value = self.navigator.attributes(&handle).into_iter().find(|attr| {
+                    attr.name.local == local_name!("lang") &&
+                        (attr.name.ns == ns!(xml) || (html && attr.name.ns.is_empty()))
+                });
+                if let Some(value) = value {
+                    let value = value.value.to_ascii_lowercase();
+                    let lang = lang.to_ascii_lowercase();
+                    return value == lang ||
+                        (value.starts_with(&lang) && value[lang.len()..].starts_with('-'));
+                }
+            }
+            current = self.navigator.parent(&handle);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{number_to_string, round, string_to_number};
+
+    #[test]
+    fn numbers() {
+        assert_eq!(string_to_number(" 12.5\n"), 12.5);
+        assert_eq!(string_to_number("-.5"), -0.5);
+        assert_eq!(string_to_number("5."), 5.0);
+        assert!(string_to_number("1e5").is_nan());
+        assert!(string_to_number("+1").is_nan());
+        assert!(string_to_number("inf").is_nan());
+        assert!(string_to_number(".").is_nan());
+        assert!(string_to_number("").is_nan());
+
+        assert_eq!(number_to_string(1.0), "1");
+        assert_eq!(number_to_string(-0.0), "0");
+        assert_eq!(number_to_string(0.25), "0.25");
+        assert_eq!(number_to_string(1e21), "1000000000000000000000");
+        assert_eq!(number_to_string(f64::NAN), "NaN");
+        assert_eq!(number_to_string(-f64::INFINITY), "-Infinity");
+
+        assert_eq!(round(2.5), 3.0);
+        assert_eq!(round(-2.5), -2.0);
+        assert!(round(-0.2).is_sign_negative());
+        assert!(round(f64::NAN).is_nan());
+    }
+}
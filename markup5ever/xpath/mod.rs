@@ -0,0 +1,258 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//! [XPath 1.0] evaluation over any tree that implements `TreeNavigator`.
+//!
+//! ```text
+//! let xpath = XPath::parse("//a[starts-with(@href, 'http')]")?;
+//! let links = xpath.select(&dom, &Context::html(), &dom.document)?;
+//! ```
+//!
+//! Trees built by html5ever should be queried with `Context::html()`, which makes unprefixed
+//! name tests match HTML elements case-insensitively, as [the HTML standard] requires.
+//!
+//! Doctypes have no place in the XPath data model, so they are left out of the tree, and CDATA
+//! sections are text nodes.
+//!
+//! [XPath 1.0]: https://www.w3.org/TR/1999/REC-xpath-19991116/
+//! [the HTML standard]: https://html.spec.whatwg.org/multipage/#interactions-with-xpath-and-xslt
+
+use crate::traverse::TreeNavigator;
+use crate::{Attribute, LocalName, Namespace, Prefix, QualName};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+mod eval;
+mod parser;
+
+/// The kinds of node in the XPath data model.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Document,
+    Element,
+    /// Only found in node-sets; attributes are reported by `TreeNavigator::attributes`.
+    Attribute,
+    /// Only found in node-sets; see `Node::Namespace`.
+    Namespace,
+    Text,
+    Comment,
+    ProcessingInstruction,
+}
+
+/// A node in a node-set.
+#[derive(Clone, Debug)]
+pub enum Node<Handle> {
+    /// A document, element, text, comment or processing instruction node.
+    Tree(Handle),
+    /// An attribute of an element.
+    Attribute(Handle, Attribute),
+    /// A namespace in scope for an element. Trees don't keep namespace declarations once the
+    /// names are resolved, so these are worked out from the names and `xmlns` attributes of the
+    /// element and its ancestors.
+    Namespace(Handle, Option<Prefix>, Namespace),
+}
+
+impl<Handle> Node<Handle> {
+    /// The tree node, or the element that an attribute or namespace belongs to.
+    pub fn handle(&self) -> &Handle {
+        match *self {
+            Node::Tree(ref handle) |
+            Node::Attribute(ref handle, _) |
+            Node::Namespace(ref handle, ..) => handle,
+        }
+    }
+
+    /// The [string-value] of the node.
+    ///
+    /// [string-value]: https://www.w3.org/TR/1999/REC-xpath-19991116/#dt-string-value
+    pub fn string_value<N>(&self, navigator: &N) -> String
+    where
+        N: TreeNavigator<Handle = Handle>,
+    {
+        eval::string_value(navigator, self)
+    }
+}
+
+/// The result of an XPath expression.
+#[derive(Clone, Debug)]
+pub enum Value<Handle> {
+    /// Nodes in document order, without duplicates.
+    Nodes(Vec<Node<Handle>>),
+    Boolean(bool),
+    Number(f64),
+    String(String),
+}
+
+impl<Handle> Value<Handle> {
+    /// Converts the value as the `boolean()` function does.
+    pub fn boolean(&self) -> bool {
+        match *self {
+            Value::Nodes(ref nodes) => !nodes.is_empty(),
+            Value::Boolean(boolean) => boolean,
+            Value::Number(number) => number != 0.0 && !number.is_nan(),
+            Value::String(ref string) => !string.is_empty(),
+        }
+    }
+
+    /// Converts the value as the `number()` function does.
+    pub fn number<N>(&self, navigator: &N) -> f64
+    where
+        N: TreeNavigator<Handle = Handle>,
+    {
+        match *self {
+            Value::Number(number) => number,
+            Value::Boolean(boolean) => boolean as u8 as f64,
+            _ => eval::string_to_number(&self.string(navigator)),
+        }
+    }
+
+    /// Converts the value as the `string()` function does.
+    pub fn string<N>(&self, navigator: &N) -> String
+    where
+        N: TreeNavigator<Handle = Handle>,
+    {
+        match *self {
+            Value::Nodes(ref nodes) => match nodes.first() {
+                Some(node) => node.string_value(navigator),
+                None => String::new(),
+            },
+            Value::Boolean(boolean) => boolean.to_string(),
+            Value::Number(number) => eval::number_to_string(number),
+            Value::String(ref string) => string.clone(),
+        }
+    }
+}
+
+/// The namespace bindings and variables an expression is evaluated with.
+#[derive(Clone, Debug)]
+pub struct Context<Handle> {
+    /// Namespaces for the prefixes used in the expression's names.
+    pub namespaces: HashMap<Prefix, Namespace>,
+
+    /// Values of the variables referenced with `$name`.
+    pub variables: HashMap<String, Value<Handle>>,
+
+    /// Are we querying an HTML document? Unprefixed name tests then match elements in the HTML
+    /// namespace, ignoring ASCII case.
+    ///
+    /// Default: false
+    pub html: bool,
+}
+
+impl<Handle> Default for Context<Handle> {
+    fn default() -> Context<Handle> {
+        Context {
+            namespaces: HashMap::new(),
+            variables: HashMap::new(),
+            html: false,
+        }
+    }
+}
+
+impl<Handle> Context<Handle> {
+    /// A context for querying HTML documents.
+    pub fn html() -> Context<Handle> {
+        Context {
+            html: true,
+            ..Context::default()
+        }
+    }
+
+    /// Binds a prefix for use in the expression.
+    pub fn bind(&mut self, prefix: Prefix, namespace: Namespace) {
+        self.namespaces.insert(prefix, namespace);
+    }
+}
+
+/// Failure to parse or evaluate an expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The expression isn't valid XPath. `position` is the byte offset where parsing failed.
+    Syntax {
+        position: usize,
+        message: Cow<'static, str>,
+    },
+    /// The expression is valid but can't be evaluated, e.g. it calls an unknown function or
+    /// uses an unbound prefix.
+    Evaluation(Cow<'static, str>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Syntax {
+                position,
+                ref message,
+            } => write!(f, "{} at position {}", message, position),
+            Error::Evaluation(ref message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A parsed XPath 1.0 expression.
+#[derive(Clone, Debug)]
+pub struct XPath {
+    expr: parser::Expr,
+}
+
+impl XPath {
+    /// Parses an expression.
+    pub fn parse(source: &str) -> Result<XPath, Error> {
+        Ok(XPath {
+            expr: parser::parse(source)?,
+        })
+    }
+
+    /// Evaluates the expression with `node` as the context node.
+    pub fn evaluate<N>(
+        &self,
+        navigator: &N,
+        context: &Context<N::Handle>,
+        node: &N::Handle,
+    ) -> Result<Value<N::Handle>, Error>
+    where
+        N: TreeNavigator,
+    {
+        eval::evaluate(navigator, context, &self.expr, node)
+    }
+
+    /// Evaluates an expression that selects nodes, such as a location path.
+    pub fn select<N>(
+        &self,
+        navigator: &N,
+        context: &Context<N::Handle>,
+        node: &N::Handle,
+    ) -> Result<Vec<Node<N::Handle>>, Error>
+    where
+        N: TreeNavigator,
+    {
+        match self.evaluate(navigator, context, node)? {
+            Value::Nodes(nodes) => Ok(nodes),
+            _ => Err(Error::Evaluation(Cow::Borrowed(
+                "Expression does not select nodes",
+            ))),
+        }
+    }
+}
+
+/// The name of an attribute or namespace node.
+fn expanded_name<Handle>(node: &Node<Handle>) -> Option<QualName> {
+    match *node {
+        Node::Tree(_) => None,
+        Node::Attribute(_, ref attr) => Some(attr.name.clone()),
+        Node::Namespace(_, ref prefix, _) => {
+            let local = prefix
+                .as_ref()
+                .map_or(LocalName::from(""), |p| LocalName::from(&**p));
+            Some(QualName::new(None, ns!(), local))
+        },
+    }
+}
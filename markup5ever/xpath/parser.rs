@@ -0,0 +1,772 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tokenizer and recursive descent parser for XPath 1.0 expressions.
+
+use super::Error;
+use crate::{LocalName, Prefix};
+use mac::{_tt_as_expr_hack, matches};
+use std::borrow::Cow;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+    Arithmetic(ArithmeticOp, Box<Expr>, Box<Expr>),
+    Negate(Box<Expr>),
+    Union(Box<Expr>, Box<Expr>),
+    Path(PathStart, Vec<Step>),
+    Filter(Box<Expr>, Vec<Expr>),
+    Variable(String),
+    Literal(String),
+    Number(f64),
+    Function(String, Vec<Expr>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(super) enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(super) enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum PathStart {
+    /// The root of the tree containing the context node, for `/...`.
+    Root,
+    /// The context node, for relative paths.
+    Context,
+    /// The nodes selected by a filter expression, for `$nodes/...`.
+    Expr(Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Step {
+    pub(super) axis: Axis,
+    pub(super) test: NodeTest,
+    pub(super) predicates: Vec<Expr>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(super) enum Axis {
+    Ancestor,
+    AncestorOrSelf,
+    Attribute,
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    Following,
+    FollowingSibling,
+    Namespace,
+    Parent,
+    Preceding,
+    PrecedingSibling,
+    SelfNode,
+}
+
+impl Axis {
+    /// Reverse axes number their nodes in reverse document order.
+    pub(super) fn is_reverse(self) -> bool {
+        matches!(
+            self,
+            Axis::Ancestor | Axis::AncestorOrSelf | Axis::Preceding | Axis::PrecedingSibling
+        )
+    }
+
+    fn from_name(name: &str) -> Option<Axis> {
+        Some(match name {
+            "ancestor" => Axis::Ancestor,
+            "ancestor-or-self" => Axis::AncestorOrSelf,
+            "attribute" => Axis::Attribute,
+            "child" => Axis::Child,
+            "descendant" => Axis::Descendant,
+            "descendant-or-self" => Axis::DescendantOrSelf,
+            "following" => Axis::Following,
+            "following-sibling" => Axis::FollowingSibling,
+            "namespace" => Axis::Namespace,
+            "parent" => Axis::Parent,
+            "preceding" => Axis::Preceding,
+            "preceding-sibling" => Axis::PrecedingSibling,
+            "self" => Axis::SelfNode,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum NodeTest {
+    /// A name test; `None` stands for `*`.
+    Name {
+        prefix: Option<Prefix>,
+        local: Option<LocalName>,
+    },
+    Node,
+    Text,
+    Comment,
+    ProcessingInstruction(Option<String>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Dot,
+    DotDot,
+    At,
+    Comma,
+    ColonColon,
+    /// A QName. Depending on what follows it is a name test, a function name, a node type, an
+    /// axis name or an operator name.
+    Name(Option<String>, String),
+    /// `*`, either a name test or the multiply operator.
+    Star,
+    /// `prefix:*`
+    PrefixStar(String),
+    Literal(String),
+    Number(f64),
+    Variable(String),
+    Slash,
+    DoubleSlash,
+    Pipe,
+    Plus,
+    Minus,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n')
+}
+
+fn is_name_start_char(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || (c as u32) >= 0xC0
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c) || c.is_ascii_digit() || matches!(c, '-' | '.' | '\u{B7}')
+}
+
+fn syntax_error(position: usize, message: &'static str) -> Error {
+    Error::Syntax {
+        position,
+        message: Cow::Borrowed(message),
+    }
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.source[self.position..].chars().nth(offset)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &'a str {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if !pred(c) {
+                break;
+            }
+            self.position += c.len_utf8();
+        }
+        &self.source[start..self.position]
+    }
+
+    fn ncname(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(c) if is_name_start_char(c) => Some(self.take_while(is_name_char).to_owned()),
+            _ => None,
+        }
+    }
+
+    fn tokens(mut self) -> Result<Vec<(Token, usize)>, Error> {
+        let mut tokens = vec![];
+        loop {
+            self.take_while(is_whitespace);
+            let start = self.position;
+            let c = match self.bump() {
+                Some(c) => c,
+                None => return Ok(tokens),
+            };
+            let token = match c {
+                '(' => Token::LeftParen,
+                ')' => Token::RightParen,
+                '[' => Token::LeftBracket,
+                ']' => Token::RightBracket,
+                '@' => Token::At,
+                ',' => Token::Comma,
+                '|' => Token::Pipe,
+                '+' => Token::Plus,
+                '-' => Token::Minus,
+                '=' => Token::Equal,
+                '*' => Token::Star,
+                ':' if self.peek() == Some(':') => {
+                    self.bump();
+                    Token::ColonColon
+                },
+                '/' if self.peek() == Some('/') => {
+                    self.bump();
+                    Token::DoubleSlash
+                },
+                '/' => Token::Slash,
+                '!' if self.peek() == Some('=') => {
+                    self.bump();
+                    Token::NotEqual
+                },
+                '<' | '>' => {
+                    let or_equal = self.peek() == Some('=');
+                    if or_equal {
+                        self.bump();
+                    }
+                    match (c, or_equal) {
+                        ('<', false) => Token::Less,
+                        ('<', true) => Token::LessOrEqual,
+                        ('>', false) => Token::Greater,
+                        _ => Token::GreaterOrEqual,
+                    }
+                },
+                '"' | '\'' => {
+                    let literal = self.take_while(|d| d != c).to_owned();
+                    if self.bump().is_none() {
+                        return Err(syntax_error(start, "Unterminated string literal"));
+                    }
+                    Token::Literal(literal)
+                },
+                '.' if self.peek() == Some('.') => {
+                    self.bump();
+                    Token::DotDot
+                },
+                '.' if !matches!(self.peek(), Some(d) if d.is_ascii_digit()) => Token::Dot,
+                '0'..='9' | '.' => {
+                    self.position = start;
+                    self.take_while(|d| d.is_ascii_digit());
+                    if self.peek() == Some('.') {
+                        self.bump();
+                        self.take_while(|d| d.is_ascii_digit());
+                    }
+                    Token::Number(self.source[start..self.position].parse().unwrap())
+                },
+                '$' => match self.qname() {
+                    Some((Some(prefix), local)) => Token::Variable(format!("{}:{}", prefix, local)),
+                    Some((None, local)) => Token::Variable(local),
+                    None => return Err(syntax_error(start, "Expected a variable name")),
+                },
+                c if is_name_start_char(c) => {
+                    self.position = start;
+                    let (prefix, local) = self.qname().unwrap();
+                    if prefix.is_none() && self.peek() == Some(':') && self.peek_at(1) == Some('*')
+                    {
+                        self.position += 2;
+                        Token::PrefixStar(local)
+                    } else {
+                        Token::Name(prefix, local)
+                    }
+                },
+                _ => return Err(syntax_error(start, "Unexpected character")),
+            };
+            tokens.push((token, start));
+        }
+    }
+
+    fn qname(&mut self) -> Option<(Option<String>, String)> {
+        let first = self.ncname()?;
+        if self.peek() == Some(':') && matches!(self.peek_at(1), Some(c) if is_name_start_char(c)) {
+            self.bump();
+            return Some((Some(first), self.ncname().unwrap()));
+        }
+        Some((None, first))
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+    end: usize,
+}
+
+/// Parses an XPath 1.0 expression.
+pub(super) fn parse(source: &str) -> Result<Expr, Error> {
+    let lexer = Lexer {
+        source,
+        position: 0,
+    };
+    let mut parser = Parser {
+        tokens: lexer.tokens()?,
+        index: 0,
+        end: source.len(),
+    };
+    let expr = parser.expr()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("Unexpected token"));
+    }
+    Ok(expr)
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(token, _)| token)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.index + offset).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.index)
+            .map_or(self.end, |&(_, position)| position)
+    }
+
+    fn error(&self, message: &'static str) -> Error {
+        syntax_error(self.position(), message)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).map(|(token, _)| token.clone());
+        self.index += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token, message: &'static str) -> Result<(), Error> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    fn is_operator_name(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(None, local)) if local == name)
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.and_expr()?;
+        while self.is_operator_name("or") {
+            self.index += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.equality_expr()?;
+        while self.is_operator_name("and") {
+            self.index += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.equality_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn equality_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.relational_expr()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Equal) => CompareOp::Equal,
+                Some(Token::NotEqual) => CompareOp::NotEqual,
+                _ => return Ok(expr),
+            };
+            self.index += 1;
+            expr = Expr::Compare(op, Box::new(expr), Box::new(self.relational_expr()?));
+        }
+    }
+
+    fn relational_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.additive_expr()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Less) => CompareOp::Less,
+                Some(Token::LessOrEqual) => CompareOp::LessOrEqual,
+                Some(Token::Greater) => CompareOp::Greater,
+                Some(Token::GreaterOrEqual) => CompareOp::GreaterOrEqual,
+                _ => return Ok(expr),
+            };
+            self.index += 1;
+            expr = Expr::Compare(op, Box::new(expr), Box::new(self.additive_expr()?));
+        }
+    }
+
+    fn additive_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.multiplicative_expr()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => ArithmeticOp::Add,
+                Some(Token::Minus) => ArithmeticOp::Subtract,
+                _ => return Ok(expr),
+            };
+            self.index += 1;
+            let right = self.multiplicative_expr()?;
+            expr = Expr::Arithmetic(op, Box::new(expr), Box::new(right));
+        }
+    }
+
+    fn multiplicative_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.unary_expr()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => ArithmeticOp::Multiply,
+                Some(Token::Name(None, name)) if name == "div" => ArithmeticOp::Divide,
+                Some(Token::Name(None, name)) if name == "mod" => ArithmeticOp::Modulo,
+                _ => return Ok(expr),
+            };
+            self.index += 1;
+            expr = Expr::Arithmetic(op, Box::new(expr), Box::new(self.unary_expr()?));
+        }
+    }
+
+    fn unary_expr(&mut self) -> Result<Expr, Error> {
+        if self.eat(&Token::Minus) {
+            return Ok(Expr::Negate(Box::new(self.unary_expr()?)));
+        }
+        let mut expr = self.path_expr()?;
+        while self.eat(&Token::Pipe) {
+            expr = Expr::Union(Box::new(expr), Box::new(self.path_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn path_expr(&mut self) -> Result<Expr, Error> {
+        let starts_filter_expr = match self.peek() {
+            Some(Token::Variable(_)) |
+            Some(Token::LeftParen) |
+            Some(Token::Literal(_)) |
+            Some(Token::Number(_)) => true,
+            Some(Token::Name(prefix, local)) => {
+                self.peek_at(1) == Some(&Token::LeftParen) &&
+                    (prefix.is_some() || !is_node_type(local))
+            },
+            _ => false,
+        };
+        if !starts_filter_expr {
+            return self.location_path();
+        }
+        let mut expr = self.primary_expr()?;
+        let predicates = self.predicates()?;
+        if !predicates.is_empty() {
+            expr = Expr::Filter(Box::new(expr), predicates);
+        }
+        let mut steps = vec![];
+        if !self.relative_location_path(&mut steps, false)? {
+            return Ok(expr);
+        }
+        Ok(Expr::Path(PathStart::Expr(Box::new(expr)), steps))
+    }
+
+    fn primary_expr(&mut self) -> Result<Expr, Error> {
+        match self.next() {
+            Some(Token::Variable(name)) => Ok(Expr::Variable(name)),
+            Some(Token::Literal(literal)) => Ok(Expr::Literal(literal)),
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::LeftParen) => {
+                let expr = self.expr()?;
+                self.expect(&Token::RightParen, "Expected ')'")?;
+                Ok(expr)
+            },
+            Some(Token::Name(prefix, local)) => {
+                let name = match prefix {
+                    Some(prefix) => format!("{}:{}", prefix, local),
+                    None => local,
+                };
+                self.index += 1;
+                let mut args = vec![];
+                if !self.eat(&Token::RightParen) {
+                    loop {
+                        args.push(self.expr()?);
+                        if self.eat(&Token::RightParen) {
+                            break;
+                        }
+                        self.expect(&Token::Comma, "Expected ',' or ')'")?;
+                    }
+                }
+                Ok(Expr::Function(name, args))
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn predicates(&mut self) -> Result<Vec<Expr>, Error> {
+        let mut predicates = vec![];
+        while self.eat(&Token::LeftBracket) {
+            predicates.push(self.expr()?);
+            self.expect(&Token::RightBracket, "Expected ']'")?;
+        }
+        Ok(predicates)
+    }
+
+    fn location_path(&mut self) -> Result<Expr, Error> {
+        let mut steps = vec![];
+        let start = match self.peek() {
+            Some(Token::Slash) => {
+                self.index += 1;
+                if self.starts_step() {
+                    self.step(&mut steps)?;
+                    self.relative_location_path(&mut steps, false)?;
+                }
+                PathStart::Root
+            },
+            Some(Token::DoubleSlash) => {
+                self.relative_location_path(&mut steps, true)?;
+                PathStart::Root
+            },
+            _ => {
+                self.step(&mut steps)?;
+                self.relative_location_path(&mut steps, false)?;
+                PathStart::Context
+            },
+        };
+        Ok(Expr::Path(start, steps))
+    }
+
+    /// Parses the `/step` and `//step` continuations of a path. Returns whether there were any.
+    fn relative_location_path(
+        &mut self,
+        steps: &mut Vec<Step>,
+        mut required: bool,
+    ) -> Result<bool, Error> {
+        let mut any = false;
+        loop {
+            match self.peek() {
+                Some(Token::Slash) => {},
+                Some(Token::DoubleSlash) => {
+                    steps.push(Step {
+                        axis: Axis::DescendantOrSelf,
+                        test: NodeTest::Node,
+                        predicates: vec![],
+                    });
+                },
+                _ if required => return Err(self.error("Expected a location step")),
+                _ => return Ok(any),
+            }
+            self.index += 1;
+            self.step(steps)?;
+            any = true;
+            required = false;
+        }
+    }
+
+    fn starts_step(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token::Dot) |
+                Some(Token::DotDot) |
+                Some(Token::At) |
+                Some(Token::Star) |
+                Some(Token::PrefixStar(_)) |
+                Some(Token::Name(..))
+        )
+    }
+
+    fn step(&mut self, steps: &mut Vec<Step>) -> Result<(), Error> {
+        let (axis, test) = match self.peek() {
+            Some(Token::Dot) => (Axis::SelfNode, NodeTest::Node),
+            Some(Token::DotDot) => (Axis::Parent, NodeTest::Node),
+            _ => {
+                let axis = if self.eat(&Token::At) {
+                    Axis::Attribute
+                } else if self.peek_at(1) == Some(&Token::ColonColon) {
+                    let axis = match self.peek() {
+                        Some(Token::Name(None, name)) => Axis::from_name(name),
+                        _ => None,
+                    };
+                    let axis = axis.ok_or_else(|| self.error("Unknown axis"))?;
+                    self.index += 2;
+                    axis
+                } else {
+                    Axis::Child
+                };
+                let test = self.node_test()?;
+                let predicates = self.predicates()?;
+                steps.push(Step {
+                    axis,
+                    test,
+                    predicates,
+                });
+                return Ok(());
+            },
+        };
+        self.index += 1;
+        steps.push(Step {
+            axis,
+            test,
+            predicates: vec![],
+        });
+        Ok(())
+    }
+
+    fn node_test(&mut self) -> Result<NodeTest, Error> {
+        let position = self.position();
+        match self.next() {
+            Some(Token::Star) => Ok(NodeTest::Name {
+                prefix: None,
+                local: None,
+            }),
+            Some(Token::PrefixStar(prefix)) => Ok(NodeTest::Name {
+                prefix: Some(Prefix::from(prefix)),
+                local: None,
+            }),
+            Some(Token::Name(None, ref name)) if self.eat(&Token::LeftParen) => {
+                let test = match &**name {
+                    "node" => NodeTest::Node,
+                    "text" => NodeTest::Text,
+                    "comment" => NodeTest::Comment,
+                    "processing-instruction" => match self.peek() {
+                        Some(Token::Literal(target)) => {
+                            let target = target.clone();
+                            self.index += 1;
+                            NodeTest::ProcessingInstruction(Some(target))
+                        },
+                        _ => NodeTest::ProcessingInstruction(None),
+                    },
+                    _ => return Err(syntax_error(position, "Expected a node test")),
+                };
+                self.expect(&Token::RightParen, "Expected ')'")?;
+                Ok(test)
+            },
+            Some(Token::Name(prefix, local)) => Ok(NodeTest::Name {
+                prefix: prefix.map(Prefix::from),
+                local: Some(LocalName::from(local)),
+            }),
+            _ => Err(syntax_error(position, "Expected a node test")),
+        }
+    }
+}
+
+fn is_node_type(name: &str) -> bool {
+    matches!(name, "node" | "text" | "comment" | "processing-instruction")
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Error;
+    use super::{parse, ArithmeticOp, Axis, Expr, NodeTest, PathStart, Step};
+    use crate::LocalName;
+
+    fn child(name: &str) -> Step {
+        Step {
+            axis: Axis::Child,
+            test: NodeTest::Name {
+                prefix: None,
+                local: Some(LocalName::from(name)),
+            },
+            predicates: vec![],
+        }
+    }
+
+    #[test]
+    fn operator_names() {
+        // `div` and `*` are operators only where an operator is expected.
+        assert_eq!(
+            parse("div div * * *").unwrap(),
+            Expr::Arithmetic(
+                ArithmeticOp::Multiply,
+                Box::new(Expr::Arithmetic(
+                    ArithmeticOp::Divide,
+                    Box::new(Expr::Path(PathStart::Context, vec![child("div")])),
+                    Box::new(Expr::Path(
+                        PathStart::Context,
+                        vec![Step {
+                            axis: Axis::Child,
+                            test: NodeTest::Name {
+                                prefix: None,
+                                local: None
+                            },
+                            predicates: vec![],
+                        }]
+                    )),
+                )),
+                Box::new(Expr::Path(
+                    PathStart::Context,
+                    vec![Step {
+                        axis: Axis::Child,
+                        test: NodeTest::Name {
+                            prefix: None,
+                            local: None
+                        },
+                        predicates: vec![],
+                    }]
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn abbreviations() {
+        assert_eq!(
+            parse("//a").unwrap(),
+            Expr::Path(
+                PathStart::Root,
+                vec![
+                    Step {
+                        axis: Axis::DescendantOrSelf,
+                        test: NodeTest::Node,
+                        predicates: vec![],
+                    },
+                    child("a"),
+                ]
+            )
+        );
+        assert_eq!(parse("/").unwrap(), Expr::Path(PathStart::Root, vec![]));
+        assert_eq!(parse(" 1.5 ").unwrap(), Expr::Number(1.5));
+        assert_eq!(parse(".5").unwrap(), Expr::Number(0.5));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let position = |source| match parse(source) {
+            Err(Error::Syntax { position, .. }) => position,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(position("a["), 2);
+        assert_eq!(position("a]"), 1);
+        assert_eq!(position("'a"), 0);
+        assert_eq!(position("foo::a"), 0);
+        assert_eq!(position("a/"), 2);
+        assert_eq!(position("a # b"), 2);
+        assert_eq!(position("text(1)"), 5);
+    }
+}
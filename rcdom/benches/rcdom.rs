@@ -0,0 +1,75 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::{black_box, BatchSize, Criterion};
+
+use html5ever::tendril::*;
+use markup5ever_rcdom::{Handle, Node, NodeData, RcDom};
+
+/// Benchmark parsing a document whose body has `count` children, each made from `repeated`.
+fn run_bench(c: &mut Criterion, name: &str, prefix: &str, repeated: &str, count: usize) {
+    let input = format!("{}{}", prefix, repeated.repeat(count));
+    let test_name = format!("rcdom parsing {} x{}", name, count);
+
+    c.bench_function(&test_name, move |b| {
+        b.iter(|| {
+            let dom =
+                html5ever::parse_document(RcDom::default(), Default::default()).one(input.as_str());
+            black_box(dom);
+        })
+    });
+}
+
+fn comment() -> Handle {
+    Node::new(NodeData::Comment {
+        contents: StrTendril::from_slice("x"),
+    })
+}
+
+/// A node with `count` children.
+fn wide_parent(count: usize) -> Handle {
+    let parent = comment();
+    for _ in 0..count {
+        parent.append_child(comment());
+    }
+    parent
+}
+
+/// Benchmark changing the front of a node with `count` children, which renumbers all of them.
+fn run_front_bench(c: &mut Criterion, count: usize) {
+    c.bench_function(&format!("rcdom insert at front x{}", count), move |b| {
+        b.iter_batched(
+            || (wide_parent(count), comment()),
+            |(parent, child)| {
+                let first = parent.children.borrow()[0].clone();
+                parent.insert_before(child, &first);
+                parent
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function(&format!("rcdom remove at front x{}", count), move |b| {
+        b.iter_batched(
+            || wide_parent(count),
+            |parent| {
+                let first = parent.children.borrow()[0].clone();
+                first.remove();
+                parent
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn rcdom_benchmark(c: &mut Criterion) {
+    // Each <div> is inserted before the table, next to the earlier ones.
+    run_bench(c, "foster parenting", "<table>", "<div>x</div>", 10000);
+    // Each </b> makes the adoption agency move the paragraph out of the <b>.
+    run_bench(c, "misnested formatting", "", "<b><p>x</b>", 10000);
+    run_bench(c, "wide list", "<ul>", "<li>x", 10000);
+    run_front_bench(c, 10000);
+}
+
+criterion_group!(benches, rcdom_benchmark);
+criterion_main!(benches);
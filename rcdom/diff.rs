@@ -0,0 +1,902 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Structural diffs between two trees.
+//!
+//! `diff` compares two trees and returns an edit script: the nodes inserted, removed and moved,
+//! the attributes added, changed and removed, the text that changed, and the elements whose
+//! prefix changed. The script prints as a report with one edit per line, and `Diff::apply` runs
+//! it against a copy of the old tree to turn it into the new one.
+//!
+//! Children are matched by kind and, for elements, by namespace and local name, keeping as many
+//! identical subtrees and then as many others as possible in order. Nodes are found again by
+//! their path from the root, which is only meaningful part way through the script: each edit's
+//! path is where the node is once the edits before it have been applied.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter;
+use std::mem;
+use std::ops::Range;
+
+use mac::{_tt_as_expr_hack, matches};
+use markup5ever::{Attribute, QualName};
+use tendril::StrTendril;
+
+use super::{append, insert, Handle, Node, NodeData};
+
+/// The largest number of pairs of children compared to find those that stay in order. Beyond
+/// it, only children that are the same at the start and end of both lists are kept in order, and
+/// the rest are moved.
+const MAX_COMPARISONS: usize = 1 << 20;
+
+/// Options for `diff`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiffOpts {
+    /// Leave out text nodes that are only whitespace, so that changes in indentation don't
+    /// count.
+    pub ignore_whitespace: bool,
+
+    /// Don't count it as a change when an element has the same attributes in another order.
+    pub ignore_attribute_order: bool,
+}
+
+/// One step down from a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// The child at this position.
+    Child(usize),
+    /// The contents of a `<template>` element.
+    TemplateContents,
+}
+
+/// The steps from the root of a tree down to a node. It prints like `/1/0/#template/2`, or `/`
+/// for the root.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Path(pub Vec<Step>);
+
+impl Path {
+    fn child(&self, i: usize) -> Path {
+        let mut steps = self.0.clone();
+        steps.push(Step::Child(i));
+        Path(steps)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("/");
+        }
+        for step in &self.0 {
+            match *step {
+                Step::Child(i) => write!(f, "/{}", i)?,
+                Step::TemplateContents => f.write_str("/#template")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One change to a tree. Each path is where the node is after the edits before this one.
+#[derive(Clone, Debug)]
+pub enum Edit {
+    /// Insert a copy of a node from the new tree, with its descendants, so that it ends up at
+    /// `path`.
+    Insert { path: Path, node: Handle },
+
+    /// Remove a node and its descendants. `node` is the node in the old tree.
+    Remove { path: Path, node: Handle },
+
+    /// Take a node out of its parent and put it back so that it ends up at `to`.
+    Move { from: Path, to: Path },
+
+    /// Change the contents of a text node, comment, CDATA section or processing instruction.
+    SetText {
+        path: Path,
+        old: StrTendril,
+        new: StrTendril,
+    },
+
+    /// Add an attribute, or change the prefix or value of the attribute with the same namespace
+    /// and local name. `old` is the value it had, if any.
+    SetAttribute {
+        path: Path,
+        name: QualName,
+        old: Option<StrTendril>,
+        value: StrTendril,
+    },
+
+    /// Remove the attribute with the same namespace and local name as `name`.
+    RemoveAttribute {
+        path: Path,
+        name: QualName,
+        old: StrTendril,
+    },
+
+    /// Put an element's attributes in this order.
+    ReorderAttributes { path: Path, names: Vec<QualName> },
+
+    /// Change the prefix of an element, which is replaced by a new element with the same
+    /// attributes, contents and children.
+    Rename {
+        path: Path,
+        old: QualName,
+        new: QualName,
+    },
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Edit::Insert { ref path, ref node } => {
+                write!(f, "insert {} at {}", Describe(node), path)
+            },
+            Edit::Remove { ref path, ref node } => {
+                write!(f, "remove {} at {}", Describe(node), path)
+            },
+            Edit::Move { ref from, ref to } => write!(f, "move {} to {}", from, to),
+            Edit::SetText {
+                ref path,
+                ref old,
+                ref new,
+            } => write!(f, "change text at {}: {:?} -> {:?}", path, &**old, &**new),
+            Edit::SetAttribute {
+                ref path,
+                ref name,
+                old: Some(ref old),
+                ref value,
+            } => write!(
+                f,
+                "change attribute {} at {}: {:?} -> {:?}",
+                Name(name),
+                path,
+                &**old,
+                &**value
+            ),
+            Edit::SetAttribute {
+                ref path,
+                ref name,
+                old: None,
+                ref value,
+            } => write!(f, "add attribute {}={:?} at {}", Name(name), &**value, path),
+            Edit::RemoveAttribute {
+                ref path,
+                ref name,
+                ref old,
+            } => write!(
+                f,
+                "remove attribute {}={:?} at {}",
+                Name(name),
+                &**old,
+                path
+            ),
+            Edit::ReorderAttributes {
+                ref path,
+                ref names,
+            } => {
+                write!(f, "reorder attributes at {}:", path)?;
+                for name in names {
+                    write!(f, " {}", Name(name))?;
+                }
+                Ok(())
+            },
+            Edit::Rename {
+                ref path,
+                ref old,
+                ref new,
+            } => write!(f, "rename <{}> at {} to <{}>", Name(old), path, Name(new)),
+        }
+    }
+}
+
+/// A qualified name as written, with `{namespace}` in front of the local name when it's in a
+/// namespace other than HTML's and has no prefix.
+struct Name<'a>(&'a QualName);
+
+impl fmt::Display for Name<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.0;
+        match name.prefix {
+            Some(ref prefix) => write!(f, "{}:", prefix)?,
+            None if name.ns != ns!() && name.ns != ns!(html) => write!(f, "{{{}}}", name.ns)?,
+            None => (),
+        }
+        f.write_str(&name.local)
+    }
+}
+
+/// A short description of a node for reports.
+struct Describe<'a>(&'a Handle);
+
+impl fmt::Display for Describe<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.data {
+            NodeData::Document => f.write_str("#document"),
+            NodeData::Doctype { ref name, .. } => write!(f, "<!DOCTYPE {}>", name),
+            NodeData::Text { ref contents } => write!(f, "{:?}", &**contents.borrow()),
+            NodeData::Comment { ref contents } => write!(f, "<!--{}-->", contents),
+            NodeData::CData { ref contents } => write!(f, "<![CDATA[{}]]>", contents),
+            NodeData::Element { ref name, .. } => write!(f, "<{}>", Name(name)),
+            NodeData::ProcessingInstruction {
+                ref target,
+                ref contents,
+            } => write!(f, "<?{} {}?>", target, contents),
+        }
+    }
+}
+
+/// The differences between two trees, as the edits that turn the first into the second.
+#[derive(Clone, Debug, Default)]
+pub struct Diff {
+    pub edits: Vec<Edit>,
+}
+
+impl Diff {
+    /// Whether the trees are the same.
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Apply the edits to a tree, which should be the old tree or a copy of it. Inserted nodes
+    /// are copied, so the new tree is left alone.
+    ///
+    /// Stops at the first edit whose path doesn't lead to a suitable node, leaving the edits
+    /// before it applied.
+    pub fn apply(&self, root: &Handle) -> Result<(), Error> {
+        for (i, edit) in self.edits.iter().enumerate() {
+            apply(root, edit).map_err(|message| Error {
+                edit: i,
+                message: message.into(),
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for edit in &self.edits {
+            writeln!(f, "{}", edit)?;
+        }
+        Ok(())
+    }
+}
+
+/// An edit that couldn't be applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    /// The position of the edit in `Diff::edits`.
+    pub edit: usize,
+    pub message: Cow<'static, str>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in edit {}", self.message, self.edit)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Find the edits that turn `old` into `new`. The roots are compared with each other, whatever
+/// they are, and only their descendants can be inserted, removed or moved.
+pub fn diff(old: &Handle, new: &Handle, opts: &DiffOpts) -> Diff {
+    let hashes = hash_trees(&[old, new], opts);
+    let mut edits = vec![];
+    let mut pairs = vec![(old.clone(), new.clone(), Path::default())];
+    while let Some((old, new, path)) = pairs.pop() {
+        diff_data(&old, &new, &path, opts, &mut edits);
+
+        let first = pairs.len();
+        diff_children(&old, &new, &path, opts, &hashes, &mut edits, &mut pairs);
+        pairs[first..].reverse();
+
+        if let Some((old_contents, new_contents)) = template_contents(&old, &new) {
+            let mut path = path;
+            path.0.push(Step::TemplateContents);
+            pairs.push((old_contents, new_contents, path));
+        }
+    }
+    Diff { edits }
+}
+
+/// The template contents of two elements, if both have them.
+fn template_contents(old: &Handle, new: &Handle) -> Option<(Handle, Handle)> {
+    let contents = |node: &Handle| match node.data {
+        NodeData::Element {
+            ref template_contents,
+            ..
+        } => template_contents.borrow().clone(),
+        _ => None,
+    };
+    Some((contents(old)?, contents(new)?))
+}
+
+/// What has to be the same for two nodes to be matched with each other.
+#[derive(PartialEq)]
+enum Key<'a> {
+    Document,
+    Doctype(&'a StrTendril, &'a StrTendril, &'a StrTendril),
+    Text,
+    Comment,
+    CData,
+    Element(&'a QualName),
+    ProcessingInstruction(&'a StrTendril),
+}
+
+fn key(node: &Handle) -> Key<'_> {
+    match node.data {
+        NodeData::Document => Key::Document,
+        NodeData::Doctype {
+            ref name,
+            ref public_id,
+            ref system_id,
+        } => Key::Doctype(name, public_id, system_id),
+        NodeData::Text { .. } => Key::Text,
+        NodeData::Comment { .. } => Key::Comment,
+        NodeData::CData { .. } => Key::CData,
+        // The prefix doesn't matter.
+        NodeData::Element { ref name, .. } => Key::Element(name),
+        NodeData::ProcessingInstruction { ref target, .. } => Key::ProcessingInstruction(target),
+    }
+}
+
+fn same_key(a: &Handle, b: &Handle) -> bool {
+    match (key(a), key(b)) {
+        (Key::Element(a), Key::Element(b)) => a.expanded() == b.expanded(),
+        (a, b) => a == b,
+    }
+}
+
+fn is_ignored(node: &Handle, opts: &DiffOpts) -> bool {
+    match node.data {
+        NodeData::Text { ref contents } if opts.ignore_whitespace => contents
+            .borrow()
+            .chars()
+            .all(|c| matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')),
+        _ => false,
+    }
+}
+
+/// A node's children that take part in the diff, with their positions among all its children.
+fn children(node: &Handle, opts: &DiffOpts) -> Vec<(usize, Handle)> {
+    node.children
+        .borrow()
+        .iter()
+        .enumerate()
+        .filter(|&(_, child)| !is_ignored(child, opts))
+        .map(|(i, child)| (i, child.clone()))
+        .collect()
+}
+
+/// The text of a node that has some besides its children.
+fn text(node: &Handle) -> Option<StrTendril> {
+    match node.data {
+        NodeData::Text { ref contents } => Some(contents.borrow().clone()),
+        NodeData::Comment { ref contents } |
+        NodeData::CData { ref contents } |
+        NodeData::ProcessingInstruction { ref contents, .. } => Some(contents.clone()),
+        _ => None,
+    }
+}
+
+fn attrs(node: &Handle) -> Option<&RefCell<Vec<Attribute>>> {
+    match node.data {
+        NodeData::Element { ref attrs, .. } => Some(attrs),
+        _ => None,
+    }
+}
+
+/// Compare the text or attributes of two matched nodes.
+fn diff_data(old: &Handle, new: &Handle, path: &Path, opts: &DiffOpts, edits: &mut Vec<Edit>) {
+    if !same_key(old, new) {
+        return;
+    }
+    if let (Some(old), Some(new)) = (text(old), text(new)) {
+        if old != new {
+            edits.push(Edit::SetText {
+                path: path.clone(),
+                old,
+                new,
+            });
+        }
+    }
+    if let (NodeData::Element { name: ref old, .. }, NodeData::Element { name: ref new, .. }) =
+        (&old.data, &new.data)
+    {
+        if old.prefix != new.prefix {
+            edits.push(Edit::Rename {
+                path: path.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            });
+        }
+    }
+    if let (Some(old_attrs), Some(new_attrs)) = (attrs(old), attrs(new)) {
+        let (old_attrs, new_attrs) = (old_attrs.borrow(), new_attrs.borrow());
+        let find = |attrs: &[Attribute], name: &QualName| {
+            attrs
+                .iter()
+                .position(|attr| attr.name.expanded() == name.expanded())
+        };
+
+        // The order the attributes end up in: removing one keeps the others in order, and
+        // adding one puts it at the end.
+        let mut order = vec![];
+        for attr in old_attrs.iter() {
+            if find(&new_attrs, &attr.name).is_some() {
+                order.push(attr.name.expanded());
+            } else {
+                edits.push(Edit::RemoveAttribute {
+                    path: path.clone(),
+                    name: attr.name.clone(),
+                    old: attr.value.clone(),
+                });
+            }
+        }
+        for attr in new_attrs.iter() {
+            let old = find(&old_attrs, &attr.name).map(|i| &old_attrs[i]);
+            if old == Some(attr) {
+                continue;
+            }
+            if old.is_none() {
+                order.push(attr.name.expanded());
+            }
+            edits.push(Edit::SetAttribute {
+                path: path.clone(),
+                name: attr.name.clone(),
+                old: old.map(|old| old.value.clone()),
+                value: attr.value.clone(),
+            });
+        }
+        let new_order: Vec<_> = new_attrs.iter().map(|attr| attr.name.expanded()).collect();
+        if !opts.ignore_attribute_order && order != new_order {
+            edits.push(Edit::ReorderAttributes {
+                path: path.clone(),
+                names: new_attrs.iter().map(|attr| attr.name.clone()).collect(),
+            });
+        }
+    }
+}
+
+/// Hashes of subtrees, by node, such that subtrees that would give no edits have the same hash.
+type Hashes = HashMap<*const Node, u64>;
+
+/// Hash every subtree of some trees, from the leaves up.
+fn hash_trees(roots: &[&Handle], opts: &DiffOpts) -> Hashes {
+    let mut hashes = Hashes::new();
+    let mut stack: Vec<(Handle, bool)> = roots.iter().map(|&root| (root.clone(), false)).collect();
+    while let Some((node, visited)) = stack.pop() {
+        if !visited {
+            stack.push((node.clone(), true));
+            stack.extend(
+                node.children
+                    .borrow()
+                    .iter()
+                    .map(|child| (child.clone(), false)),
+            );
+            if let NodeData::Element {
+                ref template_contents,
+                ..
+            } = node.data
+            {
+                stack.extend(
+                    template_contents
+                        .borrow()
+                        .clone()
+                        .map(|contents| (contents, false)),
+                );
+            }
+            continue;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        match node.data {
+            NodeData::Document => 0.hash(&mut hasher),
+            NodeData::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+            } => (1, &**name, &**public_id, &**system_id).hash(&mut hasher),
+            NodeData::Text { ref contents } => (2, &**contents.borrow()).hash(&mut hasher),
+            NodeData::Comment { ref contents } => (3, &**contents).hash(&mut hasher),
+            NodeData::CData { ref contents } => (4, &**contents).hash(&mut hasher),
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ref template_contents,
+                ..
+            } => {
+                (5, name).hash(&mut hasher);
+                let mut attrs = attrs
+                    .borrow()
+                    .iter()
+                    .map(|attr| {
+                        let mut hasher = DefaultHasher::new();
+                        (&attr.name, &*attr.value).hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .collect::<Vec<_>>();
+                if opts.ignore_attribute_order {
+                    attrs.sort_unstable();
+                }
+                attrs.hash(&mut hasher);
+                template_contents
+                    .borrow()
+                    .as_ref()
+                    .map(|contents| hashes[&(&**contents as *const Node)])
+                    .hash(&mut hasher);
+            },
+            NodeData::ProcessingInstruction {
+                ref target,
+                ref contents,
+            } => (6, &**target, &**contents).hash(&mut hasher),
+        }
+        for child in node.children.borrow().iter() {
+            if !is_ignored(child, opts) {
+                hashes[&(&**child as *const Node)].hash(&mut hasher);
+            }
+        }
+        hashes.insert(&*node as *const Node, hasher.finish());
+    }
+    hashes
+}
+
+/// Add the pairs of positions of the longest common subsequence of two ranges, by `same`, in
+/// order.
+fn common_subsequence(
+    old: Range<usize>,
+    new: Range<usize>,
+    same: &dyn Fn(usize, usize) -> bool,
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    let (mut start, mut old_end, mut new_end) = (0, old.end, new.end);
+    while old.start + start < old_end &&
+        new.start + start < new_end &&
+        same(old.start + start, new.start + start)
+    {
+        pairs.push((old.start + start, new.start + start));
+        start += 1;
+    }
+    let (old_start, new_start) = (old.start + start, new.start + start);
+    let mut suffix = vec![];
+    while old_start < old_end && new_start < new_end && same(old_end - 1, new_end - 1) {
+        old_end -= 1;
+        new_end -= 1;
+        suffix.push((old_end, new_end));
+    }
+
+    let (n, m) = (old_end - old_start, new_end - new_start);
+    if n > 0 && m > 0 && n.saturating_mul(m) <= MAX_COMPARISONS {
+        // lengths[i][j] is the length of the longest common subsequence of the middle parts
+        // from old_start + i and new_start + j on.
+        let mut lengths = vec![vec![0u32; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lengths[i][j] = if same(old_start + i, new_start + j) {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if same(old_start + i, new_start + j) {
+                pairs.push((old_start + i, new_start + j));
+                i += 1;
+                j += 1;
+            } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+    pairs.extend(suffix.into_iter().rev());
+}
+
+/// A child of the old node while its children are being rearranged.
+#[derive(Clone, Copy, PartialEq)]
+enum Entry {
+    /// A child that takes part in the diff, by its position in the filtered list.
+    Old(usize),
+    /// A child that is already in place, or an ignored text node.
+    Other,
+}
+
+/// Compare the children of two matched nodes, adding the edits that rearrange them and the
+/// pairs of children to compare next, in order.
+fn diff_children(
+    old: &Handle,
+    new: &Handle,
+    path: &Path,
+    opts: &DiffOpts,
+    hashes: &Hashes,
+    edits: &mut Vec<Edit>,
+    pairs: &mut Vec<(Handle, Handle, Path)>,
+) {
+    let old_children = children(old, opts);
+    let new_children = children(new, opts);
+    if old_children.is_empty() && new_children.is_empty() {
+        return;
+    }
+
+    let identical = |i: usize, j: usize| {
+        let (old_child, new_child) = (&*old_children[i].1, &*new_children[j].1);
+        hashes[&(old_child as *const Node)] == hashes[&(new_child as *const Node)]
+    };
+    let similar = |i: usize, j: usize| same_key(&old_children[i].1, &new_children[j].1);
+
+    // Keep as many identical children as possible in order, then as many of the same kind as
+    // possible between them.
+    let (n, m) = (old_children.len(), new_children.len());
+    let mut unchanged = vec![];
+    common_subsequence(0..n, 0..m, &identical, &mut unchanged);
+    let mut in_order = vec![];
+    let (mut old_start, mut new_start) = (0, 0);
+    for (i, j) in unchanged.into_iter().chain(iter::once((n, m))) {
+        common_subsequence(old_start..i, new_start..j, &similar, &mut in_order);
+        in_order.push((i, j));
+        old_start = i + 1;
+        new_start = j + 1;
+    }
+    in_order.pop();
+
+    let mut old_match = vec![None; n];
+    let mut new_match = vec![None; m];
+    for (i, j) in in_order {
+        old_match[i] = Some(j);
+        new_match[j] = Some(i);
+    }
+
+    // The rest are moves if there's a node to move, first looking for an identical one.
+    for same in &[&identical as &dyn Fn(usize, usize) -> bool, &similar] {
+        for (j, matched) in new_match.iter_mut().enumerate() {
+            if matched.is_some() {
+                continue;
+            }
+            if let Some(i) = (0..n).find(|&i| old_match[i].is_none() && same(i, j)) {
+                old_match[i] = Some(j);
+                *matched = Some(i);
+            }
+        }
+    }
+
+    // Remove from the end, so the positions of the others don't change.
+    for (i, &(position, ref child)) in old_children.iter().enumerate().rev() {
+        if old_match[i].is_none() {
+            edits.push(Edit::Remove {
+                path: path.child(position),
+                node: child.clone(),
+            });
+        }
+    }
+
+    let mut current = vec![Entry::Other; old.children.borrow().len()];
+    for (i, &(position, _)) in old_children.iter().enumerate() {
+        current[position] = Entry::Old(i);
+    }
+    let mut removed = old_children
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| old_match[i].is_none())
+        .map(|(_, &(position, _))| position)
+        .collect::<Vec<_>>();
+    removed.reverse();
+    for position in removed {
+        current.remove(position);
+    }
+
+    // Put the new children in order. Everything before `next` is in place, or ignored.
+    let mut next = 0;
+    for (j, (_, new_child)) in new_children.iter().enumerate() {
+        match new_match[j] {
+            Some(i) => {
+                let entry = Entry::Old(i);
+                let mut position = next;
+                while current[position] == Entry::Other {
+                    position += 1;
+                }
+                if current[position] != entry {
+                    position += current[position..]
+                        .iter()
+                        .position(|&e| e == entry)
+                        .unwrap();
+                    current.remove(position);
+                    current.insert(next, Entry::Other);
+                    edits.push(Edit::Move {
+                        from: path.child(position),
+                        to: path.child(next),
+                    });
+                    position = next;
+                } else {
+                    current[position] = Entry::Other;
+                }
+                pairs.push((
+                    old_children[i].1.clone(),
+                    new_child.clone(),
+                    path.child(position),
+                ));
+                next = position + 1;
+            },
+            None => {
+                current.insert(next, Entry::Other);
+                edits.push(Edit::Insert {
+                    path: path.child(next),
+                    node: new_child.clone(),
+                });
+                next += 1;
+            },
+        }
+    }
+}
+
+/// Find the node at the end of a path.
+fn find(root: &Handle, path: &[Step]) -> Result<Handle, &'static str> {
+    let mut node = root.clone();
+    for step in path {
+        node = match *step {
+            Step::Child(i) => node.children.borrow().get(i).cloned(),
+            Step::TemplateContents => match node.data {
+                NodeData::Element {
+                    ref template_contents,
+                    ..
+                } => template_contents.borrow().clone(),
+                _ => None,
+            },
+        }
+        .ok_or("no node at path")?;
+    }
+    Ok(node)
+}
+
+/// Put a parentless node where `path` says.
+fn place(root: &Handle, path: &Path, node: Handle) -> Result<(), &'static str> {
+    let (parent, i) = match path.0.split_last() {
+        Some((&Step::Child(i), parent)) => (find(root, parent)?, i),
+        _ => return Err("path doesn't end at a child"),
+    };
+    if i > parent.children.borrow().len() {
+        return Err("no node at path");
+    }
+    insert(&parent, i, node);
+    Ok(())
+}
+
+fn apply(root: &Handle, edit: &Edit) -> Result<(), &'static str> {
+    match *edit {
+        Edit::Insert { ref path, ref node } => place(root, path, node.deep_clone()),
+        Edit::Remove { ref path, .. } => {
+            if !matches!(path.0.last(), Some(Step::Child(_))) {
+                return Err("path doesn't end at a child");
+            }
+            find(root, &path.0)?.remove();
+            Ok(())
+        },
+        Edit::Move { ref from, ref to } => {
+            if !matches!(from.0.last(), Some(Step::Child(_))) {
+                return Err("path doesn't end at a child");
+            }
+            let node = find(root, &from.0)?;
+            node.remove();
+            place(root, to, node)
+        },
+        Edit::SetText {
+            ref path, ref new, ..
+        } => {
+            let node = find(root, &path.0)?;
+            let data = match node.data {
+                NodeData::Text { ref contents } => {
+                    *contents.borrow_mut() = new.clone();
+                    return Ok(());
+                },
+                NodeData::Comment { .. } => NodeData::Comment {
+                    contents: new.clone(),
+                },
+                NodeData::CData { .. } => NodeData::CData {
+                    contents: new.clone(),
+                },
+                NodeData::ProcessingInstruction { ref target, .. } => {
+                    NodeData::ProcessingInstruction {
+                        target: target.clone(),
+                        contents: new.clone(),
+                    }
+                },
+                _ => return Err("node has no text"),
+            };
+            if path.0.is_empty() {
+                return Err("can't replace the root");
+            }
+            node.replace_with(Node::new(data));
+            Ok(())
+        },
+        Edit::SetAttribute {
+            ref path,
+            ref name,
+            ref value,
+            ..
+        } => {
+            let node = find(root, &path.0)?;
+            let mut attrs = attrs(&node).ok_or("node isn't an element")?.borrow_mut();
+            let attr = Attribute {
+                name: name.clone(),
+                value: value.clone(),
+            };
+            match attrs
+                .iter_mut()
+                .find(|attr| attr.name.expanded() == name.expanded())
+            {
+                Some(existing) => *existing = attr,
+                None => attrs.push(attr),
+            }
+            Ok(())
+        },
+        Edit::RemoveAttribute {
+            ref path, ref name, ..
+        } => {
+            let node = find(root, &path.0)?;
+            let mut attrs = attrs(&node).ok_or("node isn't an element")?.borrow_mut();
+            attrs.retain(|attr| attr.name.expanded() != name.expanded());
+            Ok(())
+        },
+        Edit::ReorderAttributes {
+            ref path,
+            ref names,
+        } => {
+            let node = find(root, &path.0)?;
+            let mut attrs = attrs(&node).ok_or("node isn't an element")?.borrow_mut();
+            // Attributes that aren't listed go at the end.
+            attrs.sort_by_key(|attr| {
+                names
+                    .iter()
+                    .position(|name| name.expanded() == attr.name.expanded())
+                    .unwrap_or(names.len())
+            });
+            Ok(())
+        },
+        Edit::Rename {
+            ref path, ref new, ..
+        } => {
+            let node = find(root, &path.0)?;
+            let data = match node.data {
+                NodeData::Element {
+                    ref attrs,
+                    ref template_contents,
+                    mathml_annotation_xml_integration_point,
+                    ref shadow_root,
+                    ..
+                } => NodeData::Element {
+                    name: new.clone(),
+                    attrs: RefCell::new(attrs.borrow().clone()),
+                    template_contents: RefCell::new(template_contents.borrow_mut().take()),
+                    mathml_annotation_xml_integration_point,
+                    shadow_root: RefCell::new(shadow_root.borrow_mut().take()),
+                },
+                _ => return Err("node isn't an element"),
+            };
+            if path.0.is_empty() {
+                return Err("can't replace the root");
+            }
+            let renamed = Node::new(data);
+            let children = mem::take(&mut *node.children.borrow_mut());
+            for child in children {
+                child.parent.set(None);
+                append(&renamed, child);
+            }
+            node.replace_with(renamed);
+            Ok(())
+        },
+    }
+}
@@ -55,7 +55,7 @@ fn walk(indent: usize, handle: &Handle) {
             println!(">");
         },
 
-        NodeData::ProcessingInstruction { .. } => unreachable!(),
+        NodeData::ProcessingInstruction { .. } | NodeData::CData { .. } => unreachable!(),
     }
 
     for child in node.children.borrow().iter() {
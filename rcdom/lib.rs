@@ -31,11 +31,20 @@
 //! This implementation stores the information associated with each node once, and then hands out
 //! refs to children. The nodes themselves are reference-counted to avoid copying - you can create
 //! a new ref and then a node will outlive the document. Nodes own their children, but only have
-//! weak references to their parents.
+//! weak references to their parents. Methods on `Node` such as `append_child`, `replace_with` and
+//! `set_attribute` change the tree in place, and `RcDom` and `HandleNavigator` implement
+//! `TreeNavigator` from `markup5ever::traverse` for walking it.
+//!
+//! The `sync_dom` module copies a document parsed with `markup5ever_arena` into a DOM that can't
+//! be changed but can be shared between threads, and the `text` module extracts readable text
+//! from a tree, like `innerText`. The `sanitize` module cleans untrusted HTML against allowlists,
+//! and the `diff` module compares two trees and patches one into the other. With the
+//! `serde_support` feature, `RcDom` implements `Serialize` and `Deserialize`.
 //!
 //! [tree structure]: https://en.wikipedia.org/wiki/Tree_(data_structure)
 //! [dom wiki]: https://en.wikipedia.org/wiki/Document_Object_Model
 
+#[macro_use]
 extern crate markup5ever;
 extern crate tendril;
 
@@ -51,14 +60,27 @@ use std::rc::{Rc, Weak};
 use tendril::StrTendril;
 
 use markup5ever::interface::tree_builder;
+use markup5ever::interface::tree_builder::XmlDeclaration;
 use markup5ever::interface::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use markup5ever::select;
 use markup5ever::serialize::TraversalScope;
 use markup5ever::serialize::TraversalScope::{ChildrenOnly, IncludeNode};
-use markup5ever::serialize::{Serialize, Serializer};
+use markup5ever::serialize::{Serialize, Serializer, ShadowRootInit};
+use markup5ever::traverse;
 use markup5ever::Attribute;
 use markup5ever::ExpandedName;
 use markup5ever::QualName;
 
+pub mod diff;
+pub mod sanitize;
+#[cfg(feature = "serde_support")]
+mod serde_support;
+mod streaming;
+pub mod sync_dom;
+pub mod text;
+
+pub use crate::streaming::StreamingSerializer;
+
 /// The different kinds of nodes in the DOM.
 #[derive(Debug)]
 pub enum NodeData {
@@ -81,6 +103,9 @@ pub enum NodeData {
     /// A comment.
     Comment { contents: StrTendril },
 
+    /// A CDATA section.
+    CData { contents: StrTendril },
+
     /// An element with attributes.
     Element {
         name: QualName,
@@ -95,6 +120,11 @@ pub enum NodeData {
         ///
         /// [HTML integration point]: https://html.spec.whatwg.org/multipage/#html-integration-point
         mathml_annotation_xml_integration_point: bool,
+
+        /// The [shadow root] attached to this element, if it is a shadow host.
+        ///
+        /// [shadow root]: https://dom.spec.whatwg.org/#concept-element-shadow-root
+        shadow_root: RefCell<Option<ShadowRoot>>,
     },
 
     /// A Processing instruction.
@@ -104,7 +134,20 @@ pub enum NodeData {
     },
 }
 
+/// A shadow root attached to an element with `Node::attach_shadow`.
+#[derive(Clone, Debug)]
+pub struct ShadowRoot {
+    /// The shadow root's mode and flags.
+    pub init: ShadowRootInit,
+    /// The root of the shadow tree, a `Document` node whose children are the shadow root's
+    /// children.
+    pub root: Handle,
+}
+
 /// A DOM node.
+///
+/// Change the tree with methods such as `append_child` and `remove`, which keep each node's
+/// `parent` in step with its parent's `children`.
 pub struct Node {
     /// Parent node.
     pub parent: Cell<Option<WeakHandle>>,
@@ -112,6 +155,13 @@ pub struct Node {
     pub children: RefCell<Vec<Handle>>,
     /// Represents this node's data.
     pub data: NodeData,
+    /// This node's position in its parent's `children`. It is checked before use, in case
+    /// `children` has been changed directly.
+    ///
+    /// It makes finding a node's siblings constant-time, but inserting or removing a child has to
+    /// renumber the children after it, so that is still linear in their number, as moving them
+    /// along the `Vec` already is.
+    index: Cell<usize>,
 }
 
 impl Node {
@@ -121,8 +171,257 @@ impl Node {
             data,
             parent: Cell::new(None),
             children: RefCell::new(Vec::new()),
+            index: Cell::new(0),
         })
     }
+
+    /// Attach a shadow root to this element and return the root of the new shadow tree.
+    ///
+    /// Returns `None` if the element is already a shadow host. Panics if this is not an element.
+    pub fn attach_shadow(&self, init: ShadowRootInit) -> Option<Handle> {
+        let shadow_root = match self.data {
+            NodeData::Element {
+                ref shadow_root, ..
+            } => shadow_root,
+            _ => panic!("not an element!"),
+        };
+        let mut shadow_root = shadow_root.borrow_mut();
+        if shadow_root.is_some() {
+            return None;
+        }
+        let root = Node::new(NodeData::Document);
+        *shadow_root = Some(ShadowRoot {
+            init,
+            root: root.clone(),
+        });
+        Some(root)
+    }
+
+    /// Append a node to this node's children, first removing it from its current parent.
+    ///
+    /// Panics if `child` is this node or one of its ancestors.
+    pub fn append_child(self: &Rc<Self>, child: Handle) {
+        self.check_can_insert(&child);
+        remove_from_parent(&child);
+        append(self, child);
+    }
+
+    /// Insert a node into this node's children before `reference`, first removing it from its
+    /// current parent.
+    ///
+    /// Panics if `reference` is not a child of this node, or if `child` is this node or one of
+    /// its ancestors.
+    pub fn insert_before(self: &Rc<Self>, child: Handle, reference: &Handle) {
+        self.check_can_insert(&child);
+        match get_parent_and_index(reference) {
+            Some((ref parent, _)) if Rc::ptr_eq(parent, self) => (),
+            _ => panic!("reference is not a child of this node!"),
+        }
+        if Rc::ptr_eq(&child, reference) {
+            return;
+        }
+        remove_from_parent(&child);
+        let (_, i) = get_parent_and_index(reference).unwrap();
+        insert(self, i, child);
+    }
+
+    /// The node after this one in its parent's children.
+    pub fn next_sibling(self: &Rc<Self>) -> Option<Handle> {
+        let (parent, i) = get_parent_and_index(self)?;
+        let children = parent.children.borrow();
+        children.get(i + 1).cloned()
+    }
+
+    /// The node before this one in its parent's children.
+    pub fn previous_sibling(self: &Rc<Self>) -> Option<Handle> {
+        let (parent, i) = get_parent_and_index(self)?;
+        let children = parent.children.borrow();
+        i.checked_sub(1).map(|i| children[i].clone())
+    }
+
+    /// Remove this node from its parent, if it has one.
+    pub fn remove(self: &Rc<Self>) {
+        remove_from_parent(self);
+    }
+
+    /// Put another node in this node's place in its parent, first removing the replacement from
+    /// its own parent. Does nothing if this node has no parent.
+    ///
+    /// Panics if `replacement` is this node's parent or one of its ancestors.
+    pub fn replace_with(self: &Rc<Self>, replacement: Handle) {
+        if Rc::ptr_eq(self, &replacement) {
+            return;
+        }
+        let parent = match get_parent_and_index(self) {
+            Some((parent, _)) => parent,
+            None => return,
+        };
+        parent.check_can_insert(&replacement);
+        remove_from_parent(&replacement);
+        let (_, i) = get_parent_and_index(self).unwrap();
+        replacement.parent.set(Some(Rc::downgrade(&parent)));
+        replacement.index.set(i);
+        let previous = mem::replace(&mut parent.children.borrow_mut()[i], replacement);
+        previous.parent.set(None);
+    }
+
+    /// Set an attribute, replacing any attribute with the same namespace and local name.
+    ///
+    /// Panics if this is not an element.
+    pub fn set_attribute(&self, name: QualName, value: StrTendril) {
+        let mut attrs = self.attrs().borrow_mut();
+        match attrs
+            .iter_mut()
+            .find(|attr| attr.name.expanded() == name.expanded())
+        {
+            Some(attr) => attr.value = value,
+            None => attrs.push(Attribute { name, value }),
+        }
+    }
+
+    /// Remove the attribute with the same namespace and local name as `name`, returning its value.
+    ///
+    /// Panics if this is not an element.
+    pub fn remove_attribute(&self, name: &QualName) -> Option<StrTendril> {
+        let mut attrs = self.attrs().borrow_mut();
+        let i = attrs
+            .iter()
+            .position(|attr| attr.name.expanded() == name.expanded())?;
+        Some(attrs.remove(i).value)
+    }
+
+    /// Set the contents of a text node, or replace the children of an element or document with a
+    /// single text node, like the DOM's `textContent` setter. Setting an element's text to the
+    /// empty string leaves it with no children.
+    ///
+    /// Panics if this is any other kind of node.
+    pub fn set_text(self: &Rc<Self>, text: StrTendril) {
+        match self.data {
+            NodeData::Text { ref contents } => *contents.borrow_mut() = text,
+            NodeData::Element { .. } | NodeData::Document => {
+                let children = mem::replace(&mut *self.children.borrow_mut(), vec![]);
+                for child in children {
+                    child.parent.set(None);
+                }
+                if !text.is_empty() {
+                    append(
+                        self,
+                        Node::new(NodeData::Text {
+                            contents: RefCell::new(text),
+                        }),
+                    );
+                }
+            },
+            _ => panic!("cannot set the text of this node!"),
+        }
+    }
+
+    /// Copy this node and its descendants, including the contents of templates. The copy has no
+    /// parent, and shadow roots are not copied.
+    pub fn deep_clone(&self) -> Handle {
+        let root = Node::new(clone_data(&self.data));
+        let mut pending = vec![];
+        self.queue_children_for_clone(&root, &mut pending);
+        while let Some((child, parent)) = pending.pop() {
+            let copy = Node::new(clone_data(&child.data));
+            child.queue_children_for_clone(&copy, &mut pending);
+            append(&parent, copy);
+        }
+        root
+    }
+
+    /// Queue this node's children, and its template contents' children, to be copied into
+    /// `copy`. They are queued in reverse so that they are popped in order.
+    fn queue_children_for_clone(&self, copy: &Handle, pending: &mut Vec<(Handle, Handle)>) {
+        for child in self.children.borrow().iter().rev() {
+            pending.push((child.clone(), copy.clone()));
+        }
+        if let (Some(contents), Some(copy)) = (self.template_contents(), copy.template_contents()) {
+            for child in contents.children.borrow().iter().rev() {
+                pending.push((child.clone(), copy.clone()));
+            }
+        }
+    }
+
+    fn attrs(&self) -> &RefCell<Vec<Attribute>> {
+        match self.data {
+            NodeData::Element { ref attrs, .. } => attrs,
+            _ => panic!("not an element!"),
+        }
+    }
+
+    fn template_contents(&self) -> Option<Handle> {
+        match self.data {
+            NodeData::Element {
+                ref template_contents,
+                ..
+            } => template_contents.borrow().clone(),
+            _ => None,
+        }
+    }
+
+    /// Panics if inserting `child` into this node would make a node its own ancestor.
+    fn check_can_insert(self: &Rc<Self>, child: &Handle) {
+        let mut ancestor = Some(self.clone());
+        while let Some(node) = ancestor {
+            if Rc::ptr_eq(&node, child) {
+                panic!("cannot insert a node into itself or its descendants!");
+            }
+            let parent = node.parent.take();
+            node.parent.set(parent.clone());
+            ancestor = parent.and_then(|parent| parent.upgrade());
+        }
+    }
+}
+
+/// Copy a node's data, giving template elements new, empty contents.
+fn clone_data(data: &NodeData) -> NodeData {
+    match *data {
+        NodeData::Document => NodeData::Document,
+        NodeData::Doctype {
+            ref name,
+            ref public_id,
+            ref system_id,
+        } => NodeData::Doctype {
+            name: name.clone(),
+            public_id: public_id.clone(),
+            system_id: system_id.clone(),
+        },
+        NodeData::Text { ref contents } => NodeData::Text {
+            contents: contents.clone(),
+        },
+        NodeData::Comment { ref contents } => NodeData::Comment {
+            contents: contents.clone(),
+        },
+        NodeData::CData { ref contents } => NodeData::CData {
+            contents: contents.clone(),
+        },
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ref template_contents,
+            mathml_annotation_xml_integration_point,
+            ..
+        } => NodeData::Element {
+            name: name.clone(),
+            attrs: attrs.clone(),
+            template_contents: RefCell::new(
+                template_contents
+                    .borrow()
+                    .as_ref()
+                    .map(|_| Node::new(NodeData::Document)),
+            ),
+            mathml_annotation_xml_integration_point,
+            shadow_root: RefCell::new(None),
+        },
+        NodeData::ProcessingInstruction {
+            ref target,
+            ref contents,
+        } => NodeData::ProcessingInstruction {
+            target: target.clone(),
+            contents: contents.clone(),
+        },
+    }
 }
 
 impl Drop for Node {
@@ -131,10 +430,18 @@ impl Drop for Node {
         while let Some(node) = nodes.pop() {
             let children = mem::replace(&mut *node.children.borrow_mut(), vec![]);
             nodes.extend(children.into_iter());
-            if let NodeData::Element { ref template_contents, .. } = node.data {
+            if let NodeData::Element {
+                ref template_contents,
+                ref shadow_root,
+                ..
+            } = node.data
+            {
                 if let Some(template_contents) = template_contents.borrow_mut().take() {
                     nodes.push(template_contents);
                 }
+                if let Some(shadow_root) = shadow_root.borrow_mut().take() {
+                    nodes.push(shadow_root.root);
+                }
             }
         }
     }
@@ -160,7 +467,26 @@ fn append(new_parent: &Handle, child: Handle) {
     let previous_parent = child.parent.replace(Some(Rc::downgrade(new_parent)));
     // Invariant: child cannot have existing parent
     assert!(previous_parent.is_none());
-    new_parent.children.borrow_mut().push(child);
+    let mut children = new_parent.children.borrow_mut();
+    child.index.set(children.len());
+    children.push(child);
+}
+
+/// Insert a parentless node into another node's children at position `i`
+fn insert(new_parent: &Handle, i: usize, child: Handle) {
+    let previous_parent = child.parent.replace(Some(Rc::downgrade(new_parent)));
+    assert!(previous_parent.is_none());
+    let mut children = new_parent.children.borrow_mut();
+    children.insert(i, child);
+    update_indices(&children, i);
+}
+
+/// Update the cached positions of the children from `from` onwards, after an insertion or
+/// removal. This takes time linear in the number of children after `from`.
+fn update_indices(children: &[Handle], from: usize) {
+    for (i, child) in children.iter().enumerate().skip(from) {
+        child.index.set(i);
+    }
 }
 
 /// If the node has a parent, get it and this node's position in its children
@@ -168,15 +494,22 @@ fn get_parent_and_index(target: &Handle) -> Option<(Handle, usize)> {
     if let Some(weak) = target.parent.take() {
         let parent = weak.upgrade().expect("dangling weak pointer");
         target.parent.set(Some(weak));
-        let i = match parent
-            .children
-            .borrow()
-            .iter()
-            .enumerate()
-            .find(|&(_, child)| Rc::ptr_eq(&child, &target))
-        {
-            Some((i, _)) => i,
-            None => panic!("have parent but couldn't find in parent's children!"),
+        let cached = target.index.get();
+        let i = match parent.children.borrow().get(cached) {
+            Some(child) if Rc::ptr_eq(child, target) => cached,
+            _ => {
+                let i = match parent
+                    .children
+                    .borrow()
+                    .iter()
+                    .position(|child| Rc::ptr_eq(child, target))
+                {
+                    Some(i) => i,
+                    None => panic!("have parent but couldn't find in parent's children!"),
+                };
+                target.index.set(i);
+                i
+            },
         };
         Some((parent, i))
     } else {
@@ -196,7 +529,9 @@ fn append_to_existing_text(prev: &Handle, text: &str) -> bool {
 
 fn remove_from_parent(target: &Handle) {
     if let Some((parent, i)) = get_parent_and_index(target) {
-        parent.children.borrow_mut().remove(i);
+        let mut children = parent.children.borrow_mut();
+        children.remove(i);
+        update_indices(&children, i);
         target.parent.set(None);
     }
 }
@@ -211,6 +546,9 @@ pub struct RcDom {
 
     /// The document's quirks mode.
     pub quirks_mode: QuirksMode,
+
+    /// The document's XML declaration, if it has one.
+    pub xml_declaration: Option<XmlDeclaration>,
 }
 
 impl TreeSink for RcDom {
@@ -235,7 +573,11 @@ impl TreeSink for RcDom {
             ..
         } = target.data
         {
-            template_contents.borrow().as_ref().expect("not a template element!").clone()
+            template_contents
+                .borrow()
+                .as_ref()
+                .expect("not a template element!")
+                .clone()
         } else {
             panic!("not a template element!")
         }
@@ -245,6 +587,10 @@ impl TreeSink for RcDom {
         self.quirks_mode = mode;
     }
 
+    fn set_xml_declaration(&mut self, declaration: XmlDeclaration) {
+        self.xml_declaration = Some(declaration);
+    }
+
     fn same_node(&self, x: &Handle, y: &Handle) -> bool {
         Rc::ptr_eq(x, y)
     }
@@ -271,6 +617,7 @@ impl TreeSink for RcDom {
                 None
             }),
             mathml_annotation_xml_integration_point: flags.mathml_annotation_xml_integration_point,
+            shadow_root: RefCell::new(None),
         })
     }
 
@@ -310,6 +657,10 @@ impl TreeSink for RcDom {
         );
     }
 
+    fn append_cdata(&mut self, parent: &Handle, text: StrTendril) {
+        append(parent, Node::new(NodeData::CData { contents: text }));
+    }
+
     fn append_before_sibling(&mut self, sibling: &Handle, child: NodeOrText<Handle>) {
         let (parent, i) = get_parent_and_index(&sibling)
             .expect("append_before_sibling called on node without parent");
@@ -340,9 +691,7 @@ impl TreeSink for RcDom {
         };
 
         remove_from_parent(&child);
-
-        child.parent.set(Some(Rc::downgrade(&parent)));
-        parent.children.borrow_mut().insert(i, child);
+        insert(&parent, i, child);
     }
 
     fn append_based_on_parent_node(
@@ -403,7 +752,9 @@ impl TreeSink for RcDom {
     fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
         let mut children = node.children.borrow_mut();
         let mut new_children = new_parent.children.borrow_mut();
-        for child in children.iter() {
+        let offset = new_children.len();
+        for (i, child) in children.iter().enumerate() {
+            child.index.set(offset + i);
             let previous_parent = child.parent.replace(Some(Rc::downgrade(&new_parent)));
             assert!(Rc::ptr_eq(
                 &node,
@@ -432,21 +783,283 @@ impl Default for RcDom {
             document: Node::new(NodeData::Document),
             errors: vec![],
             quirks_mode: tree_builder::NoQuirks,
+            xml_declaration: None,
         }
     }
 }
 
+/// A `TreeNavigator` for `Handle`s that holds no state, so a tree can be walked without its
+/// `RcDom`, e.g. one put together with `Node::new`. Template contents and shadow roots aren't
+/// children, as in the DOM.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HandleNavigator;
+
+impl traverse::TreeNavigator for HandleNavigator {
+    type Handle = Handle;
+
+    fn kind(&self, node: &Handle) -> traverse::NodeKind {
+        match node.data {
+            NodeData::Document => traverse::NodeKind::Document,
+            NodeData::Doctype { .. } => traverse::NodeKind::Doctype,
+            NodeData::Element { .. } => traverse::NodeKind::Element,
+            NodeData::Text { .. } => traverse::NodeKind::Text,
+            NodeData::Comment { .. } => traverse::NodeKind::Comment,
+            NodeData::CData { .. } => traverse::NodeKind::CData,
+            NodeData::ProcessingInstruction { .. } => traverse::NodeKind::ProcessingInstruction,
+        }
+    }
+
+    fn parent(&self, node: &Handle) -> Option<Handle> {
+        let parent = node.parent.take();
+        node.parent.set(parent.clone());
+        parent.and_then(|parent| parent.upgrade())
+    }
+
+    fn first_child(&self, node: &Handle) -> Option<Handle> {
+        node.children.borrow().first().cloned()
+    }
+
+    fn last_child(&self, node: &Handle) -> Option<Handle> {
+        node.children.borrow().last().cloned()
+    }
+
+    fn next_sibling(&self, node: &Handle) -> Option<Handle> {
+        node.next_sibling()
+    }
+
+    fn previous_sibling(&self, node: &Handle) -> Option<Handle> {
+        node.previous_sibling()
+    }
+
+    fn position(&self, node: &Handle) -> usize {
+        get_parent_and_index(node).map_or(0, |(_, i)| i)
+    }
+
+    fn name(&self, node: &Handle) -> Option<QualName> {
+        match node.data {
+            NodeData::Element { ref name, .. } => Some(name.clone()),
+            NodeData::ProcessingInstruction { ref target, .. } => Some(QualName::new(
+                None,
+                ns!(),
+                markup5ever::LocalName::from(&**target),
+            )),
+            _ => None,
+        }
+    }
+
+    fn attributes(&self, node: &Handle) -> Vec<Attribute> {
+        match node.data {
+            NodeData::Element { ref attrs, .. } => attrs.borrow().clone(),
+            _ => vec![],
+        }
+    }
+
+    fn attribute(
+        &self,
+        node: &Handle,
+        ns: &markup5ever::Namespace,
+        local: &markup5ever::LocalName,
+    ) -> Option<StrTendril> {
+        match node.data {
+            NodeData::Element { ref attrs, .. } => attrs
+                .borrow()
+                .iter()
+                .find(|attr| attr.name.ns == *ns && attr.name.local == *local)
+                .map(|attr| attr.value.clone()),
+            _ => None,
+        }
+    }
+
+    fn text(&self, node: &Handle) -> Option<StrTendril> {
+        match node.data {
+            NodeData::Text { ref contents } => Some(contents.borrow().clone()),
+            NodeData::Comment { ref contents } |
+            NodeData::CData { ref contents } |
+            NodeData::ProcessingInstruction { ref contents, .. } => Some(contents.clone()),
+            _ => None,
+        }
+    }
+
+    fn doctype(&self, node: &Handle) -> Option<traverse::Doctype> {
+        match node.data {
+            NodeData::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+            } => Some(traverse::Doctype {
+                name: name.clone(),
+                public_id: public_id.clone(),
+                system_id: system_id.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn template_contents(&self, node: &Handle) -> Option<Handle> {
+        match node.data {
+            NodeData::Element {
+                ref template_contents,
+                ..
+            } => template_contents.borrow().clone(),
+            _ => None,
+        }
+    }
+
+    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+        Rc::ptr_eq(x, y)
+    }
+}
+
+/// Lets code written against `TreeNavigator`, such as XPath expressions and CSS selectors, walk
+/// the DOM, in the same way as `HandleNavigator`.
+impl traverse::TreeNavigator for RcDom {
+    type Handle = Handle;
+
+    fn kind(&self, node: &Handle) -> traverse::NodeKind {
+        HandleNavigator.kind(node)
+    }
+
+    fn parent(&self, node: &Handle) -> Option<Handle> {
+        HandleNavigator.parent(node)
+    }
+
+    fn first_child(&self, node: &Handle) -> Option<Handle> {
+        HandleNavigator.first_child(node)
+    }
+
+    fn last_child(&self, node: &Handle) -> Option<Handle> {
+        HandleNavigator.last_child(node)
+    }
+
+    fn next_sibling(&self, node: &Handle) -> Option<Handle> {
+        HandleNavigator.next_sibling(node)
+    }
+
+    fn previous_sibling(&self, node: &Handle) -> Option<Handle> {
+        HandleNavigator.previous_sibling(node)
+    }
+
+    fn position(&self, node: &Handle) -> usize {
+        HandleNavigator.position(node)
+    }
+
+    fn name(&self, node: &Handle) -> Option<QualName> {
+        HandleNavigator.name(node)
+    }
+
+    fn attributes(&self, node: &Handle) -> Vec<Attribute> {
+        HandleNavigator.attributes(node)
+    }
+
+    fn attribute(
+        &self,
+        node: &Handle,
+        ns: &markup5ever::Namespace,
+        local: &markup5ever::LocalName,
+    ) -> Option<StrTendril> {
+        HandleNavigator.attribute(node, ns, local)
+    }
+
+    fn text(&self, node: &Handle) -> Option<StrTendril> {
+        HandleNavigator.text(node)
+    }
+
+    fn doctype(&self, node: &Handle) -> Option<traverse::Doctype> {
+        HandleNavigator.doctype(node)
+    }
+
+    fn template_contents(&self, node: &Handle) -> Option<Handle> {
+        HandleNavigator.template_contents(node)
+    }
+
+    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+        HandleNavigator.same_node(x, y)
+    }
+}
+
+/// The descendant elements of a document or element that match a CSS selector list, in
+/// document order, like `querySelectorAll()`.
+pub fn select(node: &Handle, selectors: &str) -> Result<Vec<Handle>, select::Error> {
+    Ok(select::Selector::parse(selectors)?.select(&HandleNavigator, node))
+}
+
+/// The first descendant element of a document or element that matches a CSS selector list,
+/// like `querySelector()`.
+pub fn select_first(node: &Handle, selectors: &str) -> Result<Option<Handle>, select::Error> {
+    Ok(select::Selector::parse(selectors)?.select_first(&HandleNavigator, node))
+}
+
 enum SerializeOp {
     Open(Handle),
     Close(QualName),
+    CloseShadowRoot,
+}
+
+/// Options for serializing shadow roots, mirroring the [`getHTML()`] options dictionary.
+///
+/// [`getHTML()`]: https://html.spec.whatwg.org/multipage/#dom-element-gethtml
+#[derive(Clone, Default)]
+pub struct GetHtmlOptions {
+    /// Serialize every shadow root whose `serializable` flag is set.
+    pub serializable_shadow_roots: bool,
+
+    /// Serialize the shadow roots in this list, identified by their root node, whether or not
+    /// they are serializable.
+    pub shadow_roots: Vec<Handle>,
+}
+
+pub struct SerializableHandle {
+    handle: Handle,
+    opts: GetHtmlOptions,
 }
 
-pub struct SerializableHandle(Handle);
+impl SerializableHandle {
+    /// Serialize `handle`, including the shadow roots selected by `opts`.
+    pub fn new(handle: Handle, opts: GetHtmlOptions) -> SerializableHandle {
+        SerializableHandle { handle, opts }
+    }
+
+    /// The shadow root of `handle`, if it has one that the options ask us to serialize.
+    fn shadow_root_to_serialize(&self, handle: &Handle) -> Option<ShadowRoot> {
+        let shadow_root = match handle.data {
+            NodeData::Element {
+                ref shadow_root, ..
+            } => shadow_root.borrow().clone()?,
+            _ => return None,
+        };
+        let selected = (self.opts.serializable_shadow_roots && shadow_root.init.serializable) ||
+            self
+                .opts
+                .shadow_roots
+                .iter()
+                .any(|root| Rc::ptr_eq(root, &shadow_root.root));
+        if selected {
+            Some(shadow_root)
+        } else {
+            None
+        }
+    }
+}
 
 impl From<Handle> for SerializableHandle {
     fn from(h: Handle) -> SerializableHandle {
-        SerializableHandle(h)
+        SerializableHandle::new(h, GetHtmlOptions::default())
+    }
+}
+
+/// The children to serialize for a node: the template contents' children for a `<template>`,
+/// and the node's own children otherwise.
+fn serialized_children(handle: &Handle) -> Vec<Handle> {
+    if let NodeData::Element {
+        ref template_contents,
+        ..
+    } = handle.data
+    {
+        if let Some(ref contents) = *template_contents.borrow() {
+            return contents.children.borrow().clone();
+        }
     }
+    handle.children.borrow().clone()
 }
 
 impl Serialize for SerializableHandle {
@@ -456,13 +1069,26 @@ impl Serialize for SerializableHandle {
     {
         let mut ops = VecDeque::new();
         match traversal_scope {
-            IncludeNode => ops.push_back(SerializeOp::Open(self.0.clone())),
-            ChildrenOnly(_) => ops.extend(self
-                .0
-                .children
-                .borrow()
-                .iter()
-                .map(|h| SerializeOp::Open(h.clone())))
+            IncludeNode => ops.push_back(SerializeOp::Open(self.handle.clone())),
+            ChildrenOnly(_) => {
+                if let Some(shadow_root) = self.shadow_root_to_serialize(&self.handle) {
+                    serializer.start_shadow_root(shadow_root.init)?;
+                    ops.extend(
+                        shadow_root
+                            .root
+                            .children
+                            .borrow()
+                            .iter()
+                            .map(|h| SerializeOp::Open(h.clone())),
+                    );
+                    ops.push_back(SerializeOp::CloseShadowRoot);
+                }
+                ops.extend(
+                    serialized_children(&self.handle)
+                        .into_iter()
+                        .map(SerializeOp::Open),
+                )
+            },
         }
 
         while let Some(op) = ops.pop_front() {
@@ -478,22 +1104,31 @@ impl Serialize for SerializableHandle {
                             attrs.borrow().iter().map(|at| (&at.name, &at.value[..])),
                         )?;
 
-                        ops.reserve(1 + handle.children.borrow().len());
+                        let children = serialized_children(&handle);
+                        ops.reserve(1 + children.len());
                         ops.push_front(SerializeOp::Close(name.clone()));
 
-                        for child in handle.children.borrow().iter().rev() {
-                            ops.push_front(SerializeOp::Open(child.clone()));
+                        for child in children.into_iter().rev() {
+                            ops.push_front(SerializeOp::Open(child));
+                        }
+
+                        if let Some(shadow_root) = self.shadow_root_to_serialize(&handle) {
+                            serializer.start_shadow_root(shadow_root.init)?;
+                            ops.push_front(SerializeOp::CloseShadowRoot);
+                            for child in shadow_root.root.children.borrow().iter().rev() {
+                                ops.push_front(SerializeOp::Open(child.clone()));
+                            }
                         }
                     },
 
                     NodeData::Doctype { ref name, .. } => serializer.write_doctype(&name)?,
 
-                    NodeData::Text { ref contents } => {
-                        serializer.write_text(&contents.borrow())?
-                    },
+                    NodeData::Text { ref contents } => serializer.write_text(&contents.borrow())?,
 
                     NodeData::Comment { ref contents } => serializer.write_comment(&contents)?,
 
+                    NodeData::CData { ref contents } => serializer.write_cdata(&contents)?,
+
                     NodeData::ProcessingInstruction {
                         ref target,
                         ref contents,
@@ -505,6 +1140,10 @@ impl Serialize for SerializableHandle {
                 SerializeOp::Close(name) => {
                     serializer.end_elem(name)?;
                 },
+
+                SerializeOp::CloseShadowRoot => {
+                    serializer.end_shadow_root()?;
+                },
             }
         }
 
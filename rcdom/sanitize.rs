@@ -0,0 +1,265 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An allowlist-based HTML sanitizer.
+//!
+//! `sanitize` removes everything that isn't allowed from a parsed tree, and `clean` parses an
+//! untrusted fragment, sanitizes it and serializes it again with `HtmlSerializer`.
+//!
+//! Some trees serialize to markup that parses differently, which is how mutation XSS slips
+//! through sanitizers that only look at the first parse. `clean` therefore keeps reparsing and
+//! sanitizing its output until that is stable: what it returns parses to a tree that passes
+//! `sanitize` unchanged and serializes back to the same string.
+//!
+//! Beyond the allowlists, some things are never allowed:
+//!
+//! * Elements in the SVG and MathML namespaces are removed with their contents, as the rules
+//!   for moving between namespaces are the usual source of mutation XSS.
+//! * `<script>`, `<style>`, `<noscript>` and the other elements whose text isn't escaped when
+//!   serialized are removed with their contents.
+//! * Comments, processing instructions and doctypes are removed.
+//! * Attributes with a namespace, and event handler attributes such as `onclick`, are removed.
+//! * URL attributes such as `href` are removed if the URL has a scheme that isn't allowed, after
+//!   stripping the whitespace and control characters that URL parsers ignore.
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use html5ever::tendril::TendrilSink;
+use html5ever::{parse_fragment, serialize, LocalName, ParseOpts, QualName};
+use mac::{_tt_as_expr_hack, matches};
+use markup5ever::Attribute;
+
+use super::{Handle, NodeData, RcDom, SerializableHandle};
+
+/// How many times `clean` reparses its output looking for a stable result before giving up.
+const MAX_PASSES: usize = 8;
+
+/// What `sanitize` allows.
+#[derive(Clone, Debug)]
+pub struct SanitizeOpts {
+    /// The HTML elements that are kept. Other elements are replaced by their children.
+    pub elements: HashSet<LocalName>,
+
+    /// HTML elements that are removed along with their children.
+    pub remove_contents: HashSet<LocalName>,
+
+    /// Attributes allowed on every allowed element.
+    pub generic_attributes: HashSet<LocalName>,
+
+    /// Attributes allowed on particular elements, by element name.
+    pub attributes: HashMap<LocalName, HashSet<LocalName>>,
+
+    /// The schemes allowed in URL attributes, in lowercase. URLs without a scheme are allowed.
+    pub url_schemes: HashSet<String>,
+}
+
+impl Default for SanitizeOpts {
+    /// Text formatting, lists, tables, links and images, with `http`, `https` and `mailto`
+    /// URLs.
+    fn default() -> SanitizeOpts {
+        let names = |names: &str| names.split_whitespace().map(LocalName::from).collect();
+        let attributes = &[
+            ("a", "href hreflang"),
+            ("blockquote", "cite"),
+            ("col", "span"),
+            ("colgroup", "span"),
+            ("del", "cite datetime"),
+            ("img", "alt height src width"),
+            ("ins", "cite datetime"),
+            ("ol", "reversed start"),
+            ("q", "cite"),
+            ("td", "colspan headers rowspan"),
+            ("th", "colspan headers rowspan scope"),
+            ("time", "datetime"),
+        ];
+        SanitizeOpts {
+            elements: names(
+                "a abbr b bdi bdo blockquote br caption cite code col colgroup dd del dfn div dl \
+                 dt em figcaption figure h1 h2 h3 h4 h5 h6 hr i img ins kbd li mark ol p pre q rp \
+                 rt ruby s samp small span strong sub sup table tbody td tfoot th thead time tr \
+                 u ul var wbr",
+            ),
+            remove_contents: names("head object select template textarea title"),
+            generic_attributes: names("dir lang title"),
+            attributes: attributes
+                .iter()
+                .map(|&(element, attributes)| (LocalName::from(element), names(attributes)))
+                .collect(),
+            url_schemes: ["http", "https", "mailto"]
+                .iter()
+                .map(|&scheme| scheme.to_owned())
+                .collect(),
+        }
+    }
+}
+
+enum Action {
+    Keep,
+    Remove,
+    Unwrap,
+}
+
+/// Remove everything that isn't allowed from the descendants of a node, returning whether
+/// anything was removed.
+///
+/// The contents of allowed `<template>` elements are sanitized too.
+pub fn sanitize(node: &Handle, opts: &SanitizeOpts) -> bool {
+    let mut changed = false;
+    let mut parents = vec![node.clone()];
+    while let Some(parent) = parents.pop() {
+        let mut i = 0;
+        loop {
+            let child = parent.children.borrow().get(i).cloned();
+            let child = match child {
+                Some(child) => child,
+                None => break,
+            };
+            match action(&child, opts) {
+                Action::Keep => {
+                    if let NodeData::Element {
+                        ref name,
+                        ref attrs,
+                        ref template_contents,
+                        ..
+                    } = child.data
+                    {
+                        let mut attrs = attrs.borrow_mut();
+                        let len = attrs.len();
+                        attrs.retain(|attr| is_allowed_attribute(&name.local, attr, opts));
+                        changed |= attrs.len() != len;
+                        parents.extend(template_contents.borrow().clone());
+                    }
+                    parents.push(child);
+                    i += 1;
+                },
+                Action::Remove => {
+                    child.remove();
+                    changed = true;
+                },
+                Action::Unwrap => {
+                    // The children take the element's place, and are looked at next.
+                    let grandchildren = mem::take(&mut *child.children.borrow_mut());
+                    for grandchild in grandchildren {
+                        grandchild.parent.set(None);
+                        parent.insert_before(grandchild, &child);
+                    }
+                    child.remove();
+                    changed = true;
+                },
+            }
+        }
+    }
+    changed
+}
+
+fn action(node: &Handle, opts: &SanitizeOpts) -> Action {
+    let name = match node.data {
+        NodeData::Text { .. } => return Action::Keep,
+        NodeData::Element { ref name, .. } => name,
+        _ => return Action::Remove,
+    };
+    if name.ns != ns!(html) || opts.remove_contents.contains(&name.local) {
+        return Action::Remove;
+    }
+    match name.local {
+        // Their text is written out unescaped.
+        local_name!("iframe") |
+        local_name!("noembed") |
+        local_name!("noframes") |
+        local_name!("noscript") |
+        local_name!("plaintext") |
+        local_name!("script") |
+        local_name!("style") |
+        local_name!("xmp") => Action::Remove,
+        ref local if opts.elements.contains(local) => Action::Keep,
+        _ => Action::Unwrap,
+    }
+}
+
+fn is_allowed_attribute(element: &LocalName, attr: &Attribute, opts: &SanitizeOpts) -> bool {
+    let local = &attr.name.local;
+    if attr.name.ns != ns!() || (local.len() > 2 && local[..2].eq_ignore_ascii_case("on")) {
+        return false;
+    }
+    let allowed = opts.generic_attributes.contains(local) ||
+        match opts.attributes.get(element) {
+            Some(attributes) => attributes.contains(local),
+            None => false,
+        };
+    if !allowed {
+        return false;
+    }
+    match *local {
+        local_name!("action") |
+        local_name!("background") |
+        local_name!("cite") |
+        local_name!("data") |
+        local_name!("formaction") |
+        local_name!("href") |
+        local_name!("longdesc") |
+        local_name!("poster") |
+        local_name!("src") => is_allowed_url(&attr.value, opts),
+        local_name!("srcset") => attr.value.split(',').all(|candidate| {
+            let url = candidate.trim_start().split(char::is_whitespace).next();
+            is_allowed_url(url.unwrap_or(""), opts)
+        }),
+        _ => true,
+    }
+}
+
+fn is_allowed_url(url: &str, opts: &SanitizeOpts) -> bool {
+    // URL parsers strip leading and trailing C0 controls and spaces, and tabs and newlines
+    // anywhere.
+    let url = url
+        .trim_matches(|c| c <= ' ')
+        .chars()
+        .filter(|&c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect::<String>();
+    let scheme = match url.find(&[':', '/', '?', '#'][..]) {
+        Some(i) if url[i..].starts_with(':') => &url[..i],
+        _ => return true,
+    };
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic()) &&
+        scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    // Something like `a:b` that isn't a scheme is a relative URL.
+    !valid || opts.url_schemes.contains(&scheme.to_ascii_lowercase())
+}
+
+/// Parse and sanitize a fragment of HTML meant to be inserted in a `<body>`, returning the
+/// sanitized HTML.
+///
+/// The result is stable: parsing it gives a tree that `sanitize` leaves alone and that
+/// serializes back to the same string. On the rare input where that can't be reached, the
+/// result is empty.
+pub fn clean(html: &str, opts: &SanitizeOpts) -> String {
+    let (mut output, _) = parse_and_sanitize(html, opts);
+    for _ in 0..MAX_PASSES {
+        let (reparsed, changed) = parse_and_sanitize(&output, opts);
+        if !changed && reparsed == output {
+            return output;
+        }
+        output = reparsed;
+    }
+    String::new()
+}
+
+fn parse_and_sanitize(html: &str, opts: &SanitizeOpts) -> (String, bool) {
+    let context = QualName::new(None, ns!(html), local_name!("body"));
+    let dom = parse_fragment(RcDom::default(), ParseOpts::default(), context, vec![]).one(html);
+    let root = dom.document.children.borrow()[0].clone();
+    let changed = sanitize(&root, opts);
+
+    let mut output = vec![];
+    let root: SerializableHandle = root.into();
+    serialize(&mut output, &root, Default::default()).expect("writing to a Vec failed");
+    (String::from_utf8(output).unwrap(), changed)
+}
@@ -0,0 +1,223 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `Serialize` and `Deserialize` for `RcDom`.
+//!
+//! The tree is written as a flat list of nodes in document order, each with the number of
+//! children that follow it, so neither direction recurses however deep the tree is. The
+//! template contents of an element come straight after it, before its children. Shadow roots
+//! are left out.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use mac::{_tt_as_expr_hack, matches};
+use markup5ever::interface::tree_builder::{QuirksMode, XmlDeclaration};
+use markup5ever::{serde_tendril, Attribute, QualName};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tendril::StrTendril;
+
+use super::{insert, Handle, Node, NodeData, RcDom};
+
+#[derive(Serialize, Deserialize)]
+enum Record {
+    /// The number of children.
+    Document(usize),
+    Doctype(
+        #[serde(with = "serde_tendril")] StrTendril,
+        #[serde(with = "serde_tendril")] StrTendril,
+        #[serde(with = "serde_tendril")] StrTendril,
+    ),
+    Text(#[serde(with = "serde_tendril")] StrTendril),
+    Comment(#[serde(with = "serde_tendril")] StrTendril),
+    CData(#[serde(with = "serde_tendril")] StrTendril),
+    /// The name, attributes and number of children, then whether the element has template
+    /// contents and whether it's a MathML annotation-xml integration point.
+    Element(QualName, Vec<Attribute>, usize, bool, bool),
+    ProcessingInstruction(
+        #[serde(with = "serde_tendril")] StrTendril,
+        #[serde(with = "serde_tendril")] StrTendril,
+    ),
+}
+
+#[derive(Serialize)]
+struct SerializedDom<'a> {
+    nodes: Vec<Record>,
+    quirks_mode: QuirksMode,
+    errors: &'a [Cow<'static, str>],
+    xml_declaration: &'a Option<XmlDeclaration>,
+}
+
+#[derive(Deserialize)]
+struct DeserializedDom {
+    nodes: Vec<Record>,
+    quirks_mode: QuirksMode,
+    errors: Vec<Cow<'static, str>>,
+    xml_declaration: Option<XmlDeclaration>,
+}
+
+impl Serialize for RcDom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut nodes = vec![];
+        let mut stack = vec![self.document.clone()];
+        while let Some(node) = stack.pop() {
+            let children = node.children.borrow();
+            let record = match node.data {
+                NodeData::Document => Record::Document(children.len()),
+                NodeData::Doctype {
+                    ref name,
+                    ref public_id,
+                    ref system_id,
+                } => Record::Doctype(name.clone(), public_id.clone(), system_id.clone()),
+                NodeData::Text { ref contents } => Record::Text(contents.borrow().clone()),
+                NodeData::Comment { ref contents } => Record::Comment(contents.clone()),
+                NodeData::CData { ref contents } => Record::CData(contents.clone()),
+                NodeData::Element {
+                    ref name,
+                    ref attrs,
+                    ref template_contents,
+                    mathml_annotation_xml_integration_point,
+                    ..
+                } => {
+                    stack.extend(children.iter().rev().cloned());
+                    let template_contents = template_contents.borrow();
+                    stack.extend(template_contents.clone());
+                    nodes.push(Record::Element(
+                        name.clone(),
+                        attrs.borrow().clone(),
+                        children.len(),
+                        template_contents.is_some(),
+                        mathml_annotation_xml_integration_point,
+                    ));
+                    continue;
+                },
+                NodeData::ProcessingInstruction {
+                    ref target,
+                    ref contents,
+                } => Record::ProcessingInstruction(target.clone(), contents.clone()),
+            };
+            stack.extend(children.iter().rev().cloned());
+            nodes.push(record);
+        }
+
+        SerializedDom {
+            nodes,
+            quirks_mode: self.quirks_mode,
+            errors: &self.errors,
+            xml_declaration: &self.xml_declaration,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RcDom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RcDom, D::Error> {
+        let dom = DeserializedDom::deserialize(deserializer)?;
+        let mut records = dom.nodes.into_iter();
+        let document = match records.next() {
+            Some(Record::Document(children)) => (Node::new(NodeData::Document), children),
+            _ => return Err(D::Error::custom("the first node must be a document")),
+        };
+        let root = document.0.clone();
+
+        // Nodes that still have children to come, with how many.
+        let mut parents = vec![document];
+        // An element whose template contents are next.
+        let mut template: Option<Handle> = None;
+        for record in records {
+            let (data, children, has_template_contents) = match record {
+                Record::Document(children) => (NodeData::Document, children, false),
+                Record::Doctype(name, public_id, system_id) => (
+                    NodeData::Doctype {
+                        name,
+                        public_id,
+                        system_id,
+                    },
+                    0,
+                    false,
+                ),
+                Record::Text(contents) => (
+                    NodeData::Text {
+                        contents: RefCell::new(contents),
+                    },
+                    0,
+                    false,
+                ),
+                Record::Comment(contents) => (NodeData::Comment { contents }, 0, false),
+                Record::CData(contents) => (NodeData::CData { contents }, 0, false),
+                Record::Element(
+                    name,
+                    attrs,
+                    children,
+                    has_template_contents,
+                    integration_point,
+                ) => (
+                    NodeData::Element {
+                        name,
+                        attrs: RefCell::new(attrs),
+                        template_contents: RefCell::new(None),
+                        mathml_annotation_xml_integration_point: integration_point,
+                        shadow_root: RefCell::new(None),
+                    },
+                    children,
+                    has_template_contents,
+                ),
+                Record::ProcessingInstruction(target, contents) => (
+                    NodeData::ProcessingInstruction { target, contents },
+                    0,
+                    false,
+                ),
+            };
+            let is_document = matches!(data, NodeData::Document);
+            let node = Node::new(data);
+
+            match template.take() {
+                Some(element) if is_document => match element.data {
+                    NodeData::Element {
+                        ref template_contents,
+                        ..
+                    } => *template_contents.borrow_mut() = Some(node.clone()),
+                    _ => unreachable!(),
+                },
+                Some(_) => return Err(D::Error::custom("missing template contents")),
+                None if is_document => {
+                    return Err(D::Error::custom("a document can't have a parent"))
+                },
+                None => {
+                    while parents.last().map_or(false, |&(_, children)| children == 0) {
+                        parents.pop();
+                    }
+                    let (parent, children) = match parents.last_mut() {
+                        Some(parent) => parent,
+                        None => return Err(D::Error::custom("more nodes than children")),
+                    };
+                    *children -= 1;
+                    let i = parent.children.borrow().len();
+                    insert(parent, i, node.clone());
+                },
+            }
+
+            if has_template_contents {
+                template = Some(node.clone());
+            }
+            parents.push((node, children));
+        }
+
+        if template.is_some() || parents.iter().any(|&(_, children)| children > 0) {
+            return Err(D::Error::custom("fewer nodes than children"));
+        }
+        Ok(RcDom {
+            document: root,
+            errors: dom.errors,
+            quirks_mode: dom.quirks_mode,
+            xml_declaration: dom.xml_declaration,
+        })
+    }
+}
@@ -0,0 +1,483 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A tree sink that serializes the document while it is being parsed.
+//!
+//! The tree builder does not build the tree strictly in document order: the adoption agency
+//! algorithm moves open elements that sit below an open formatting element, foster parenting
+//! inserts content before an open `<table>`, and a closed `<head>` can be reopened in the "after
+//! head" insertion mode. `StreamingSerializer` therefore only writes a node once nothing can be
+//! inserted before it any more, and only closes an element once the tree builder reports it
+//! through `TreeSink::element_closed` and it can't be reparented. Everything written is
+//! detached from the tree, so memory use is bounded by the parts of the document that are still
+//! in flux.
+//!
+//! Start tags are held back until the element's first child or its end tag is written. The tree
+//! builder can add attributes to the `<html>` and `<body>` start tags, or replace an empty
+//! `<body>` with a `<frameset>`, and this way those changes still make it into the output.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+
+use mac::{_tt_as_expr_hack, matches};
+use markup5ever::interface::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use markup5ever::serialize::Serializer;
+use markup5ever::{Attribute, ExpandedName, LocalName, QualName};
+use tendril::StrTendril;
+
+use super::{remove_from_parent, Handle, Node, NodeData, RcDom};
+
+/// A `TreeSink` that writes the document to a `Serializer` as soon as parts of it are final.
+///
+/// The output is identical to serializing the finished `RcDom` with
+/// `TraversalScope::ChildrenOnly(None)`, unless `errors()` reports that an element was changed
+/// after its start tag was written, as a stray `<body>` tag late in the document can do.
+///
+/// ```ignore
+/// let sink = StreamingSerializer::new(HtmlSerializer::new(writer, Default::default()));
+/// let sink = parse_document(sink, Default::default()).one(input);
+/// let writer = sink.into_serializer()?.writer;
+/// ```
+pub struct StreamingSerializer<S: Serializer> {
+    /// The part of the tree that hasn't been written out yet.
+    dom: RcDom,
+
+    serializer: S,
+
+    /// Elements whose children are being written and whose end tag hasn't been, paired with the
+    /// node holding their children (the template contents for a `<template>`). The bottom entry
+    /// is the document.
+    cursor: Vec<(Handle, Handle)>,
+
+    /// How many entries at the bottom of `cursor` have had their start tag written. The start
+    /// tags of the others are held back until something inside them is written.
+    started: usize,
+
+    /// The position in `cursor` of each element in it.
+    cursor_index: HashMap<*const Node, usize>,
+
+    /// Elements that the tree builder hasn't reported as closed yet.
+    open: HashMap<*const Node, Handle>,
+
+    /// Set once parsing has finished and everything left can be written.
+    finished: bool,
+
+    /// The first error returned by the serializer. Nothing is written after it.
+    io_error: Option<io::Error>,
+}
+
+impl<S: Serializer> StreamingSerializer<S> {
+    /// Create a sink writing to the given serializer.
+    pub fn new(serializer: S) -> StreamingSerializer<S> {
+        let dom = RcDom::default();
+        let document = dom.document.clone();
+        StreamingSerializer {
+            dom,
+            serializer,
+            cursor: vec![(document.clone(), document)],
+            started: 1,
+            cursor_index: HashMap::new(),
+            open: HashMap::new(),
+            finished: false,
+            io_error: None,
+        }
+    }
+
+    /// The serializer, with everything written so far.
+    pub fn serializer(&self) -> &S {
+        &self.serializer
+    }
+
+    /// Errors that occurred during parsing, and changes to elements whose start tag had already
+    /// been written, which the output can't include.
+    pub fn errors(&self) -> &[Cow<'static, str>] {
+        &self.dom.errors
+    }
+
+    /// The document's quirks mode.
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.dom.quirks_mode
+    }
+
+    /// Return the serializer, or the first error it returned.
+    pub fn into_serializer(self) -> io::Result<S> {
+        match self.io_error {
+            Some(err) => Err(err),
+            None => Ok(self.serializer),
+        }
+    }
+
+    fn is_open(&self, node: &Handle) -> bool {
+        self.open.contains_key(&ptr(node))
+    }
+
+    fn is_started(&self, node: &Handle) -> bool {
+        match self.cursor_index.get(&ptr(node)) {
+            Some(&index) => index < self.started,
+            None => false,
+        }
+    }
+
+    /// Whether the element can still be moved by the adoption agency algorithm, i.e. whether it
+    /// is open and has an open formatting element ancestor with no open marker in between.
+    fn is_movable(&self, node: &Handle) -> bool {
+        let mut node = node.clone();
+        loop {
+            if is_html(&node, is_marker) && self.is_open(&node) {
+                return false;
+            }
+            let parent = match parent(&node) {
+                Some(parent) => parent,
+                None => return false,
+            };
+            if is_html(&parent, is_formatting) && self.is_open(&parent) {
+                return true;
+            }
+            node = parent;
+        }
+    }
+
+    /// Whether an element's start tag can be written: nothing can be inserted before it and it
+    /// can't be moved elsewhere.
+    fn is_placed(&self, node: &Handle) -> bool {
+        self.finished ||
+            !self.is_open(node) ||
+            !(is_html(node, |name| *name == local_name!("table")) || self.is_movable(node))
+    }
+
+    /// Whether an element whose start tag has been written can't get any more children.
+    fn is_complete(&self, node: &Handle) -> bool {
+        if self.finished {
+            return true;
+        }
+        if self.is_open(node) {
+            return false;
+        }
+        // In the "after head" insertion mode the head is put back on the stack for elements such
+        // as `<meta>`, which ends once the body or frameset is inserted after it.
+        if is_html(node, |name| *name == local_name!("head")) {
+            return match node.next_sibling() {
+                Some(sibling) => matches!(sibling.data, NodeData::Element { .. }),
+                None => false,
+            };
+        }
+        true
+    }
+
+    /// Whether no more text can be merged into a text node.
+    fn is_text_complete(&self, node: &Handle, text: &Handle) -> bool {
+        match text.next_sibling() {
+            Some(sibling) => match sibling.data {
+                NodeData::Element { .. } => self.is_placed(&sibling),
+                _ => true,
+            },
+            None => self.cursor.len() > 1 && self.is_complete(node),
+        }
+    }
+
+    /// Write out everything that can no longer change, and drop it from the tree.
+    fn flush(&mut self) {
+        if self.io_error.is_some() {
+            return;
+        }
+        if let Err(err) = self.write_final_nodes() {
+            self.io_error = Some(err);
+        }
+    }
+
+    /// Write the start tags that have been held back, before writing something inside them.
+    fn write_start_tags(&mut self) -> io::Result<()> {
+        while self.started < self.cursor.len() {
+            let node = self.cursor[self.started].0.clone();
+            if let NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } = node.data
+            {
+                self.serializer.start_elem(
+                    name.clone(),
+                    attrs.borrow().iter().map(|at| (&at.name, &at.value[..])),
+                )?;
+            }
+            self.started += 1;
+        }
+        Ok(())
+    }
+
+    /// Drop the entries of `cursor` from `index` upwards.
+    fn truncate_cursor(&mut self, index: usize) {
+        for (node, _) in self.cursor.drain(index..) {
+            self.cursor_index.remove(&ptr(&node));
+        }
+        if self.started > index {
+            self.started = index;
+        }
+    }
+
+    fn write_final_nodes(&mut self) -> io::Result<()> {
+        loop {
+            let (node, holder) = self.cursor.last().cloned().expect("empty cursor");
+            let child = holder.children.borrow().first().cloned();
+            let child = match child {
+                Some(child) => child,
+                None => {
+                    if self.cursor.len() == 1 || !self.is_complete(&node) {
+                        return Ok(());
+                    }
+                    self.write_start_tags()?;
+                    self.truncate_cursor(self.cursor.len() - 1);
+                    if let NodeData::Element { ref name, .. } = node.data {
+                        self.serializer.end_elem(name.clone())?;
+                    }
+                    remove_from_parent(&node);
+                    continue;
+                },
+            };
+
+            match child.data {
+                NodeData::Element {
+                    ref template_contents,
+                    ..
+                } => {
+                    if !self.is_placed(&child) {
+                        return Ok(());
+                    }
+                    let holder = template_contents
+                        .borrow()
+                        .clone()
+                        .unwrap_or_else(|| child.clone());
+                    self.cursor_index.insert(ptr(&child), self.cursor.len());
+                    self.cursor.push((child.clone(), holder));
+                    continue;
+                },
+
+                NodeData::Text { ref contents } => {
+                    if !self.finished && !self.is_text_complete(&node, &child) {
+                        return Ok(());
+                    }
+                    self.write_start_tags()?;
+                    self.serializer.write_text(&contents.borrow())?;
+                },
+
+                NodeData::Doctype { ref name, .. } => {
+                    self.write_start_tags()?;
+                    self.serializer.write_doctype(name)?
+                },
+
+                NodeData::Comment { ref contents } => {
+                    self.write_start_tags()?;
+                    self.serializer.write_comment(contents)?
+                },
+
+                NodeData::CData { ref contents } => {
+                    self.write_start_tags()?;
+                    self.serializer.write_cdata(contents)?
+                },
+
+                NodeData::ProcessingInstruction {
+                    ref target,
+                    ref contents,
+                } => {
+                    self.write_start_tags()?;
+                    self.serializer
+                        .write_processing_instruction(target, contents)?
+                },
+
+                NodeData::Document => panic!("Can't serialize Document node itself"),
+            }
+            remove_from_parent(&child);
+        }
+    }
+}
+
+fn ptr(node: &Handle) -> *const Node {
+    &**node
+}
+
+fn parent(node: &Handle) -> Option<Handle> {
+    let weak = node.parent.take()?;
+    let parent = weak.upgrade().expect("dangling weak pointer");
+    node.parent.set(Some(weak));
+    Some(parent)
+}
+
+fn is_html<F: Fn(&LocalName) -> bool>(node: &Handle, pred: F) -> bool {
+    match node.data {
+        NodeData::Element { ref name, .. } => name.ns == ns!(html) && pred(&name.local),
+        _ => false,
+    }
+}
+
+fn is_formatting(name: &LocalName) -> bool {
+    matches!(
+        *name,
+        local_name!("a") |
+            local_name!("b") |
+            local_name!("big") |
+            local_name!("code") |
+            local_name!("em") |
+            local_name!("font") |
+            local_name!("i") |
+            local_name!("nobr") |
+            local_name!("s") |
+            local_name!("small") |
+            local_name!("strike") |
+            local_name!("strong") |
+            local_name!("tt") |
+            local_name!("u")
+    )
+}
+
+fn is_marker(name: &LocalName) -> bool {
+    matches!(
+        *name,
+        local_name!("applet") |
+            local_name!("object") |
+            local_name!("marquee") |
+            local_name!("template") |
+            local_name!("td") |
+            local_name!("th") |
+            local_name!("caption")
+    )
+}
+
+impl<S: Serializer> TreeSink for StreamingSerializer<S> {
+    type Handle = Handle;
+    type Output = Self;
+
+    fn finish(mut self) -> Self {
+        self.finished = true;
+        self.flush();
+        self
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.dom.parse_error(msg);
+    }
+
+    fn get_document(&mut self) -> Handle {
+        self.dom.get_document()
+    }
+
+    fn get_template_contents(&mut self, target: &Handle) -> Handle {
+        self.dom.get_template_contents(target)
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.dom.set_quirks_mode(mode);
+    }
+
+    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+        self.dom.same_node(x, y)
+    }
+
+    fn elem_name<'a>(&self, target: &'a Handle) -> ExpandedName<'a> {
+        match target.data {
+            NodeData::Element { ref name, .. } => name.expanded(),
+            _ => panic!("not an element!"),
+        }
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Handle {
+        let elem = self.dom.create_element(name, attrs, flags);
+        self.open.insert(ptr(&elem), elem.clone());
+        elem
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Handle {
+        self.dom.create_comment(text)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Handle {
+        self.dom.create_pi(target, data)
+    }
+
+    fn append(&mut self, parent: &Handle, child: NodeOrText<Handle>) {
+        self.dom.append(parent, child);
+        self.flush();
+    }
+
+    fn append_before_sibling(&mut self, sibling: &Handle, child: NodeOrText<Handle>) {
+        self.dom.append_before_sibling(sibling, child);
+        self.flush();
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Handle,
+        prev_element: &Handle,
+        child: NodeOrText<Handle>,
+    ) {
+        self.dom
+            .append_based_on_parent_node(element, prev_element, child);
+        self.flush();
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.dom
+            .append_doctype_to_document(name, public_id, system_id);
+        self.flush();
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+        if self.is_started(target) {
+            let existing = match target.data {
+                NodeData::Element { ref attrs, .. } => attrs.borrow().len(),
+                _ => panic!("not an element"),
+            };
+            self.dom.add_attrs_if_missing(target, attrs);
+            if let NodeData::Element { ref attrs, .. } = target.data {
+                if attrs.borrow().len() != existing {
+                    self.dom.errors.push(Cow::Borrowed(
+                        "Attributes added to a start tag that was already written",
+                    ));
+                }
+            }
+        } else {
+            self.dom.add_attrs_if_missing(target, attrs);
+        }
+    }
+
+    fn remove_from_parent(&mut self, target: &Handle) {
+        if self.is_started(target) {
+            self.dom
+                .errors
+                .push(Cow::Borrowed("Removed an element that was already written"));
+        } else if let Some(&index) = self.cursor_index.get(&ptr(target)) {
+            // Nothing of it has been written, so it can just be dropped.
+            self.truncate_cursor(index);
+        }
+        self.dom.remove_from_parent(target);
+    }
+
+    fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+        self.dom.reparent_children(node, new_parent);
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, target: &Handle) -> bool {
+        self.dom.is_mathml_annotation_xml_integration_point(target)
+    }
+
+    fn element_closed(&mut self, node: &Handle) {
+        self.open.remove(&ptr(node));
+        self.flush();
+    }
+}
@@ -0,0 +1,536 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A DOM that can be shared between threads.
+//!
+//! `SyncDom` is a copy of a document parsed into the `markup5ever_arena` crate's DOM, whose tree
+//! sink does the parsing. It keeps the nodes in a vector instead of an arena and links them to
+//! each other by index, and owns its strings instead of using tendrils, so it is `Send` and
+//! `Sync`. It can't be changed: it is read through `NodeRef`s or `TreeNavigator`, and can be
+//! shared by putting it in an `Arc`. The arena can be dropped once the copy is made.
+//!
+//! ```text
+//! let arena = Arena::new();
+//! let dom = parse_document(ArenaDom::new(&arena), Default::default()).one(html);
+//! let dom = Arc::new(SyncDom::from(dom));
+//! ```
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::ptr;
+
+use markup5ever::interface::tree_builder::{self, QuirksMode};
+use markup5ever::serialize::TraversalScope::{ChildrenOnly, IncludeNode};
+use markup5ever::serialize::{Serialize, Serializer, TraversalScope};
+use markup5ever::traverse::{self, TreeNavigator};
+use markup5ever::{LocalName, Namespace, QualName};
+use markup5ever_arena::{self as arena, ArenaDom};
+use tendril::StrTendril;
+
+/// The position of a node in a `SyncDom`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// The different kinds of nodes in the DOM.
+#[derive(Debug)]
+pub enum NodeData {
+    /// The `Document` itself, or the contents of a template.
+    Document,
+
+    /// A `DOCTYPE` with name, public id, and system id.
+    Doctype {
+        name: String,
+        public_id: String,
+        system_id: String,
+    },
+
+    /// A text node.
+    Text { contents: String },
+
+    /// A comment.
+    Comment { contents: String },
+
+    /// A CDATA section.
+    CData { contents: String },
+
+    /// An element with attributes.
+    Element {
+        name: QualName,
+        attrs: Vec<Attribute>,
+
+        /// For HTML \<template\> elements, the [template contents].
+        ///
+        /// [template contents]: https://html.spec.whatwg.org/multipage/#template-contents
+        template_contents: Option<NodeId>,
+
+        /// Whether the node is a [HTML integration point].
+        ///
+        /// [HTML integration point]: https://html.spec.whatwg.org/multipage/#html-integration-point
+        mathml_annotation_xml_integration_point: bool,
+    },
+
+    /// A processing instruction.
+    ProcessingInstruction { target: String, contents: String },
+}
+
+/// An element's attribute.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute {
+    pub name: QualName,
+    pub value: String,
+}
+
+/// An XML declaration, as in `tree_builder::XmlDeclaration`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XmlDeclaration {
+    /// The `version` pseudo-attribute.
+    pub version: String,
+    /// The `encoding` pseudo-attribute, if present.
+    pub encoding: Option<String>,
+    /// The `standalone` pseudo-attribute, if present.
+    pub standalone: Option<bool>,
+}
+
+struct Node {
+    parent: Option<NodeId>,
+    previous_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+    first_child: Option<NodeId>,
+    last_child: Option<NodeId>,
+    data: NodeData,
+}
+
+impl Node {
+    fn new(data: NodeData) -> Node {
+        Node {
+            parent: None,
+            previous_sibling: None,
+            next_sibling: None,
+            first_child: None,
+            last_child: None,
+            data,
+        }
+    }
+}
+
+/// The DOM itself; the result of parsing.
+pub struct SyncDom {
+    nodes: Vec<Node>,
+
+    /// Errors that occurred during parsing.
+    pub errors: Vec<Cow<'static, str>>,
+
+    /// The document's quirks mode.
+    pub quirks_mode: QuirksMode,
+
+    /// The document's XML declaration, if it has one.
+    pub xml_declaration: Option<XmlDeclaration>,
+}
+
+impl SyncDom {
+    /// The `Document` node.
+    pub fn document(&self) -> NodeRef<'_> {
+        self.get(NodeId(0))
+    }
+
+    /// The node with the given id. Panics if the id is not from this DOM.
+    pub fn get(&self, id: NodeId) -> NodeRef<'_> {
+        assert!(id.0 < self.nodes.len(), "no such node!");
+        NodeRef { dom: self, id }
+    }
+
+    fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    fn new_node(&mut self, data: NodeData) -> NodeId {
+        self.nodes.push(Node::new(data));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Add a copy of an arena node, without its children, and queue a template's contents to be
+    /// copied.
+    fn copy_node<'arena>(
+        &mut self,
+        node: arena::Ref<'arena>,
+        pending: &mut Vec<(arena::Ref<'arena>, NodeId)>,
+    ) -> NodeId {
+        let data = match node.data {
+            arena::NodeData::Document => NodeData::Document,
+            arena::NodeData::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+            } => NodeData::Doctype {
+                name: name.to_string(),
+                public_id: public_id.to_string(),
+                system_id: system_id.to_string(),
+            },
+            arena::NodeData::Text { ref contents } => NodeData::Text {
+                contents: contents.borrow().to_string(),
+            },
+            arena::NodeData::Comment { ref contents } => NodeData::Comment {
+                contents: contents.to_string(),
+            },
+            arena::NodeData::CData { ref contents } => NodeData::CData {
+                contents: contents.to_string(),
+            },
+            arena::NodeData::Element {
+                ref name,
+                ref attrs,
+                template_contents,
+                mathml_annotation_xml_integration_point,
+            } => NodeData::Element {
+                name: name.clone(),
+                attrs: attrs.borrow().iter().map(Attribute::from).collect(),
+                template_contents: template_contents.map(|contents| {
+                    let id = self.new_node(NodeData::Document);
+                    pending.push((contents, id));
+                    id
+                }),
+                mathml_annotation_xml_integration_point,
+            },
+            arena::NodeData::ProcessingInstruction {
+                ref target,
+                ref contents,
+            } => NodeData::ProcessingInstruction {
+                target: target.to_string(),
+                contents: contents.to_string(),
+            },
+        };
+        self.new_node(data)
+    }
+
+    fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        let last_child = self.node_mut(parent).last_child.replace(child);
+        match last_child {
+            Some(last_child) => self.node_mut(last_child).next_sibling = Some(child),
+            None => self.node_mut(parent).first_child = Some(child),
+        }
+        let child = self.node_mut(child);
+        child.parent = Some(parent);
+        child.previous_sibling = last_child;
+    }
+}
+
+/// Copies the document out of the arena. Nodes that the tree builder removed from the tree are
+/// left behind.
+impl<'arena> From<ArenaDom<'arena>> for SyncDom {
+    fn from(dom: ArenaDom<'arena>) -> SyncDom {
+        let mut sync_dom = SyncDom {
+            nodes: vec![Node::new(NodeData::Document)],
+            errors: dom.errors,
+            quirks_mode: dom.quirks_mode,
+            xml_declaration: dom.xml_declaration.map(XmlDeclaration::from),
+        };
+        let mut pending = vec![(dom.document, NodeId(0))];
+        while let Some((from, to)) = pending.pop() {
+            for child in from.children() {
+                let id = sync_dom.copy_node(child, &mut pending);
+                sync_dom.append_child(to, id);
+                pending.push((child, id));
+            }
+        }
+        sync_dom
+    }
+}
+
+/// Lets code written against `TreeNavigator` walk the DOM, with `NodeId`s as handles. Template
+/// contents aren't children, as in the DOM.
+impl TreeNavigator for SyncDom {
+    type Handle = NodeId;
+
+    fn kind(&self, node: &NodeId) -> traverse::NodeKind {
+        match self.node(*node).data {
+            NodeData::Document => traverse::NodeKind::Document,
+            NodeData::Doctype { .. } => traverse::NodeKind::Doctype,
+            NodeData::Element { .. } => traverse::NodeKind::Element,
+            NodeData::Text { .. } => traverse::NodeKind::Text,
+            NodeData::Comment { .. } => traverse::NodeKind::Comment,
+            NodeData::CData { .. } => traverse::NodeKind::CData,
+            NodeData::ProcessingInstruction { .. } => traverse::NodeKind::ProcessingInstruction,
+        }
+    }
+
+    fn parent(&self, node: &NodeId) -> Option<NodeId> {
+        self.node(*node).parent
+    }
+
+    fn first_child(&self, node: &NodeId) -> Option<NodeId> {
+        self.node(*node).first_child
+    }
+
+    fn last_child(&self, node: &NodeId) -> Option<NodeId> {
+        self.node(*node).last_child
+    }
+
+    fn next_sibling(&self, node: &NodeId) -> Option<NodeId> {
+        self.node(*node).next_sibling
+    }
+
+    fn previous_sibling(&self, node: &NodeId) -> Option<NodeId> {
+        self.node(*node).previous_sibling
+    }
+
+    fn name(&self, node: &NodeId) -> Option<QualName> {
+        match self.node(*node).data {
+            NodeData::Element { ref name, .. } => Some(name.clone()),
+            NodeData::ProcessingInstruction { ref target, .. } => {
+                Some(QualName::new(None, ns!(), LocalName::from(&**target)))
+            },
+            _ => None,
+        }
+    }
+
+    fn attributes(&self, node: &NodeId) -> Vec<markup5ever::Attribute> {
+        match self.node(*node).data {
+            NodeData::Element { ref attrs, .. } => attrs
+                .iter()
+                .map(|attr| markup5ever::Attribute {
+                    name: attr.name.clone(),
+                    value: StrTendril::from_slice(&attr.value),
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    fn attribute(&self, node: &NodeId, ns: &Namespace, local: &LocalName) -> Option<StrTendril> {
+        match self.node(*node).data {
+            NodeData::Element { ref attrs, .. } => attrs
+                .iter()
+                .find(|attr| attr.name.ns == *ns && attr.name.local == *local)
+                .map(|attr| StrTendril::from_slice(&attr.value)),
+            _ => None,
+        }
+    }
+
+    fn text(&self, node: &NodeId) -> Option<StrTendril> {
+        match self.node(*node).data {
+            NodeData::Text { ref contents } |
+            NodeData::Comment { ref contents } |
+            NodeData::CData { ref contents } |
+            NodeData::ProcessingInstruction { ref contents, .. } => {
+                Some(StrTendril::from_slice(contents))
+            },
+            _ => None,
+        }
+    }
+
+    fn doctype(&self, node: &NodeId) -> Option<traverse::Doctype> {
+        match self.node(*node).data {
+            NodeData::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+            } => Some(traverse::Doctype {
+                name: StrTendril::from_slice(name),
+                public_id: StrTendril::from_slice(public_id),
+                system_id: StrTendril::from_slice(system_id),
+            }),
+            _ => None,
+        }
+    }
+
+    fn template_contents(&self, node: &NodeId) -> Option<NodeId> {
+        match self.node(*node).data {
+            NodeData::Element {
+                template_contents, ..
+            } => template_contents,
+            _ => None,
+        }
+    }
+
+    fn same_node(&self, x: &NodeId, y: &NodeId) -> bool {
+        x == y
+    }
+}
+
+impl<'a> From<&'a markup5ever::Attribute> for Attribute {
+    fn from(attr: &'a markup5ever::Attribute) -> Attribute {
+        Attribute {
+            name: attr.name.clone(),
+            value: attr.value.to_string(),
+        }
+    }
+}
+
+impl From<tree_builder::XmlDeclaration> for XmlDeclaration {
+    fn from(declaration: tree_builder::XmlDeclaration) -> XmlDeclaration {
+        XmlDeclaration {
+            version: declaration.version.into(),
+            encoding: declaration.encoding.map(String::from),
+            standalone: declaration.standalone,
+        }
+    }
+}
+
+/// A reference to a node in a `SyncDom`, for reading the tree.
+#[derive(Clone, Copy)]
+pub struct NodeRef<'a> {
+    dom: &'a SyncDom,
+    id: NodeId,
+}
+
+impl<'a> NodeRef<'a> {
+    /// The node's id, which `SyncDom::get` turns back into a `NodeRef`.
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// The node's contents.
+    pub fn data(&self) -> &'a NodeData {
+        &self.dom.node(self.id).data
+    }
+
+    pub fn parent(&self) -> Option<NodeRef<'a>> {
+        self.link(|node| node.parent)
+    }
+
+    pub fn previous_sibling(&self) -> Option<NodeRef<'a>> {
+        self.link(|node| node.previous_sibling)
+    }
+
+    pub fn next_sibling(&self) -> Option<NodeRef<'a>> {
+        self.link(|node| node.next_sibling)
+    }
+
+    pub fn first_child(&self) -> Option<NodeRef<'a>> {
+        self.link(|node| node.first_child)
+    }
+
+    pub fn last_child(&self) -> Option<NodeRef<'a>> {
+        self.link(|node| node.last_child)
+    }
+
+    /// The node's children, in order.
+    pub fn children(&self) -> Children<'a> {
+        Children {
+            next: self.first_child(),
+        }
+    }
+
+    /// For a \<template\> element, the `Document` node that holds its contents.
+    pub fn template_contents(&self) -> Option<NodeRef<'a>> {
+        match *self.data() {
+            NodeData::Element {
+                template_contents, ..
+            } => template_contents.map(|id| self.dom.get(id)),
+            _ => None,
+        }
+    }
+
+    fn link<F: Fn(&Node) -> Option<NodeId>>(&self, link: F) -> Option<NodeRef<'a>> {
+        link(self.dom.node(self.id)).map(|id| self.dom.get(id))
+    }
+
+    /// The children to serialize: the template contents' children for a `<template>`, and the
+    /// node's own children otherwise.
+    fn serialized_children(&self) -> Children<'a> {
+        self.template_contents().unwrap_or(*self).children()
+    }
+}
+
+impl<'a> PartialEq for NodeRef<'a> {
+    fn eq(&self, other: &NodeRef<'a>) -> bool {
+        ptr::eq(self.dom, other.dom) && self.id == other.id
+    }
+}
+
+impl<'a> Eq for NodeRef<'a> {}
+
+impl<'a> fmt::Debug for NodeRef<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("NodeRef")
+            .field("id", &self.id)
+            .field("data", self.data())
+            .finish()
+    }
+}
+
+/// An iterator over the children of a node.
+pub struct Children<'a> {
+    next: Option<NodeRef<'a>>,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = NodeRef<'a>;
+
+    fn next(&mut self) -> Option<NodeRef<'a>> {
+        let node = self.next?;
+        self.next = node.next_sibling();
+        Some(node)
+    }
+}
+
+enum SerializeOp<'a> {
+    Open(NodeRef<'a>),
+    Close(QualName),
+}
+
+impl<'a> Serialize for NodeRef<'a> {
+    fn serialize<S>(&self, serializer: &mut S, traversal_scope: TraversalScope) -> io::Result<()>
+    where
+        S: Serializer,
+    {
+        let mut ops = VecDeque::new();
+        match traversal_scope {
+            IncludeNode => ops.push_back(SerializeOp::Open(*self)),
+            ChildrenOnly(_) => ops.extend(self.serialized_children().map(SerializeOp::Open)),
+        }
+
+        while let Some(op) = ops.pop_front() {
+            match op {
+                SerializeOp::Open(node) => match *node.data() {
+                    NodeData::Element {
+                        ref name,
+                        ref attrs,
+                        ..
+                    } => {
+                        serializer.start_elem(
+                            name.clone(),
+                            attrs.iter().map(|at| (&at.name, &at.value[..])),
+                        )?;
+
+                        ops.push_front(SerializeOp::Close(name.clone()));
+                        let children = node.serialized_children().collect::<Vec<_>>();
+                        for child in children.into_iter().rev() {
+                            ops.push_front(SerializeOp::Open(child));
+                        }
+                    },
+
+                    NodeData::Doctype { ref name, .. } => serializer.write_doctype(name)?,
+
+                    NodeData::Text { ref contents } => serializer.write_text(contents)?,
+
+                    NodeData::Comment { ref contents } => serializer.write_comment(contents)?,
+
+                    NodeData::CData { ref contents } => serializer.write_cdata(contents)?,
+
+                    NodeData::ProcessingInstruction {
+                        ref target,
+                        ref contents,
+                    } => serializer.write_processing_instruction(target, contents)?,
+
+                    NodeData::Document => panic!("Can't serialize Document node itself"),
+                },
+
+                SerializeOp::Close(name) => serializer.end_elem(name)?,
+            }
+        }
+
+        Ok(())
+    }
+}
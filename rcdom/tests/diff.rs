@@ -0,0 +1,206 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use html5ever::tendril::TendrilSink;
+use html5ever::{parse_document, serialize};
+use markup5ever_rcdom::diff::{diff, DiffOpts, Edit, Path, Step};
+use markup5ever_rcdom::{Handle, RcDom, SerializableHandle};
+
+fn parse(html: &str) -> Handle {
+    parse_document(RcDom::default(), Default::default())
+        .one(html)
+        .document
+}
+
+fn parse_xml(xml: &str) -> Handle {
+    xml5ever::driver::parse_document(RcDom::default(), Default::default())
+        .one(xml)
+        .document
+}
+
+fn to_html(node: &Handle) -> String {
+    let node: SerializableHandle = node.clone().into();
+    let mut output = vec![];
+    serialize(&mut output, &node, Default::default()).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+fn to_xml(node: &Handle) -> String {
+    let node: SerializableHandle = node.clone().into();
+    let mut output = vec![];
+    xml5ever::serialize::serialize(&mut output, &node, Default::default()).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+/// Diff two documents, check that applying the diff to the old one gives the new one, and
+/// return the report.
+fn check(old: &str, new: &str, opts: &DiffOpts) -> String {
+    let (old_dom, new_dom) = (parse(old), parse(new));
+    let expected = to_html(&new_dom);
+    let result = diff(&old_dom, &new_dom, opts);
+    result.apply(&old_dom).unwrap();
+    if !opts.ignore_attribute_order {
+        assert_eq!(
+            to_html(&old_dom),
+            expected,
+            "{} -> {}\n{}",
+            old,
+            new,
+            result
+        );
+    }
+    assert_eq!(to_html(&new_dom), expected);
+    assert!(
+        diff(&old_dom, &new_dom, opts).is_empty(),
+        "{} -> {}",
+        old,
+        new
+    );
+    result.to_string()
+}
+
+#[test]
+fn patches() {
+    let documents = &[
+        "",
+        "<!DOCTYPE html><title>A</title><p class=x id=y>One<!-- two --><b>three</b>",
+        "<!DOCTYPE html><title>B</title><p id=y class=z>One<!-- 2 --><i>three</i>four",
+        "<ul><li>1<li>2<li>3<li>4<li>5</ul>",
+        "<ul><li>5<li>1<li>3<li>2<li>6</ul>",
+        "<div><p>a</p><p>b</p></div><div><p>c</p></div>",
+        "<div><p>c</p></div><div><p>a</p></div><p>b</p>",
+        "<table><tr><td>1<td>2</tr><tr><td>3</table>",
+        "<template><b>x</b>y</template><template></template>",
+        "<template><i>x</i>z</template><template>w</template><svg><rect x=1 /></svg>",
+        "<svg><circle r=2 /><rect x=1 /></svg><math><mi>x</mi></math>",
+        "<div>\n  <p>a</p>\n  <p>b</p>\n</div>",
+    ];
+    for opts in &[
+        DiffOpts::default(),
+        DiffOpts {
+            ignore_whitespace: false,
+            ignore_attribute_order: true,
+        },
+    ] {
+        for old in documents {
+            for new in documents {
+                check(old, new, opts);
+            }
+        }
+    }
+
+    let report = check(
+        "<p class=x id=y>One<!-- two --><b>three</b>",
+        "<p id=y class=z>One<!-- 2 --><i>three</i>",
+        &DiffOpts::default(),
+    );
+    assert_eq!(
+        report,
+        "change attribute class at /0/1/0: \"x\" -> \"z\"\n\
+         reorder attributes at /0/1/0: id class\n\
+         remove <b> at /0/1/0/2\n\
+         insert <i> at /0/1/0/2\n\
+         change text at /0/1/0/1: \" two \" -> \" 2 \"\n"
+    );
+}
+
+#[test]
+fn moves() {
+    let report = check(
+        "<ul><li>1<li>2<li>3</ul>",
+        "<ul><li>3<li>1<li>2</ul>",
+        &DiffOpts::default(),
+    );
+    assert_eq!(report, "move /0/1/0/2 to /0/1/0/0\n");
+
+    // A moved subtree is still compared with the one it matches.
+    let report = check(
+        "<div id=a>x</div><p>y</p>",
+        "<p>y</p><div id=b>x</div>",
+        &DiffOpts::default(),
+    );
+    assert_eq!(
+        report,
+        "move /0/1/1 to /0/1/0\n\
+         change attribute id at /0/1/1: \"a\" -> \"b\"\n"
+    );
+}
+
+#[test]
+fn ignore_options() {
+    let old = parse("<div>\n  <p b=2 a=1>x</p>\n</div>");
+    let new = parse("<div><p a=1 b=2>x</p><p>y</p></div>");
+    assert_eq!(
+        diff(&old, &new, &DiffOpts::default()).to_string(),
+        "remove \"\\n\" at /0/1/0/2\n\
+         remove \"\\n  \" at /0/1/0/0\n\
+         insert <p> at /0/1/0/1\n\
+         reorder attributes at /0/1/0/0: a b\n"
+    );
+
+    let opts = DiffOpts {
+        ignore_whitespace: true,
+        ignore_attribute_order: true,
+    };
+    let result = diff(&old, &new, &opts);
+    assert_eq!(result.to_string(), "insert <p> at /0/1/0/2\n");
+    result.apply(&old).unwrap();
+    assert_eq!(
+        to_html(&old),
+        "<html><head></head><body><div>\n  <p b=\"2\" a=\"1\">x</p><p>y</p>\n</div></body></html>"
+    );
+    assert!(diff(&old, &new, &opts).is_empty());
+    assert!(!diff(&old, &new, &DiffOpts::default()).is_empty());
+}
+
+#[test]
+fn namespaces() {
+    let old = parse_xml("<doc xmlns:a='urn:a' xmlns:b='urn:b'><a:x a:v='1'/><b:x/></doc>");
+    let new = parse_xml(
+        "<doc xmlns:a='urn:a' xmlns:b='urn:b'><b:x/><c:x xmlns:c='urn:a' c:v='2'/></doc>",
+    );
+    let expected = to_xml(&new);
+    let result = diff(&old, &new, &DiffOpts::default());
+    // The prefixes changed, but `a:x` and `c:x` are the same element.
+    assert_eq!(
+        result.to_string(),
+        "move /0/1 to /0/0\n\
+         rename <a:x> at /0/1 to <c:x>\n\
+         change attribute c:v at /0/1: \"1\" -> \"2\"\n"
+    );
+    result.apply(&old).unwrap();
+    assert_eq!(to_xml(&old), expected);
+}
+
+#[test]
+fn bad_patches() {
+    let old = parse("<p>x</p>");
+    let new = parse("<p>y</p><p>z</p>");
+    let result = diff(&old, &new, &DiffOpts::default());
+    let other = parse("");
+    let error = result.apply(&other).unwrap_err();
+    assert_eq!(error.edit, 0);
+    assert_eq!(error.to_string(), "no node at path in edit 0");
+
+    let mut result = result;
+    result.edits = vec![Edit::Move {
+        from: Path(vec![Step::Child(0), Step::TemplateContents]),
+        to: Path(vec![Step::Child(0)]),
+    }];
+    assert!(result.apply(&parse("")).is_err());
+}
+
+#[test]
+fn deep_tree() {
+    let old = "<div>".repeat(10_000) + "x";
+    let new = "<div>".repeat(10_000) + "y";
+    let report = check(&old, &new, &DiffOpts::default());
+    assert!(report.starts_with("change text at /0/1/0/0/"));
+    assert_eq!(report.lines().count(), 1);
+}
@@ -9,11 +9,15 @@
 
 use html5ever::driver::ParseOpts;
 use html5ever::serialize::{Serialize, SerializeOpts, Serializer, TraversalScope};
+use html5ever::serialize::{CharRefStyle, EscapePolicy, SingleQuote};
+use html5ever::serialize::{ShadowRootInit, ShadowRootMode};
 use html5ever::tendril::{SliceExt, StrTendril, TendrilSink};
 use html5ever::tokenizer::{TagKind, Token, TokenSink, TokenSinkResult, Tokenizer};
 use html5ever::{parse_document, parse_fragment, serialize, QualName};
+use markup5ever::encoding_rs;
+use markup5ever::interface::TreeSink;
 use markup5ever::{local_name, namespace_url, ns};
-use markup5ever_rcdom::{RcDom, SerializableHandle};
+use markup5ever_rcdom::{GetHtmlOptions, Handle, RcDom, SerializableHandle};
 
 use std::io;
 
@@ -231,6 +235,17 @@ test!(attr_ns_2, r#"<svg xmlns:foo="bleh"></svg>"#);
 test!(attr_ns_3, r#"<svg xmlns:xlink="bleh"></svg>"#);
 test!(attr_ns_4, r#"<svg xlink:href="bleh"></svg>"#);
 
+test!(template_contents, r#"<template><p>Hello</p></template>"#);
+test!(
+    nested_template_contents,
+    r#"<template><template><b>x</b></template>y</template>"#
+);
+test!(
+    template_in_table,
+    r#"<table><template><tr><td>x</td></tr></template></table>"#,
+    r#"<table><template><tr><td>x</td></tr></template></table>"#
+);
+
 test_no_parse!(malformed_tokens, r#"foo</div><div>"#);
 
 #[test]
@@ -259,3 +274,222 @@ fn deep_tree() {
     serialize(&mut ret_val, &document, opts)
         .expect("Writing to a string shouldn't fail (expect on OOM)");
 }
+
+fn parse_body_fragment(input: &str) -> RcDom {
+    parse_fragment(
+        RcDom::default(),
+        ParseOpts::default(),
+        QualName::new(None, ns!(html), local_name!("body")),
+        vec![],
+    )
+    .one(input)
+}
+
+fn serialize_with_shadow_roots(node: Handle, opts: GetHtmlOptions) -> String {
+    let mut result = vec![];
+    serialize(&mut result, &SerializableHandle::new(node, opts), Default::default()).unwrap();
+    String::from_utf8(result).unwrap()
+}
+
+#[test]
+fn shadow_root_serialization() {
+    let dom = parse_body_fragment("<div><p>light</p></div>");
+    let root = dom.document.children.borrow()[0].clone();
+    let host = root.children.borrow()[0].clone();
+    let shadow = host
+        .attach_shadow(ShadowRootInit {
+            serializable: true,
+            ..ShadowRootInit::new(ShadowRootMode::Open)
+        })
+        .unwrap();
+    let mut slot = parse_body_fragment("<slot></slot>");
+    let slot_root = slot.document.children.borrow()[0].clone();
+    slot.reparent_children(&slot_root, &shadow);
+
+    // Shadow roots are left out unless asked for.
+    assert_eq!(
+        serialize_with_shadow_roots(root.clone(), GetHtmlOptions::default()),
+        "<div><p>light</p></div>"
+    );
+
+    let opts = GetHtmlOptions {
+        serializable_shadow_roots: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        serialize_with_shadow_roots(root.clone(), opts.clone()),
+        r#"<div><template shadowrootmode="open" shadowrootserializable=""><slot></slot></template><p>light</p></div>"#
+    );
+
+    // Serializing the host's children starts with its own shadow root.
+    assert_eq!(
+        serialize_with_shadow_roots(host.clone(), opts),
+        r#"<template shadowrootmode="open" shadowrootserializable=""><slot></slot></template><p>light</p>"#
+    );
+}
+
+#[test]
+fn explicit_shadow_roots() {
+    let dom = parse_body_fragment("<span></span>");
+    let root = dom.document.children.borrow()[0].clone();
+    let host = root.children.borrow()[0].clone();
+    let shadow = host
+        .attach_shadow(ShadowRootInit {
+            delegates_focus: true,
+            clonable: true,
+            ..ShadowRootInit::new(ShadowRootMode::Closed)
+        })
+        .unwrap();
+    assert!(host
+        .attach_shadow(ShadowRootInit::new(ShadowRootMode::Open))
+        .is_none());
+
+    // Not serializable, so `serializable_shadow_roots` alone doesn't include it.
+    let opts = GetHtmlOptions {
+        serializable_shadow_roots: true,
+        ..Default::default()
+    };
+    assert_eq!(serialize_with_shadow_roots(root.clone(), opts), "<span></span>");
+
+    let opts = GetHtmlOptions {
+        serializable_shadow_roots: false,
+        shadow_roots: vec![shadow],
+    };
+    assert_eq!(
+        serialize_with_shadow_roots(root, opts),
+        r#"<span><template shadowrootmode="closed" shadowrootdelegatesfocus="" shadowrootclonable=""></template></span>"#
+    );
+}
+
+fn serialize_with_policy(input: &str, escape_policy: EscapePolicy) -> String {
+    let dom = parse_body_fragment(input);
+    let inner: SerializableHandle = dom.document.children.borrow()[0].clone().into();
+    let mut result = vec![];
+    let opts = SerializeOpts {
+        escape_policy,
+        ..Default::default()
+    };
+    serialize(&mut result, &inner, opts).unwrap();
+    String::from_utf8(result).unwrap()
+}
+
+#[test]
+fn escape_ascii_only() {
+    let input = "<p title=\"caf\u{e9} \u{1F600}\">\u{a0}caf\u{e9} \u{2014} \u{1F600}</p>";
+    let mut policy = EscapePolicy {
+        ascii_only: true,
+        ..EscapePolicy::html()
+    };
+    assert_eq!(
+        serialize_with_policy(input, policy.clone()),
+        "<p title=\"caf&#233; &#128512;\">&nbsp;caf&#233; &#8212; &#128512;</p>"
+    );
+
+    policy.char_refs = CharRefStyle::Hex;
+    assert_eq!(
+        serialize_with_policy(input, policy.clone()),
+        "<p title=\"caf&#xE9; &#x1F600;\">&nbsp;caf&#xE9; &#x2014; &#x1F600;</p>"
+    );
+
+    policy.char_refs = CharRefStyle::Named;
+    policy.nbsp = false;
+    assert_eq!(
+        serialize_with_policy(input, policy),
+        "<p title=\"caf&eacute; &#128512;\">&nbsp;caf&eacute; &mdash; &#128512;</p>"
+    );
+}
+
+#[test]
+fn escape_single_quote_and_nbsp() {
+    let input = "<p title=\"it's\">it's\u{a0}&amp;</p>";
+    assert_eq!(
+        serialize_with_policy(input, EscapePolicy::html()),
+        "<p title=\"it's\">it's&nbsp;&amp;</p>"
+    );
+    let policy = EscapePolicy {
+        single_quote: SingleQuote::Numeric,
+        nbsp: false,
+        ..EscapePolicy::html()
+    };
+    assert_eq!(
+        serialize_with_policy(input, policy),
+        "<p title=\"it&#39;s\">it's\u{a0}&amp;</p>"
+    );
+    let policy = EscapePolicy {
+        single_quote: SingleQuote::Apos,
+        ..EscapePolicy::html()
+    };
+    assert_eq!(
+        serialize_with_policy(input, policy),
+        "<p title=\"it&apos;s\">it's&nbsp;&amp;</p>"
+    );
+}
+
+#[test]
+fn escape_leaves_raw_text_alone() {
+    let policy = EscapePolicy {
+        ascii_only: true,
+        ..EscapePolicy::html()
+    };
+    assert_eq!(
+        serialize_with_policy("<script>'\u{e9}'</script>\u{e9}", policy),
+        "<script>'\u{e9}'</script>&#233;"
+    );
+}
+
+fn serialize_document(input: &str, opts: SerializeOpts) -> Vec<u8> {
+    let dom = parse_document(RcDom::default(), ParseOpts::default()).one(input);
+    let document: SerializableHandle = dom.document.clone().into();
+    let mut result = vec![];
+    serialize(&mut result, &document, opts).unwrap();
+    result
+}
+
+#[test]
+fn output_encoding() {
+    let opts = SerializeOpts {
+        encoding: encoding_rs::WINDOWS_1252,
+        ..Default::default()
+    };
+    assert_eq!(
+        serialize_document("<p title=\"\u{65e5}\">caf\u{e9} \u{20ac} \u{65e5}", opts),
+        &b"<html><head></head><body><p title=\"&#26085;\">caf\xe9 \x80 &#26085;</p></body></html>"[..]
+    );
+}
+
+#[test]
+fn declare_encoding() {
+    let opts = SerializeOpts {
+        encoding: encoding_rs::SHIFT_JIS,
+        declare_encoding: true,
+        ..Default::default()
+    };
+    let input = "<meta charset=utf-8><title>\u{65e5}</title>\
+                 <meta http-equiv=Content-Type content=\"text/html; charset=utf-8\">\
+                 <meta name=viewport content=width=device-width>";
+    assert_eq!(
+        serialize_document(input, opts.clone()),
+        &b"<html><head><meta charset=\"Shift_JIS\"><title>\x93\xfa</title>\
+           <meta name=\"viewport\" content=\"width=device-width\"></head><body></body></html>"[..]
+    );
+
+    // References aren't decoded in raw text or comments.
+    for input in &[
+        "<script>'\u{e9}'</script>",
+        "<style>p::after { content: '\u{e9}' }</style>",
+        "<!--\u{e9}-->",
+        "<p \u{e9}=1>",
+    ] {
+        let dom = parse_document(RcDom::default(), ParseOpts::default()).one(*input);
+        let document: SerializableHandle = dom.document.clone().into();
+        let error = serialize(&mut vec![], &document, opts.clone()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{}", input);
+    }
+
+    // Without a <head>, there is nowhere to declare the encoding.
+    let dom = parse_body_fragment("<meta charset=utf-8><p>x</p>");
+    let inner: SerializableHandle = dom.document.children.borrow()[0].clone().into();
+    let mut result = vec![];
+    serialize(&mut result, &inner, opts).unwrap();
+    assert_eq!(result, b"<p>x</p>");
+}
@@ -0,0 +1,145 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use html5ever::serialize::{HtmlSerializer, SerializeOpts};
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::{parse_document, serialize};
+use markup5ever_rcdom::{RcDom, SerializableHandle, StreamingSerializer};
+
+type Sink = StreamingSerializer<HtmlSerializer<Vec<u8>>>;
+
+fn new_sink() -> Sink {
+    StreamingSerializer::new(HtmlSerializer::new(vec![], SerializeOpts::default()))
+}
+
+fn serialize_tree(input: &str) -> String {
+    let dom = parse_document(RcDom::default(), Default::default()).one(input);
+    let mut result = vec![];
+    let document: SerializableHandle = dom.document.clone().into();
+    serialize(&mut result, &document, Default::default()).unwrap();
+    String::from_utf8(result).unwrap()
+}
+
+fn written(sink: &Sink) -> String {
+    String::from_utf8(sink.serializer().writer.clone()).unwrap()
+}
+
+/// Parse `input` one character at a time and check the streamed output against serializing the
+/// finished tree.
+fn check(input: &str) {
+    let mut parser = parse_document(new_sink(), Default::default());
+    for c in input.chars() {
+        parser.process(StrTendril::from_char(c));
+    }
+    let sink = parser.finish();
+    assert!(sink.errors().iter().all(|e| !e.contains("already written")));
+    let streamed = String::from_utf8(sink.into_serializer().unwrap().writer).unwrap();
+    assert_eq!(streamed, serialize_tree(input), "input: {}", input);
+}
+
+macro_rules! test {
+    ($name:ident, $input:expr) => {
+        #[test]
+        fn $name() {
+            check($input);
+        }
+    };
+}
+
+test!(simple, "<!DOCTYPE html><title>x</title><p>a<b>b</b>c</p>");
+test!(implied_tags, "<p>one<p>two<li>three");
+test!(
+    after_head,
+    "<head></head> <meta charset=utf-8> <!-- c --><body>x"
+);
+test!(misnested_formatting, "<p>1<b>2<i>3</b>4</i>5</p>");
+test!(adoption_agency, "<a>1<p>2</a>3</p><div>4</div>");
+test!(
+    adoption_agency_nested,
+    "<b><div><i><p>x</b>y</i>z</p></div>"
+);
+test!(
+    adoption_agency_table,
+    "<a><table><tr><td>x</td></tr><a>y</table>"
+);
+test!(
+    foster_parenting,
+    "<table><tr><td>a</td></tr>b<div>c</div><tr>d</table>e"
+);
+test!(foster_text_merge, "x<table>y<tr>z</tr></table>");
+test!(
+    nested_tables,
+    "<table><td><table>x<tr><td>y</table>z</table>"
+);
+test!(template, "<template><tr><td>x</td></tr></template><p>y");
+test!(
+    formatting_in_cell,
+    "<font><table><tr><td><p>a</p><p>b</p></table>c</font>"
+);
+test!(
+    comments_after_body,
+    "<p>x</p></body><!-- a --></html><!-- b -->"
+);
+test!(reconstruct_formatting, "<p><b>1<p>2<p>3</b>4");
+test!(void_elements, "<p>a<br>b<img src=x>c<hr>d");
+test!(select, "<select><option>a<option>b</select>c");
+test!(
+    merged_attributes,
+    "<html><html lang=en><head></head><html dir=ltr><body><body class=x>y"
+);
+test!(frameset, "<div><frameset><frame></frameset>");
+
+#[test]
+fn writes_before_finish() {
+    let mut parser = parse_document(new_sink(), Default::default());
+    parser.process("<!DOCTYPE html><p>one</p><p>two</p>".into());
+    parser.process("<p>thr".into());
+    assert_eq!(
+        written(&parser.tokenizer.sink.sink),
+        "<!DOCTYPE html><html><head></head><body><p>one</p><p>two</p>"
+    );
+    let sink = parser.finish();
+    assert_eq!(
+        String::from_utf8(sink.into_serializer().unwrap().writer).unwrap(),
+        "<!DOCTYPE html><html><head></head><body><p>one</p><p>two</p><p>thr</p></body></html>"
+    );
+}
+
+#[test]
+fn holds_back_open_table() {
+    let mut parser = parse_document(new_sink(), Default::default());
+    parser.process("<p>x</p><table><tr><td>y</td></tr>".into());
+    assert_eq!(
+        written(&parser.tokenizer.sink.sink),
+        "<html><head></head><body><p>x</p>"
+    );
+    parser.process("z</table>".into());
+    assert_eq!(
+        written(&parser.tokenizer.sink.sink),
+        "<html><head></head><body><p>x</p>z<table><tbody><tr><td>y</td></tr></tbody></table>"
+    );
+}
+
+#[test]
+fn holds_back_inside_formatting_element() {
+    let mut parser = parse_document(new_sink(), Default::default());
+    parser.process("<b>1<p>2".into());
+    assert_eq!(written(&parser.tokenizer.sink.sink), "<html><head></head>");
+    parser.process("</b>3</p>".into());
+    assert_eq!(
+        written(&parser.tokenizer.sink.sink),
+        "<html><head></head><body><b>1</b><p><b>2</b>3</p>"
+    );
+}
+
+#[test]
+fn attributes_after_start_tag_are_reported() {
+    let sink = parse_document(new_sink(), Default::default()).one("<p>x</p><body class=late>");
+    assert!(sink.errors().iter().any(|e| e.contains("already written")));
+}
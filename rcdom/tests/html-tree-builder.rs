@@ -7,6 +7,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+extern crate markup5ever_arena as arena;
 extern crate markup5ever_rcdom as rcdom;
 extern crate rustc_test as test;
 #[macro_use]
@@ -25,10 +26,14 @@ use std::path::Path;
 use std::{env, fs, io};
 use test::{DynTestName, TestDesc, TestDescAndFn, TestFn};
 
+use arena::{Arena, ArenaDom};
 use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::tree_builder::TreeSink;
 use html5ever::{parse_document, parse_fragment, ParseOpts};
 use html5ever::{LocalName, QualName};
-use rcdom::{Handle, NodeData, RcDom};
+use markup5ever::traverse::{NodeKind, TreeNavigator};
+use rcdom::sync_dom::SyncDom;
+use rcdom::RcDom;
 
 fn parse_tests<It: Iterator<Item = String>>(mut lines: It) -> Vec<HashMap<String, String>> {
     let mut tests = vec![];
@@ -74,44 +79,37 @@ fn parse_tests<It: Iterator<Item = String>>(mut lines: It) -> Vec<HashMap<String
     tests
 }
 
-fn serialize(buf: &mut String, indent: usize, handle: Handle) {
+fn serialize<N: TreeNavigator>(buf: &mut String, indent: usize, dom: &N, node: N::Handle) {
     buf.push_str("|");
     buf.push_str(&repeat(" ").take(indent).collect::<String>());
 
-    let node = handle;
-    match node.data {
-        NodeData::Document => panic!("should not reach Document"),
+    match dom.kind(&node) {
+        NodeKind::Document => panic!("should not reach Document"),
 
-        NodeData::Doctype {
-            ref name,
-            ref public_id,
-            ref system_id,
-        } => {
+        NodeKind::Doctype => {
+            let doctype = dom.doctype(&node).unwrap();
             buf.push_str("<!DOCTYPE ");
-            buf.push_str(&name);
-            if !public_id.is_empty() || !system_id.is_empty() {
-                buf.push_str(&format!(" \"{}\" \"{}\"", public_id, system_id));
+            buf.push_str(&doctype.name);
+            if !doctype.public_id.is_empty() || !doctype.system_id.is_empty() {
+                buf.push_str(&format!(" \"{}\" \"{}\"", doctype.public_id, doctype.system_id));
             }
             buf.push_str(">\n");
         },
 
-        NodeData::Text { ref contents } => {
+        NodeKind::Text => {
             buf.push_str("\"");
-            buf.push_str(&contents.borrow());
+            buf.push_str(&dom.text(&node).unwrap());
             buf.push_str("\"\n");
         },
 
-        NodeData::Comment { ref contents } => {
+        NodeKind::Comment => {
             buf.push_str("<!-- ");
-            buf.push_str(&contents);
+            buf.push_str(&dom.text(&node).unwrap());
             buf.push_str(" -->\n");
         },
 
-        NodeData::Element {
-            ref name,
-            ref attrs,
-            ..
-        } => {
+        NodeKind::Element => {
+            let name = dom.name(&node).unwrap();
             buf.push_str("<");
             match name.ns {
                 ns!(svg) => buf.push_str("svg "),
@@ -121,7 +119,7 @@ fn serialize(buf: &mut String, indent: usize, handle: Handle) {
             buf.push_str(&*name.local);
             buf.push_str(">\n");
 
-            let mut attrs = attrs.borrow().clone();
+            let mut attrs = dom.attributes(&node);
             attrs.sort_by(|x, y| x.name.local.cmp(&y.name.local));
             // FIXME: sort by UTF-16 code unit
 
@@ -138,29 +136,53 @@ fn serialize(buf: &mut String, indent: usize, handle: Handle) {
             }
         },
 
-        NodeData::ProcessingInstruction { .. } => unreachable!(),
+        NodeKind::ProcessingInstruction | NodeKind::CData => unreachable!(),
     }
 
-    for child in node.children.borrow().iter() {
-        serialize(buf, indent + 2, child.clone());
+    for child in dom.children(&node) {
+        serialize(buf, indent + 2, dom, child);
     }
 
-    if let NodeData::Element {
-        ref template_contents,
-        ..
-    } = node.data
-    {
-        if let Some(ref content) = &*template_contents.borrow() {
-            buf.push_str("|");
-            buf.push_str(&repeat(" ").take(indent + 2).collect::<String>());
-            buf.push_str("content\n");
-            for child in content.children.borrow().iter() {
-                serialize(buf, indent + 4, child.clone());
-            }
+    if let Some(content) = dom.template_contents(&node) {
+        buf.push_str("|");
+        buf.push_str(&repeat(" ").take(indent + 2).collect::<String>());
+        buf.push_str("content\n");
+        for child in dom.children(&content) {
+            serialize(buf, indent + 4, dom, child);
         }
     }
 }
 
+/// Parse a test's input with a tree builder for `sink`, and print the children of the document,
+/// or of the root element for a fragment.
+fn parse<Sink: TreeSink>(
+    sink: Sink,
+    opts: ParseOpts,
+    data: StrTendril,
+    context: Option<&QualName>,
+) -> Sink::Output {
+    match context {
+        None => parse_document(sink, opts).one(data),
+        Some(context) => parse_fragment(sink, opts, context.clone(), vec![]).one(data),
+    }
+}
+
+/// Print the children of the document, or of the root element for a fragment.
+fn print_tree<N: TreeNavigator>(dom: &N, document: N::Handle, fragment: bool) -> String {
+    let parent = if fragment {
+        dom.first_child(&document).unwrap()
+    } else {
+        document
+    };
+    let mut result = String::new();
+    for child in dom.children(&parent) {
+        serialize(&mut result, 1, dom, child);
+    }
+    let len = result.len();
+    result.truncate(len - 1); // drop the trailing newline
+    result
+}
+
 fn make_test(
     tests: &mut Vec<TestDescAndFn>,
     ignores: &HashSet<String>,
@@ -218,34 +240,25 @@ fn make_test_desc_with_scripting_flag(
         testfn: TestFn::dyn_test_fn(move || {
             // Do this here because Tendril isn't Send.
             let data = StrTendril::from_slice(&data);
-            let mut result = String::new();
-            match context {
-                None => {
-                    let dom = parse_document(RcDom::default(), opts).one(data.clone());
-                    for child in dom.document.children.borrow().iter() {
-                        serialize(&mut result, 1, child.clone());
-                    }
-                },
-                Some(ref context) => {
-                    let dom = parse_fragment(RcDom::default(), opts, context.clone(), vec![])
-                        .one(data.clone());
-                    // fragment case: serialize children of the html element
-                    // rather than children of the document
-                    let doc = &dom.document;
-                    let root = &doc.children.borrow()[0];
-                    for child in root.children.borrow().iter() {
-                        serialize(&mut result, 1, child.clone());
-                    }
-                },
-            };
-            let len = result.len();
-            result.truncate(len - 1); // drop the trailing newline
-
-            if result != expected {
-                panic!(
-                    "\ninput: {}\ngot:\n{}\nexpected:\n{}\n",
-                    data, result, expected
-                );
+            let context = context.as_ref();
+            let fragment = context.is_some();
+            let rc_dom = parse(RcDom::default(), opts.clone(), data.clone(), context);
+            let arena = Arena::new();
+            let arena_dom = parse(ArenaDom::new(&arena), opts, data.clone(), context);
+            let mut results = vec![
+                ("RcDom", print_tree(&rc_dom, rc_dom.document.clone(), fragment)),
+                ("ArenaDom", print_tree(&arena_dom, arena_dom.document, fragment)),
+            ];
+            let sync_dom = SyncDom::from(arena_dom);
+            let document = sync_dom.document().id();
+            results.push(("SyncDom", print_tree(&sync_dom, document, fragment)));
+            for (dom, result) in results {
+                if result != expected {
+                    panic!(
+                        "\ninput: {}\ngot from {}:\n{}\nexpected:\n{}\n",
+                        data, dom, result, expected
+                    );
+                }
             }
         }),
     }
@@ -11,9 +11,23 @@ use std::borrow::Cow;
 pub struct LineCountingDOM {
     pub line_vec: Vec<(QualName, u64)>,
     pub current_line: u64,
+    pub popped: Vec<String>,
+    pub closed: Vec<String>,
     pub rcdom: RcDom,
 }
 
+impl LineCountingDOM {
+    fn new() -> Self {
+        LineCountingDOM {
+            line_vec: vec![],
+            current_line: 1,
+            popped: vec![],
+            closed: vec![],
+            rcdom: RcDom::default(),
+        }
+    }
+}
+
 impl TreeSink for LineCountingDOM {
     type Output = Self;
 
@@ -112,17 +126,22 @@ impl TreeSink for LineCountingDOM {
     fn set_current_line(&mut self, line_number: u64) {
         self.current_line = line_number;
     }
+
+    fn pop(&mut self, node: &Handle) {
+        let name = self.elem_name(node).local.to_string();
+        self.popped.push(name);
+    }
+
+    fn element_closed(&mut self, node: &Handle) {
+        let name = self.elem_name(node).local.to_string();
+        self.closed.push(name);
+    }
 }
 
 #[test]
 fn check_four_lines() {
     // Input
-    let sink = LineCountingDOM {
-        line_vec: vec![],
-        current_line: 1,
-        rcdom: RcDom::default(),
-    };
-    let mut result_tok = driver::parse_document(sink, Default::default());
+    let mut result_tok = driver::parse_document(LineCountingDOM::new(), Default::default());
     result_tok.process(StrTendril::from("<a>\n"));
     result_tok.process(StrTendril::from("</a>\n"));
     result_tok.process(StrTendril::from("<b>\n"));
@@ -140,3 +159,17 @@ fn check_four_lines() {
     // Assertion
     assert_eq!(actual.line_vec, expected);
 }
+
+#[test]
+fn check_pop_and_element_closed() {
+    let actual = driver::parse_document(LineCountingDOM::new(), Default::default())
+        .one("<p>a<br><b>b<i>c</p>d<table>e</table><b>1<div>2</b>3</div>");
+    // Void elements and elements dropped from the stack by the adoption agency algorithm
+    // are never popped, but every element is reported as closed exactly once.
+    assert_eq!(actual.popped, ["head", "b", "i", "b", "body", "html"]);
+    assert_eq!(
+        actual.closed,
+        ["head", "br", "i", "b", "p", "table", "b", "b", "div", "i", "b", "body", "html"]
+    );
+    assert_eq!(actual.closed.len(), actual.line_vec.len());
+}
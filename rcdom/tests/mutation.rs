@@ -0,0 +1,233 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use html5ever::tendril::TendrilSink;
+use html5ever::{local_name, namespace_url, ns, serialize, QualName};
+use markup5ever_rcdom::{select_first, Handle, Node, NodeData, RcDom, SerializableHandle};
+
+fn parse(input: &str) -> RcDom {
+    html5ever::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .one(input.as_bytes())
+}
+
+fn body_html(dom: &RcDom) -> String {
+    let body = find(dom, "body");
+    let mut result = vec![];
+    let body: SerializableHandle = body.into();
+    serialize(&mut result, &body, Default::default()).unwrap();
+    String::from_utf8(result).unwrap()
+}
+
+fn find(dom: &RcDom, selectors: &str) -> Handle {
+    select_first(&dom.document, selectors).unwrap().unwrap()
+}
+
+fn text(contents: &str) -> Handle {
+    Node::new(NodeData::Text {
+        contents: RefCell::new(contents.into()),
+    })
+}
+
+fn parent(node: &Handle) -> Option<Handle> {
+    let parent = node.parent.take();
+    node.parent.set(parent.clone());
+    parent.and_then(|parent| parent.upgrade())
+}
+
+/// Every node's parent pointer refers to the node whose children include it.
+fn check_parents(node: &Handle) {
+    for child in node.children.borrow().iter() {
+        assert!(Rc::ptr_eq(&parent(child).unwrap(), node));
+        check_parents(child);
+    }
+}
+
+#[test]
+fn append_insert_remove() {
+    let dom = parse("<p id=a>One</p><p id=b>Two</p><ul><li>1</ul>");
+    let a = find(&dom, "#a");
+    let b = find(&dom, "#b");
+    let li = find(&dom, "li");
+
+    a.append_child(li.clone());
+    assert_eq!(
+        body_html(&dom),
+        "<p id=\"a\">One<li>1</li></p><p id=\"b\">Two</p><ul></ul>"
+    );
+    let two = b.children.borrow()[0].clone();
+    b.insert_before(li.clone(), &two);
+    assert_eq!(
+        body_html(&dom),
+        "<p id=\"a\">One</p><p id=\"b\"><li>1</li>Two</p><ul></ul>"
+    );
+    b.insert_before(li.clone(), &li);
+    assert_eq!(
+        body_html(&dom),
+        "<p id=\"a\">One</p><p id=\"b\"><li>1</li>Two</p><ul></ul>"
+    );
+    b.append_child(text(" and three"));
+    assert_eq!(b.children.borrow().len(), 3);
+    check_parents(&dom.document);
+
+    li.remove();
+    assert!(parent(&li).is_none());
+    li.remove();
+    a.remove();
+    assert_eq!(body_html(&dom), "<p id=\"b\">Two and three</p><ul></ul>");
+    check_parents(&dom.document);
+}
+
+#[test]
+fn replace_with() {
+    let dom = parse("<div><p>One</p><p>Two</p></div><span>Three</span>");
+    let div = find(&dom, "div");
+    let span = find(&dom, "span");
+
+    let one = div.children.borrow()[0].clone();
+    one.replace_with(span.clone());
+    assert_eq!(body_html(&dom), "<div><span>Three</span><p>Two</p></div>");
+    check_parents(&dom.document);
+
+    // A node without a parent can't be replaced.
+    let orphan = text("orphan");
+    orphan.replace_with(span.clone());
+    assert!(Rc::ptr_eq(&parent(&span).unwrap(), &div));
+
+    // Replacing a node with one of its descendants.
+    div.replace_with(span.clone());
+    assert_eq!(body_html(&dom), "<span>Three</span>");
+    assert!(parent(&div).is_none());
+    check_parents(&dom.document);
+}
+
+#[test]
+#[should_panic(expected = "cannot insert a node into itself or its descendants!")]
+fn append_ancestor() {
+    let dom = parse("<div><p>One</p></div>");
+    find(&dom, "p").append_child(find(&dom, "div"));
+}
+
+#[test]
+#[should_panic(expected = "cannot insert a node into itself or its descendants!")]
+fn replace_with_ancestor() {
+    let dom = parse("<div><p>One</p></div>");
+    find(&dom, "p").replace_with(find(&dom, "div"));
+}
+
+#[test]
+#[should_panic(expected = "reference is not a child of this node!")]
+fn insert_before_non_child() {
+    let dom = parse("<div><p>One</p></div><span></span>");
+    find(&dom, "span").insert_before(text("x"), &find(&dom, "p"));
+}
+
+#[test]
+fn attributes() {
+    let dom = parse("<a href=/one title=One>One</a>");
+    let a = find(&dom, "a");
+    let href = QualName::new(None, ns!(), local_name!("href"));
+    let xlink_href = QualName::new(None, ns!(xlink), local_name!("href"));
+
+    a.set_attribute(href.clone(), "/two".into());
+    a.set_attribute(xlink_href.clone(), "#x".into());
+    assert_eq!(a.remove_attribute(&xlink_href), Some("#x".into()));
+    assert_eq!(
+        a.remove_attribute(&QualName::new(None, ns!(), local_name!("title"))),
+        Some("One".into())
+    );
+    assert_eq!(a.remove_attribute(&xlink_href), None);
+    a.set_attribute(QualName::new(None, ns!(), local_name!("id")), "a".into());
+    assert_eq!(body_html(&dom), "<a href=\"/two\" id=\"a\">One</a>");
+}
+
+#[test]
+fn set_text() {
+    let dom = parse("<div>One <b>Two</b></div><p>Three</p>");
+    let div = find(&dom, "div");
+    let b = find(&dom, "b");
+
+    div.set_text("<Four>".into());
+    assert_eq!(body_html(&dom), "<div>&lt;Four&gt;</div><p>Three</p>");
+    assert!(parent(&b).is_none());
+    check_parents(&dom.document);
+
+    let p = find(&dom, "p");
+    p.children.borrow()[0].set_text("Five".into());
+    div.set_text("".into());
+    assert_eq!(body_html(&dom), "<div></div><p>Five</p>");
+}
+
+#[test]
+fn deep_clone() {
+    let dom =
+        parse("<div id=a>One<!--c--><template><b>Two</b><i>Three</i></template><p>Four</p></div>");
+    let div = find(&dom, "div");
+    let copy = div.deep_clone();
+    assert!(parent(&copy).is_none());
+    check_parents(&copy);
+
+    find(&dom, "body").append_child(copy.clone());
+    copy.set_attribute(QualName::new(None, ns!(), local_name!("id")), "b".into());
+    div.children.borrow()[0].set_text("Changed".into());
+    assert_eq!(
+        body_html(&dom),
+        "<div id=\"a\">Changed<!--c--><template><b>Two</b><i>Three</i></template><p>Four</p></div>\
+         <div id=\"b\">One<!--c--><template><b>Two</b><i>Three</i></template><p>Four</p></div>"
+    );
+
+    let template = |div: &Handle| match div.children.borrow()[2].data {
+        NodeData::Element {
+            ref template_contents,
+            ..
+        } => template_contents.borrow().clone().unwrap(),
+        _ => panic!("not an element"),
+    };
+    assert!(!Rc::ptr_eq(&template(&div), &template(&copy)));
+    check_parents(&template(&copy));
+}
+
+#[test]
+fn siblings() {
+    let dom = parse("<p>One</p><p>Two</p><p>Three</p>");
+    let body = find(&dom, "body");
+    let children = body.children.borrow().clone();
+    assert!(children[0].previous_sibling().is_none());
+    assert!(Rc::ptr_eq(
+        &children[0].next_sibling().unwrap(),
+        &children[1]
+    ));
+    assert!(Rc::ptr_eq(
+        &children[2].previous_sibling().unwrap(),
+        &children[1]
+    ));
+    assert!(children[2].next_sibling().is_none());
+    assert!(body.next_sibling().is_none());
+    assert!(dom.document.previous_sibling().is_none());
+
+    children[0].remove();
+    assert!(children[1].previous_sibling().is_none());
+    body.insert_before(children[0].clone(), &children[2]);
+    assert!(Rc::ptr_eq(
+        &children[2].previous_sibling().unwrap(),
+        &children[0]
+    ));
+
+    // Changing `children` directly leaves the cached positions out of date.
+    body.children.borrow_mut().reverse();
+    assert!(Rc::ptr_eq(
+        &children[1].previous_sibling().unwrap(),
+        &children[0]
+    ));
+    children[2].remove();
+    assert_eq!(body_html(&dom), "<p>One</p><p>Two</p>");
+}
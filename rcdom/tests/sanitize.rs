@@ -0,0 +1,168 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use html5ever::tendril::TendrilSink;
+use html5ever::{local_name, namespace_url, ns, parse_document, parse_fragment, serialize};
+use html5ever::{LocalName, QualName};
+use markup5ever_rcdom::sanitize::{clean, sanitize, SanitizeOpts};
+use markup5ever_rcdom::{RcDom, SerializableHandle};
+
+/// Mutation XSS payloads. Each of them runs script if it reaches a browser unsanitized, or after
+/// a naive sanitize and reparse.
+///
+/// This is a curated subset, not an imported suite: html5lib-tests has no sanitizer cases. Each
+/// group says where its payloads come from; the ones marked as ours were written for this crate
+/// from the parsing rules they exercise.
+const MXSS: &[&str] = &[
+    // DOMPurify 2.0.0 bypass, Michał Bentkowski, "Write-up of DOMPurify 2.0.0 bypass using
+    // mutation XSS" (securitum, 2019).
+    "<svg></p><style><a id=\"</style><img src=1 onerror=alert(1)>\">",
+    // Ours: the same namespace switch without the stray end tag.
+    "<svg><p><style><img src=x onerror=alert(1)>",
+    // DOMPurify 2.0.17 bypass, Michał Bentkowski, "Mutation XSS via namespace confusion -
+    // DOMPurify < 2.0.17 bypass" (securitum, 2020), and the nested-form payload from the same
+    // write-up.
+    "<math><mtext><table><mglyph><style><!--</style>\
+     <img title=\"--&gt;&lt;/mglyph&gt;&lt;img&Tab;src=1&Tab;onerror=alert(1)&gt;\">",
+    "<form><math><mtext></form><form><mglyph><style></math><img src onerror=alert(1)>",
+    // Ours: variations on the namespace confusion above, through svg and foreignObject.
+    "<math><mi><mglyph><svg><mtext><textarea><path id=\"</textarea><img onerror=alert(1) src=1>\">",
+    "<svg><foreignobject><p><style><img src=x onerror=alert(1)></style></p></foreignobject></svg>",
+    // Google Search mutation XSS, Masato Kinugawa (2019): noscript parses differently with
+    // scripting on and off.
+    "<noscript><p title=\"</noscript><img src=x onerror=alert(1)>\">",
+    // Ours: the same trick with the other raw text elements in the WHATWG HTML parsing rules.
+    "<noembed><img title=\"</noembed><img src onerror=alert(1)>\"></noembed>",
+    "<xmp><img title=\"</xmp><img src onerror=alert(1)>\"></xmp>",
+    "<iframe><img title=\"</iframe><img src onerror=alert(1)>\"></iframe>",
+    // WHATWG HTML parse errors abrupt-closing-of-empty-comment, incorrectly-closed-comment and
+    // unexpected-question-mark-instead-of-tag-name.
+    "<!--><img src=x onerror=alert(1)>-->",
+    "<!-- --!><img src=x onerror=alert(1)> -->",
+    "<?xml ><img src=x onerror=alert(1)>?>",
+    // Ours: template contents, which live outside the tree and are easy to skip.
+    "<template><script>alert(1)</script><img src=x onerror=alert(1)></template>",
+    "<table><template><style></table><img src=x onerror=alert(1)></style></template>",
+    "<select><template><style><!--</style><a rel=\"--></style></template></select>\
+     <img src onerror=alert(1)>\">",
+    // OWASP XSS Filter Evasion Cheat Sheet: plain, case-insensitive, embedded tab, leading
+    // meta character, embedded newline, VBScript and data URLs, and event handlers.
+    "<a href=\"javascript:alert(1)\">x</a>",
+    "<a href=\"  JaVaScRiPt:alert(1)\">x</a>",
+    "<a href=\"jav&#x09;ascript:alert(1)\">x</a>",
+    "<a href=\"&#x0E;javascript:alert(1)\">x</a>",
+    "<a href=\"java\nscript:alert(1)\">x</a>",
+    "<img src=\"vbscript:msgbox(1)\"><img src=\"data:text/html,<script>alert(1)</script>\">",
+    "<a href=x onclick=alert(1) ONMOUSEOVER=alert(1)>x</a>",
+    // Ours: an attribute name that only looks like a handler once whitespace is dropped.
+    "<img src=x one rror=1 onerror=alert(1)>",
+    // WHATWG HTML adoption agency and plaintext state: markup that reparses differently.
+    "<p><b>1<p>2</b>3<a href=a><table><a href=b>4</table>5",
+    "<plaintext><img src=x onerror=alert(1)>",
+    // Ours: the elements removed with their contents by default.
+    "<script>alert(1)</script><style>*{}</style>",
+];
+
+fn parse(html: &str) -> RcDom {
+    let context = QualName::new(None, ns!(html), local_name!("body"));
+    parse_fragment(RcDom::default(), Default::default(), context, vec![]).one(html)
+}
+
+fn reserialize(html: &str) -> String {
+    let dom = parse(html);
+    let root: SerializableHandle = dom.document.children.borrow()[0].clone().into();
+    let mut output = vec![];
+    serialize(&mut output, &root, Default::default()).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+fn clean_default(html: &str) -> String {
+    clean(html, &SanitizeOpts::default())
+}
+
+#[test]
+fn mutation_xss() {
+    for input in MXSS {
+        let output = clean_default(input);
+        let lowercase = output.to_ascii_lowercase();
+        for forbidden in &[
+            "<script", "<style", "<svg", "<math", "onerror", "onclick", "script:",
+        ] {
+            assert!(!lowercase.contains(forbidden), "{} -> {}", input, output);
+        }
+        assert_eq!(reserialize(&output), output, "{}", input);
+        assert_eq!(clean_default(&output), output, "{}", input);
+    }
+}
+
+#[test]
+fn allowlists() {
+    assert_eq!(
+        clean_default(
+            "<div class=x title=t><custom>Hello <b>world</b></custom><font>!</font></div>"
+        ),
+        "<div title=\"t\">Hello <b>world</b>!</div>"
+    );
+    assert_eq!(
+        clean_default("<a href=https://example.com/ target=_blank>x</a><a href=/y>y</a>"),
+        "<a href=\"https://example.com/\">x</a><a href=\"/y\">y</a>"
+    );
+    assert_eq!(
+        clean_default("<img src=\"javascript:x\" alt=a><a href=\"a:b:c\">relative</a>"),
+        "<img alt=\"a\"><a>relative</a>"
+    );
+    assert_eq!(
+        clean_default("<p>1<title>2</title><select><option>3</select><textarea>4</textarea>"),
+        "<p>1</p>"
+    );
+
+    let mut opts = SanitizeOpts::default();
+    opts.elements.insert(local_name!("template"));
+    opts.remove_contents.remove(&local_name!("template"));
+    opts.generic_attributes.insert(LocalName::from("class"));
+    opts.url_schemes.insert("data".to_owned());
+    assert_eq!(
+        clean(
+            "<template class=x><img src=data:x onload=y><script>z</script></template>\
+             <style>p {}</style><svg class=x></svg>",
+            &opts
+        ),
+        "<template class=\"x\"><img src=\"data:x\"></template>"
+    );
+}
+
+#[test]
+fn stable_output() {
+    // These parse to trees that serialize to markup that parses to something else.
+    let cases = &[
+        (
+            "<a href=a><table><a href=b>x</table>y",
+            "<a href=\"a\"></a><a href=\"b\">x</a><table></table><a href=\"b\">y</a>",
+        ),
+        ("<pre>\n\nx</pre>", "<pre>x</pre>"),
+    ];
+    for &(input, expected) in cases {
+        let once = reserialize(input);
+        assert_ne!(reserialize(&once), once);
+        assert_eq!(clean_default(input), expected);
+        assert_eq!(reserialize(expected), expected);
+    }
+}
+
+#[test]
+fn tree_pass() {
+    let dom = parse_document(RcDom::default(), Default::default())
+        .one("<!DOCTYPE html><title>x</title><p onclick=x>Text<!-- comment --><script>y</script>");
+    assert!(sanitize(&dom.document, &SanitizeOpts::default()));
+    let document: SerializableHandle = dom.document.clone().into();
+    let mut output = vec![];
+    serialize(&mut output, &document, Default::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "<p>Text</p>");
+    assert!(!sanitize(&dom.document, &SanitizeOpts::default()));
+}
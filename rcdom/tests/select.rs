@@ -0,0 +1,202 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::HashMap;
+
+use html5ever::tendril::TendrilSink;
+use markup5ever::select::{Error, Selector};
+use markup5ever::{Namespace, Prefix};
+use markup5ever_rcdom::{select, select_first, Handle, NodeData, RcDom};
+
+const HTML: &str = "<!DOCTYPE html><html lang=en-GB><title>Test</title>\
+    <div class='article main' id=top>\
+      <h1>Heading</h1>\
+      <p>One <a href='http://example.com/1' TYPE=Text/HTML>link</a></p>\
+      <p class=note><a href=/two>two</a><a>three</a></p>\
+      <p lang=fr><span></span><!-- comment --></p>\
+      <svg viewBox='0 0 1 1'><foreignObject><p>Quatre</p></foreignObject></svg>\
+    </div>\
+    <ul><li>1<li>2<li>3<li>4<li>5</ul>\
+    <form><input type=checkbox checked><input type=text disabled><select>\
+      <option>a<option selected>b</select></form>";
+
+fn parse_html(input: &str) -> RcDom {
+    html5ever::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .one(input.as_bytes())
+}
+
+fn parse_xml(input: &str) -> RcDom {
+    xml5ever::driver::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .one(input.as_bytes())
+}
+
+/// A description of each element: its local name, with its id or text if it has any.
+fn describe(elements: &[Handle]) -> Vec<String> {
+    elements
+        .iter()
+        .map(|element| {
+            let name = match element.data {
+                NodeData::Element { ref name, .. } => name.local.to_string(),
+                _ => panic!("not an element"),
+            };
+            let text = element
+                .children
+                .borrow()
+                .iter()
+                .filter_map(|child| match child.data {
+                    NodeData::Text { ref contents } => Some(contents.borrow().to_string()),
+                    _ => None,
+                })
+                .collect::<String>();
+            if text.is_empty() {
+                name
+            } else {
+                format!("{} {}", name, text.trim())
+            }
+        })
+        .collect()
+}
+
+fn query(dom: &RcDom, selectors: &str) -> Vec<String> {
+    describe(&select(&dom.document, selectors).unwrap())
+}
+
+#[test]
+fn selectors() {
+    let dom = parse_html(HTML);
+    let query = |selectors| query(&dom, selectors);
+
+    assert_eq!(query("div.article > p > a[href^=http]"), ["a link"]);
+    assert_eq!(query("#top h1, title"), ["title Test", "h1 Heading"]);
+    assert_eq!(query(".main.article > .note a"), ["a two", "a three"]);
+    assert_eq!(query("h1 + p a"), ["a link"]);
+    assert_eq!(query("h1 ~ p:not(.note) > *"), ["a link", "span"]);
+    assert_eq!(query("a[href]:not([href$='1'])"), ["a two"]);
+    assert_eq!(query("[class~=note] a[href*=wo]"), ["a two"]);
+    assert_eq!(query("p[lang|=fr]"), ["p"]);
+    assert_eq!(query("a[type='text/html']"), ["a link"]);
+    assert_eq!(query("a[href='HTTP://EXAMPLE.COM/1' i]"), ["a link"]);
+    assert_eq!(query("input[type=CHECKBOX s]").len(), 0);
+    assert_eq!(query("li:nth-child(2n+1)"), ["li 1", "li 3", "li 5"]);
+    assert_eq!(query("li:nth-last-child(-n+2)"), ["li 4", "li 5"]);
+    assert_eq!(query("li:first-child, li:last-child"), ["li 1", "li 5"]);
+    assert_eq!(query("p > a:nth-of-type(2)"), ["a three"]);
+    assert_eq!(query("p > a:only-of-type"), ["a link"]);
+    assert_eq!(query("p > :only-child"), ["a link", "span"]);
+    assert_eq!(query("div > :nth-child(odd of p)"), ["p One", "p"]);
+    assert_eq!(query(":empty"), ["span", "input", "input"]);
+    assert_eq!(query(":root > body > ul"), ["ul"]);
+    assert_eq!(query("p:has(> a[href])"), ["p One", "p"]);
+    assert_eq!(query("h1:has(+ p a)"), ["h1 Heading"]);
+    assert_eq!(query("div:has(span):is(.article)"), ["div"]);
+    assert_eq!(query(":where(h1, ul) li:nth-child(3)"), ["li 3"]);
+    assert_eq!(query("p:lang(en) a"), ["a link", "a two", "a three"]);
+    assert_eq!(query(":lang(fr)"), ["p", "span"]);
+    assert_eq!(query(":checked"), ["input", "option b"]);
+    assert_eq!(query(":disabled"), ["input"]);
+    assert_eq!(query("a:link"), ["a link", "a two"]);
+    assert_eq!(query("a:hover, p::before, a:first-line").len(), 0);
+
+    let div = select_first(&dom.document, "div").unwrap().unwrap();
+    assert_eq!(
+        describe(&select(&div, ":scope > h1").unwrap()),
+        ["h1 Heading"]
+    );
+    assert_eq!(select(&div, "div").unwrap().len(), 0);
+    assert!(select_first(&dom.document, "table").unwrap().is_none());
+}
+
+#[test]
+fn many_siblings() {
+    // Counting siblings for :nth-* once took time quadratic in the number of siblings.
+    let items = "<li>item</li>".repeat(20000);
+    let dom = parse_html(&format!("<ul>{}<p>end</ul><ol>{}</ol>", items, items));
+    let query = |selectors| select(&dom.document, selectors).unwrap().len();
+    assert_eq!(query("li:nth-child(2n)"), 20000);
+    assert_eq!(query("li:last-child, li:nth-last-of-type(3)"), 3);
+    assert_eq!(query("ul > :nth-last-child(-n+3 of li)"), 3);
+    assert_eq!(query("li:nth-of-type(20000)"), 2);
+}
+
+#[test]
+fn case_sensitivity() {
+    let dom = parse_html(HTML);
+    let query = |selectors| query(&dom, selectors);
+
+    // HTML elements and attributes match ignoring case, SVG ones don't.
+    assert_eq!(query("DIV > H1"), ["h1 Heading"]);
+    assert_eq!(query("A[HREF^=http]"), ["a link"]);
+    assert_eq!(query("foreignObject p"), ["p Quatre"]);
+    assert_eq!(query("foreignobject").len(), 0);
+    assert_eq!(query("svg[viewBox]"), ["svg"]);
+    assert_eq!(query("svg[viewbox]").len(), 0);
+    // Class names and ids are case-sensitive.
+    assert_eq!(query(".NOTE, #TOP").len(), 0);
+}
+
+#[test]
+fn xml_case_sensitivity() {
+    let dom = parse_xml("<Doc><Item Kind='A'/><item kind='a'/></Doc>");
+    assert_eq!(query(&dom, "Item"), ["Item"]);
+    assert_eq!(query(&dom, "[Kind=A]"), ["Item"]);
+    assert_eq!(query(&dom, "[kind=A i]"), ["item"]);
+}
+
+#[test]
+fn namespaces() {
+    let dom = parse_xml(
+        "<feed xmlns='http://www.w3.org/2005/Atom' xmlns:x='urn:x'>\
+         <entry x:id='1'><title>One</title></entry>\
+         <x:entry><title>Two</title></x:entry></feed>",
+    );
+    let mut namespaces = HashMap::new();
+    namespaces.insert(
+        Prefix::from("a"),
+        Namespace::from("http://www.w3.org/2005/Atom"),
+    );
+    namespaces.insert(Prefix::from("x"), Namespace::from("urn:x"));
+    let query = |selectors| {
+        let selector = Selector::parse_with_namespaces(selectors, &namespaces).unwrap();
+        describe(&selector.select(&dom, &dom.document))
+    };
+
+    assert_eq!(query("entry"), ["entry", "entry"]);
+    assert_eq!(query("a|entry title"), ["title One"]);
+    assert_eq!(query("x|* > title"), ["title Two"]);
+    assert_eq!(query("*|entry[x|id]"), ["entry"]);
+    assert_eq!(query("[id]").len(), 0);
+    assert_eq!(query("[*|id='1']"), ["entry"]);
+    assert_eq!(query("|entry").len(), 0);
+
+    let title = select_first(&dom.document, "title").unwrap().unwrap();
+    assert!(Selector::parse("entry > title:first-child")
+        .unwrap()
+        .matches(&dom, &title));
+}
+
+#[test]
+fn errors() {
+    let dom = parse_html("");
+    let error = |selectors| select(&dom.document, selectors).unwrap_err();
+    assert_eq!(
+        error("div >"),
+        Error {
+            position: 5,
+            message: "Expected a selector".into(),
+        }
+    );
+    assert_eq!(error("a:nth-child(x)").position, 12);
+    assert_eq!(
+        error("a:hovr").to_string(),
+        "Unknown pseudo-class :hovr at position 2"
+    );
+    assert_eq!(error("svg|rect").message, "Unknown namespace prefix svg");
+}
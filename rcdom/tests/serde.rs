@@ -0,0 +1,157 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::{local_name, namespace_prefix, namespace_url, ns, parse_document, serialize};
+use html5ever::{Attribute, LocalName, Namespace, QualName};
+use markup5ever::interface::QuirksMode;
+use markup5ever_rcdom::{NodeData, RcDom, SerializableHandle};
+use serde_json::json;
+
+fn to_html(dom: &RcDom) -> String {
+    let document: SerializableHandle = dom.document.clone().into();
+    let mut output = vec![];
+    serialize(&mut output, &document, Default::default()).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+fn round_trip(dom: &RcDom) -> RcDom {
+    serde_json::from_str(&serde_json::to_string(dom).unwrap()).unwrap()
+}
+
+#[test]
+fn names_and_attributes() {
+    let name = QualName::new(None, ns!(svg), local_name!("foreignObject"));
+    assert_eq!(
+        serde_json::to_value(&name).unwrap(),
+        json!({"prefix": null, "ns": "http://www.w3.org/2000/svg", "local": "foreignObject"})
+    );
+    let attribute = Attribute {
+        name: QualName::new(
+            Some(namespace_prefix!("xlink")),
+            ns!(xlink),
+            local_name!("href"),
+        ),
+        value: StrTendril::from("#x"),
+    };
+    let value = serde_json::to_value(&attribute).unwrap();
+    assert_eq!(value["value"], "#x");
+    assert_eq!(
+        serde_json::from_value::<Attribute>(value).unwrap(),
+        attribute
+    );
+
+    let local: LocalName = serde_json::from_str("\"not-an-atom-yet\"").unwrap();
+    assert_eq!(&*local, "not-an-atom-yet");
+    let namespace: Namespace = serde_json::from_str("\"\"").unwrap();
+    assert_eq!(namespace, ns!());
+}
+
+#[test]
+fn html_round_trip() {
+    let inputs = &[
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\" \"http://www.w3.org/TR/html4/loose.dtd\">\
+         <title>Test</title><p class=a id=b>One<!-- two --><b>three</b>",
+        "<p>No doctype<table><tr><td>1</td>x</table>",
+        "<template><tr><td>cell</template><template></template><div><template><b>x</b></template>y</div>",
+        "<svg><foreignObject><p>html</p></foreignObject></svg>\
+         <math><annotation-xml encoding=text/html><div>x</div></annotation-xml></math>",
+    ];
+    for input in inputs {
+        let dom = parse_document(RcDom::default(), Default::default()).one(*input);
+        let copy = round_trip(&dom);
+        assert_eq!(to_html(&copy), to_html(&dom), "{}", input);
+        assert_eq!(copy.quirks_mode, dom.quirks_mode);
+        assert_eq!(copy.errors, dom.errors);
+    }
+
+    let dom = parse_document(RcDom::default(), Default::default()).one(inputs[0]);
+    let copy = round_trip(&dom);
+    assert_eq!(copy.quirks_mode, QuirksMode::LimitedQuirks);
+    match copy.document.children.borrow()[0].data {
+        NodeData::Doctype {
+            ref public_id,
+            ref system_id,
+            ..
+        } => {
+            assert_eq!(&**public_id, "-//W3C//DTD HTML 4.01 Transitional//EN");
+            assert_eq!(&**system_id, "http://www.w3.org/TR/html4/loose.dtd");
+        },
+        _ => panic!("not a doctype"),
+    }
+
+    let dom = parse_document(RcDom::default(), Default::default()).one(inputs[2]);
+    let copy = round_trip(&dom);
+    let head = copy.document.children.borrow()[0].children.borrow()[0].clone();
+    let template = head.children.borrow()[0].clone();
+    match template.data {
+        NodeData::Element {
+            ref template_contents,
+            ..
+        } => {
+            let contents = template_contents.borrow().clone().unwrap();
+            assert!(contents.parent.take().is_none());
+            assert_eq!(contents.children.borrow().len(), 1);
+        },
+        _ => panic!("not an element"),
+    }
+}
+
+#[test]
+fn xml_round_trip() {
+    let input = "<?xml version='1.0' encoding='UTF-8'?><?style sheet?>\
+                 <doc xmlns:x='urn:x'><x:a x:b='c'><![CDATA[<d>]]></x:a>e<!--f--></doc>";
+    let dom = xml5ever::driver::parse_document(RcDom::default(), Default::default()).one(input);
+    let copy = round_trip(&dom);
+
+    let mut expected = vec![];
+    let document: SerializableHandle = dom.document.clone().into();
+    xml5ever::serialize::serialize(&mut expected, &document, Default::default()).unwrap();
+    let mut output = vec![];
+    let document: SerializableHandle = copy.document.clone().into();
+    xml5ever::serialize::serialize(&mut output, &document, Default::default()).unwrap();
+    assert_eq!(output, expected);
+    assert_eq!(copy.xml_declaration, dom.xml_declaration);
+}
+
+#[test]
+fn deep_tree() {
+    let input = "<div>".repeat(10_000);
+    let dom = parse_document(RcDom::default(), Default::default()).one(input);
+    let copy = round_trip(&dom);
+    assert_eq!(to_html(&copy).len(), to_html(&dom).len());
+}
+
+#[test]
+fn invalid_trees() {
+    let dom = |nodes| json!({"nodes": nodes, "quirks_mode": "NoQuirks", "errors": [], "xml_declaration": null});
+    let element = |children, template| json!({"Element": [{"prefix": null, "ns": "", "local": "a"}, [], children, template, false]});
+    let invalid = &[
+        dom(json!([])),
+        dom(json!([{"Text": "x"}])),
+        dom(json!([{"Document": 1}])),
+        dom(json!([{"Document": 1}, {"Text": "x"}, {"Text": "y"}])),
+        dom(json!([{"Document": 1}, {"Document": 0}])),
+        dom(json!([{"Document": 1}, element(0, true)])),
+        dom(json!([{"Document": 1}, element(0, true), {"Text": "x"}])),
+    ];
+    for value in invalid {
+        assert!(
+            serde_json::from_value::<RcDom>(value.clone()).is_err(),
+            "{}",
+            value
+        );
+    }
+
+    let valid = dom(
+        json!([{"Document": 2}, element(1, false), {"Text": "x"}, element(0, true), {"Document": 1}, {"Text": "y"}]),
+    );
+    let dom = serde_json::from_value::<RcDom>(valid).unwrap();
+    assert_eq!(to_html(&dom), "<a>x</a><a>y</a>");
+}
@@ -0,0 +1,184 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::Arc;
+use std::thread;
+
+use html5ever::tendril::TendrilSink;
+use html5ever::{local_name, namespace_url, ns, serialize, QualName};
+use markup5ever::interface::QuirksMode;
+use markup5ever::select::Selector;
+use markup5ever::serialize::Serialize;
+use markup5ever::traverse::TreeNavigator;
+use markup5ever::xpath::{Context, XPath};
+use markup5ever_arena::{Arena, ArenaDom};
+use markup5ever_rcdom::sync_dom::{NodeData, SyncDom, XmlDeclaration};
+use markup5ever_rcdom::text::inner_text;
+use markup5ever_rcdom::{RcDom, SerializableHandle};
+
+/// Documents that make the tree builder move nodes around.
+const HTML: &[&str] = &[
+    "<!DOCTYPE html><title>Test</title><p>One<p>Two",
+    "<table><tr><td>1</td>x<div>y</div>z<tr><td>2</table>",
+    "<b>1<p>2</b>3</p><a href=x>4<div>5</a>6</div>",
+    "<template><tr><td>cell</template><template><template>nested</template></template>",
+    "<svg><foreignObject><p>html</svg><math><annotation-xml encoding=text/html><div>",
+    "<html a=1><body b=2><html c=3><body d=4 b=5>text",
+    "<frameset><frame></frameset><noframes>x</noframes><!-- end -->",
+    "<table><template><td>in template</td></template></table>",
+];
+
+fn parse_html<Sink: html5ever::interface::TreeSink>(sink: Sink, input: &str) -> Sink::Output {
+    html5ever::parse_document(sink, Default::default()).one(input)
+}
+
+/// Parse into an arena and copy the result into a `SyncDom`.
+fn sync_html(input: &str) -> SyncDom {
+    let arena = Arena::new();
+    SyncDom::from(parse_html(ArenaDom::new(&arena), input))
+}
+
+fn to_string<T: Serialize>(node: &T) -> String {
+    let mut result = vec![];
+    serialize(&mut result, node, Default::default()).unwrap();
+    String::from_utf8(result).unwrap()
+}
+
+fn rcdom_html(dom: &RcDom) -> String {
+    let document: SerializableHandle = dom.document.clone().into();
+    to_string(&document)
+}
+
+#[test]
+fn same_as_rcdom() {
+    for input in HTML {
+        let expected = parse_html(RcDom::default(), input);
+        let dom = sync_html(input);
+        assert_eq!(
+            to_string(&dom.document()),
+            rcdom_html(&expected),
+            "{}",
+            input
+        );
+        assert_eq!(dom.errors, expected.errors, "{}", input);
+        assert_eq!(dom.quirks_mode, expected.quirks_mode, "{}", input);
+    }
+
+    let dom = sync_html("<p>Quirky");
+    assert_eq!(dom.quirks_mode, QuirksMode::Quirks);
+
+    let context = QualName::new(None, ns!(html), local_name!("tr"));
+    let arena = Arena::new();
+    let dom = html5ever::parse_fragment(ArenaDom::new(&arena), Default::default(), context, vec![])
+        .one("<td>1<td>2");
+    let dom = SyncDom::from(dom);
+    let html = dom.document().first_child().unwrap();
+    assert_eq!(to_string(&html), "<td>1</td><td>2</td>");
+}
+
+#[test]
+fn navigation() {
+    let dom = sync_html("<ul id=list><li>1<li>2<li>3</ul><template><b>x</b></template>");
+    let body = dom.document().last_child().unwrap().last_child().unwrap();
+    let list = body.first_child().unwrap();
+    match *list.data() {
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } => {
+            assert_eq!(name.local, local_name!("ul"));
+            assert_eq!(attrs[0].name.local, local_name!("id"));
+            assert_eq!(attrs[0].value, "list");
+        },
+        _ => panic!("not an element"),
+    }
+
+    let items = list.children().collect::<Vec<_>>();
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].previous_sibling(), None);
+    assert_eq!(items[0].next_sibling(), Some(items[1]));
+    assert_eq!(items[2].previous_sibling(), Some(items[1]));
+    assert_eq!(list.last_child(), Some(items[2]));
+    assert_eq!(items[1].parent(), Some(list));
+    assert_eq!(dom.get(items[1].id()), items[1]);
+    match *items[2].first_child().unwrap().data() {
+        NodeData::Text { ref contents } => assert_eq!(contents, "3"),
+        _ => panic!("not text"),
+    }
+
+    let template = list.next_sibling().unwrap();
+    assert_eq!(template.first_child(), None);
+    let contents = template.template_contents().unwrap();
+    assert_eq!(contents.parent(), None);
+    assert_eq!(to_string(&contents), "<b>x</b>");
+    assert_eq!(to_string(&template), "<b>x</b>");
+    assert_eq!(list.template_contents(), None);
+
+    // The same tree through `TreeNavigator`, with ids as handles.
+    let ids = dom.children(&list.id()).collect::<Vec<_>>();
+    assert_eq!(ids, items.iter().map(|item| item.id()).collect::<Vec<_>>());
+    assert_eq!(dom.parent(&ids[1]), Some(list.id()));
+    assert_eq!(dom.name(&list.id()).unwrap().local, local_name!("ul"));
+    assert_eq!(dom.template_contents(&template.id()), Some(contents.id()));
+    assert_eq!(dom.descendants(&contents.id()).count(), 2);
+
+    // So selectors and XPath expressions work on it.
+    let document = dom.document().id();
+    let selector = Selector::parse("#list > :nth-child(2)").unwrap();
+    assert_eq!(selector.select(&dom, &document), [ids[1]]);
+    let xpath = XPath::parse("string(//li[last()])").unwrap();
+    let value = xpath.evaluate(&dom, &Context::html(), &document).unwrap();
+    assert_eq!(value.string(&dom), "3");
+    assert_eq!(inner_text(&dom, &document, Default::default()), "1\n2\n3");
+}
+
+#[test]
+fn xml() {
+    let input = "<?xml version='1.0' standalone='yes'?>\
+                 <?style sheet?><doc xmlns:x='urn:x'><x:a x:b='c'><![CDATA[<d>]]></x:a>e</doc>";
+    let expected =
+        xml5ever::driver::parse_document(RcDom::default(), Default::default()).one(input);
+    let arena = Arena::new();
+    let dom = xml5ever::driver::parse_document(ArenaDom::new(&arena), Default::default());
+    let dom = SyncDom::from(dom.one(input));
+
+    let mut rcdom_xml = vec![];
+    let document: SerializableHandle = expected.document.clone().into();
+    xml5ever::serialize::serialize(&mut rcdom_xml, &document, Default::default()).unwrap();
+    let mut xml = vec![];
+    xml5ever::serialize::serialize(&mut xml, &dom.document(), Default::default()).unwrap();
+    assert_eq!(xml, rcdom_xml);
+    assert_eq!(
+        dom.xml_declaration,
+        Some(XmlDeclaration {
+            version: "1.0".into(),
+            encoding: None,
+            standalone: Some(true),
+        })
+    );
+}
+
+#[test]
+fn shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SyncDom>();
+
+    let dom = Arc::new(sync_html(HTML[1]));
+    let expected = to_string(&dom.document());
+    let threads = (0..4)
+        .map(|_| {
+            let dom = dom.clone();
+            thread::spawn(move || to_string(&dom.document()))
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), expected);
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use html5ever::tendril::TendrilSink;
+use html5ever::{local_name, namespace_url, ns, parse_document, parse_fragment, QualName};
+use markup5ever_rcdom::text::{inner_text, TextFormat, TextOpts};
+use markup5ever_rcdom::{select_first, RcDom};
+
+fn text(input: &str) -> String {
+    let dom = parse_document(RcDom::default(), Default::default()).one(input);
+    inner_text(&dom, &dom.document, Default::default())
+}
+
+fn markdown(input: &str) -> String {
+    let dom = parse_document(RcDom::default(), Default::default()).one(input);
+    let opts = TextOpts {
+        format: TextFormat::Markdown,
+    };
+    inner_text(&dom, &dom.document, opts)
+}
+
+#[test]
+fn not_rendered() {
+    assert_eq!(
+        text(
+            "<title>Title</title><style>p {}</style><script>x()</script>\
+             <p>Shown<span hidden>hidden</span></p><template>template</template>\
+             <noscript>no script</noscript><svg><text>drawn</text></svg>"
+        ),
+        "Shown\n\ndrawn"
+    );
+    assert_eq!(text("<body hidden>Hidden"), "");
+}
+
+#[test]
+fn whitespace() {
+    assert_eq!(
+        text("  One \n  two\t<b> three </b>four  "),
+        "One two three four"
+    );
+    assert_eq!(text("<div> One </div> <div>\n two </div>"), "One\ntwo");
+    assert_eq!(
+        text("<p>a</p><pre>\n  keep   this\n\n</pre>b"),
+        "a\n\n  keep   this\n\n\nb"
+    );
+}
+
+#[test]
+fn line_breaks() {
+    assert_eq!(
+        text("<h1>Heading</h1>One<p>Two</p><div><div>Three</div></div>Four"),
+        "Heading\nOne\n\nTwo\n\nThree\nFour"
+    );
+    assert_eq!(text("One<br>two <br> <br>three<br>"), "One\ntwo\n\nthree\n");
+    assert_eq!(text("<p>One<br></p><p>Two"), "One\n\n\nTwo");
+    assert_eq!(text("<ul><li>One<li>Two</ul>After"), "One\nTwo\nAfter");
+}
+
+#[test]
+fn tables() {
+    assert_eq!(
+        text(
+            "<table><caption>Caption</caption><tr><th>A</th> <th> B </th></tr>\
+             <tr><td>1<td><td>3</table>After"
+        ),
+        "Caption\nA\tB\n1\t\t3\nAfter"
+    );
+}
+
+#[test]
+fn elements() {
+    let dom = parse_document(RcDom::default(), Default::default())
+        .one("<div id=a>One <b>two</b></div><script>x()</script>");
+    let div = select_first(&dom.document, "#a").unwrap().unwrap();
+    assert_eq!(inner_text(&dom, &div, Default::default()), "One two");
+    let script = select_first(&dom.document, "script").unwrap().unwrap();
+    assert_eq!(inner_text(&dom, &script, Default::default()), "");
+
+    let context = QualName::new(None, ns!(html), local_name!("tr"));
+    let dom = parse_fragment(RcDom::default(), Default::default(), context, vec![])
+        .one("<td>1</td> <td>2</td>");
+    assert_eq!(inner_text(&dom, &dom.document, Default::default()), "1\t2");
+}
+
+#[test]
+fn markdown_blocks() {
+    assert_eq!(
+        markdown(
+            "<h1>Title</h1><h3>Section</h3><p>Text</p><hr>\
+             <blockquote><p>Quoted</p><p>twice</p></blockquote>\
+             <pre><code>let x = *y;\n\nz</code></pre>"
+        ),
+        "# Title\n\n### Section\n\nText\n\n---\n\n> Quoted\n>\n> twice\n\n\
+         ```\nlet x = *y;\n\nz\n```"
+    );
+}
+
+#[test]
+fn markdown_inline() {
+    assert_eq!(
+        markdown(
+            "A <b>bold</b>, <em> emphasized </em>and <code>a_b</code> <a href=/x>link</a>, \
+             <a>no link</a><i></i>. <img src=x.png alt=Picture> 2*3_[4]"
+        ),
+        "A **bold**, *emphasized* and `a_b` [link](/x), no link. ![Picture](x.png) \
+         2\\*3\\_\\[4\\]"
+    );
+    assert_eq!(
+        markdown(
+            "<code>a``b</code> <code>`x`</code> <kbd>Ctrl+<kbd>C</kbd></kbd>\
+             <code><b></b></code>."
+        ),
+        "```a``b``` `` `x` `` `Ctrl+C`."
+    );
+}
+
+#[test]
+fn markdown_lists() {
+    assert_eq!(
+        markdown(
+            "<p>Before</p><ul><li>One<li><p>Two</p><p>paragraphs</p>\
+             <li>Three<ol start=3><li>Nested<li>list</ol></ul>After"
+        ),
+        "Before\n\n- One\n\n- Two\n\n  paragraphs\n\n- Three\n  3. Nested\n  4. list\n\nAfter"
+    );
+}
+
+#[test]
+fn markdown_tables() {
+    assert_eq!(
+        markdown("<table><tr><th>A<th>B|C<tr><td><p>1</p><p>2</p><td><tr><td>3</table>"),
+        "| A | B\\|C |\n| --- | --- |\n| 1 2 |  |\n| 3 |"
+    );
+}
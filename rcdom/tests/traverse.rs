@@ -0,0 +1,231 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use html5ever::tendril::TendrilSink;
+use html5ever::{local_name, namespace_url, ns, parse_document, serialize, QualName};
+use markup5ever::traverse::{Edge, Filter, NodeKind, TreeNavigator, TreeWalker};
+use markup5ever_rcdom::{Handle, HandleNavigator, Node, NodeData, RcDom, SerializableHandle};
+use std::cell::RefCell;
+
+const HTML: &str = "<!DOCTYPE html><div id=a><p id=b>x</p><p id=c>y<!--z--></p></div>\
+                    <span id=d>w</span>";
+
+fn parse() -> RcDom {
+    parse_document(RcDom::default(), Default::default()).one(HTML)
+}
+
+fn by_id(dom: &RcDom, id: &str) -> Handle {
+    dom.descendants(&dom.document)
+        .find(|node| dom.attribute(node, &ns!(), &local_name!("id")).as_deref() == Some(id))
+        .unwrap()
+}
+
+fn label(dom: &RcDom, node: &Handle) -> String {
+    match dom.kind(node) {
+        NodeKind::Document => "#document".to_owned(),
+        NodeKind::Doctype => "!doctype".to_owned(),
+        NodeKind::Element => {
+            let name = dom.name(node).unwrap();
+            match dom.attribute(node, &ns!(), &local_name!("id")) {
+                Some(id) => format!("{}#{}", name.local, id),
+                None => name.local.to_string(),
+            }
+        },
+        NodeKind::Comment => format!("<!--{}-->", dom.text(node).unwrap()),
+        _ => format!("{:?}", &*dom.text(node).unwrap()),
+    }
+}
+
+fn labels(dom: &RcDom, nodes: impl Iterator<Item = Handle>) -> Vec<String> {
+    nodes.map(|node| label(dom, &node)).collect()
+}
+
+#[test]
+fn axes() {
+    let dom = parse();
+    let body = dom.parent(&by_id(&dom, "a")).unwrap();
+    let (b, c) = (by_id(&dom, "b"), by_id(&dom, "c"));
+
+    assert_eq!(labels(&dom, dom.children(&body)), ["div#a", "span#d"]);
+    assert_eq!(
+        labels(&dom, dom.children(&dom.document)),
+        ["!doctype", "html"]
+    );
+    assert_eq!(
+        labels(&dom, dom.ancestors(&c)),
+        ["div#a", "body", "html", "#document"]
+    );
+    assert_eq!(
+        labels(&dom, dom.descendants(&by_id(&dom, "a"))),
+        ["p#b", "\"x\"", "p#c", "\"y\"", "<!--z-->"]
+    );
+    assert_eq!(
+        labels(&dom, dom.following(&b)),
+        ["p#c", "\"y\"", "<!--z-->", "span#d", "\"w\""]
+    );
+    assert_eq!(
+        labels(&dom, dom.preceding(&c)),
+        ["\"x\"", "p#b", "head", "!doctype"]
+    );
+    assert_eq!(labels(&dom, dom.following(&body)), Vec::<String>::new());
+    assert_eq!(
+        labels(&dom, dom.preceding(&dom.document)),
+        Vec::<String>::new()
+    );
+    assert_eq!(dom.descendants(&dom.document).count(), 12);
+    assert_eq!(dom.attributes(&b)[0].value.as_ref(), "b");
+    assert!(dom.same_node(&dom.last_child(&body).unwrap(), &by_id(&dom, "d")));
+}
+
+#[test]
+fn depth_and_breadth_first() {
+    let dom = parse();
+    let a = by_id(&dom, "a");
+    let edges = dom
+        .depth_first(&a)
+        .map(|edge| match edge {
+            Edge::Open(node) => format!("+{}", label(&dom, &node)),
+            Edge::Close(node) => format!("-{}", label(&dom, &node)),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        edges.join(" "),
+        "+div#a +p#b +\"x\" -\"x\" -p#b +p#c +\"y\" -\"y\" +<!--z--> -<!--z--> -p#c -div#a"
+    );
+    assert_eq!(
+        labels(&dom, dom.breadth_first(&a)),
+        ["div#a", "p#b", "p#c", "\"x\"", "\"y\"", "<!--z-->"]
+    );
+
+    // A serializer written against the trait.
+    let mut output = String::new();
+    for edge in dom.depth_first(&dom.document) {
+        match edge {
+            Edge::Open(node) => match dom.kind(&node) {
+                NodeKind::Doctype => output.push_str("<!DOCTYPE html>"),
+                NodeKind::Element => {
+                    output.push('<');
+                    output.push_str(&dom.name(&node).unwrap().local);
+                    for attr in dom.attributes(&node) {
+                        output.push_str(&format!(" {}=\"{}\"", attr.name.local, attr.value));
+                    }
+                    output.push('>');
+                },
+                NodeKind::Text => output.push_str(&dom.text(&node).unwrap()),
+                NodeKind::Comment => {
+                    output.push_str(&format!("<!--{}-->", dom.text(&node).unwrap()))
+                },
+                _ => (),
+            },
+            Edge::Close(node) => {
+                if let Some(name) = dom.name(&node) {
+                    output.push_str(&format!("</{}>", name.local));
+                }
+            },
+        }
+    }
+    let document: SerializableHandle = dom.document.clone().into();
+    let mut expected = vec![];
+    serialize(&mut expected, &document, Default::default()).unwrap();
+    assert_eq!(output, String::from_utf8(expected).unwrap());
+}
+
+#[test]
+fn tree_walker() {
+    let dom = parse();
+    let body = dom.parent(&by_id(&dom, "a")).unwrap();
+    let elements = |node: &Handle| match dom.kind(node) {
+        NodeKind::Element => Filter::Accept,
+        _ => Filter::Skip,
+    };
+    let walker = TreeWalker::new(&dom, body.clone(), elements);
+    assert_eq!(labels(&dom, walker), ["div#a", "p#b", "p#c", "span#d"]);
+
+    let is_a =
+        |node: &Handle| dom.attribute(node, &ns!(), &local_name!("id")).as_deref() == Some("a");
+    let reject_a = |node: &Handle| {
+        if is_a(node) {
+            Filter::Reject
+        } else {
+            elements(node)
+        }
+    };
+    let walker = TreeWalker::new(&dom, body.clone(), reject_a);
+    assert_eq!(labels(&dom, walker), ["span#d"]);
+
+    let skip_a = |node: &Handle| {
+        if is_a(node) {
+            Filter::Skip
+        } else {
+            elements(node)
+        }
+    };
+    let mut walker = TreeWalker::new(&dom, body.clone(), skip_a);
+    // Each step moves the walker.
+    let steps = vec![
+        walker.first_child(),
+        walker.next_sibling(),
+        walker.next_sibling(),
+        walker.next_sibling(),
+        walker.previous_sibling(),
+        walker.parent_node(),
+        walker.last_child(),
+        walker.previous_node(),
+        walker.previous_node(),
+        walker.previous_node(),
+        walker.previous_node(),
+        walker.first_child(),
+    ];
+    let steps = steps
+        .iter()
+        .map(|step| {
+            step.as_ref()
+                .map_or("-".to_owned(), |node| label(&dom, node))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        steps,
+        ["p#b", "p#c", "span#d", "-", "p#c", "body", "span#d", "p#c", "p#b", "body", "-", "p#b"]
+    );
+    assert!(dom.same_node(walker.current(), &by_id(&dom, "b")));
+    assert!(walker.parent_node().is_some());
+    walker.set_current(by_id(&dom, "b"));
+    assert!(walker.last_child().is_none());
+    assert!(dom.same_node(walker.root(), &body));
+}
+
+#[test]
+fn deep_tree() {
+    // Built from the bottom up, as `append_child` looks through the parent's ancestors.
+    let document = Node::new(NodeData::Document);
+    let dom = HandleNavigator;
+    let div = || {
+        Node::new(NodeData::Element {
+            name: QualName::new(None, ns!(html), local_name!("div")),
+            attrs: RefCell::new(vec![]),
+            template_contents: RefCell::new(None),
+            mathml_annotation_xml_integration_point: false,
+            shadow_root: RefCell::new(None),
+        })
+    };
+    let node = div();
+    let mut top = node.clone();
+    for _ in 1..100_000 {
+        let parent = div();
+        parent.append_child(top);
+        top = parent;
+    }
+    document.append_child(top);
+    assert_eq!(dom.descendants(&document).count(), 100_000);
+    assert_eq!(dom.ancestors(&node).count(), 100_000);
+    assert_eq!(dom.depth_first(&document).count(), 200_002);
+    assert_eq!(dom.preceding(&node).count(), 0);
+    let walker = TreeWalker::new(&dom, document.clone(), |_: &Handle| Filter::Skip);
+    assert_eq!(walker.count(), 0);
+}
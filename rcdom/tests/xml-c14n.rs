@@ -0,0 +1,365 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Canonicalization tests, mostly the examples of section 3 of
+//! https://www.w3.org/TR/xml-c14n.
+
+use std::io;
+
+use markup5ever_rcdom::{Handle, NodeData, RcDom, SerializableHandle};
+use xml5ever::serialize::{
+    canonicalize, C14nMethod, C14nOpts, C14nSerializer, Serialize, Serializer, TraversalScope,
+};
+use xml5ever::tendril::TendrilSink;
+use xml5ever::{driver, namespace_url, ns, LocalName, Namespace, Prefix, QualName};
+
+fn parse(input: &str) -> RcDom {
+    driver::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .one(input.as_bytes())
+}
+
+fn c14n(input: &str, opts: C14nOpts) -> String {
+    let dom = parse(input);
+    let document: SerializableHandle = dom.document.clone().into();
+    let mut result = vec![];
+    canonicalize(&mut result, &document, opts).unwrap();
+    String::from_utf8(result).unwrap()
+}
+
+fn find(node: &Handle, local: &str) -> Option<Handle> {
+    if let NodeData::Element { ref name, .. } = node.data {
+        if &*name.local == local {
+            return Some(node.clone());
+        }
+    }
+    node.children
+        .borrow()
+        .iter()
+        .find_map(|child| find(child, local))
+}
+
+/// Canonicalize the document subset rooted at the first element named `local`.
+fn c14n_subset(input: &str, local: &str, method: C14nMethod) -> String {
+    let dom = parse(input);
+    let apex = find(&dom.document, local).unwrap();
+
+    let mut ancestors = vec![];
+    let mut node = apex.clone();
+    while let Some(parent) = node.parent.take().and_then(|weak| weak.upgrade()) {
+        node.parent.set(Some(std::rc::Rc::downgrade(&parent)));
+        ancestors.push(parent.clone());
+        node = parent;
+    }
+
+    let opts = C14nOpts {
+        method,
+        traversal_scope: TraversalScope::IncludeNode,
+        ..Default::default()
+    };
+    let mut result = vec![];
+    {
+        let mut ser = C14nSerializer::new(&mut result, opts);
+        for ancestor in ancestors.iter().rev() {
+            if let NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } = ancestor.data
+            {
+                let attrs = attrs.borrow();
+                ser.add_ancestor(name, attrs.iter().map(|at| (&at.name, &at.value[..])));
+            }
+        }
+        let apex: SerializableHandle = apex.into();
+        apex.serialize(&mut ser, TraversalScope::IncludeNode)
+            .unwrap();
+    }
+    String::from_utf8(result).unwrap()
+}
+
+const PIS_AND_COMMENTS: &str = "<?xml version=\"1.0\"?>
+
+<?xml-stylesheet   href=\"doc.xsl\"
+   type=\"text/xsl\"   ?>
+
+<!DOCTYPE doc SYSTEM \"doc.dtd\">
+
+<doc>Hello, world!<!-- Comment 1 --></doc>
+
+<?pi-without-data     ?>
+
+<!-- Comment 2 -->
+
+<!-- Comment 3 -->
+";
+
+#[test]
+fn pis_and_comments_without_comments() {
+    assert_eq!(
+        c14n(PIS_AND_COMMENTS, Default::default()),
+        "<?xml-stylesheet href=\"doc.xsl\"
+   type=\"text/xsl\"   ?>
+<doc>Hello, world!</doc>
+<?pi-without-data?>"
+    );
+}
+
+#[test]
+fn pis_and_comments_with_comments() {
+    let opts = C14nOpts {
+        with_comments: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        c14n(PIS_AND_COMMENTS, opts),
+        "<?xml-stylesheet href=\"doc.xsl\"
+   type=\"text/xsl\"   ?>
+<doc>Hello, world!<!-- Comment 1 --></doc>
+<?pi-without-data?>
+<!-- Comment 2 -->
+<!-- Comment 3 -->"
+    );
+}
+
+#[test]
+fn whitespace_in_content() {
+    let input = "<doc>
+   <clean>   </clean>
+   <dirty>   A   B   </dirty>
+   <mixed>
+      A
+      <clean>   </clean>
+      B
+      <dirty>   A   B   </dirty>
+      C
+   </mixed>
+</doc>";
+    assert_eq!(c14n(input, Default::default()), input);
+}
+
+// The example's DTD gives e9 a default attribute, which needs DTD support, so it's left out. The
+// tree doesn't keep namespace declarations, so only the namespaces the names use are declared,
+// which makes the inclusive result match the exclusive one.
+const START_AND_END_TAGS: &str = "<doc>
+   <e1   />
+   <e2   ></e2>
+   <e3   name = \"elem3\"   id=\"elem3\"   />
+   <e4   name=\"elem4\"   id=\"elem4\"   ></e4>
+   <e5 a:attr=\"out\" b:attr=\"sorted\" attr2=\"all\" attr=\"I'm\"
+      xmlns:b=\"http://www.ietf.org\"
+      xmlns:a=\"http://www.w3.org\"
+      xmlns=\"http://example.org\"/>
+   <e6 xmlns=\"\" xmlns:a=\"http://www.w3.org\">
+      <e7 xmlns=\"http://www.ietf.org\">
+         <e8 xmlns=\"\" xmlns:a=\"http://www.w3.org\">
+            <e9 xmlns=\"\" xmlns:a=\"http://www.ietf.org\"/>
+         </e8>
+      </e7>
+   </e6>
+</doc>";
+
+const START_AND_END_TAGS_C14N: &str = "<doc>
+   <e1></e1>
+   <e2></e2>
+   <e3 id=\"elem3\" name=\"elem3\"></e3>
+   <e4 id=\"elem4\" name=\"elem4\"></e4>
+   <e5 xmlns=\"http://example.org\" xmlns:a=\"http://www.w3.org\" xmlns:b=\"http://www.ietf.org\" attr=\"I'm\" attr2=\"all\" b:attr=\"sorted\" a:attr=\"out\"></e5>
+   <e6>
+      <e7 xmlns=\"http://www.ietf.org\">
+         <e8 xmlns=\"\">
+            <e9></e9>
+         </e8>
+      </e7>
+   </e6>
+</doc>";
+
+#[test]
+fn start_and_end_tags() {
+    assert_eq!(
+        c14n(START_AND_END_TAGS, Default::default()),
+        START_AND_END_TAGS_C14N
+    );
+    let opts = C14nOpts {
+        method: C14nMethod::Exclusive,
+        ..Default::default()
+    };
+    assert_eq!(c14n(START_AND_END_TAGS, opts), START_AND_END_TAGS_C14N);
+}
+
+// normNames and normId would be normalized further if the DTD declared their attributes as
+// NMTOKENS and ID.
+#[test]
+fn character_modifications_and_references() {
+    let input = "<doc>
+   <text>First line&#x0d;&#10;Second line</text>
+   <value>&#x32;</value>
+   <compute><![CDATA[value>\"0\" && value<\"10\" ?\"valid\":\"error\"]]></compute>
+   <compute expr='value>\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"'>valid</compute>
+   <norm attr=' &apos;   &#x20;&#13;&#xa;&#9;   &apos; '/>
+   <normNames attr='   A   &#x20;&#13;&#xa;&#9;   B   '/>
+   <normId id=' &apos;   &#x20;&#13;&#xa;&#9;   &apos; '/>
+</doc>";
+    assert_eq!(
+        c14n(input, Default::default()),
+        "<doc>
+   <text>First line&#xD;
+Second line</text>
+   <value>2</value>
+   <compute>value&gt;\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"</compute>
+   <compute expr=\"value>&quot;0&quot; &amp;&amp; value&lt;&quot;10&quot; ?&quot;valid&quot;:&quot;error&quot;\">valid</compute>
+   <norm attr=\" '    &#xD;&#xA;&#x9;   ' \"></norm>
+   <normNames attr=\"   A    &#xD;&#xA;&#x9;   B   \"></normNames>
+   <normId id=\" '    &#xD;&#xA;&#x9;   ' \"></normId>
+</doc>"
+    );
+}
+
+#[test]
+fn attribute_value_whitespace() {
+    assert_eq!(
+        c14n("<doc a='x\r\ny\tz\nw'/>", Default::default()),
+        "<doc a=\"x y z w\"></doc>"
+    );
+}
+
+#[test]
+fn utf8_encoding() {
+    assert_eq!(
+        c14n(
+            "<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><doc>&#169;</doc>",
+            Default::default()
+        ),
+        "<doc>\u{a9}</doc>"
+    );
+}
+
+const SUBSET: &str = "<a xml:base=\"http://example.com/x/y/\" xml:lang=\"en\" xml:id=\"a1\">\
+                      <b xml:base=\"../z/\"><c attr=\"1\" xml:base=\"d/e\">t</c></b></a>";
+
+#[test]
+fn subset_inherits_xml_attributes() {
+    assert_eq!(
+        c14n_subset(SUBSET, "c", C14nMethod::Canonical10),
+        "<c attr=\"1\" xml:base=\"d/e\" xml:id=\"a1\" xml:lang=\"en\">t</c>"
+    );
+    assert_eq!(
+        c14n_subset(SUBSET, "c", C14nMethod::Canonical11),
+        "<c attr=\"1\" xml:base=\"http://example.com/x/z/d/e\" xml:lang=\"en\">t</c>"
+    );
+    assert_eq!(
+        c14n_subset(SUBSET, "c", C14nMethod::Exclusive),
+        "<c attr=\"1\" xml:base=\"d/e\">t</c>"
+    );
+}
+
+#[test]
+fn subset_joins_relative_xml_base() {
+    let input = "<a xml:base=\"../one/\"><b xml:base=\"../../two/./three/..\"><c/></b></a>";
+    assert_eq!(
+        c14n_subset(input, "c", C14nMethod::Canonical11),
+        "<c xml:base=\"../../two/\"></c>"
+    );
+    assert_eq!(
+        c14n_subset(input, "c", C14nMethod::Canonical10),
+        "<c xml:base=\"../../two/./three/..\"></c>"
+    );
+}
+
+#[test]
+fn subset_declares_inherited_namespaces() {
+    let input = "<r xmlns=\"urn:d\" xmlns:p=\"urn:p\"><p:a><b/></p:a></r>";
+    assert_eq!(
+        c14n_subset(input, "a", C14nMethod::Canonical10),
+        "<p:a xmlns=\"urn:d\" xmlns:p=\"urn:p\"><b></b></p:a>"
+    );
+    assert_eq!(
+        c14n_subset(input, "a", C14nMethod::Exclusive),
+        "<p:a xmlns:p=\"urn:p\"><b xmlns=\"urn:d\"></b></p:a>"
+    );
+}
+
+/// `<r xmlns="urn:d" xmlns:u="urn:u"><p:a xmlns:p="urn:p"><c xmlns=""/></p:a></r>`, with the
+/// namespace declarations passed as attributes.
+struct WithDeclarations;
+
+fn declaration(prefix: Option<&str>, value: &'static str) -> (QualName, &'static str) {
+    let name = match prefix {
+        Some(prefix) => QualName::new(
+            Some(Prefix::from("xmlns")),
+            ns!(xmlns),
+            LocalName::from(prefix),
+        ),
+        None => QualName::new(None, ns!(xmlns), LocalName::from("xmlns")),
+    };
+    (name, value)
+}
+
+impl Serialize for WithDeclarations {
+    fn serialize<S: Serializer>(&self, serializer: &mut S, _: TraversalScope) -> io::Result<()> {
+        let r = QualName::new(None, Namespace::from("urn:d"), LocalName::from("r"));
+        let a = QualName::new(
+            Some(Prefix::from("p")),
+            Namespace::from("urn:p"),
+            LocalName::from("a"),
+        );
+        let c = QualName::new(None, ns!(), LocalName::from("c"));
+        let r_attrs = [declaration(None, "urn:d"), declaration(Some("u"), "urn:u")];
+        let a_attrs = [declaration(Some("p"), "urn:p")];
+        let c_attrs = [declaration(None, "")];
+
+        serializer.start_elem(r.clone(), r_attrs.iter().map(|(n, v)| (n, *v)))?;
+        serializer.start_elem(a.clone(), a_attrs.iter().map(|(n, v)| (n, *v)))?;
+        serializer.start_elem(c.clone(), c_attrs.iter().map(|(n, v)| (n, *v)))?;
+        serializer.end_elem(c)?;
+        serializer.end_elem(a)?;
+        serializer.end_elem(r)
+    }
+}
+
+fn c14n_with_declarations(method: C14nMethod, inclusive_prefixes: Vec<Option<Prefix>>) -> String {
+    let opts = C14nOpts {
+        method,
+        inclusive_prefixes,
+        ..Default::default()
+    };
+    let mut result = vec![];
+    canonicalize(&mut result, &WithDeclarations, opts).unwrap();
+    String::from_utf8(result).unwrap()
+}
+
+#[test]
+fn inclusive_namespaces() {
+    assert_eq!(
+        c14n_with_declarations(C14nMethod::Canonical10, vec![]),
+        "<r xmlns=\"urn:d\" xmlns:u=\"urn:u\"><p:a xmlns:p=\"urn:p\"><c xmlns=\"\"></c></p:a></r>"
+    );
+}
+
+#[test]
+fn exclusive_namespaces() {
+    assert_eq!(
+        c14n_with_declarations(C14nMethod::Exclusive, vec![]),
+        "<r xmlns=\"urn:d\"><p:a xmlns:p=\"urn:p\"><c xmlns=\"\"></c></p:a></r>"
+    );
+    assert_eq!(
+        c14n_with_declarations(C14nMethod::Exclusive, vec![Some(Prefix::from("u"))]),
+        "<r xmlns=\"urn:d\" xmlns:u=\"urn:u\"><p:a xmlns:p=\"urn:p\"><c xmlns=\"\"></c></p:a></r>"
+    );
+}
+
+#[test]
+fn attribute_order() {
+    // Attributes sort by namespace URI, then local name, regardless of prefix.
+    let input = "<doc xmlns:z=\"urn:a\" xmlns:a=\"urn:b\" a:x=\"1\" z:y=\"2\" b=\"3\"/>";
+    assert_eq!(
+        c14n(input, Default::default()),
+        "<doc xmlns:a=\"urn:b\" xmlns:z=\"urn:a\" b=\"3\" z:y=\"2\" a:x=\"1\"></doc>"
+    );
+}
@@ -1,7 +1,13 @@
-use markup5ever_rcdom::{RcDom, SerializableHandle};
+use markup5ever::interface::XmlDeclaration;
+use markup5ever::interface::{Attribute, QualName};
+use markup5ever::{local_name, namespace_url, ns, LocalName, Namespace, Prefix};
+use markup5ever_rcdom::{Node, NodeData, RcDom, SerializableHandle};
+use std::borrow::Cow::Borrowed;
 use xml5ever::driver;
 use xml5ever::serialize;
 use xml5ever::tendril::TendrilSink;
+use xml5ever::tokenizer::{PIToken, ParseError, Pi, TokenSink};
+use xml5ever::tree_builder::XmlTreeBuilder;
 
 #[test]
 fn el_ns_serialize() {
@@ -99,3 +105,552 @@ fn assert_serialization(text: &'static str, dom: RcDom) {
     serialize::serialize(&mut serialized, &document, Default::default()).unwrap();
     assert_eq!(String::from_utf8(serialized).unwrap(), text);
 }
+
+#[test]
+fn escape_policy_serialize() {
+    let dom = driver::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .one("<a b=\"it's caf\u{e9}\">it's caf\u{e9} &amp; &lt;</a>".as_bytes());
+    let document: SerializableHandle = dom.document.clone().into();
+
+    let mut serialized = Vec::new();
+    serialize::serialize(&mut serialized, &document, Default::default()).unwrap();
+    assert_eq!(
+        String::from_utf8(serialized).unwrap(),
+        "<a b=\"it&apos;s caf\u{e9}\">it's caf\u{e9} &amp; &lt;</a>"
+    );
+
+    let opts = serialize::SerializeOpts {
+        escape_policy: serialize::EscapePolicy {
+            ascii_only: true,
+            char_refs: serialize::CharRefStyle::Hex,
+            ..serialize::EscapePolicy::xml()
+        },
+        ..Default::default()
+    };
+    let mut serialized = Vec::new();
+    serialize::serialize(&mut serialized, &document, opts).unwrap();
+    assert_eq!(
+        String::from_utf8(serialized).unwrap(),
+        "<a b=\"it&apos;s caf&#xE9;\">it's caf&#xE9; &amp; &lt;</a>"
+    );
+}
+
+#[test]
+fn encoding_serialize() {
+    let dom = driver::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .one("<a b=\"\u{e9}\u{3b1}\">\u{e9}\u{3b1}</a>".as_bytes());
+    let document: SerializableHandle = dom.document.clone().into();
+    let opts = serialize::SerializeOpts {
+        encoding: xml5ever::encoding_rs::ISO_8859_2,
+        ..Default::default()
+    };
+    let mut serialized = Vec::new();
+    serialize::serialize(&mut serialized, &document, opts.clone()).unwrap();
+    assert_eq!(
+        serialized,
+        &b"<?xml version=\"1.0\" encoding=\"ISO-8859-2\"?><a b=\"\xe9&#945;\">\xe9&#945;</a>"[..]
+    );
+
+    // The document's own declaration names the encoding it is written in.
+    let declaration = XmlDeclaration {
+        version: "1.0".into(),
+        encoding: Some("UTF-8".into()),
+        standalone: Some(true),
+    };
+    let opts = serialize::SerializeOpts {
+        xml_declaration: Some(declaration),
+        ..opts
+    };
+    let mut serialized = Vec::new();
+    serialize::serialize(&mut serialized, &document, opts.clone()).unwrap();
+    assert!(serialized
+        .starts_with(b"<?xml version=\"1.0\" encoding=\"ISO-8859-2\" standalone=\"yes\"?><a"));
+
+    // References aren't decoded in comments, CDATA sections or names.
+    for input in &[
+        "<a><!--\u{3b1}--></a>",
+        "<a><![CDATA[\u{3b1}]]></a>",
+        "<\u{3b1}/>",
+        "<a \u{3b1}='1'/>",
+        "<a><?\u{3b1} x?></a>",
+    ] {
+        let mut parse_opts = driver::XmlParseOpts::default();
+        parse_opts.tokenizer.cdata_tokens = true;
+        let dom = driver::parse_document(RcDom::default(), parse_opts).one(*input);
+        let document: SerializableHandle = dom.document.clone().into();
+        let error = serialize::serialize(&mut Vec::new(), &document, opts.clone()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{}", input);
+    }
+}
+
+fn parse_entities(input: &str) -> (String, Vec<String>) {
+    parse_entities_with(input, Default::default())
+}
+
+fn parse_entities_with(input: &str, opts: driver::XmlParseOpts) -> (String, Vec<String>) {
+    let dom = driver::parse_document(RcDom::default(), opts)
+        .from_utf8()
+        .one(input.as_bytes());
+    let root = dom.document.children.borrow().last().unwrap().clone();
+    let root: SerializableHandle = root.into();
+    let opts = serialize::SerializeOpts {
+        traversal_scope: serialize::TraversalScope::IncludeNode,
+        ..Default::default()
+    };
+    let mut serialized = Vec::new();
+    serialize::serialize(&mut serialized, &root, opts).unwrap();
+    let errors = dom.errors.iter().map(|e| e.to_string()).collect();
+    (String::from_utf8(serialized).unwrap(), errors)
+}
+
+#[test]
+fn entity_expansion() {
+    let (serialized, errors) = parse_entities(
+        "<!DOCTYPE a [
+           <!ENTITY inner '<b c=\"&#38;amp;\">x</b>'>
+           <!ENTITY outer 'y&inner;z'>
+           <!ENTITY attr 'p&#38;#9;q\nr'>
+         ]><a d='&attr;'>&outer;&amp;</a>",
+    );
+    assert_eq!(
+        serialized,
+        "<a d=\"p\tq r\">y<b c=\"&amp;\">x</b>z&amp;</a>"
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+#[test]
+fn entity_errors() {
+    let (serialized, errors) = parse_entities(
+        "<!DOCTYPE a [
+           <!ENTITY self 'a&self;'>
+           <!ENTITY ext SYSTEM 'file:///etc/passwd'>
+         ]><a>&self;&ext;</a>",
+    );
+    assert_eq!(serialized, "<a>a</a>");
+    assert_eq!(
+        errors,
+        [
+            "Recursive entity reference",
+            "External entities are not loaded"
+        ]
+    );
+
+    let (_, errors) = parse_entities("<!DOCTYPE a [<!ENTITY tag '<b/>'>]><a b='&tag;'/>");
+    assert_eq!(errors, ["'<' in the replacement text of an attribute"]);
+
+    // HTML's named references aren't XML entities.
+    let (serialized, errors) = parse_entities(
+        "<!DOCTYPE a [<!ENTITY copy '(c)'>]><a b='&eacute;&copy;'>&nbsp;&copy;&not;&lt;</a>",
+    );
+    assert_eq!(
+        serialized,
+        "<a b=\"&amp;eacute;(c)\">&amp;nbsp;(c)&amp;not;&lt;</a>"
+    );
+    assert_eq!(errors, ["Reference to an undeclared entity"; 3]);
+}
+
+#[test]
+fn entity_expansion_limit() {
+    let mut input = String::from("<!DOCTYPE a [<!ENTITY l0 'lol'>");
+    for i in 1..10 {
+        input.push_str(&format!(
+            "<!ENTITY l{} '{}'>",
+            i,
+            format!("&l{};", i - 1).repeat(10)
+        ));
+    }
+    input.push_str("]><a>&l9;</a>");
+    let mut opts = driver::XmlParseOpts::default();
+    opts.tree_builder.max_entity_expansion = 1 << 16;
+    let (serialized, errors) = parse_entities_with(&input, opts);
+    assert!(serialized.len() <= (1 << 16) + "<a></a>".len());
+    assert!(errors.contains(&"Entity expansion limit exceeded".to_string()));
+
+    // References to empty entities count towards the limit.
+    let mut input = String::from("<!DOCTYPE a [<!ENTITY e0 ''>");
+    for i in 1..10 {
+        input.push_str(&format!(
+            "<!ENTITY e{} '{}'>",
+            i,
+            format!("&e{};", i - 1).repeat(10)
+        ));
+    }
+    input.push_str("]><a>&e9;</a>");
+    let mut opts = driver::XmlParseOpts::default();
+    opts.tree_builder.max_entity_expansion = 1 << 16;
+    let (serialized, errors) = parse_entities_with(&input, opts);
+    assert_eq!(serialized, "<a></a>");
+    assert!(errors.contains(&"Entity expansion limit exceeded".to_string()));
+}
+
+#[test]
+fn entity_expansion_tokenizer_opts() {
+    let input = "<!DOCTYPE a [<!ENTITY e '<![CDATA[1 < 2]]>'>]><a>&e;</a>";
+    let mut opts = driver::XmlParseOpts::default();
+    opts.tokenizer.cdata_tokens = true;
+    let (serialized, _) = parse_entities_with(input, opts);
+    assert_eq!(serialized, "<a><![CDATA[1 < 2]]></a>");
+    let (serialized, _) = parse_entities(input);
+    assert_eq!(serialized, "<a>1 &lt; 2</a>");
+
+    let input = "<!DOCTYPE a [<!ENTITY e '<b c/>'>]><a>&e;</a>";
+    let mut opts = driver::XmlParseOpts::default();
+    opts.tokenizer.strict = true;
+    opts.tree_builder.strict = true;
+    let (_, errors) = parse_entities_with(input, opts);
+    assert_eq!(errors, ["1:41: 1:5: Attribute without a value"]);
+    let (_, errors) = parse_entities(input);
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+fn parse_bytes(chunks: &[&[u8]], opts: driver::BytesOpts) -> (RcDom, String) {
+    let mut parser = driver::parse_document(RcDom::default(), Default::default()).from_bytes(opts);
+    for chunk in chunks {
+        parser.process((*chunk).into());
+    }
+    let dom = parser.finish();
+    let mut serialized = Vec::new();
+    let document: SerializableHandle = dom.document.clone().into();
+    serialize::serialize(&mut serialized, &document, Default::default()).unwrap();
+    (dom, String::from_utf8(serialized).unwrap())
+}
+
+fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+    text.encode_utf16()
+        .flat_map(|unit| {
+            if big_endian {
+                unit.to_be_bytes()
+            } else {
+                unit.to_le_bytes()
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn xml_declaration() {
+    let (dom, serialized) = parse_bytes(
+        &[b"<?xml version='1.0' encoding='UTF-8' standalone='no'?><a/>"],
+        Default::default(),
+    );
+    assert_eq!(
+        dom.xml_declaration,
+        Some(XmlDeclaration {
+            version: "1.0".into(),
+            encoding: Some("UTF-8".into()),
+            standalone: Some(false),
+        })
+    );
+    // The declaration is also kept as a processing instruction, so it's written back out.
+    assert_eq!(
+        serialized,
+        "<?xml version='1.0' encoding='UTF-8' standalone='no'?><a></a>"
+    );
+    assert!(dom.errors.is_empty());
+
+    // A declaration passed to the serializer is written in its place.
+    let mut serialized = Vec::new();
+    let document: SerializableHandle = dom.document.clone().into();
+    let opts = serialize::SerializeOpts {
+        xml_declaration: dom.xml_declaration.clone(),
+        require_well_formed: true,
+        ..Default::default()
+    };
+    serialize::serialize(&mut serialized, &document, opts).unwrap();
+    assert_eq!(
+        String::from_utf8(serialized).unwrap(),
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?><a></a>"
+    );
+
+    let (dom, _) = parse_bytes(&[b"<?xml encoding='UTF-8'?><a/>"], Default::default());
+    assert_eq!(dom.xml_declaration, None);
+    assert_eq!(dom.errors.len(), 1);
+
+    // Only the very start of the document may hold a declaration.
+    let (dom, _) = parse_bytes(&[b"<a/><?xml version='1.0'?>"], Default::default());
+    assert_eq!(dom.xml_declaration, None);
+
+    // A parse error isn't content, so a declaration may still follow it.
+    let mut builder = XmlTreeBuilder::new(RcDom::default(), Default::default());
+    builder.process_token(ParseError(Borrowed("error")));
+    builder.process_token(PIToken(Pi {
+        target: "xml".into(),
+        data: "version='1.0'".into(),
+    }));
+    assert_eq!(
+        builder.sink.xml_declaration.map(|declaration| declaration.version),
+        Some("1.0".into())
+    );
+}
+
+#[test]
+fn detect_encoding() {
+    let text = "<?xml version='1.0'?><a>caf\u{e9} \u{3b1}</a>";
+    let expected = text;
+    for &big_endian in [false, true].iter() {
+        let bytes = utf16(text, big_endian);
+        assert_eq!(parse_bytes(&[&bytes], Default::default()).1, expected);
+        let bom = "\u{feff}";
+        let bytes = utf16(&format!("{}{}", bom, text), big_endian);
+        assert_eq!(parse_bytes(&[&bytes], Default::default()).1, expected);
+    }
+
+    let mut bytes = b"\xef\xbb\xbf".to_vec();
+    bytes.extend_from_slice(text.as_bytes());
+    assert_eq!(parse_bytes(&[&bytes], Default::default()).1, expected);
+
+    let (dom, serialized) = parse_bytes(
+        &[
+            b"<?xml version='1.0' enc",
+            b"oding='ISO-8859-1'?><a>caf\xe9</a>",
+        ],
+        Default::default(),
+    );
+    // The declaration is kept as written, even though the output is UTF-8.
+    assert_eq!(
+        serialized,
+        "<?xml version='1.0' encoding='ISO-8859-1'?><a>caf\u{e9}</a>"
+    );
+    assert!(dom.errors.is_empty());
+
+    // The transport layer wins over the declaration.
+    let opts = driver::BytesOpts {
+        transport_layer_encoding: Some(xml5ever::encoding_rs::WINDOWS_1252),
+    };
+    let (_, serialized) = parse_bytes(
+        &[b"<?xml version='1.0' encoding='UTF-8'?><a>\x80</a>"],
+        opts,
+    );
+    assert_eq!(
+        serialized,
+        "<?xml version='1.0' encoding='UTF-8'?><a>\u{20ac}</a>"
+    );
+
+    let (dom, serialized) = parse_bytes(
+        &[b"<?xml version='1.0' encoding='no-such-encoding'?><a>\xc3\xa9</a>"],
+        Default::default(),
+    );
+    assert_eq!(
+        serialized,
+        "<?xml version='1.0' encoding='no-such-encoding'?><a>\u{e9}</a>"
+    );
+    assert_eq!(dom.errors, ["Unsupported encoding no-such-encoding"]);
+}
+
+#[test]
+fn xml_declaration_serialize() {
+    let dom = driver::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .one("<?xml version=\"1.0\" standalone=\"yes\"?><a/>".as_bytes());
+    let document: SerializableHandle = dom.document.clone().into();
+    let opts = serialize::SerializeOpts {
+        xml_declaration: dom.xml_declaration.clone(),
+        ..Default::default()
+    };
+    let mut serialized = Vec::new();
+    serialize::serialize(&mut serialized, &document, opts).unwrap();
+    assert_eq!(
+        String::from_utf8(serialized).unwrap(),
+        "<?xml version=\"1.0\" standalone=\"yes\"?><a></a>"
+    );
+}
+
+#[test]
+fn cdata_round_trip() {
+    let input = "<svg><script><![CDATA[if (a < b && c) { x = d[e[0]]; }]]></script>\
+                 <text>1 &lt; 2<![CDATA[ ]]]]><![CDATA[> ]]></text></svg>";
+    let mut opts = driver::XmlParseOpts::default();
+    opts.tokenizer.cdata_tokens = true;
+    let dom = driver::parse_document(RcDom::default(), opts)
+        .from_utf8()
+        .one(input.as_bytes());
+    assert_serialization(input, dom);
+
+    // Without the option, CDATA sections are text.
+    assert_serialization(
+        "<a>1 &lt; 2</a>",
+        driver::parse_document(RcDom::default(), Default::default())
+            .from_utf8()
+            .one("<a>1 <![CDATA[< 2]]></a>".as_bytes()),
+    );
+}
+
+#[test]
+fn cdata_serialize() {
+    let cdata: SerializableHandle = Node::new(NodeData::CData {
+        contents: "a]]>b]]>".into(),
+    })
+    .into();
+    let opts = serialize::SerializeOpts {
+        traversal_scope: serialize::TraversalScope::IncludeNode,
+        ..Default::default()
+    };
+    let mut serialized = Vec::new();
+    serialize::serialize(&mut serialized, &cdata, opts).unwrap();
+    assert_eq!(
+        String::from_utf8(serialized).unwrap(),
+        "<![CDATA[a]]]]><![CDATA[>b]]]]><![CDATA[>]]>"
+    );
+}
+
+#[test]
+fn strip_whitespace() {
+    let input = "<a>\n  <b> x </b>\n  <c xml:space=\"preserve\">\n    <d> </d>\n    \
+                 <e xml:space=\"default\"> <f/> </e>\n  </c>\n  <g>&#32;<!-- y --> </g>\n</a>";
+    let mut opts = driver::XmlParseOpts::default();
+    opts.tree_builder.strip_whitespace = true;
+    let (serialized, errors) = parse_entities_with(input, opts);
+    assert_eq!(
+        serialized,
+        "<a><b> x </b><c xml:space=\"preserve\">\n    <d> </d>\n    \
+         <e xml:space=\"default\"><f></f></e>\n  </c><g><!-- y --></g></a>"
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    // Whitespace is kept by default.
+    let (serialized, _) = parse_entities("<a> <b/> </a>");
+    assert_eq!(serialized, "<a> <b></b> </a>");
+}
+
+#[test]
+fn namespaced_attributes_on_sibling_tags() {
+    // Each tag's attributes are only checked against each other for duplicates.
+    let (serialized, errors) = parse_entities(
+        "<doc xmlns:p='urn:p'><a p:x='1' x='2'/><b p:x='3' x='4'/><c p:x='5' p:x='6'/></doc>",
+    );
+    assert_eq!(
+        serialized,
+        "<doc><a xmlns:p=\"urn:p\" p:x=\"1\" x=\"2\"></a><b xmlns:p=\"urn:p\" p:x=\"3\" x=\"4\"></b>\
+         <c xmlns:p=\"urn:p\" p:x=\"5\"></c></doc>"
+    );
+    assert_eq!(errors, ["Duplicate attribute"]);
+}
+
+#[test]
+fn namespace_recovery() {
+    use xml5ever::tree_builder::NamespaceRecovery;
+
+    let input = "<a xmlns:x='urn:x' xmlns:y='urn:x'><p:b q:c='1' x:d='2' y:d='3'/>x<e/></a>";
+    let parse = |recovery| {
+        let mut opts = driver::XmlParseOpts::default();
+        opts.tree_builder.namespace_recovery = recovery;
+        parse_entities_with(input, opts)
+    };
+
+    let (serialized, errors) = parse(NamespaceRecovery::NoNamespace);
+    assert_eq!(
+        serialized,
+        "<a><b c=\"1\" xmlns:x=\"urn:x\" x:d=\"2\"></b>x<e></e></a>"
+    );
+    assert_eq!(
+        errors,
+        [
+            "No appropriate namespace found",
+            "Duplicate attribute after namespace binding",
+            "No appropriate namespace found",
+        ]
+    );
+
+    let (serialized, errors) = parse(NamespaceRecovery::Literal);
+    assert_eq!(
+        serialized,
+        "<a><p:b q:c=\"1\" xmlns:x=\"urn:x\" x:d=\"2\"></p:b>x<e></e></a>"
+    );
+    assert_eq!(errors.len(), 3);
+
+    let (serialized, errors) = parse(NamespaceRecovery::Fail);
+    // The element with the error is still built, as in strict mode.
+    assert_eq!(
+        serialized,
+        "<a><b c=\"1\" xmlns:x=\"urn:x\" x:d=\"2\"></b></a>"
+    );
+    assert_eq!(errors, ["No appropriate namespace found"]);
+}
+
+#[test]
+fn namespace_context() {
+    let mut opts = driver::XmlParseOpts::default();
+    opts.namespaces = vec![
+        (None, Namespace::from("urn:default")),
+        (Some(Prefix::from("p")), Namespace::from("urn:p")),
+    ]
+    .into_iter()
+    .collect();
+    let (serialized, errors) = parse_entities_with(
+        "<p:a p:b='1'><c/><d xmlns='urn:d' xmlns:p='urn:q'><p:e/></d></p:a>",
+        opts,
+    );
+    assert_eq!(
+        serialized,
+        "<p:a xmlns:p=\"urn:p\" p:b=\"1\"><c xmlns=\"urn:default\"></c>\
+         <d xmlns=\"urn:d\"><p:e xmlns:p=\"urn:q\"></p:e></d></p:a>"
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+fn parse_fragment(
+    input: &str,
+    opts: driver::XmlParseOpts,
+    context_name: QualName,
+    context_attrs: Vec<Attribute>,
+) -> (String, Vec<String>) {
+    let dom = driver::parse_fragment(RcDom::default(), opts, context_name, context_attrs)
+        .from_utf8()
+        .one(input.as_bytes());
+    let root = dom.document.children.borrow()[0].clone();
+    let root: SerializableHandle = root.into();
+    let mut serialized = Vec::new();
+    serialize::serialize(&mut serialized, &root, Default::default()).unwrap();
+    let errors = dom.errors.iter().map(|e| e.to_string()).collect();
+    (String::from_utf8(serialized).unwrap(), errors)
+}
+
+#[test]
+fn fragment() {
+    let atom = Namespace::from("http://www.w3.org/2005/Atom");
+    let xhtml = Namespace::from("http://www.w3.org/1999/xhtml");
+    let content = QualName::new(Some(Prefix::from("a")), atom, LocalName::from("content"));
+    let attrs = vec![
+        Attribute {
+            name: QualName::new(None, ns!(), LocalName::from("type")),
+            value: "xhtml".into(),
+        },
+        Attribute {
+            name: QualName::new(None, ns!(xmlns), local_name!("xmlns")),
+            value: xhtml.to_string().into(),
+        },
+    ];
+    let (serialized, errors) = parse_fragment(
+        "<p>One</p> two <a:b/><?pi?></content></a:content>",
+        Default::default(),
+        content,
+        attrs,
+    );
+    assert_eq!(
+        serialized,
+        "<p xmlns=\"http://www.w3.org/1999/xhtml\">One</p> two \
+         <a:b xmlns:a=\"http://www.w3.org/2005/Atom\"></a:b><?pi ?>"
+    );
+    assert_eq!(
+        errors,
+        [
+            "Current node doesn't match tag",
+            "Current node doesn't match tag"
+        ]
+    );
+
+    // Namespaces can also be supplied as a map.
+    let mut opts = driver::XmlParseOpts::default();
+    opts.namespaces.insert(Some(Prefix::from("s")), Namespace::from("urn:s"));
+    opts.tree_builder.strict = true;
+    let body = QualName::new(None, ns!(), LocalName::from("Body"));
+    let (serialized, errors) = parse_fragment("<s:x/><s:y/>", opts, body, vec![]);
+    assert_eq!(
+        serialized,
+        "<s:x xmlns:s=\"urn:s\"></s:x><s:y xmlns:s=\"urn:s\"></s:y>"
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+}
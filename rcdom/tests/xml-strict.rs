@@ -0,0 +1,155 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checks what strict mode accepts and rejects.
+//!
+//! The cases in `data/xml-strict/catalog.xml` were written for xml5ever and
+//! always run. They aren't taken from the W3C XML conformance suite, but use
+//! the format of its catalogs, so the suite can be run the same way: unpack
+//! it and point `XMLCONF_CATALOG` at one of its catalogs, e.g.
+//! `xmlconf/xmltest/xmltest.xml`. Only standalone cases (`ENTITIES="none"`)
+//! in UTF-8 are run, since external entities are never loaded.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use xml5ever::driver::{self, XmlParseOpts};
+use xml5ever::tendril::TendrilSink;
+
+struct Case {
+    id: String,
+    kind: String,
+    path: PathBuf,
+}
+
+fn attr(handle: &Handle, name: &str) -> Option<String> {
+    match handle.data {
+        NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|attr| &*attr.name.local == name)
+            .map(|attr| attr.value.to_string()),
+        _ => None,
+    }
+}
+
+fn find_cases(handle: &Handle, base: &Path, cases: &mut Vec<Case>) {
+    if let NodeData::Element { ref name, .. } = handle.data {
+        if &*name.local == "TEST" && attr(handle, "ENTITIES").as_deref() == Some("none") {
+            cases.push(Case {
+                id: attr(handle, "ID").unwrap(),
+                kind: attr(handle, "TYPE").unwrap(),
+                path: base.join(attr(handle, "URI").unwrap()),
+            });
+        }
+    }
+    for child in handle.children.borrow().iter() {
+        find_cases(child, base, cases);
+    }
+}
+
+fn parse_strict(input: &str) -> Vec<String> {
+    let mut opts = XmlParseOpts::default();
+    opts.tokenizer.strict = true;
+    opts.tree_builder.strict = true;
+    let dom = driver::parse_document(RcDom::default(), opts)
+        .from_utf8()
+        .one(input.as_bytes());
+    dom.errors.iter().map(|error| error.to_string()).collect()
+}
+
+fn run_catalog(catalog: &Path) {
+    let dom = driver::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .from_file(catalog)
+        .unwrap();
+    let mut cases = vec![];
+    find_cases(&dom.document, catalog.parent().unwrap(), &mut cases);
+    assert!(!cases.is_empty(), "no cases in {}", catalog.display());
+
+    let mut failures = vec![];
+    for case in cases.iter() {
+        let input = match String::from_utf8(fs::read(&case.path).unwrap()) {
+            Ok(input) => input,
+            Err(_) => continue,
+        };
+        let errors = parse_strict(&input);
+        match &*case.kind {
+            "not-wf" if errors.is_empty() => {
+                failures.push(format!("{}: accepted", case.id));
+            },
+            "valid" | "invalid" if !errors.is_empty() => {
+                failures.push(format!("{}: {}", case.id, errors[0]));
+            },
+            _ => (),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} cases failed:\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn catalog() {
+    run_catalog(&Path::new(env!("CARGO_MANIFEST_DIR")).join("data/xml-strict/catalog.xml"));
+}
+
+#[test]
+fn w3c_suite() {
+    if let Some(path) = env::var_os("XMLCONF_CATALOG") {
+        run_catalog(&PathBuf::from(path));
+    }
+}
+
+#[test]
+fn position() {
+    assert_eq!(
+        parse_strict("<doc>\r\n  <a x='1'></b>\n</doc>"),
+        ["2:15: Current node doesn't match tag"]
+    );
+    assert_eq!(
+        parse_strict("<doc>\n<a b='<'/>&bad</doc>"),
+        ["2:7: '<' in an attribute value"]
+    );
+}
+
+#[test]
+fn undeclared_html_entities() {
+    assert_eq!(
+        parse_strict("<doc>&nbsp;</doc>"),
+        ["1:11: Reference to an undeclared entity"]
+    );
+    assert_eq!(
+        parse_strict("<doc>&copy;</doc>"),
+        ["1:11: Reference to an undeclared entity"]
+    );
+    assert_eq!(
+        parse_strict("<doc>&not;</doc>"),
+        ["1:10: Reference to an undeclared entity"]
+    );
+    assert_eq!(
+        parse_strict("<doc a='&eacute;'/>"),
+        ["1:16: Reference to an undeclared entity"]
+    );
+}
+
+#[test]
+fn text_after_root() {
+    assert_eq!(
+        parse_strict("<doc/>text"),
+        ["1:7: Text after the root element"]
+    );
+    assert_eq!(parse_strict("<doc/>\n<!-- c -->\n"), Vec::<String>::new());
+}
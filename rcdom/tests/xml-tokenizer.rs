@@ -256,6 +256,7 @@ fn json_to_token(js: &Value) -> Token {
             name: args[0].get_nullable_tendril(),
             public_id: args[1].get_nullable_tendril(),
             system_id: args[2].get_nullable_tendril(),
+            internal_subset: vec![],
         }),
 
         // We don't need to produce NullCharacterToken because
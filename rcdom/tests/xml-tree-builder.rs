@@ -7,7 +7,10 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use markup5ever::traverse::{NodeKind, TreeNavigator};
 use markup5ever::{namespace_url, ns};
+use markup5ever_arena::{Arena, ArenaDom};
+use markup5ever_rcdom::sync_dom::SyncDom;
 use markup5ever_rcdom::*;
 use rustc_test::{DynTestFn, DynTestName, TestDesc, TestDescAndFn};
 use std::collections::{HashMap, HashSet};
@@ -69,55 +72,51 @@ fn parse_tests<It: Iterator<Item = String>>(mut lines: It) -> Vec<HashMap<String
     tests
 }
 
-fn serialize(buf: &mut String, indent: usize, handle: Handle) {
+fn serialize<N: TreeNavigator>(buf: &mut String, indent: usize, dom: &N, node: N::Handle) {
     buf.push_str("|");
     buf.push_str(&repeat(" ").take(indent).collect::<String>());
 
-    let node = handle;
-    match node.data {
-        NodeData::Document => panic!("should not reach Document"),
+    match dom.kind(&node) {
+        NodeKind::Document => panic!("should not reach Document"),
 
-        NodeData::Doctype {
-            ref name,
-            ref public_id,
-            ref system_id,
-        } => {
+        NodeKind::Doctype => {
+            let doctype = dom.doctype(&node).unwrap();
             buf.push_str("<!DOCTYPE ");
-            buf.push_str(&name);
-            if !public_id.is_empty() || !system_id.is_empty() {
-                buf.push_str(&format!(" \"{}\" \"{}\"", public_id, system_id));
+            buf.push_str(&doctype.name);
+            if !doctype.public_id.is_empty() || !doctype.system_id.is_empty() {
+                buf.push_str(&format!(" \"{}\" \"{}\"", doctype.public_id, doctype.system_id));
             }
             buf.push_str(">\n");
         },
 
-        NodeData::Text { ref contents } => {
+        NodeKind::Text => {
             buf.push_str("\"");
-            buf.push_str(&contents.borrow());
+            buf.push_str(&dom.text(&node).unwrap());
             buf.push_str("\"\n");
         },
 
-        NodeData::ProcessingInstruction {
-            ref target,
-            ref contents,
-        } => {
+        NodeKind::ProcessingInstruction => {
             buf.push_str("<?");
-            buf.push_str(&target);
+            buf.push_str(&dom.name(&node).unwrap().local);
             buf.push_str(" ");
-            buf.push_str(&contents);
+            buf.push_str(&dom.text(&node).unwrap());
             buf.push_str("?>\n");
         },
 
-        NodeData::Comment { ref contents } => {
+        NodeKind::Comment => {
             buf.push_str("<!-- ");
-            buf.push_str(&contents);
+            buf.push_str(&dom.text(&node).unwrap());
             buf.push_str(" -->\n");
         },
 
-        NodeData::Element {
-            ref name,
-            ref attrs,
-            ..
-        } => {
+        NodeKind::CData => {
+            buf.push_str("<![CDATA[");
+            buf.push_str(&dom.text(&node).unwrap());
+            buf.push_str("]]>\n");
+        },
+
+        NodeKind::Element => {
+            let name = dom.name(&node).unwrap();
             buf.push_str("<");
 
             if name.ns != ns!() {
@@ -134,7 +133,7 @@ fn serialize(buf: &mut String, indent: usize, handle: Handle) {
             buf.push_str(&*name.local);
             buf.push_str(">\n");
 
-            let mut attrs = attrs.borrow().clone();
+            let mut attrs = dom.attributes(&node);
             attrs.sort_by(|x, y| x.name.local.cmp(&y.name.local));
             // FIXME: sort by UTF-16 code unit
 
@@ -158,9 +157,18 @@ fn serialize(buf: &mut String, indent: usize, handle: Handle) {
         },
     }
 
-    for child in node.children.borrow().iter() {
-        serialize(buf, indent + 2, child.clone());
+    for child in dom.children(&node) {
+        serialize(buf, indent + 2, dom, child);
+    }
+}
+
+/// Print the children of the document after what is already in `buf`.
+fn serialize_children<N: TreeNavigator>(buf: &mut String, dom: &N, document: N::Handle) {
+    for child in dom.children(&document) {
+        serialize(buf, 1, dom, child);
     }
+    let len = buf.len();
+    buf.truncate(len - 1); // drop the trailing newline
 }
 
 // Ignore tests containing these strings; we don't support these features yet.
@@ -192,12 +200,7 @@ fn make_xml_test(
             let mut result = String::new();
 
             let dom = parse_document(RcDom::default(), Default::default()).one(data.clone());
-            for child in dom.document.children.borrow().iter() {
-                serialize(&mut result, 1, child.clone());
-            }
-
-            let len = result.len();
-            result.truncate(len - 1); // drop the trailing newline
+            serialize_children(&mut result, &dom, dom.document.clone());
 
             if result != expected {
                 panic!(
@@ -205,6 +208,24 @@ fn make_xml_test(
                     data, result, expected
                 );
             }
+
+            let arena = Arena::new();
+            let arena_dom =
+                parse_document(ArenaDom::new(&arena), Default::default()).one(data.clone());
+            let mut arena_result = String::new();
+            serialize_children(&mut arena_result, &arena_dom, arena_dom.document);
+            let sync_dom = SyncDom::from(arena_dom);
+            let mut sync_result = String::new();
+            serialize_children(&mut sync_result, &sync_dom, sync_dom.document().id());
+
+            for &(dom, ref result) in &[("ArenaDom", arena_result), ("SyncDom", sync_result)] {
+                if *result != expected {
+                    panic!(
+                        "\ninput: {}\ngot from {}:\n{}\nexpected:\n{}\n",
+                        data, dom, result, expected
+                    );
+                }
+            }
         })),
     });
 }
@@ -0,0 +1,305 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use html5ever::tendril::TendrilSink;
+use markup5ever::xpath::{Context, Error, Node, Value, XPath};
+use markup5ever::{Namespace, Prefix};
+use markup5ever_rcdom::{Handle, RcDom};
+
+const XML: &str = r#"<?xml version="1.0"?>
+<library xmlns="urn:library" xmlns:x="urn:extra" xml:lang="en-GB">
+  <?index books?>
+  <book id="b1" x:year="1999"><title>Alpha</title><price>10</price></book>
+  <!-- out of print -->
+  <book id="b2" x:year="2005"><title>Beta</title><price>25.5</price></book>
+  <book id="b3" xml:lang="fr"><title>Gamma <![CDATA[&]]> Delta</title><price>7</price></book>
+</library>"#;
+
+const HTML: &str = "<!DOCTYPE html><title>Test</title>\
+    <div class=article><a href=http://example.com/1>One</a><A HREF=/two>Two</A>\
+    <svg><foreignObject><p lang=de>Drei</p></foreignObject></svg></div>";
+
+fn parse_xml(input: &str) -> RcDom {
+    xml5ever::driver::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .one(input.as_bytes())
+}
+
+fn parse_html(input: &str) -> RcDom {
+    html5ever::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .one(input.as_bytes())
+}
+
+fn library_context() -> Context<Handle> {
+    let mut context = Context::default();
+    context.bind(Prefix::from("l"), Namespace::from("urn:library"));
+    context.bind(Prefix::from("x"), Namespace::from("urn:extra"));
+    context
+}
+
+fn eval(dom: &RcDom, context: &Context<Handle>, expr: &str) -> Value<Handle> {
+    XPath::parse(expr)
+        .unwrap()
+        .evaluate(dom, context, &dom.document)
+        .unwrap()
+}
+
+/// The string-values of the selected nodes.
+fn select(dom: &RcDom, context: &Context<Handle>, expr: &str) -> Vec<String> {
+    let nodes = XPath::parse(expr)
+        .unwrap()
+        .select(dom, context, &dom.document)
+        .unwrap();
+    nodes.iter().map(|node| node.string_value(dom)).collect()
+}
+
+fn string(dom: &RcDom, context: &Context<Handle>, expr: &str) -> String {
+    eval(dom, context, expr).string(dom)
+}
+
+#[test]
+fn location_paths() {
+    let dom = parse_xml(XML);
+    let context = library_context();
+    let select = |expr| select(&dom, &context, expr);
+
+    assert_eq!(
+        select("/l:library/l:book/l:title"),
+        ["Alpha", "Beta", "Gamma & Delta"]
+    );
+    assert_eq!(select("//l:book[2]/l:title"), ["Beta"]);
+    assert_eq!(select("//l:book[last()]/@id"), ["b3"]);
+    assert_eq!(select("//l:book[@x:year > 2000]/@id"), ["b2"]);
+    assert_eq!(select("//l:book[l:price < 20]/@id"), ["b1", "b3"]);
+    assert_eq!(select("//l:title[. = 'Beta']/../@id"), ["b2"]);
+    assert_eq!(select("//l:price/ancestor::*[1]/@id"), ["b1", "b2", "b3"]);
+    assert_eq!(select("(//l:price)[1]/ancestor::*/@id"), ["b1"]);
+    assert_eq!(
+        select("//l:book[1]/following-sibling::l:book/@id"),
+        ["b2", "b3"]
+    );
+    assert_eq!(
+        select("//l:book[3]/preceding-sibling::l:book[1]/@id"),
+        ["b2"]
+    );
+    assert_eq!(select("//l:book[3]/preceding::l:title"), ["Alpha", "Beta"]);
+    assert_eq!(select("//l:book[3]/preceding::l:title[1]"), ["Beta"]);
+    assert_eq!(
+        select("//l:title[1]/following::l:price"),
+        ["10", "25.5", "7"]
+    );
+    assert_eq!(
+        select("//@x:year/following::l:title"),
+        ["Alpha", "Beta", "Gamma & Delta"]
+    );
+    assert_eq!(
+        select("//l:book/@*"),
+        ["b1", "1999", "b2", "2005", "b3", "fr"]
+    );
+    assert_eq!(
+        select("//l:price | //l:title[1]"),
+        ["Alpha", "10", "Beta", "25.5", "Gamma & Delta", "7"]
+    );
+    assert_eq!(select("//comment()"), [" out of print "]);
+    assert_eq!(select("//processing-instruction('index')"), ["books"]);
+    assert_eq!(select("//processing-instruction('other')").len(), 0);
+    assert_eq!(
+        select("//l:book[@id = 'b2']/descendant::text()"),
+        ["Beta", "25.5"]
+    );
+    assert_eq!(select("id('b3 b1')/@id"), ["b1", "b3"]);
+    assert_eq!(select("//l:book[lang('fr')]/@id"), ["b3"]);
+    assert_eq!(select("//l:book[lang('en')]/@id"), ["b1", "b2"]);
+    // Unprefixed names are in no namespace.
+    assert_eq!(select("//book").len(), 0);
+    assert_eq!(select("/"), [select("/l:library")[0].clone()]);
+}
+
+#[test]
+fn namespaces() {
+    let dom = parse_xml(XML);
+    let context = library_context();
+    let nodes = XPath::parse("/l:library/namespace::*")
+        .unwrap()
+        .select(&dom, &context, &dom.document)
+        .unwrap();
+    let namespaces: Vec<_> = nodes
+        .iter()
+        .map(|node| match *node {
+            Node::Namespace(_, ref prefix, ref ns) => {
+                (prefix.as_ref().map(|p| p.to_string()), ns.to_string())
+            },
+            _ => panic!("{:?}", node),
+        })
+        .collect();
+    assert_eq!(
+        namespaces,
+        [
+            (None, "urn:library".to_owned()),
+            (
+                Some("xml".to_owned()),
+                "http://www.w3.org/XML/1998/namespace".to_owned()
+            ),
+        ]
+    );
+    assert_eq!(
+        string(&dom, &context, "namespace-uri(//l:book[1])"),
+        "urn:library"
+    );
+    assert_eq!(string(&dom, &context, "name(//@x:year)"), "x:year");
+    assert_eq!(string(&dom, &context, "local-name(//@x:year)"), "year");
+    assert_eq!(
+        string(&dom, &context, "name(/*//processing-instruction())"),
+        "index"
+    );
+    assert_eq!(
+        XPath::parse("//p:book")
+            .unwrap()
+            .evaluate(&dom, &context, &dom.document)
+            .unwrap_err(),
+        Error::Evaluation("No namespace bound to prefix p".into())
+    );
+}
+
+#[test]
+fn functions() {
+    let dom = parse_xml(XML);
+    let context = library_context();
+    let string = |expr| string(&dom, &context, expr);
+    let number = |expr| eval(&dom, &context, expr).number(&dom);
+    let boolean = |expr| eval(&dom, &context, expr).boolean();
+
+    assert_eq!(number("count(//l:book)"), 3.0);
+    assert_eq!(number("sum(//l:price)"), 42.5);
+    assert_eq!(number("sum(//l:price) div count(//l:price)"), 42.5 / 3.0);
+    assert_eq!(number("7 mod -3"), 1.0);
+    assert_eq!(number("-7 mod 3"), -1.0);
+    assert_eq!(number("floor(-1.5) + ceiling(1.2) + round(2.5)"), 3.0);
+    assert!(number("number('12px')").is_nan());
+    assert_eq!(string("1 div 0"), "Infinity");
+    assert_eq!(string("0 div 0"), "NaN");
+    assert_eq!(string("(//l:price)[2] * 2"), "51");
+    assert_eq!(string("concat('a', 1, true())"), "a1true");
+    assert_eq!(string("substring('12345', 1.5, 2.6)"), "234");
+    assert_eq!(string("substring('12345', 0, 3)"), "12");
+    assert_eq!(string("substring('12345', 0 div 0, 3)"), "");
+    assert_eq!(string("substring('12345', -42, 1 div 0)"), "12345");
+    assert_eq!(string("substring-before('1999/04/01', '/')"), "1999");
+    assert_eq!(string("substring-after('1999/04/01', '/')"), "04/01");
+    assert_eq!(string("normalize-space('  a \n b  ')"), "a b");
+    assert_eq!(string("translate('--aaa--', 'abc-', 'ABC')"), "AAA");
+    assert_eq!(number("string-length(//l:book[3]/l:title)"), 13.0);
+    assert!(boolean(
+        "starts-with(//l:title[1], 'Al') and contains('abc', 'b')"
+    ));
+    assert!(boolean("not(//l:missing) and //l:book"));
+    assert!(boolean("//l:price = 7"));
+    assert!(boolean("//l:price != 7"));
+    assert!(!boolean("//l:missing = ''"));
+    assert!(boolean("//l:book[1]/@id = //@id"));
+    assert!(boolean("//l:price > '20'"));
+    assert!(boolean("true() = //l:book"));
+    assert!(boolean("'1.0' = 1"));
+    assert!(!boolean("'1.0' = '1'"));
+
+    let mut context = library_context();
+    context
+        .variables
+        .insert("limit".to_owned(), Value::Number(9.0));
+    context.variables.insert(
+        "books".to_owned(),
+        eval(&dom, &context, "//l:book[l:price > 9]"),
+    );
+    assert_eq!(
+        select(&dom, &context, "$books[l:price > $limit * 2]/@id"),
+        ["b2"]
+    );
+    assert_eq!(select(&dom, &context, "$books/l:title"), ["Alpha", "Beta"]);
+}
+
+#[test]
+fn html() {
+    let dom = parse_html(HTML);
+    let context = Context::html();
+    let query = |expr| select(&dom, &context, expr);
+
+    assert_eq!(query("//a/@href"), ["http://example.com/1", "/two"]);
+    assert_eq!(query("//A"), ["One", "Two"]);
+    assert_eq!(
+        query("//DIV[@class = 'article']/a[starts-with(@href, 'http')]"),
+        ["One"]
+    );
+    assert_eq!(query("//p[lang('de')]"), ["Drei"]);
+    assert_eq!(query("/html/head/title"), ["Test"]);
+    // SVG elements are matched by their namespace, case-sensitively.
+    assert_eq!(query("//svg").len(), 0);
+    let mut svg = Context::html();
+    svg.bind(
+        Prefix::from("s"),
+        Namespace::from("http://www.w3.org/2000/svg"),
+    );
+    assert_eq!(select(&dom, &svg, "//s:foreignObject/*"), ["Drei"]);
+    assert_eq!(select(&dom, &svg, "//s:foreignobject").len(), 0);
+    // Outside of HTML mode, unprefixed names are in no namespace.
+    assert_eq!(select(&dom, &Context::default(), "//a").len(), 0);
+
+    // The doctype is not part of the tree.
+    assert_eq!(select(&dom, &Context::html(), "/node()").len(), 1);
+}
+
+#[test]
+fn errors() {
+    let position = |source| match XPath::parse(source) {
+        Err(Error::Syntax { position, .. }) => position,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(position("//a[@href"), 9);
+    assert_eq!(position("//a/bogus::b"), 4);
+
+    let dom = parse_xml("<a/>");
+    let context = Context::default();
+    let error = |expr| {
+        XPath::parse(expr)
+            .unwrap()
+            .evaluate(&dom, &context, &dom.document)
+            .unwrap_err()
+    };
+    assert_eq!(
+        error("frobnicate(1)"),
+        Error::Evaluation("Unknown function frobnicate()".into())
+    );
+    assert_eq!(
+        error("count(1)"),
+        Error::Evaluation("Expected an expression that selects nodes".into())
+    );
+    assert_eq!(
+        error("concat('a')"),
+        Error::Evaluation("Wrong number of arguments to concat()".into())
+    );
+    assert_eq!(
+        error("$missing"),
+        Error::Evaluation("Unknown variable $missing".into())
+    );
+    assert!(XPath::parse("1 + 1")
+        .unwrap()
+        .select(&dom, &context, &dom.document)
+        .is_err());
+}
+
+#[test]
+fn large_node_sets() {
+    // Sorting into document order once took time quadratic in the number of siblings.
+    let items = "<li>item</li>".repeat(20000);
+    let dom = parse_html(&format!("<ul>{}</ul><ol>{}</ol>", items, items));
+    let context = Context::html();
+    assert_eq!(select(&dom, &context, "//li/text()").len(), 40000);
+    assert_eq!(select(&dom, &context, "//li | //ul | //ol").len(), 40002);
+    assert_eq!(select(&dom, &context, "//li/..").len(), 2);
+}
@@ -0,0 +1,672 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Readable text from a parsed document, in the manner of [`innerText`].
+//!
+//! ```text
+//! let text = inner_text(&dom, &dom.document, TextOpts::default());
+//! ```
+//!
+//! There is no style sheet, so the rendering is decided by element names alone, following the
+//! defaults of the [rendering section] of the HTML standard:
+//!
+//! * Elements that aren't rendered, such as `<script>`, `<style>`, `<template>`, `<head>` and
+//!   anything with a `hidden` attribute, are skipped along with their contents.
+//! * Block-level elements such as `<div>` and `<li>` start on a new line, and `<p>` is separated
+//!   from its surroundings by a blank line. `<br>` is a line break.
+//! * Table cells are separated by tabs and rows by line breaks.
+//! * Runs of whitespace collapse to a single space and whitespace at the start and end of lines
+//!   is removed, except inside `<pre>`, `<listing>`, `<plaintext>` and `<xmp>`.
+//!
+//! `TextFormat::Markdown` also marks up headings, emphasis, code, links, images, lists, block
+//! quotes, rules and tables.
+//!
+//! [`innerText`]: https://html.spec.whatwg.org/multipage/#the-innertext-idl-attribute
+//! [rendering section]: https://html.spec.whatwg.org/multipage/#rendering
+
+use std::cmp::{max, min};
+use std::mem;
+
+use mac::{_tt_as_expr_hack, matches};
+use markup5ever::traverse::{NodeKind, TreeNavigator};
+use markup5ever::{Attribute, LocalName, QualName};
+
+/// How the text is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextFormat {
+    /// Plain text, like `innerText`.
+    Plain,
+    /// Lightweight Markdown.
+    Markdown,
+}
+
+/// Options for `inner_text`.
+#[derive(Clone, Copy, Debug)]
+pub struct TextOpts {
+    /// How the text is written. Default: `TextFormat::Plain`.
+    pub format: TextFormat,
+}
+
+impl Default for TextOpts {
+    fn default() -> TextOpts {
+        TextOpts {
+            format: TextFormat::Plain,
+        }
+    }
+}
+
+/// The rendered text of a document or element and its descendants, in any tree that implements
+/// `TreeNavigator`.
+///
+/// Nothing is returned for a node that isn't rendered. Line breaks at the start and end of the
+/// text are left out.
+pub fn inner_text<N: TreeNavigator>(navigator: &N, node: &N::Handle, opts: TextOpts) -> String {
+    enum Op<Handle> {
+        Open(Handle),
+        Close(QualName, Vec<Attribute>),
+    }
+
+    let mut writer = Writer::new(opts.format);
+    let mut ops = vec![Op::Open(node.clone())];
+    while let Some(op) = ops.pop() {
+        match op {
+            Op::Open(node) => {
+                match navigator.kind(&node) {
+                    NodeKind::Document => (),
+                    NodeKind::Text | NodeKind::CData => {
+                        writer.text(&navigator.text(&node).unwrap_or_default())
+                    },
+                    NodeKind::Element => {
+                        let name = navigator.name(&node).expect("element without a name");
+                        let attrs = navigator.attributes(&node);
+                        if !is_rendered(&name, &attrs) {
+                            continue;
+                        }
+                        writer.open(&name, &attrs);
+                        ops.push(Op::Close(name, attrs));
+                    },
+                    _ => continue,
+                }
+                let children = navigator.children(&node).collect::<Vec<_>>();
+                ops.extend(children.into_iter().rev().map(Op::Open));
+            },
+            Op::Close(name, attrs) => writer.close(&name, &attrs),
+        }
+    }
+    writer.out
+}
+
+fn is_rendered(name: &QualName, attrs: &[Attribute]) -> bool {
+    if name.ns != ns!(html) {
+        return true;
+    }
+    let hidden = attrs
+        .iter()
+        .any(|attr| attr.name.ns == ns!() && attr.name.local == local_name!("hidden"));
+    !hidden &&
+        !matches!(
+            name.local,
+            local_name!("area") |
+                local_name!("base") |
+                local_name!("basefont") |
+                local_name!("datalist") |
+                local_name!("head") |
+                local_name!("link") |
+                local_name!("meta") |
+                local_name!("noembed") |
+                local_name!("noframes") |
+                local_name!("noscript") |
+                local_name!("param") |
+                local_name!("rp") |
+                local_name!("script") |
+                local_name!("style") |
+                local_name!("template") |
+                local_name!("title")
+        )
+}
+
+fn is_block(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("address") |
+            local_name!("article") |
+            local_name!("aside") |
+            local_name!("blockquote") |
+            local_name!("body") |
+            local_name!("caption") |
+            local_name!("center") |
+            local_name!("dd") |
+            local_name!("details") |
+            local_name!("dialog") |
+            local_name!("dir") |
+            local_name!("div") |
+            local_name!("dl") |
+            local_name!("dt") |
+            local_name!("fieldset") |
+            local_name!("figcaption") |
+            local_name!("figure") |
+            local_name!("footer") |
+            local_name!("form") |
+            local_name!("h1") |
+            local_name!("h2") |
+            local_name!("h3") |
+            local_name!("h4") |
+            local_name!("h5") |
+            local_name!("h6") |
+            local_name!("header") |
+            local_name!("hgroup") |
+            local_name!("hr") |
+            local_name!("html") |
+            local_name!("legend") |
+            local_name!("li") |
+            local_name!("listing") |
+            local_name!("main") |
+            local_name!("menu") |
+            local_name!("nav") |
+            local_name!("ol") |
+            local_name!("plaintext") |
+            local_name!("pre") |
+            local_name!("section") |
+            local_name!("summary") |
+            local_name!("table") |
+            local_name!("ul") |
+            local_name!("xmp")
+    )
+}
+
+fn is_preformatted(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("pre") | local_name!("listing") | local_name!("plaintext") | local_name!("xmp")
+    )
+}
+
+fn heading_level(local: &LocalName) -> Option<usize> {
+    match *local {
+        local_name!("h1") => Some(1),
+        local_name!("h2") => Some(2),
+        local_name!("h3") => Some(3),
+        local_name!("h4") => Some(4),
+        local_name!("h5") => Some(5),
+        local_name!("h6") => Some(6),
+        _ => None,
+    }
+}
+
+fn attribute(attrs: &[Attribute], local: LocalName) -> Option<&str> {
+    attrs
+        .iter()
+        .find(|attr| attr.name.ns == ns!() && attr.name.local == local)
+        .map(|attr| &*attr.value)
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+struct Table {
+    /// The number of rows started so far.
+    rows: usize,
+    /// The number of cells started so far in the current row.
+    cells: usize,
+}
+
+struct Writer {
+    format: TextFormat,
+    out: String,
+
+    /// Whitespace has been collapsed since the last text that was written.
+    space: bool,
+
+    /// How many line breaks to write before the next text.
+    breaks: usize,
+
+    /// Nothing has been written on the current line yet.
+    line_start: bool,
+
+    /// Written at the start of each line, such as `> ` inside a Markdown block quote.
+    prefixes: Vec<String>,
+
+    /// How many of the prefixes are written on blank lines: those that were there both when the
+    /// line breaks were required and when they are written.
+    blank_prefixes: usize,
+
+    /// A list item marker, written instead of the innermost prefix at the start of the next
+    /// line that isn't blank.
+    marker: Option<String>,
+
+    /// Markdown for the start of inline elements that have no text yet.
+    pending: String,
+
+    /// Nesting depth of elements whose whitespace is kept.
+    pre: usize,
+
+    /// Nesting depth of Markdown code spans, whose text isn't escaped.
+    code: usize,
+
+    /// Where the opening backtick of the outermost code span is in `out`, once its text has
+    /// started.
+    code_start: Option<usize>,
+
+    /// The lists the current node is in, innermost last. Ordered lists hold the number of their
+    /// next item.
+    lists: Vec<Option<usize>>,
+
+    /// The tables the current node is in, innermost last.
+    tables: Vec<Table>,
+}
+
+impl Writer {
+    fn new(format: TextFormat) -> Writer {
+        Writer {
+            format,
+            out: String::new(),
+            space: false,
+            breaks: 0,
+            line_start: true,
+            prefixes: vec![],
+            blank_prefixes: 0,
+            marker: None,
+            pending: String::new(),
+            pre: 0,
+            code: 0,
+            code_start: None,
+            lists: vec![],
+            tables: vec![],
+        }
+    }
+
+    fn markdown(&self) -> bool {
+        self.format == TextFormat::Markdown
+    }
+
+    /// Inside a Markdown table cell, where line breaks would end the row.
+    fn in_markdown_cell(&self) -> bool {
+        self.markdown() && matches!(self.tables.last(), Some(table) if table.cells > 0)
+    }
+
+    /// The innermost table. One is made up for rows and cells parsed as a fragment.
+    fn table(&mut self) -> &mut Table {
+        if self.tables.is_empty() {
+            self.tables.push(Table { rows: 0, cells: 0 });
+        }
+        self.tables.last_mut().unwrap()
+    }
+
+    fn open(&mut self, name: &QualName, attrs: &[Attribute]) {
+        if name.ns != ns!(html) {
+            return;
+        }
+        let local = &name.local;
+        if is_block(local) {
+            self.require_breaks(1);
+        }
+        if is_preformatted(local) {
+            self.pre += 1;
+        }
+
+        match *local {
+            local_name!("p") => self.require_breaks(2),
+            local_name!("br") => self.line_feed(),
+            local_name!("tr") => {
+                let table = self.table();
+                table.rows += 1;
+                table.cells = 0;
+                if table.rows > 1 {
+                    self.line_feed();
+                }
+            },
+            local_name!("td") | local_name!("th") => {
+                let table = self.table();
+                table.cells += 1;
+                let first = table.cells == 1;
+                if self.markdown() {
+                    self.markup(if first { "| " } else { " | " });
+                } else if !first {
+                    self.start(false);
+                    self.out.push('\t');
+                }
+            },
+            local_name!("table") => {
+                if self.markdown() {
+                    self.require_breaks(2);
+                }
+                self.tables.push(Table { rows: 0, cells: 0 });
+            },
+            local_name!("ul") | local_name!("ol") => {
+                if self.markdown() && self.lists.is_empty() {
+                    self.require_breaks(2);
+                }
+                let number = if *local == local_name!("ol") {
+                    let start = attribute(attrs, local_name!("start"));
+                    Some(
+                        start
+                            .and_then(|start| start.trim().parse().ok())
+                            .unwrap_or(1),
+                    )
+                } else {
+                    None
+                };
+                self.lists.push(number);
+            },
+            _ if !self.markdown() => (),
+
+            local_name!("li") => {
+                let marker = match self.lists.last_mut() {
+                    Some(&mut Some(ref mut number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    },
+                    _ => "- ".to_owned(),
+                };
+                self.prefixes.push(" ".repeat(marker.len()));
+                self.marker = Some(marker);
+            },
+            local_name!("blockquote") => {
+                self.require_breaks(2);
+                self.prefixes.push("> ".to_owned());
+            },
+            local_name!("pre") => {
+                self.require_breaks(2);
+                self.markup("```");
+                self.require_breaks(1);
+            },
+            local_name!("hr") => {
+                self.require_breaks(2);
+                self.markup("---");
+                self.require_breaks(2);
+            },
+            local_name!("img") => {
+                let alt = attribute(attrs, local_name!("alt")).unwrap_or("");
+                let src = attribute(attrs, local_name!("src")).unwrap_or("");
+                self.start(true);
+                self.out.push_str("![");
+                self.push_escaped(alt.trim());
+                self.out.push_str("](");
+                self.out.push_str(src.trim());
+                self.out.push(')');
+            },
+            _ => {
+                if let Some(level) = heading_level(local) {
+                    self.require_breaks(2);
+                    self.markup(&"#".repeat(level));
+                    self.out.push(' ');
+                } else if let Some((start, _)) = self.inline_markup(local, attrs) {
+                    if start == "`" {
+                        self.code += 1;
+                    }
+                    // Code inside code is just text.
+                    if start != "`" || self.code == 1 {
+                        self.pending.push_str(&start);
+                    }
+                }
+            },
+        }
+    }
+
+    fn close(&mut self, name: &QualName, attrs: &[Attribute]) {
+        if name.ns != ns!(html) {
+            return;
+        }
+        let local = &name.local;
+        if is_preformatted(local) {
+            self.pre -= 1;
+        }
+
+        match *local {
+            local_name!("p") => self.require_breaks(2),
+            local_name!("tr") if self.markdown() => {
+                let (rows, cells) = match self.tables.last_mut() {
+                    Some(table) => (table.rows, mem::replace(&mut table.cells, 0)),
+                    None => (0, 0),
+                };
+                if cells > 0 {
+                    self.markup(" |");
+                }
+                if rows == 1 {
+                    self.line_feed();
+                    self.markup("|");
+                    self.out.push_str(&" --- |".repeat(cells));
+                }
+            },
+            local_name!("table") => {
+                self.tables.pop();
+                if self.markdown() {
+                    self.require_breaks(2);
+                }
+            },
+            local_name!("ul") | local_name!("ol") => {
+                self.lists.pop();
+                if self.markdown() && self.lists.is_empty() {
+                    self.require_breaks(2);
+                }
+            },
+            _ if !self.markdown() => (),
+
+            local_name!("li") => {
+                self.pop_prefix();
+                self.marker = None;
+            },
+            local_name!("blockquote") => {
+                self.pop_prefix();
+                self.require_breaks(2);
+            },
+            local_name!("pre") => {
+                self.require_breaks(1);
+                self.markup("```");
+                self.require_breaks(2);
+            },
+            _ => {
+                if heading_level(local).is_some() {
+                    self.require_breaks(2);
+                } else if let Some((start, end)) = self.inline_markup(local, attrs) {
+                    if start == "`" {
+                        self.code -= 1;
+                        if self.code == 0 {
+                            self.close_code();
+                        }
+                    } else if self.pending.ends_with(&start) {
+                        // Leave out the Markdown for elements without text.
+                        let len = self.pending.len() - start.len();
+                        self.pending.truncate(len);
+                    } else {
+                        self.out.push_str(&end);
+                    }
+                }
+            },
+        }
+
+        if is_block(local) {
+            self.require_breaks(1);
+        }
+    }
+
+    /// The Markdown around the text of an inline element.
+    fn inline_markup(&self, local: &LocalName, attrs: &[Attribute]) -> Option<(String, String)> {
+        let (start, end) = match *local {
+            local_name!("b") | local_name!("strong") => ("**", "**"),
+            local_name!("i") | local_name!("em") => ("*", "*"),
+            local_name!("code") | local_name!("kbd") | local_name!("samp") if self.pre == 0 => {
+                ("`", "`")
+            },
+            local_name!("a") => {
+                let href = attribute(attrs, local_name!("href"))?;
+                return Some(("[".to_owned(), format!("]({})", href.trim())));
+            },
+            _ => return None,
+        };
+        Some((start.to_owned(), end.to_owned()))
+    }
+
+    /// Finish the outermost code span, fenced with more backticks than any run of them in its
+    /// text.
+    fn close_code(&mut self) {
+        let start = match self.code_start.take() {
+            Some(start) => start,
+            None => {
+                // Leave out the Markdown for code without text.
+                let len = self.pending.len() - 1;
+                self.pending.truncate(len);
+                return;
+            },
+        };
+        let text = self.out.split_off(start + 1);
+        self.out.truncate(start);
+        let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(longest + 1);
+        let padding = if text.starts_with('`') || text.ends_with('`') {
+            " "
+        } else {
+            ""
+        };
+        self.out.push_str(&fence);
+        self.out.push_str(padding);
+        self.out.push_str(&text);
+        self.out.push_str(padding);
+        self.out.push_str(&fence);
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.pre > 0 {
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    self.line_feed();
+                }
+                if !line.is_empty() {
+                    self.start(true);
+                    self.out.push_str(line);
+                }
+            }
+            return;
+        }
+
+        let mut start = 0;
+        for (i, c) in text.char_indices() {
+            if is_whitespace(c) {
+                if start < i {
+                    self.start(true);
+                    self.push_escaped(&text[start..i]);
+                }
+                start = i + 1;
+                self.space = true;
+            }
+        }
+        if start < text.len() {
+            self.start(true);
+            self.push_escaped(&text[start..]);
+        }
+    }
+
+    fn push_escaped(&mut self, text: &str) {
+        if !self.markdown() || self.code > 0 {
+            self.out.push_str(text);
+            return;
+        }
+        let in_cell = self.in_markdown_cell();
+        for c in text.chars() {
+            if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') || (in_cell && c == '|') {
+                self.out.push('\\');
+            }
+            self.out.push(c);
+        }
+    }
+
+    /// Write Markdown syntax that isn't separated from the text before it by whitespace.
+    fn markup(&mut self, markup: &str) {
+        self.start(false);
+        self.out.push_str(markup);
+    }
+
+    /// Get ready to write on the current line, writing any line breaks, prefixes and collapsed
+    /// whitespace that come first.
+    fn start(&mut self, text: bool) {
+        for _ in 0..self.breaks {
+            self.push_line_feed();
+        }
+        self.breaks = 0;
+        if self.line_start {
+            self.push_prefixes(false);
+            self.line_start = false;
+            self.space = false;
+        }
+        if text {
+            if self.space && !self.out.ends_with(is_whitespace) {
+                self.out.push(' ');
+            }
+            let pending = mem::take(&mut self.pending);
+            if self.code > 0 && self.code_start.is_none() {
+                self.code_start = pending.find('`').map(|i| self.out.len() + i);
+            }
+            self.out.push_str(&pending);
+        }
+        self.space = false;
+    }
+
+    /// Start a new line. Unlike `require_breaks`, this is never merged with other line breaks.
+    fn line_feed(&mut self) {
+        if self.in_markdown_cell() {
+            self.space = true;
+            return;
+        }
+        for _ in 0..self.breaks {
+            self.push_line_feed();
+        }
+        self.breaks = 0;
+        self.blank_prefixes = self.prefixes.len();
+        self.push_line_feed();
+        self.space = false;
+    }
+
+    /// Make sure the next text is at least this many line breaks after the text before it.
+    fn require_breaks(&mut self, breaks: usize) {
+        if self.in_markdown_cell() {
+            self.space = true;
+        } else if !self.out.is_empty() {
+            if self.breaks == 0 {
+                self.blank_prefixes = self.prefixes.len();
+            }
+            self.breaks = max(self.breaks, breaks);
+            self.space = false;
+        }
+    }
+
+    fn push_line_feed(&mut self) {
+        if self.line_start {
+            self.push_prefixes(true);
+        }
+        self.out.push('\n');
+        self.line_start = true;
+    }
+
+    fn pop_prefix(&mut self) {
+        self.prefixes.pop();
+        self.blank_prefixes = min(self.blank_prefixes, self.prefixes.len());
+    }
+
+    fn push_prefixes(&mut self, blank_line: bool) {
+        let prefixes = if blank_line {
+            &self.prefixes[..self.blank_prefixes]
+        } else {
+            &self.prefixes[..]
+        };
+        let innermost = self.prefixes.len().wrapping_sub(1);
+        for (i, prefix) in prefixes.iter().enumerate() {
+            match self.marker {
+                Some(ref marker) if i == innermost && !blank_line => self.out.push_str(marker),
+                _ => self.out.push_str(prefix),
+            }
+        }
+        if blank_line {
+            let len = self.out.trim_end_matches(' ').len();
+            self.out.truncate(len);
+        } else {
+            self.marker = None;
+        }
+    }
+}
@@ -17,9 +17,9 @@ use std::io;
 
 use markup5ever::buffer_queue::BufferQueue;
 use xml5ever::tendril::{ByteTendril, ReadExt};
-use xml5ever::tokenizer::{CharacterTokens, NullCharacterToken, TagToken};
+use xml5ever::tokenizer::{CDataToken, CharacterTokens, NullCharacterToken, TagToken};
 use xml5ever::tokenizer::{CommentToken, PIToken, Pi};
-use xml5ever::tokenizer::{Doctype, DoctypeToken, EOFToken};
+use xml5ever::tokenizer::{Doctype, DoctypeToken, EOFToken, EntityRefToken};
 use xml5ever::tokenizer::{ParseError, Token, TokenSink, XmlTokenizer};
 
 struct SimpleTokenPrinter;
@@ -30,6 +30,9 @@ impl TokenSink for SimpleTokenPrinter {
             CharacterTokens(b) => {
                 println!("TEXT: {}", &*b);
             },
+            CDataToken(b) => {
+                println!("CDATA: {}", &*b);
+            },
             NullCharacterToken => print!("NULL"),
             TagToken(tag) => {
                 println!("{:?} {} ", tag.kind, &*tag.name.local);
@@ -49,6 +52,9 @@ impl TokenSink for SimpleTokenPrinter {
             EOFToken => {
                 println!("EOF");
             },
+            EntityRefToken(ref name) => {
+                println!("ENTITY: &{};", &*name);
+            },
             DoctypeToken(Doctype {
                 ref name,
                 ref public_id,
@@ -7,15 +7,21 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::interface::{Attribute, QualName};
+use crate::{Namespace, Prefix};
 use crate::tokenizer::{XmlTokenizer, XmlTokenizerOpts};
+use crate::tree_builder::xml_decl::parse_xml_decl;
 use crate::tree_builder::{TreeSink, XmlTreeBuilder, XmlTreeBuilderOpts};
 
-use std::borrow::Cow;
+use std::borrow::Cow::{self, Borrowed, Owned};
+use std::collections::BTreeMap;
+use std::mem;
 
 use crate::tendril;
 use crate::tendril::stream::{TendrilSink, Utf8LossyDecoder};
-use crate::tendril::StrTendril;
+use crate::tendril::{ByteTendril, StrTendril};
 use markup5ever::buffer_queue::BufferQueue;
+use markup5ever::encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8};
 
 /// All-encompasing parser setting structure.
 #[derive(Clone, Default)]
@@ -24,6 +30,9 @@ pub struct XmlParseOpts {
     pub tokenizer: XmlTokenizerOpts,
     /// Xml tree builder .
     pub tree_builder: XmlTreeBuilderOpts,
+    /// Namespaces in scope around the document, as for
+    /// `XmlTreeBuilder::declare_context_namespaces`.
+    pub namespaces: BTreeMap<Option<Prefix>, Namespace>,
 }
 
 /// Parse and send results to a `TreeSink`.
@@ -38,7 +47,45 @@ pub fn parse_document<Sink>(sink: Sink, opts: XmlParseOpts) -> XmlParser<Sink>
 where
     Sink: TreeSink,
 {
-    let tb = XmlTreeBuilder::new(sink, opts.tree_builder);
+    let mut tb = XmlTreeBuilder::new(sink, opts.tree_builder);
+    tb.declare_context_namespaces(&opts.namespaces);
+    let tok = XmlTokenizer::new(tb, opts.tokenizer);
+    XmlParser {
+        tokenizer: tok,
+        input_buffer: BufferQueue::new(),
+    }
+}
+
+/// Parse a fragment of XML, such as the content of an element taken from a
+/// larger document, and send the results to a `TreeSink`.
+///
+/// The fragment may have any number of top-level nodes. They are appended
+/// to a root element that is created from `context_name` and
+/// `context_attrs` and appended to the document, as `html5ever` does for
+/// HTML fragments. The namespaces declared by the context's attributes and
+/// used by its name and attributes are in scope for the fragment, as are
+/// those of `opts.namespaces`.
+///
+/// ## Example
+///
+/// ```ignore
+/// let name = QualName::new(None, ns!(), local_name!("body"));
+/// let dom = parse_fragment(RcDom::default(), Default::default(), name, vec![])
+///     .from_utf8()
+///     .one(input);
+/// ```
+pub fn parse_fragment<Sink>(
+    sink: Sink,
+    opts: XmlParseOpts,
+    context_name: QualName,
+    context_attrs: Vec<Attribute>,
+) -> XmlParser<Sink>
+where
+    Sink: TreeSink,
+{
+    let mut tb =
+        XmlTreeBuilder::new_for_fragment(sink, context_name, context_attrs, opts.tree_builder);
+    tb.declare_context_namespaces(&opts.namespaces);
     let tok = XmlTokenizer::new(tb, opts.tokenizer);
     XmlParser {
         tokenizer: tok,
@@ -86,4 +133,191 @@ impl<Sink: TreeSink> XmlParser<Sink> {
     pub fn from_utf8(self) -> Utf8LossyDecoder<Self> {
         Utf8LossyDecoder::new(self)
     }
+
+    /// Wrap this parser into a `TendrilSink` that accepts bytes in any encoding.
+    ///
+    /// The encoding is detected as described in Appendix F of the XML specification, from a
+    /// byte order mark, the first characters of a UTF-16 document, or the `encoding` of the
+    /// XML declaration. It defaults to UTF-8. Decoding is lossy.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_bytes(self, opts: BytesOpts) -> BytesParser<Sink> {
+        BytesParser {
+            state: match opts.transport_layer_encoding {
+                Some(encoding) => BytesParserState::Decoding(encoding.new_decoder()),
+                None => BytesParserState::Detecting(vec![]),
+            },
+            parser: self,
+        }
+    }
+}
+
+/// Options for choosing a character encoding
+#[derive(Clone, Default)]
+pub struct BytesOpts {
+    /// The character encoding specified by the transport layer, if any.
+    /// If present, it is used instead of the encoding detected from the document, unless the
+    /// document starts with a byte order mark.
+    pub transport_layer_encoding: Option<&'static Encoding>,
+}
+
+/// An XML parser,
+/// ready to receive bytes input through the `tendril::TendrilSink` trait’s methods.
+///
+/// See `XmlParser::from_bytes`.
+pub struct BytesParser<Sink>
+where
+    Sink: TreeSink,
+{
+    state: BytesParserState,
+    parser: XmlParser<Sink>,
+}
+
+enum BytesParserState {
+    /// The first bytes, held back until they determine the encoding.
+    Detecting(Vec<u8>),
+    Decoding(Decoder),
+}
+
+/// The declaration is only looked for this far into the document.
+const DETECTION_LIMIT: usize = 1024;
+
+impl<Sink: TreeSink> BytesParser<Sink> {
+    /// Access the underlying Parser
+    pub fn str_parser(&self) -> &XmlParser<Sink> {
+        &self.parser
+    }
+
+    /// Access the underlying Parser
+    pub fn str_parser_mut(&mut self) -> &mut XmlParser<Sink> {
+        &mut self.parser
+    }
+
+    /// Insert a Unicode chunk in the middle of the byte stream.
+    ///
+    /// This is e.g. for supporting `document.write`.
+    pub fn process_unicode(&mut self, t: StrTendril) {
+        self.parser.process(t)
+    }
+
+    fn start_decoding(&mut self, bytes: &[u8], last: bool) {
+        let encoding = detect_encoding(bytes, |msg| self.parser.error(msg));
+        let mut decoder = encoding.new_decoder();
+        decode(&mut self.parser, &mut decoder, bytes, last);
+        self.state = BytesParserState::Decoding(decoder);
+    }
+}
+
+fn decode<Sink: TreeSink>(
+    parser: &mut XmlParser<Sink>,
+    decoder: &mut Decoder,
+    bytes: &[u8],
+    last: bool,
+) {
+    let capacity = decoder
+        .max_utf8_buffer_length(bytes.len())
+        .expect("input too long");
+    let mut decoded = String::with_capacity(capacity);
+    let (_, _, had_errors) = decoder.decode_to_string(bytes, &mut decoded, last);
+    if had_errors {
+        let msg = format!("Invalid byte sequence for {}", decoder.encoding().name());
+        parser.error(Owned(msg));
+    }
+    if !decoded.is_empty() {
+        parser.process(StrTendril::from(decoded));
+    }
+}
+
+impl<Sink: TreeSink> TendrilSink<tendril::fmt::Bytes> for BytesParser<Sink> {
+    type Output = Sink::Output;
+
+    fn process(&mut self, t: ByteTendril) {
+        let buffer = match self.state {
+            BytesParserState::Decoding(ref mut decoder) => {
+                return decode(&mut self.parser, decoder, &t, false);
+            },
+            BytesParserState::Detecting(ref mut buffer) => {
+                buffer.extend_from_slice(&t);
+                if !enough_to_detect(buffer) {
+                    return;
+                }
+                mem::take(buffer)
+            },
+        };
+        self.start_decoding(&buffer, false);
+    }
+
+    fn error(&mut self, desc: Cow<'static, str>) {
+        self.parser.error(desc)
+    }
+
+    fn finish(mut self) -> Self::Output {
+        match self.state {
+            BytesParserState::Detecting(ref mut buffer) => {
+                let buffer = mem::take(buffer);
+                self.start_decoding(&buffer, true);
+            },
+            BytesParserState::Decoding(ref mut decoder) => {
+                decode(&mut self.parser, decoder, &[], true);
+            },
+        }
+        self.parser.finish()
+    }
+}
+
+// Have we seen enough bytes to settle on an encoding?
+fn enough_to_detect(bytes: &[u8]) -> bool {
+    if bytes.len() < 4 {
+        return false;
+    }
+    // An ASCII-compatible document that starts with a declaration needs all of it.
+    !bytes.starts_with(b"<?xml") ||
+        bytes.len() >= DETECTION_LIMIT ||
+        bytes.windows(2).any(|window| window == b"?>")
+}
+
+/// Detect the encoding of a document from its first bytes, as in Appendix F of the XML
+/// specification.
+fn detect_encoding<E>(bytes: &[u8], mut error: E) -> &'static Encoding
+where
+    E: FnMut(Cow<'static, str>),
+{
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    match bytes {
+        [0x3C, 0x00, 0x3F, 0x00, ..] => return UTF_16LE,
+        [0x00, 0x3C, 0x00, 0x3F, ..] => return UTF_16BE,
+        _ => (),
+    }
+    if !bytes.starts_with(b"<?xml") {
+        return UTF_8;
+    }
+    let bytes = &bytes[..bytes.len().min(DETECTION_LIMIT)];
+    let end = match bytes.windows(2).position(|window| window == b"?>") {
+        Some(end) => end,
+        None => return UTF_8,
+    };
+    let data = match std::str::from_utf8(&bytes[b"<?xml".len()..end]) {
+        Ok(data) => data,
+        Err(_) => return UTF_8,
+    };
+    // Errors in the declaration are reported when it is parsed again as markup.
+    let label = match parse_xml_decl(data) {
+        Ok(declaration) => match declaration.encoding {
+            Some(label) => label,
+            None => return UTF_8,
+        },
+        Err(_) => return UTF_8,
+    };
+    match Encoding::for_label(label.as_bytes()) {
+        Some(encoding) if encoding.is_ascii_compatible() => encoding,
+        Some(_) => {
+            error(Borrowed("Declared encoding contradicts the document's bytes"));
+            UTF_8
+        },
+        None => {
+            error(Owned(format!("Unsupported encoding {}", label)));
+            UTF_8
+        },
+    }
 }
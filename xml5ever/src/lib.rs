@@ -45,9 +45,12 @@ macro_rules! time {
 
 /// Driver
 pub mod driver;
+/// Pull parser, which reports a document as a sequence of events.
+pub mod reader;
 /// Serializer for XML5.
 pub mod serialize;
 /// XML5 tokenizer - converts input into tokens
 pub mod tokenizer;
 /// XML5 tree builder - converts tokens into a tree like structure
 pub mod tree_builder;
+mod uri;
@@ -0,0 +1,458 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::mem;
+use std::rc::Rc;
+
+use markup5ever::{local_name, namespace_url, ns};
+
+use crate::driver::{self, BytesOpts, BytesParser, XmlParseOpts};
+use crate::interface::{Attribute, ElementFlags, ExpandedName, NodeOrText, QualName, QuirksMode};
+use crate::tendril::{StrTendril, TendrilSink};
+use crate::tree_builder::TreeSink;
+use crate::{Namespace, Prefix};
+
+/// Size of the chunks read from the source.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// An event of an `XmlReader`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum XmlEvent {
+    /// The start of an element. An empty element is followed by its
+    /// `EndElement` right away.
+    StartElement {
+        /// Name of the element, bound to its namespace.
+        name: QualName,
+        /// Attributes of the element, bound to their namespaces. Namespace
+        /// declarations are not included.
+        attrs: Vec<Attribute>,
+        /// Namespaces declared by the element's start tag, as passed to
+        /// `TreeSink::declare_namespaces`.
+        namespaces: Vec<(Option<Prefix>, Option<Namespace>)>,
+    },
+    /// The end of an element.
+    EndElement {
+        /// Name of the element, bound to its namespace.
+        name: QualName,
+    },
+    /// Character data, with references expanded. Adjacent text is always
+    /// reported as a single event.
+    Text(StrTendril),
+    /// A CDATA section, if `XmlTokenizerOpts::cdata_tokens` is set. Otherwise
+    /// its contents are reported as text.
+    CData(StrTendril),
+    /// A comment.
+    Comment(StrTendril),
+    /// A processing instruction.
+    ProcessingInstruction {
+        /// Target of the processing instruction.
+        target: StrTendril,
+        /// Data of the processing instruction.
+        data: StrTendril,
+    },
+    /// A parse error. Parsing goes on unless the parser is strict.
+    ParseError(Cow<'static, str>),
+}
+
+/// A pull parser, which iterates over the events of a document read from a
+/// byte source.
+///
+/// Names are resolved and the document is corrected by the same tree
+/// builder as `driver::parse_document`, but no tree is kept, so memory use
+/// only depends on the depth of the document and the size of its text
+/// nodes.
+///
+/// ## Example
+///
+/// ```ignore
+/// for event in XmlReader::new(File::open("export.xml")?, Default::default()) {
+///     if let XmlEvent::StartElement { name, .. } = event? { ... }
+/// }
+/// ```
+pub struct XmlReader<R: Read> {
+    source: R,
+    parser: Option<BytesParser<EventSink>>,
+    events: VecDeque<XmlEvent>,
+}
+
+impl<R: Read> XmlReader<R> {
+    /// Create a reader for a document in any encoding, which is detected as
+    /// for `XmlParser::from_bytes`.
+    pub fn new(source: R, opts: XmlParseOpts) -> XmlReader<R> {
+        XmlReader::with_bytes_opts(source, opts, Default::default())
+    }
+
+    /// Create a reader for a document, choosing its encoding with `bytes_opts`.
+    pub fn with_bytes_opts(source: R, opts: XmlParseOpts, bytes_opts: BytesOpts) -> XmlReader<R> {
+        let sink = EventSink {
+            document: Rc::new(QualName::new(None, ns!(), local_name!(""))),
+            events: VecDeque::new(),
+            namespaces: vec![],
+        };
+        XmlReader {
+            source,
+            parser: Some(driver::parse_document(sink, opts).from_bytes(bytes_opts)),
+            events: VecDeque::new(),
+        }
+    }
+
+    // Parses the next chunk of the source. Returns false at the end of the document.
+    fn read_chunk(&mut self) -> io::Result<bool> {
+        let mut parser = match self.parser.take() {
+            Some(parser) => parser,
+            None => return Ok(false),
+        };
+        let mut buffer = [0; CHUNK_SIZE];
+        let len = loop {
+            match self.source.read(&mut buffer) {
+                Ok(len) => break len,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => {
+                    self.parser = Some(parser);
+                    return Err(e);
+                },
+            }
+        };
+        if len == 0 {
+            let events = parser.finish();
+            self.queue(events);
+            return Ok(false);
+        }
+        parser.process(buffer[..len].into());
+        let events = mem::take(&mut parser.str_parser_mut().tokenizer.sink.sink.events);
+        self.queue(events);
+        self.parser = Some(parser);
+        Ok(true)
+    }
+
+    // Queues the events of a chunk, joining text split across chunks.
+    fn queue(&mut self, mut events: VecDeque<XmlEvent>) {
+        if let (Some(XmlEvent::Text(text)), Some(XmlEvent::Text(next))) =
+            (self.events.back_mut(), events.front())
+        {
+            text.push_tendril(next);
+            events.pop_front();
+        }
+        self.events.extend(events);
+    }
+}
+
+impl<R: Read> Iterator for XmlReader<R> {
+    type Item = io::Result<XmlEvent>;
+
+    fn next(&mut self) -> Option<io::Result<XmlEvent>> {
+        loop {
+            // Text may go on in the next chunk.
+            let complete = match self.events.back() {
+                Some(XmlEvent::Text(_)) => self.events.len() > 1,
+                Some(_) => true,
+                None => false,
+            };
+            if complete || self.parser.is_none() {
+                return self.events.pop_front().map(Ok);
+            }
+            if let Err(e) = self.read_chunk() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+// Turns the tree builder's modifications into events. Handles are the names
+// of elements; other nodes share the document's.
+struct EventSink {
+    document: Rc<QualName>,
+    events: VecDeque<XmlEvent>,
+    namespaces: Vec<(Option<Prefix>, Option<Namespace>)>,
+}
+
+impl EventSink {
+    fn push(&mut self, event: XmlEvent) {
+        if let XmlEvent::Text(ref next) = event {
+            if let Some(XmlEvent::Text(ref mut text)) = self.events.back_mut() {
+                return text.push_tendril(next);
+            }
+        }
+        self.events.push_back(event);
+    }
+}
+
+impl TreeSink for EventSink {
+    type Handle = Rc<QualName>;
+    type Output = VecDeque<XmlEvent>;
+
+    fn finish(self) -> VecDeque<XmlEvent> {
+        self.events
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.push(XmlEvent::ParseError(msg));
+    }
+
+    fn get_document(&mut self) -> Rc<QualName> {
+        self.document.clone()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Rc<QualName>) -> ExpandedName<'a> {
+        target.expanded()
+    }
+
+    fn declare_namespaces(&mut self, declarations: Vec<(Option<Prefix>, Option<Namespace>)>) {
+        self.namespaces = declarations;
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        _flags: ElementFlags,
+    ) -> Rc<QualName> {
+        let handle = Rc::new(name.clone());
+        let namespaces = mem::take(&mut self.namespaces);
+        self.push(XmlEvent::StartElement {
+            name,
+            attrs,
+            namespaces,
+        });
+        handle
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Rc<QualName> {
+        self.push(XmlEvent::Comment(text));
+        self.document.clone()
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Rc<QualName> {
+        self.push(XmlEvent::ProcessingInstruction { target, data });
+        self.document.clone()
+    }
+
+    fn append(&mut self, _parent: &Rc<QualName>, child: NodeOrText<Rc<QualName>>) {
+        if let NodeOrText::AppendText(text) = child {
+            self.push(XmlEvent::Text(text));
+        }
+    }
+
+    fn append_cdata(&mut self, _parent: &Rc<QualName>, text: StrTendril) {
+        self.push(XmlEvent::CData(text));
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Rc<QualName>,
+        _prev_element: &Rc<QualName>,
+        child: NodeOrText<Rc<QualName>>,
+    ) {
+        self.append(element, child)
+    }
+
+    fn append_doctype_to_document(&mut self, _: StrTendril, _: StrTendril, _: StrTendril) {}
+
+    fn pop(&mut self, node: &Rc<QualName>) {
+        self.push(XmlEvent::EndElement {
+            name: (**node).clone(),
+        });
+    }
+
+    fn get_template_contents(&mut self, target: &Rc<QualName>) -> Rc<QualName> {
+        target.clone()
+    }
+
+    fn same_node(&self, x: &Rc<QualName>, y: &Rc<QualName>) -> bool {
+        Rc::ptr_eq(x, y)
+    }
+
+    fn set_quirks_mode(&mut self, _mode: QuirksMode) {}
+
+    fn append_before_sibling(&mut self, sibling: &Rc<QualName>, child: NodeOrText<Rc<QualName>>) {
+        self.append(sibling, child)
+    }
+
+    fn add_attrs_if_missing(&mut self, _target: &Rc<QualName>, _attrs: Vec<Attribute>) {}
+
+    fn remove_from_parent(&mut self, _target: &Rc<QualName>) {}
+
+    fn reparent_children(&mut self, _node: &Rc<QualName>, _new_parent: &Rc<QualName>) {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Read};
+
+    use mac::{_tt_as_expr_hack, matches};
+    use markup5ever::{local_name, namespace_url, ns};
+
+    use super::{XmlEvent, XmlReader};
+    use crate::driver::XmlParseOpts;
+    use crate::interface::{Attribute, QualName};
+    use crate::{LocalName, Namespace, Prefix};
+    use XmlEvent::*;
+
+    // Reads one byte at a time.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn events<R: Read>(source: R, opts: XmlParseOpts) -> Vec<XmlEvent> {
+        XmlReader::new(source, opts).map(Result::unwrap).collect()
+    }
+
+    fn local(name: &str) -> QualName {
+        QualName::new(None, ns!(), LocalName::from(name))
+    }
+
+    #[test]
+    fn reader() {
+        let input = "<?xml version='1.0'?><!DOCTYPE r [<!ENTITY e 'entity'>]>\
+                     <r xmlns='urn:r' xmlns:x='urn:x' a='1' x:b='2'>\
+                     text &e; &amp; more<x:c/><!--comment--><?pi data?></r>";
+        let name = QualName::new(None, Namespace::from("urn:r"), local_name!("r"));
+        let c = QualName::new(
+            Some(Prefix::from("x")),
+            Namespace::from("urn:x"),
+            LocalName::from("c"),
+        );
+        let expected = vec![
+            ProcessingInstruction {
+                target: "xml".into(),
+                data: "version='1.0'".into(),
+            },
+            StartElement {
+                name: name.clone(),
+                attrs: vec![
+                    Attribute {
+                        name: local("a"),
+                        value: "1".into(),
+                    },
+                    Attribute {
+                        name: QualName::new(
+                            Some(Prefix::from("x")),
+                            Namespace::from("urn:x"),
+                            LocalName::from("b"),
+                        ),
+                        value: "2".into(),
+                    },
+                ],
+                namespaces: vec![
+                    (None, Some(Namespace::from("urn:r"))),
+                    (Some(Prefix::from("x")), Some(Namespace::from("urn:x"))),
+                ],
+            },
+            Text("text entity & more".into()),
+            StartElement {
+                name: c.clone(),
+                attrs: vec![],
+                namespaces: vec![],
+            },
+            EndElement { name: c },
+            Comment("comment".into()),
+            ProcessingInstruction {
+                target: "pi".into(),
+                data: "data".into(),
+            },
+            EndElement { name },
+        ];
+        assert_eq!(events(input.as_bytes(), Default::default()), expected);
+        assert_eq!(
+            events(Trickle(input.as_bytes()), Default::default()),
+            expected
+        );
+    }
+
+    #[test]
+    fn reader_cdata() {
+        let input = &b"<a>x<![CDATA[<y>]]>z<![CDATA[]]></a>"[..];
+        let mut opts = XmlParseOpts::default();
+        opts.tokenizer.cdata_tokens = true;
+        assert_eq!(
+            events(input, opts)[1..5],
+            [
+                Text("x".into()),
+                CData("<y>".into()),
+                Text("z".into()),
+                CData("".into()),
+            ]
+        );
+        assert_eq!(events(input, Default::default())[1], Text("x<y>z".into()));
+    }
+
+    #[test]
+    fn reader_errors() {
+        assert_eq!(
+            events(&b"<a><b></a>"[..], Default::default()),
+            vec![
+                StartElement {
+                    name: local("a"),
+                    attrs: vec![],
+                    namespaces: vec![],
+                },
+                StartElement {
+                    name: local("b"),
+                    attrs: vec![],
+                    namespaces: vec![],
+                },
+                ParseError("Current node doesn't match tag".into()),
+                EndElement { name: local("b") },
+                EndElement { name: local("a") },
+            ]
+        );
+
+        let mut opts = XmlParseOpts::default();
+        opts.tokenizer.strict = true;
+        opts.tree_builder.strict = true;
+        let events = events(&b"<a>x<b></a>y"[..], opts);
+        assert_eq!(events.len(), 6, "{:?}", events);
+        assert!(matches!(events[3], ParseError(_)), "{:?}", events);
+    }
+
+    // A long document, generated as it is read.
+    struct Generated {
+        items: usize,
+        pending: Vec<u8>,
+    }
+
+    impl Read for Generated {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.pending.is_empty() && self.items > 0 {
+                self.items -= 1;
+                let end = if self.items == 0 { "</list>" } else { "" };
+                self.pending = format!("<item id='{}'>text</item>{}", self.items, end).into();
+            }
+            let len = buf.len().min(self.pending.len());
+            buf[..len].copy_from_slice(&self.pending[..len]);
+            self.pending.drain(..len);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn reader_streams() {
+        let source = Generated {
+            items: 20_000,
+            pending: b"<list>".to_vec(),
+        };
+        let mut reader = XmlReader::new(source, Default::default());
+        let mut elements = 0;
+        while let Some(event) = reader.next() {
+            if let StartElement { .. } = event.unwrap() {
+                elements += 1;
+            }
+            assert!(reader.events.len() < 1000);
+        }
+        assert_eq!(elements, 20_001);
+    }
+}
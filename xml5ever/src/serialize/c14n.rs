@@ -0,0 +1,424 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Canonical XML, as defined by [Canonical XML 1.0], [Canonical XML 1.1] and [Exclusive XML
+//! Canonicalization].
+//!
+//! The tree builder doesn't keep namespace declarations as attributes, so the namespace nodes of
+//! an element are those implied by the names of it and its attributes, plus any `xmlns`
+//! attributes a `Serialize` implementation passes to `start_elem`. Document type declarations
+//! are not processed, so default attributes and non-CDATA attribute normalization are not
+//! applied.
+//!
+//! [Canonical XML 1.0]: https://www.w3.org/TR/xml-c14n
+//! [Canonical XML 1.1]: https://www.w3.org/TR/xml-c14n11/
+//! [Exclusive XML Canonicalization]: https://www.w3.org/TR/xml-exc-c14n/
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use super::{AttrRef, Serialize, Serializer, TraversalScope};
+use crate::uri::join_uri;
+use crate::{LocalName, Namespace, Prefix, QualName};
+use markup5ever::{local_name, namespace_prefix, namespace_url, ns};
+
+/// The canonicalization algorithm a `C14nSerializer` implements.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum C14nMethod {
+    /// Canonical XML 1.0: a document subset inherits every `xml:` attribute of its ancestors.
+    Canonical10,
+    /// Canonical XML 1.1: a document subset inherits `xml:lang` and `xml:space`, and its
+    /// `xml:base` is joined with those of its ancestors.
+    Canonical11,
+    /// Exclusive XML Canonicalization 1.0: only namespaces that are visibly utilized, or listed
+    /// in `C14nOpts::inclusive_prefixes`, are declared, and nothing is inherited.
+    Exclusive,
+}
+
+/// Options for canonicalization.
+#[derive(Clone)]
+pub struct C14nOpts {
+    /// The algorithm. Default: Canonical10
+    pub method: C14nMethod,
+    /// Keep comments. Default: false
+    pub with_comments: bool,
+    /// The InclusiveNamespaces PrefixList of exclusive canonicalization, with `None` standing
+    /// for `#default`. Ignored by the other methods. Default: empty
+    pub inclusive_prefixes: Vec<Option<Prefix>>,
+    /// Canonicalize the root node? `ChildrenOnly(None)` canonicalizes a whole document.
+    /// Default: ChildrenOnly(None)
+    pub traversal_scope: TraversalScope,
+}
+
+impl Default for C14nOpts {
+    fn default() -> C14nOpts {
+        C14nOpts {
+            method: C14nMethod::Canonical10,
+            with_comments: false,
+            inclusive_prefixes: vec![],
+            traversal_scope: TraversalScope::ChildrenOnly(None),
+        }
+    }
+}
+
+/// Write the canonical form of `node` to `writer`.
+pub fn canonicalize<Wr, T>(writer: Wr, node: &T, opts: C14nOpts) -> io::Result<()>
+where
+    Wr: Write,
+    T: Serialize,
+{
+    let traversal_scope = opts.traversal_scope.clone();
+    let mut ser = C14nSerializer::new(writer, opts);
+    node.serialize(&mut ser, traversal_scope)
+}
+
+/// Prefix-to-namespace bindings. An empty namespace for the `None` prefix means the default
+/// namespace is undeclared.
+type Bindings = BTreeMap<Option<Prefix>, Namespace>;
+
+struct ElemFrame {
+    /// The namespaces in scope for the element.
+    in_scope: Bindings,
+    /// The namespaces declared by the element or its output ancestors.
+    rendered: Bindings,
+}
+
+/// A `Serializer` writing the canonical form of a document or document subset.
+pub struct C14nSerializer<Wr> {
+    writer: Wr,
+    opts: C14nOpts,
+    stack: Vec<ElemFrame>,
+    /// Whether the nodes at the top level are the children of the document, which puts line
+    /// breaks around comments and processing instructions and drops text.
+    document_level: bool,
+    /// Whether the document element has been written.
+    after_document_element: bool,
+    /// The namespaces in scope for the ancestors of the node being canonicalized.
+    ancestor_bindings: Bindings,
+    /// The `xml:` attributes of those ancestors, nearest last.
+    ancestor_xml_attrs: Vec<(LocalName, String)>,
+}
+
+impl<Wr: Write> C14nSerializer<Wr> {
+    /// Create a serializer writing to `writer`.
+    pub fn new(writer: Wr, opts: C14nOpts) -> Self {
+        let document_level = opts.traversal_scope == TraversalScope::ChildrenOnly(None);
+        C14nSerializer {
+            writer,
+            opts,
+            stack: vec![],
+            document_level,
+            after_document_element: false,
+            ancestor_bindings: Bindings::new(),
+            ancestor_xml_attrs: vec![],
+        }
+    }
+
+    /// Record an ancestor of the node about to be canonicalized, outermost first, so that its
+    /// namespaces and `xml:` attributes are inherited as the method requires. Nothing is
+    /// written.
+    pub fn add_ancestor<'a, AttrIter>(&mut self, name: &QualName, attrs: AttrIter)
+    where
+        AttrIter: Iterator<Item = AttrRef<'a>>,
+    {
+        let attrs: Vec<AttrRef> = attrs.collect();
+        let mut bindings = std::mem::take(&mut self.ancestor_bindings);
+        bind_names(&mut bindings, name, &attrs);
+        self.ancestor_bindings = bindings;
+        for &(attr, value) in attrs.iter() {
+            if attr.ns == ns!(xml) {
+                self.ancestor_xml_attrs
+                    .push((attr.local.clone(), value.to_owned()));
+            }
+        }
+    }
+
+    fn write_name(&mut self, name: &QualName) -> io::Result<()> {
+        if let Some(ref prefix) = name.prefix {
+            self.writer.write_all(prefix.as_bytes())?;
+            self.writer.write_all(b":")?;
+        }
+        self.writer.write_all(name.local.as_bytes())
+    }
+
+    /// The namespace declarations to write for an element, sorted by prefix with the default
+    /// namespace first.
+    fn namespaces_to_render(
+        &self,
+        name: &QualName,
+        attrs: &[AttrRef],
+        in_scope: &Bindings,
+        rendered: &Bindings,
+    ) -> Vec<(Option<Prefix>, Namespace)> {
+        let candidates: Vec<&Option<Prefix>> = match self.opts.method {
+            C14nMethod::Exclusive => {
+                let mut utilized = vec![&name.prefix];
+                for &(attr, _) in attrs {
+                    if attr.prefix.is_some() && !is_namespace_declaration(attr) {
+                        utilized.push(&attr.prefix);
+                    }
+                }
+                utilized.extend(self.opts.inclusive_prefixes.iter());
+                in_scope
+                    .keys()
+                    .filter(|prefix| utilized.contains(prefix))
+                    .collect()
+            },
+            C14nMethod::Canonical10 | C14nMethod::Canonical11 => in_scope.keys().collect(),
+        };
+
+        let mut namespaces = vec![];
+        for prefix in candidates {
+            if *prefix == Some(namespace_prefix!("xml")) {
+                continue;
+            }
+            let ns = &in_scope[prefix];
+            let differs = match rendered.get(prefix) {
+                Some(rendered) => rendered != ns,
+                // `xmlns=""` is only needed to undo a default namespace.
+                None => !(prefix.is_none() && ns.is_empty()),
+            };
+            // An undeclared prefix can't be written.
+            if differs && (prefix.is_none() || !ns.is_empty()) {
+                namespaces.push((prefix.clone(), ns.clone()));
+            }
+        }
+        namespaces.sort_by(|a, b| a.0.as_deref().cmp(&b.0.as_deref()));
+        namespaces
+    }
+
+    /// The `xml:` attributes a document subset's apex inherits from its ancestors.
+    fn inherited_xml_attrs(&self, attrs: &[AttrRef]) -> Vec<(LocalName, String)> {
+        let own = |local: &LocalName| {
+            attrs
+                .iter()
+                .find(|&&(name, _)| name.ns == ns!(xml) && name.local == *local)
+                .map(|&(_, value)| value)
+        };
+        let mut inherited: Vec<(LocalName, String)> = vec![];
+        let mut add = |local: &LocalName, value: String| match inherited
+            .iter_mut()
+            .find(|(name, _)| name == local)
+        {
+            Some(existing) => existing.1 = value,
+            None => inherited.push((local.clone(), value)),
+        };
+        match self.opts.method {
+            C14nMethod::Exclusive => (),
+            C14nMethod::Canonical10 => {
+                for (local, value) in self.ancestor_xml_attrs.iter() {
+                    if own(local).is_none() {
+                        add(local, value.clone());
+                    }
+                }
+            },
+            C14nMethod::Canonical11 => {
+                let mut base: Option<String> = None;
+                for (local, value) in self.ancestor_xml_attrs.iter() {
+                    match *local {
+                        local_name!("lang") | local_name!("space") if own(local).is_none() => {
+                            add(local, value.clone())
+                        },
+                        local_name!("base") => {
+                            base = Some(match base {
+                                Some(base) => join_uri(&base, value),
+                                None => value.clone(),
+                            })
+                        },
+                        _ => (),
+                    }
+                }
+                if let Some(base) = base {
+                    let base = match own(&local_name!("base")) {
+                        Some(value) => join_uri(&base, value),
+                        None => base,
+                    };
+                    add(&local_name!("base"), base);
+                }
+            },
+        }
+        inherited
+    }
+}
+
+fn is_namespace_declaration(name: &QualName) -> bool {
+    name.ns == ns!(xmlns) ||
+        name.prefix == Some(namespace_prefix!("xmlns")) ||
+        (name.prefix.is_none() && name.local == local_name!("xmlns"))
+}
+
+/// Add the bindings declared by `xmlns` attributes and implied by the names of an element and
+/// its attributes.
+fn bind_names(bindings: &mut Bindings, name: &QualName, attrs: &[AttrRef]) {
+    for &(attr, value) in attrs {
+        if is_namespace_declaration(attr) {
+            let prefix = if attr.local == local_name!("xmlns") && attr.prefix.is_none() {
+                None
+            } else {
+                Some(Prefix::from(&*attr.local))
+            };
+            bindings.insert(prefix, Namespace::from(value));
+        }
+    }
+    bindings.insert(name.prefix.clone(), name.ns.clone());
+    for &(attr, _) in attrs {
+        if attr.prefix.is_some() && !is_namespace_declaration(attr) {
+            bindings.insert(attr.prefix.clone(), attr.ns.clone());
+        }
+    }
+}
+
+fn write_escaped<W: Write>(writer: &mut W, text: &str, attr_mode: bool) -> io::Result<()> {
+    let mut written = 0;
+    for (i, c) in text.char_indices() {
+        let replacement: &[u8] = match c {
+            '&' => b"&amp;",
+            '<' => b"&lt;",
+            '>' if !attr_mode => b"&gt;",
+            '"' if attr_mode => b"&quot;",
+            '\t' if attr_mode => b"&#x9;",
+            '\n' if attr_mode => b"&#xA;",
+            '\r' => b"&#xD;",
+            _ => continue,
+        };
+        writer.write_all(&text.as_bytes()[written..i])?;
+        writer.write_all(replacement)?;
+        written = i + 1;
+    }
+    writer.write_all(&text.as_bytes()[written..])
+}
+
+impl<Wr: Write> Serializer for C14nSerializer<Wr> {
+    fn start_elem<'a, AttrIter>(&mut self, name: QualName, attrs: AttrIter) -> io::Result<()>
+    where
+        AttrIter: Iterator<Item = AttrRef<'a>>,
+    {
+        let attrs: Vec<AttrRef> = attrs.collect();
+        let (mut in_scope, rendered) = match self.stack.last() {
+            Some(parent) => (parent.in_scope.clone(), parent.rendered.clone()),
+            None => (self.ancestor_bindings.clone(), Bindings::new()),
+        };
+        bind_names(&mut in_scope, &name, &attrs);
+
+        let namespaces = self.namespaces_to_render(&name, &attrs, &in_scope, &rendered);
+
+        let inherited = if self.stack.is_empty() {
+            self.inherited_xml_attrs(&attrs)
+        } else {
+            vec![]
+        };
+        let mut sorted: Vec<(QualName, &str)> = attrs
+            .iter()
+            .filter(|&&(name, _)| !is_namespace_declaration(name))
+            .filter(|&&(name, _)| {
+                !(name.ns == ns!(xml) && inherited.iter().any(|(local, _)| *local == name.local))
+            })
+            .map(|&(name, value)| (name.clone(), value))
+            .collect();
+        for (local, value) in inherited.iter() {
+            let name = QualName::new(Some(namespace_prefix!("xml")), ns!(xml), local.clone());
+            sorted.push((name, value));
+        }
+        sorted.sort_by(|a, b| (&*a.0.ns, &*a.0.local).cmp(&(&*b.0.ns, &*b.0.local)));
+
+        self.writer.write_all(b"<")?;
+        self.write_name(&name)?;
+        let mut rendered = rendered;
+        for (prefix, ns) in namespaces {
+            match prefix {
+                Some(ref prefix) => {
+                    self.writer.write_all(b" xmlns:")?;
+                    self.writer.write_all(prefix.as_bytes())?;
+                },
+                None => self.writer.write_all(b" xmlns")?,
+            }
+            self.writer.write_all(b"=\"")?;
+            write_escaped(&mut self.writer, &ns, true)?;
+            self.writer.write_all(b"\"")?;
+            rendered.insert(prefix, ns);
+        }
+        for (name, value) in sorted {
+            self.writer.write_all(b" ")?;
+            self.write_name(&name)?;
+            self.writer.write_all(b"=\"")?;
+            write_escaped(&mut self.writer, value, true)?;
+            self.writer.write_all(b"\"")?;
+        }
+        self.writer.write_all(b">")?;
+
+        self.stack.push(ElemFrame { in_scope, rendered });
+        Ok(())
+    }
+
+    fn end_elem(&mut self, name: QualName) -> io::Result<()> {
+        self.stack.pop();
+        if self.stack.is_empty() {
+            self.after_document_element = true;
+        }
+        self.writer.write_all(b"</")?;
+        self.write_name(&name)?;
+        self.writer.write_all(b">")
+    }
+
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        if self.document_level && self.stack.is_empty() {
+            return Ok(());
+        }
+        write_escaped(&mut self.writer, text, false)
+    }
+
+    fn write_comment(&mut self, text: &str) -> io::Result<()> {
+        if !self.opts.with_comments {
+            return Ok(());
+        }
+        self.before_top_level_node()?;
+        self.writer.write_all(b"<!--")?;
+        self.writer.write_all(text.as_bytes())?;
+        self.writer.write_all(b"-->")?;
+        self.after_top_level_node()
+    }
+
+    fn write_doctype(&mut self, _name: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_processing_instruction(&mut self, target: &str, data: &str) -> io::Result<()> {
+        // The XML declaration isn't part of the canonical form. Recovery keeps
+        // a misplaced one as a processing instruction, so drop that too.
+        if target == "xml" {
+            return Ok(());
+        }
+        self.before_top_level_node()?;
+        self.writer.write_all(b"<?")?;
+        self.writer.write_all(target.as_bytes())?;
+        let data = data.trim_start_matches(&[' ', '\t', '\n', '\r'][..]);
+        if !data.is_empty() {
+            self.writer.write_all(b" ")?;
+            self.writer.write_all(data.as_bytes())?;
+        }
+        self.writer.write_all(b"?>")?;
+        self.after_top_level_node()
+    }
+}
+
+impl<Wr: Write> C14nSerializer<Wr> {
+    fn before_top_level_node(&mut self) -> io::Result<()> {
+        if self.document_level && self.stack.is_empty() && self.after_document_element {
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn after_top_level_node(&mut self) -> io::Result<()> {
+        if self.document_level && self.stack.is_empty() && !self.after_document_element {
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
@@ -7,22 +7,53 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::tree_builder::NamespaceMap;
-use crate::QualName;
+use crate::interface::XmlDeclaration;
+use crate::tokenizer::{is_name_char, is_name_start_char, is_xml_char};
+use crate::{LocalName, Namespace, Prefix, QualName};
+use mac::{_tt_as_expr_hack, matches};
+use markup5ever::encoding_rs::{Encoding, UTF_8};
 pub use markup5ever::serialize::{AttrRef, Serialize, Serializer, TraversalScope};
+pub use markup5ever::serialize::{CharRefStyle, EncodingWriter, EscapePolicy, SingleQuote};
+use markup5ever::serialize::check_encodable;
+use markup5ever::{namespace_prefix, namespace_url, ns};
 use std::io::{self, Write};
+use std::mem;
+
+mod c14n;
+pub use self::c14n::{canonicalize, C14nMethod, C14nOpts, C14nSerializer};
 
 #[derive(Clone)]
 /// Struct for setting serializer options.
 pub struct SerializeOpts {
     /// Serialize the root node? Default: ChildrenOnly
     pub traversal_scope: TraversalScope,
+    /// Which characters are written as character references in text and attribute values.
+    /// Default: `EscapePolicy::xml()`
+    pub escape_policy: EscapePolicy,
+    /// The encoding `serialize` writes. Characters it can't represent are written as decimal
+    /// character references in text and attribute values, and are an `InvalidData` error
+    /// anywhere else. An XML declaration naming the encoding is written if it isn't UTF-8.
+    /// `XmlSerializer` itself always writes UTF-8; wrap its writer in an `EncodingWriter` and
+    /// call `set_encoding` to encode. Default: UTF-8
+    pub encoding: &'static Encoding,
+    /// An XML declaration to write before the node, e.g. the `RcDom`'s, in place of the
+    /// document's own. If it has an `encoding`, that is replaced with the name of `encoding`.
+    /// Default: None
+    pub xml_declaration: Option<XmlDeclaration>,
+    /// Fail with an `InvalidData` error instead of writing a document that isn't well-formed,
+    /// e.g. a comment containing `--` or an element name that isn't a valid local name.
+    /// Default: false
+    pub require_well_formed: bool,
 }
 
 impl Default for SerializeOpts {
     fn default() -> SerializeOpts {
         SerializeOpts {
             traversal_scope: TraversalScope::ChildrenOnly(None),
+            escape_policy: EscapePolicy::xml(),
+            encoding: UTF_8,
+            xml_declaration: None,
+            require_well_formed: false,
         }
     }
 }
@@ -33,8 +64,39 @@ where
     Wr: Write,
     T: Serialize,
 {
-    let mut ser = XmlSerializer::new(writer);
-    node.serialize(&mut ser, opts.traversal_scope)
+    let encoding = opts.encoding.output_encoding();
+    // A parser assumes UTF-8 unless the XML declaration names another encoding.
+    let mut declaration = opts.xml_declaration;
+    if encoding != UTF_8 && declaration.is_none() {
+        declaration = Some(XmlDeclaration {
+            version: "1.0".into(),
+            encoding: None,
+            standalone: None,
+        });
+    }
+    if let Some(ref mut declaration) = declaration {
+        if encoding != UTF_8 || declaration.encoding.is_some() {
+            declaration.encoding = Some(encoding.name().into());
+        }
+    }
+
+    if encoding == UTF_8 {
+        let mut ser = XmlSerializer::with_escape_policy(writer, opts.escape_policy);
+        ser.set_require_well_formed(opts.require_well_formed);
+        if let Some(ref declaration) = declaration {
+            ser.write_xml_declaration(declaration)?;
+        }
+        return node.serialize(&mut ser, opts.traversal_scope);
+    }
+    let writer = EncodingWriter::new(writer, encoding);
+    let mut ser = XmlSerializer::with_escape_policy(writer, opts.escape_policy);
+    ser.set_require_well_formed(opts.require_well_formed);
+    ser.set_encoding(encoding);
+    if let Some(ref declaration) = declaration {
+        ser.write_xml_declaration(declaration)?;
+    }
+    node.serialize(&mut ser, opts.traversal_scope)?;
+    ser.writer.finish().map(|_| ())
 }
 
 /// Struct used for serializing nodes into a text that other XML
@@ -42,103 +104,290 @@ where
 ///
 /// Serializer contains a set of functions (start_elem, end_elem...)
 /// that make parsing nodes easier.
+///
+/// Namespaces are declared as in the [XML serialization] algorithm of DOM
+/// Parsing: names keep their prefix where it is free, prefixes `ns1`, `ns2`...
+/// are generated where it isn't, and `xmlns` attributes that are redundant or
+/// contradict the names are dropped.
+///
+/// [XML serialization]: https://w3c.github.io/DOM-Parsing/#dfn-xml-serialization
 pub struct XmlSerializer<Wr> {
     writer: Wr,
-    namespace_stack: NamespaceMapStack,
+    escape_policy: EscapePolicy,
+    require_well_formed: bool,
+    encoding: &'static Encoding,
+    /// Prefixes bound by the open elements, innermost last. An empty
+    /// namespace means the prefix is undeclared.
+    bindings: Vec<(Prefix, Namespace)>,
+    open_elems: Vec<OpenElem>,
+    /// Number of the last generated prefix.
+    prefix_index: u32,
+    /// What has been written so far, to tell the document's own XML
+    /// declaration, which the tree builder keeps as a processing instruction,
+    /// from a misplaced one.
+    start: Start,
 }
 
-#[derive(Debug)]
-struct NamespaceMapStack(Vec<NamespaceMap>);
-
-impl NamespaceMapStack {
-    fn new() -> NamespaceMapStack {
-        NamespaceMapStack(vec![])
-    }
+#[derive(Clone, Copy, PartialEq)]
+enum Start {
+    /// Nothing has been written.
+    Empty,
+    /// Only an XML declaration has been written.
+    Declared,
+    /// Something else has been written.
+    Content,
+}
 
-    fn push(&mut self, namespace: NamespaceMap) {
-        self.0.push(namespace);
-    }
+struct OpenElem {
+    /// Name written in the start tag.
+    prefix: Option<Prefix>,
+    local: LocalName,
+    /// Length of `bindings` before the element's own.
+    bindings_len: usize,
+    /// Default namespace of the element's children.
+    default_ns: Namespace,
+}
 
-    fn pop(&mut self) {
-        self.0.pop();
-    }
+fn ill_formed(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
 }
 
-/// Writes given text into the Serializer, escaping it,
-/// depending on where the text is written inside the tag or attribute value.
-///
-/// For example
-///```text
-///    <tag>'&-quotes'</tag>   becomes      <tag>'&amp;-quotes'</tag>
-///    <tag = "'&-quotes'">    becomes      <tag = "&apos;&amp;-quotes&apos;"
-///```
-fn write_to_buf_escaped<W: Write>(writer: &mut W, text: &str, attr_mode: bool) -> io::Result<()> {
-    for c in text.chars() {
-        match c {
-            '&' => writer.write_all(b"&amp;"),
-            '\'' if attr_mode => writer.write_all(b"&apos;"),
-            '"' if attr_mode => writer.write_all(b"&quot;"),
-            '<' if !attr_mode => writer.write_all(b"&lt;"),
-            '>' if !attr_mode => writer.write_all(b"&gt;"),
-            c => writer.write_fmt(format_args!("{}", c)),
-        }?;
-    }
-    Ok(())
+// Names in namespace-well-formed documents have no colon.
+fn is_ncname(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c != ':' && is_name_start_char(c)) &&
+        chars.all(|c| c != ':' && is_name_char(c))
 }
 
 #[inline]
-fn write_qual_name<W: Write>(writer: &mut W, name: &QualName) -> io::Result<()> {
-    if let Some(ref prefix) = name.prefix {
-        writer.write_all(&prefix.as_bytes())?;
+fn write_qual_name<W: Write>(
+    writer: &mut W,
+    prefix: &Option<Prefix>,
+    local: &LocalName,
+) -> io::Result<()> {
+    if let Some(ref prefix) = *prefix {
+        writer.write_all(prefix.as_bytes())?;
         writer.write_all(b":")?;
-        writer.write_all(&*name.local.as_bytes())?;
-    } else {
-        writer.write_all(&*name.local.as_bytes())?;
     }
-
-    Ok(())
+    writer.write_all(local.as_bytes())
 }
 
 impl<Wr: Write> XmlSerializer<Wr> {
-    /// Creates a new Serializier from a writer and given serialization options.
+    /// Creates a new Serializier from a writer, escaping with `EscapePolicy::xml()`.
     pub fn new(writer: Wr) -> Self {
+        XmlSerializer::with_escape_policy(writer, EscapePolicy::xml())
+    }
+
+    /// Creates a new Serializer from a writer and the policy used to escape text and attribute
+    /// values.
+    ///
+    /// For example, with the default policy
+    ///```text
+    ///    <tag>'&-quotes'</tag>   becomes      <tag>'&amp;-quotes'</tag>
+    ///    <tag = "'&-quotes'">    becomes      <tag = "&apos;&amp;-quotes&apos;"
+    ///```
+    pub fn with_escape_policy(writer: Wr, escape_policy: EscapePolicy) -> Self {
         XmlSerializer {
             writer,
-            namespace_stack: NamespaceMapStack::new(),
+            escape_policy,
+            require_well_formed: false,
+            encoding: UTF_8,
+            bindings: vec![(namespace_prefix!("xml"), ns!(xml))],
+            open_elems: vec![],
+            prefix_index: 0,
+            start: Start::Empty,
+        }
+    }
+
+    /// Fail with an `InvalidData` error instead of writing XML that isn't well-formed. See
+    /// `SerializeOpts::require_well_formed`.
+    pub fn set_require_well_formed(&mut self, require_well_formed: bool) {
+        self.require_well_formed = require_well_formed;
+    }
+
+    /// Set the encoding that the output is converted to, e.g. by an `EncodingWriter`. Characters
+    /// it can't represent are an `InvalidData` error anywhere but in text and attribute values,
+    /// where they are written as character references. Default: UTF-8
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Writes an XML declaration, e.g. `<?xml version="1.0" encoding="UTF-8"?>`. If it starts
+    /// the output, it replaces the document's own declaration, which isn't written.
+    pub fn write_xml_declaration(&mut self, declaration: &XmlDeclaration) -> io::Result<()> {
+        if self.start == Start::Empty {
+            self.start = Start::Declared;
+        }
+        write!(self.writer, "<?xml version=\"{}\"", declaration.version)?;
+        if let Some(ref encoding) = declaration.encoding {
+            write!(self.writer, " encoding=\"{}\"", encoding)?;
+        }
+        match declaration.standalone {
+            Some(true) => self.writer.write_all(b" standalone=\"yes\"")?,
+            Some(false) => self.writer.write_all(b" standalone=\"no\"")?,
+            None => (),
+        }
+        self.writer.write_all(b"?>")
+    }
+
+    fn lookup(&self, prefix: &Prefix) -> Option<&Namespace> {
+        let binding = self
+            .bindings
+            .iter()
+            .rev()
+            .find(|(bound, _)| bound == prefix);
+        binding.map(|(_, ns)| ns)
+    }
+
+    // The prefix to write for a name in `ns`: `preferred` if it is bound to
+    // `ns`, otherwise the innermost prefix bound to it.
+    fn preferred_prefix(&self, ns: &Namespace, preferred: &Option<Prefix>) -> Option<Prefix> {
+        if ns.is_empty() {
+            return None;
+        }
+        if let Some(ref preferred) = *preferred {
+            if self.lookup(preferred) == Some(ns) {
+                return Some(preferred.clone());
+            }
+        }
+        let mut bound = self.bindings.iter().rev().filter(|(_, bound)| bound == ns);
+        bound
+            .find(|(prefix, _)| self.lookup(prefix) == Some(ns))
+            .map(|(prefix, _)| prefix.clone())
+    }
+
+    // Can the prefix be declared without hiding a binding in use?
+    fn is_free(&self, prefix: &Prefix, bindings_len: usize) -> bool {
+        let local = self.bindings[bindings_len..]
+            .iter()
+            .any(|(bound, _)| bound == prefix);
+        let free = match self.lookup(prefix) {
+            Some(ns) => ns.is_empty(),
+            None => true,
+        };
+        !local && free && *prefix != namespace_prefix!("xmlns")
+    }
+
+    fn generate_prefix(&mut self) -> Prefix {
+        loop {
+            self.prefix_index += 1;
+            let prefix = Prefix::from(format!("ns{}", self.prefix_index));
+            if self.lookup(&prefix).is_none() {
+                return prefix;
+            }
         }
     }
 
-    #[inline(always)]
-    fn qual_name(&mut self, name: &QualName) -> io::Result<()> {
-        self.find_or_insert_ns(name);
-        write_qual_name(&mut self.writer, name)
+    fn declare(&mut self, prefix: Prefix, ns: Namespace) -> io::Result<()> {
+        self.writer.write_all(b" xmlns:")?;
+        self.writer.write_all(prefix.as_bytes())?;
+        self.writer.write_all(b"=\"")?;
+        self.escape_policy
+            .write_escaped(&mut self.writer, &ns, true)?;
+        self.writer.write_all(b"\"")?;
+        self.bindings.push((prefix, ns));
+        Ok(())
     }
 
-    #[inline(always)]
-    fn qual_attr_name(&mut self, name: &QualName) -> io::Result<()> {
-        self.find_or_insert_ns(name);
-        write_qual_name(&mut self.writer, name)
+    fn check_chars(&self, text: &str, what: &str) -> io::Result<()> {
+        if self.require_well_formed && !text.chars().all(is_xml_char) {
+            return Err(ill_formed(&format!("Illegal character in {}", what)));
+        }
+        Ok(())
+    }
+
+    fn check_name(&self, prefix: &Option<Prefix>, local: &str, what: &str) -> io::Result<()> {
+        if let Some(ref prefix) = *prefix {
+            check_encodable(self.encoding, prefix, what)?;
+        }
+        check_encodable(self.encoding, local, what)
     }
 
-    fn find_uri(&self, name: &QualName) -> bool {
-        let mut found = false;
-        for stack in self.namespace_stack.0.iter().rev() {
-            if let Some(&Some(ref el)) = stack.get(&name.prefix) {
-                found = *el == name.ns;
-                break;
+    // Binds the prefixes declared by `xmlns:prefix` attributes, unless they
+    // are bound already. Returns the value of an `xmlns` attribute.
+    fn record_declarations(&mut self, attrs: &[AttrRef]) -> Option<Namespace> {
+        let mut default_ns = None;
+        for &(name, value) in attrs.iter() {
+            if name.ns != ns!(xmlns) {
+                continue;
+            }
+            if name.prefix.is_none() {
+                default_ns = Some(Namespace::from(value));
+                continue;
+            }
+            let prefix = Prefix::from(&*name.local);
+            let ns = Namespace::from(value);
+            if ns != ns!(xml) && self.lookup(&prefix) != Some(&ns) {
+                self.bindings.push((prefix, ns));
             }
         }
-        found
+        default_ns
     }
 
-    fn find_or_insert_ns(&mut self, name: &QualName) {
-        if name.prefix.is_some() || &*name.ns != "" {
-            if !self.find_uri(name) {
-                if let Some(last_ns) = self.namespace_stack.0.last_mut() {
-                    last_ns.insert(name);
+    fn write_attrs(
+        &mut self,
+        attrs: &[AttrRef],
+        bindings_len: usize,
+        ignore_default_declaration: bool,
+    ) -> io::Result<()> {
+        for (i, &(name, value)) in attrs.iter().enumerate() {
+            if self.require_well_formed {
+                if attrs[..i]
+                    .iter()
+                    .any(|(other, _)| other.ns == name.ns && other.local == name.local)
+                {
+                    return Err(ill_formed("Duplicate attribute"));
+                }
+                if !is_ncname(&name.local) || (name.local == *"xmlns" && name.ns.is_empty()) {
+                    return Err(ill_formed("Attribute name is not a valid local name"));
+                }
+            }
+            let mut prefix = None;
+            if name.ns == ns!(xmlns) {
+                let declared = match name.prefix {
+                    None => !ignore_default_declaration,
+                    Some(_) => {
+                        let binding = (Prefix::from(&*name.local), Namespace::from(value));
+                        self.bindings[bindings_len..].contains(&binding)
+                    },
+                };
+                if !declared || value == &*ns!(xml) {
+                    continue;
+                }
+                if self.require_well_formed {
+                    if value == &*ns!(xmlns) {
+                        return Err(ill_formed("The XMLNS namespace can't be declared"));
+                    }
+                    if name.prefix.is_some() && value.is_empty() {
+                        return Err(ill_formed("A namespace prefix can't be undeclared"));
+                    }
+                }
+                if name.prefix.is_some() {
+                    prefix = Some(namespace_prefix!("xmlns"));
+                }
+            } else if !name.ns.is_empty() {
+                prefix = self.preferred_prefix(&name.ns, &name.prefix);
+                if prefix.is_none() {
+                    let candidate = match name.prefix {
+                        Some(ref candidate) if self.is_free(candidate, bindings_len) => {
+                            candidate.clone()
+                        },
+                        _ => self.generate_prefix(),
+                    };
+                    self.declare(candidate.clone(), name.ns.clone())?;
+                    prefix = Some(candidate);
                 }
             }
+            self.check_chars(value, "an attribute value")?;
+            self.check_name(&prefix, &name.local, "an attribute name")?;
+            self.writer.write_all(b" ")?;
+            write_qual_name(&mut self.writer, &prefix, &name.local)?;
+            self.writer.write_all(b"=\"")?;
+            self.escape_policy
+                .write_escaped(&mut self.writer, value, true)?;
+            self.writer.write_all(b"\"")?;
         }
+        Ok(())
     }
 }
 
@@ -149,49 +398,109 @@ impl<Wr: Write> Serializer for XmlSerializer<Wr> {
     where
         AttrIter: Iterator<Item = AttrRef<'a>>,
     {
-        self.namespace_stack.push(NamespaceMap::empty());
+        if self.require_well_formed && !is_ncname(&name.local) {
+            return Err(ill_formed("Element name is not a valid local name"));
+        }
+        self.start = Start::Content;
+        let attrs: Vec<AttrRef> = attrs.collect();
+        let bindings_len = self.bindings.len();
+        let inherited_ns = match self.open_elems.last() {
+            Some(elem) => elem.default_ns.clone(),
+            None => ns!(),
+        };
+        let local_default_ns = self.record_declarations(&attrs);
+        let ns = name.ns;
 
-        self.writer.write_all(b"<")?;
-        self.qual_name(&name)?;
-        if let Some(current_namespace) = self.namespace_stack.0.last() {
-            for (prefix, url_opt) in current_namespace.get_scope_iter() {
-                self.writer.write_all(b" xmlns")?;
-                if let Some(ref p) = *prefix {
-                    self.writer.write_all(b":")?;
-                    self.writer.write_all(&*p.as_bytes())?;
+        let mut ignore_default_declaration = false;
+        let mut default_ns = inherited_ns.clone();
+        let mut declaration = None;
+        let prefix = if name.prefix == Some(namespace_prefix!("xmlns")) {
+            if self.require_well_formed {
+                return Err(ill_formed("An element can't have the xmlns prefix"));
+            }
+            name.prefix
+        } else if inherited_ns == ns {
+            ignore_default_declaration = local_default_ns.is_some();
+            if ns == ns!(xml) {
+                Some(namespace_prefix!("xml"))
+            } else {
+                None
+            }
+        } else if let Some(prefix) = self.preferred_prefix(&ns, &name.prefix) {
+            if let Some(ref local_default_ns) = local_default_ns {
+                if *local_default_ns != ns!(xml) {
+                    default_ns = local_default_ns.clone();
                 }
+            }
+            Some(prefix)
+        } else if let (Some(prefix), false) = (name.prefix, ns.is_empty()) {
+            let local = self.bindings[bindings_len..]
+                .iter()
+                .any(|(bound, _)| *bound == prefix);
+            let prefix = if local {
+                self.generate_prefix()
+            } else {
+                prefix
+            };
+            if let Some(ref local_default_ns) = local_default_ns {
+                default_ns = local_default_ns.clone();
+            }
+            declaration = Some(prefix.clone());
+            Some(prefix)
+        } else {
+            if local_default_ns.as_ref() != Some(&ns) {
+                ignore_default_declaration = true;
+                declaration = Some(namespace_prefix!(""));
+            }
+            default_ns = ns.clone();
+            None
+        };
 
-                self.writer.write_all(b"=\"")?;
-                let url = if let Some(ref a) = *url_opt {
-                    a.as_bytes()
-                } else {
-                    b""
-                };
-                self.writer.write_all(url)?;
+        self.check_name(&prefix, &name.local, "an element name")?;
+        self.writer.write_all(b"<")?;
+        write_qual_name(&mut self.writer, &prefix, &name.local)?;
+        match declaration {
+            Some(namespace_prefix!("")) => {
+                self.writer.write_all(b" xmlns=\"")?;
+                self.escape_policy
+                    .write_escaped(&mut self.writer, &ns, true)?;
                 self.writer.write_all(b"\"")?;
-            }
-        }
-        for (name, value) in attrs {
-            self.writer.write_all(b" ")?;
-            self.qual_attr_name(&name)?;
-            self.writer.write_all(b"=\"")?;
-            write_to_buf_escaped(&mut self.writer, value, true)?;
-            self.writer.write_all(b"\"")?;
+            },
+            Some(prefix) => self.declare(prefix, ns)?,
+            None => (),
         }
+        self.write_attrs(&attrs, bindings_len, ignore_default_declaration)?;
         self.writer.write_all(b">")?;
+        self.open_elems.push(OpenElem {
+            prefix,
+            local: name.local,
+            bindings_len,
+            default_ns,
+        });
         Ok(())
     }
 
     /// Serializes given end element into text.
     fn end_elem(&mut self, name: QualName) -> io::Result<()> {
-        self.namespace_stack.pop();
         self.writer.write_all(b"</")?;
-        self.qual_name(&name)?;
+        match self.open_elems.pop() {
+            Some(elem) => {
+                self.bindings.truncate(elem.bindings_len);
+                write_qual_name(&mut self.writer, &elem.prefix, &elem.local)?;
+            },
+            None => write_qual_name(&mut self.writer, &name.prefix, &name.local)?,
+        }
         self.writer.write_all(b">")
     }
 
     /// Serializes comment into text.
     fn write_comment(&mut self, text: &str) -> io::Result<()> {
+        if self.require_well_formed && (text.contains("--") || text.ends_with('-')) {
+            return Err(ill_formed("A comment can't contain '--' or end with '-'"));
+        }
+        self.check_chars(text, "a comment")?;
+        check_encodable(self.encoding, text, "a comment")?;
+        self.start = Start::Content;
         self.writer.write_all(b"<!--")?;
         self.writer.write_all(text.as_bytes())?;
         self.writer.write_all(b"-->")
@@ -199,6 +508,8 @@ impl<Wr: Write> Serializer for XmlSerializer<Wr> {
 
     /// Serializes given doctype
     fn write_doctype(&mut self, name: &str) -> io::Result<()> {
+        check_encodable(self.encoding, name, "a doctype")?;
+        self.start = Start::Content;
         self.writer.write_all(b"<!DOCTYPE ")?;
         self.writer.write_all(name.as_bytes())?;
         self.writer.write_all(b">")
@@ -206,15 +517,213 @@ impl<Wr: Write> Serializer for XmlSerializer<Wr> {
 
     /// Serializes text for a node or an attributes.
     fn write_text(&mut self, text: &str) -> io::Result<()> {
-        write_to_buf_escaped(&mut self.writer, text, false)
+        self.check_chars(text, "text")?;
+        self.start = Start::Content;
+        self.escape_policy
+            .write_escaped(&mut self.writer, text, false)
     }
 
     /// Serializes given processing instruction.
     fn write_processing_instruction(&mut self, target: &str, data: &str) -> io::Result<()> {
+        let start = mem::replace(&mut self.start, Start::Content);
+        if target == "xml" && start == Start::Declared {
+            return Ok(());
+        }
+        let declaration = target == "xml" && start == Start::Empty;
+        if self.require_well_formed {
+            if !is_ncname(target) || (target.eq_ignore_ascii_case("xml") && !declaration) {
+                return Err(ill_formed("Invalid processing instruction target"));
+            }
+            if data.contains("?>") {
+                return Err(ill_formed("A processing instruction can't contain '?>'"));
+            }
+        }
+        self.check_chars(data, "a processing instruction")?;
+        check_encodable(self.encoding, target, "a processing instruction")?;
+        check_encodable(self.encoding, data, "a processing instruction")?;
         self.writer.write_all(b"<?")?;
         self.writer.write_all(target.as_bytes())?;
         self.writer.write_all(b" ")?;
         self.writer.write_all(data.as_bytes())?;
         self.writer.write_all(b"?>")
     }
+
+    /// Serializes a CDATA section. A `]]>` in the text is split across two
+    /// sections.
+    fn write_cdata(&mut self, text: &str) -> io::Result<()> {
+        self.check_chars(text, "a CDATA section")?;
+        check_encodable(self.encoding, text, "a CDATA section")?;
+        self.writer.write_all(b"<![CDATA[")?;
+        for (i, section) in text.split("]]>").enumerate() {
+            if i > 0 {
+                self.writer.write_all(b"]]]]><![CDATA[>")?;
+            }
+            self.writer.write_all(section.as_bytes())?;
+        }
+        self.writer.write_all(b"]]>")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Serializer, XmlSerializer};
+    use crate::{LocalName, Namespace, Prefix, QualName};
+    use markup5ever::{namespace_url, ns};
+    use std::io;
+
+    fn qual(prefix: Option<&str>, ns: &str, local: &str) -> QualName {
+        QualName::new(
+            prefix.map(Prefix::from),
+            Namespace::from(ns),
+            LocalName::from(local),
+        )
+    }
+
+    fn xmlns(prefix: Option<&str>) -> QualName {
+        match prefix {
+            Some(prefix) => qual(Some("xmlns"), &ns!(xmlns), prefix),
+            None => qual(None, &ns!(xmlns), "xmlns"),
+        }
+    }
+
+    enum Event<'a> {
+        Start(QualName, Vec<(QualName, &'a str)>),
+        End,
+        Comment(&'a str),
+        Pi(&'a str, &'a str),
+        Text(&'a str),
+    }
+    use self::Event::*;
+
+    fn serialize(events: Vec<Event>, require_well_formed: bool) -> io::Result<String> {
+        let mut ser = XmlSerializer::new(vec![]);
+        ser.set_require_well_formed(require_well_formed);
+        let mut names = vec![];
+        for event in events {
+            match event {
+                Start(name, attrs) => {
+                    ser.start_elem(name.clone(), attrs.iter().map(|(n, v)| (n, *v)))?;
+                    names.push(name);
+                },
+                End => ser.end_elem(names.pop().unwrap())?,
+                Comment(text) => ser.write_comment(text)?,
+                Pi(target, data) => ser.write_processing_instruction(target, data)?,
+                Text(text) => ser.write_text(text)?,
+            }
+        }
+        Ok(String::from_utf8(ser.writer).unwrap())
+    }
+
+    fn check(events: Vec<Event>, expected: &str) {
+        assert_eq!(serialize(events, false).unwrap(), expected);
+    }
+
+    #[test]
+    fn default_namespace() {
+        check(
+            vec![
+                Start(qual(None, "urn:a", "a"), vec![]),
+                Start(qual(None, "urn:a", "b"), vec![]),
+                End,
+                Start(qual(None, "", "c"), vec![]),
+                End,
+                End,
+            ],
+            r#"<a xmlns="urn:a"><b></b><c xmlns=""></c></a>"#,
+        );
+    }
+
+    #[test]
+    fn preserve_prefixes() {
+        let attr = qual(Some("b"), "urn:b", "attr");
+        check(
+            vec![
+                Start(qual(Some("a"), "urn:a", "root"), vec![(attr.clone(), "1")]),
+                Start(qual(Some("a"), "urn:a", "child"), vec![(attr, "2")]),
+                End,
+                End,
+            ],
+            concat!(
+                r#"<a:root xmlns:a="urn:a" xmlns:b="urn:b" b:attr="1">"#,
+                r#"<a:child b:attr="2"></a:child></a:root>"#
+            ),
+        );
+    }
+
+    #[test]
+    fn prefix_conflict() {
+        // `p` is declared on the element for another namespace than its name's.
+        check(
+            vec![
+                Start(
+                    qual(Some("p"), "urn:a", "root"),
+                    vec![(xmlns(Some("p")), "urn:b")],
+                ),
+                End,
+            ],
+            r#"<ns1:root xmlns:ns1="urn:a" xmlns:p="urn:b"></ns1:root>"#,
+        );
+        check(
+            vec![
+                Start(
+                    qual(None, "", "root"),
+                    vec![(qual(None, "urn:a", "attr"), "1")],
+                ),
+                End,
+            ],
+            r#"<root xmlns:ns1="urn:a" ns1:attr="1"></root>"#,
+        );
+    }
+
+    #[test]
+    fn redundant_declarations() {
+        check(
+            vec![
+                Start(
+                    qual(Some("a"), "urn:a", "root"),
+                    vec![(xmlns(Some("a")), "urn:a"), (xmlns(None), "urn:d")],
+                ),
+                Start(
+                    qual(None, "urn:d", "child"),
+                    vec![(xmlns(Some("a")), "urn:a"), (xmlns(None), "urn:other")],
+                ),
+                End,
+                End,
+            ],
+            concat!(
+                r#"<a:root xmlns:a="urn:a" xmlns="urn:d">"#,
+                r#"<child></child></a:root>"#
+            ),
+        );
+    }
+
+    #[test]
+    fn require_well_formed() {
+        let ill_formed = vec![
+            vec![Start(qual(None, "", "a:b"), vec![])],
+            vec![Comment("a--b")],
+            vec![Comment("a-")],
+            vec![Comment("c"), Pi("xml", "version='1.0'")],
+            vec![Pi("target", "?>")],
+            vec![Text("\u{1}")],
+            vec![Start(
+                qual(None, "", "a"),
+                vec![(qual(None, "", "x"), "1"), (qual(None, "", "x"), "2")],
+            )],
+            vec![Start(qual(None, "", "a"), vec![(xmlns(Some("p")), "")])],
+        ];
+        for events in ill_formed {
+            let error = serialize(events, true).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        assert_eq!(
+            serialize(vec![Comment("a--b"), Text("\u{1}")], false).unwrap(),
+            "<!--a--b-->\u{1}"
+        );
+        // The tree builder keeps the document's declaration as a processing instruction.
+        assert_eq!(
+            serialize(vec![Pi("xml", "version='1.0'")], true).unwrap(),
+            "<?xml version='1.0'?>"
+        );
+    }
 }
@@ -7,6 +7,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use super::dtd::{is_name_char, is_name_start_char};
 use super::{TokenSink, XmlTokenizer};
 use crate::data;
 use crate::tendril::StrTendril;
@@ -26,6 +27,9 @@ pub struct CharRef {
 
     /// How many slots in `chars` are valid?
     pub num_chars: u8,
+
+    /// The name of a declared entity, for a reference to one.
+    pub entity: Option<StrTendril>,
 }
 
 pub enum Status {
@@ -41,7 +45,6 @@ enum State {
     Numeric(u32), // base
     NumericSemicolon,
     Named,
-    BogusName,
 }
 
 pub struct CharRefTokenizer {
@@ -55,8 +58,6 @@ pub struct CharRefTokenizer {
     hex_marker: Option<char>,
 
     name_buf_opt: Option<StrTendril>,
-    name_match: Option<(u32, u32)>,
-    name_len: usize,
 }
 
 impl CharRefTokenizer {
@@ -72,8 +73,6 @@ impl CharRefTokenizer {
             seen_digit: false,
             hex_marker: None,
             name_buf_opt: None,
-            name_match: None,
-            name_len: 0,
         }
     }
 
@@ -99,6 +98,7 @@ impl CharRefTokenizer {
         self.result = Some(CharRef {
             chars: ['\0', '\0'],
             num_chars: 0,
+            entity: None,
         });
         Done
     }
@@ -107,6 +107,18 @@ impl CharRefTokenizer {
         self.result = Some(CharRef {
             chars: [c, '\0'],
             num_chars: 1,
+            entity: None,
+        });
+        Done
+    }
+
+    fn finish_entity(&mut self) -> Status {
+        let mut name = self.name_buf_opt.take().unwrap();
+        name.pop_back(1);
+        self.result = Some(CharRef {
+            chars: ['\0', '\0'],
+            num_chars: 0,
+            entity: Some(name),
         });
         Done
     }
@@ -129,7 +141,6 @@ impl CharRefTokenizer {
             Numeric(base) => self.do_numeric(tokenizer, base, input),
             NumericSemicolon => self.do_numeric_semicolon(tokenizer, input),
             Named => self.do_named(tokenizer, input),
-            BogusName => self.do_bogus_name(tokenizer, input),
         }
     }
 
@@ -278,31 +289,10 @@ impl CharRefTokenizer {
     ) -> Status {
         let c = unwrap_or_return!(tokenizer.get_char(input), Stuck);
         self.name_buf_mut().push_char(c);
-        match data::NAMED_ENTITIES.get(&self.name_buf()[..]) {
-            // We have either a full match or a prefix of one.
-            Some(&m) => {
-                if m.0 != 0 {
-                    // We have a full match, but there might be a longer one to come.
-                    self.name_match = Some(m);
-                    self.name_len = self.name_buf().len();
-                }
-                // Otherwise we just have a prefix match.
-                Progress
-            },
-
-            // Can't continue the match.
-            None => self.finish_named(tokenizer, Some(c), input),
+        if is_name_char(c) {
+            return Progress;
         }
-    }
-
-    fn emit_name_error<Sink: TokenSink>(&mut self, tokenizer: &mut XmlTokenizer<Sink>) {
-        let msg = format_if!(
-            tokenizer.opts.exact_errors,
-            "Invalid character reference",
-            "Invalid character reference &{}",
-            self.name_buf()
-        );
-        tokenizer.emit_error(msg);
+        self.finish_named(tokenizer, Some(c), input)
     }
 
     fn unconsume_name<Sink: TokenSink>(
@@ -313,109 +303,53 @@ impl CharRefTokenizer {
         tokenizer.unconsume(input, self.name_buf_opt.take().unwrap());
     }
 
+    // Only the predefined entities and those declared in the internal subset
+    // are resolved, and only when the reference ends with a semicolon.
     fn finish_named<Sink: TokenSink>(
         &mut self,
         tokenizer: &mut XmlTokenizer<Sink>,
         end_char: Option<char>,
         input: &mut BufferQueue,
     ) -> Status {
-        match self.name_match {
-            None => {
-                match end_char {
-                    Some(c) if c.is_ascii_alphanumeric() => {
-                        // Keep looking for a semicolon, to determine whether
-                        // we emit a parse error.
-                        self.state = BogusName;
-                        return Progress;
-                    },
-
-                    // Check length because &; is not a parse error.
-                    Some(';') if self.name_buf().len() > 1 => self.emit_name_error(tokenizer),
-
-                    _ => (),
-                }
-                self.unconsume_name(tokenizer, input);
-                self.finish_none()
-            },
-
-            Some((c1, c2)) => {
-                // We have a complete match, but we may have consumed
-                // additional characters into self.name_buf.  Usually
-                // at least one, but several in cases like
-                //
-                //     &not    => match for U+00AC
-                //     &noti   => valid prefix for &notin
-                //     &notit  => can't continue match
-
-                let name_len = self.name_len;
-                assert!(name_len > 0);
-                let last_matched = self.name_buf()[name_len - 1..].chars().next().unwrap();
-
-                // There might not be a next character after the match, if
-                // we had a full match and then hit EOF.
-                let next_after = if name_len == self.name_buf().len() {
-                    None
-                } else {
-                    Some(self.name_buf()[name_len..].chars().next().unwrap())
-                };
-
-                // "If the character reference is being consumed as part of an
-                // attribute, and the last character matched is not a U+003B
-                // SEMICOLON character (;), and the next character is either a
-                // U+003D EQUALS SIGN character (=) or an alphanumeric ASCII
-                // character, then, for historical reasons, all the characters
-                // that were matched after the U+0026 AMPERSAND character (&)
-                // must be unconsumed, and nothing is returned. However, if
-                // this next character is in fact a U+003D EQUALS SIGN
-                // character (=), then this is a parse error"
-
-                let unconsume_all = match (self.addnl_allowed, last_matched, next_after) {
-                    (_, ';', _) => false,
-                    (Some(_), _, Some('=')) => {
-                        tokenizer.emit_error(Borrowed(
-                            "Equals sign after character reference in attribute",
-                        ));
-                        true
-                    },
-                    (Some(_), _, Some(c)) if c.is_ascii_alphanumeric() => true,
-                    _ => {
-                        tokenizer.emit_error(Borrowed(
-                            "Character reference does not end with semicolon",
-                        ));
-                        false
-                    },
-                };
-
-                if unconsume_all {
-                    self.unconsume_name(tokenizer, input);
-                    self.finish_none()
-                } else {
-                    tokenizer
-                        .unconsume(input, StrTendril::from_slice(&self.name_buf()[name_len..]));
-                    self.result = Some(CharRef {
-                        chars: [from_u32(c1).unwrap(), from_u32(c2).unwrap()],
-                        num_chars: if c2 == 0 { 1 } else { 2 },
-                    });
-                    Done
-                }
-            },
+        if end_char != Some(';') || self.name_buf().len() == 1 {
+            self.unconsume_name(tokenizer, input);
+            return self.finish_none();
         }
-    }
-
-    fn do_bogus_name<Sink: TokenSink>(
-        &mut self,
-        tokenizer: &mut XmlTokenizer<Sink>,
-        input: &mut BufferQueue,
-    ) -> Status {
-        let c = unwrap_or_return!(tokenizer.get_char(input), Stuck);
-        self.name_buf_mut().push_char(c);
-        match c {
-            _ if c.is_ascii_alphanumeric() => return Progress,
-            ';' => self.emit_name_error(tokenizer),
-            _ => (),
+        let name = &self.name_buf()[..self.name_buf().len() - 1];
+        let predefined = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => None,
+        };
+        if let Some(c) = predefined {
+            return self.finish_one(c);
         }
+        if tokenizer.entity_names.contains(name) {
+            return self.finish_entity();
+        }
+
+        let msg = if !name.starts_with(is_name_start_char) {
+            format_if!(
+                tokenizer.opts.exact_errors,
+                "Invalid character reference",
+                "Invalid character reference &{};",
+                name
+            )
+        } else {
+            format_if!(
+                tokenizer.opts.exact_errors,
+                "Reference to an undeclared entity",
+                "Reference to an undeclared entity &{};",
+                name
+            )
+        };
+        tokenizer.emit_error(msg);
+        // The reference is kept as text.
         self.unconsume_name(tokenizer, input);
-        self.finish_none()
+        self.finish_one('&')
     }
 
     pub fn end_of_file<Sink: TokenSink>(
@@ -436,11 +370,6 @@ impl CharRefTokenizer {
 
                 Named => drop(self.finish_named(tokenizer, None, input)),
 
-                BogusName => {
-                    self.unconsume_name(tokenizer, input);
-                    self.finish_none();
-                },
-
                 Octothorpe => {
                     tokenizer.unconsume(input, StrTendril::from_slice("#"));
                     tokenizer.emit_error(Borrowed("EOF after '#' in character reference"));
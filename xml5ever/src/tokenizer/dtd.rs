@@ -0,0 +1,578 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The internal subset of a DOCTYPE.
+//!
+//! The tokenizer collects the text between `[` and the matching `]` with a `SubsetScanner`,
+//! then `parse_internal_subset` reads the markup declarations from it.
+
+use mac::{_tt_as_expr_hack, matches};
+use std::borrow::Cow::{self, Borrowed};
+use std::char::from_u32;
+
+use super::interface::{AttDef, AttDefault, AttListDecl, ElementDecl, EntityDecl};
+use super::interface::{EntityValue, MarkupDecl, NotationDecl};
+use crate::tendril::StrTendril;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum ScanMode {
+    Markup,
+    Quoted(char),
+    Comment,
+    Pi,
+}
+
+/// Finds the end of an internal subset: the first `]` that isn't part of a literal, comment or
+/// processing instruction.
+pub struct SubsetScanner {
+    text: StrTendril,
+    mode: ScanMode,
+}
+
+impl SubsetScanner {
+    pub fn new() -> SubsetScanner {
+        SubsetScanner {
+            text: StrTendril::new(),
+            mode: ScanMode::Markup,
+        }
+    }
+
+    /// Add a character of the subset. Returns true, without adding it, for the closing `]`.
+    pub fn push(&mut self, c: char) -> bool {
+        match self.mode {
+            ScanMode::Markup => match c {
+                ']' => return true,
+                '"' | '\'' => self.mode = ScanMode::Quoted(c),
+                '-' if self.text.ends_with("<!-") => self.mode = ScanMode::Comment,
+                '?' if self.text.ends_with('<') => self.mode = ScanMode::Pi,
+                _ => (),
+            },
+            ScanMode::Quoted(quote) if c == quote => self.mode = ScanMode::Markup,
+            ScanMode::Comment if c == '>' && self.text.ends_with("--") => {
+                self.mode = ScanMode::Markup
+            },
+            ScanMode::Pi if c == '>' && self.text.ends_with('?') => self.mode = ScanMode::Markup,
+            _ => (),
+        }
+        self.text.push_char(c);
+        false
+    }
+
+    pub fn take_text(&mut self) -> StrTendril {
+        self.mode = ScanMode::Markup;
+        std::mem::replace(&mut self.text, StrTendril::new())
+    }
+}
+
+/// The `NameStartChar` production of XML 1.0.
+pub fn is_name_start_char(c: char) -> bool {
+    match c {
+        ':' | 'A'..='Z' | '_' | 'a'..='z' => true,
+        _ => matches!(c as u32,
+            0xC0..=0xD6 | 0xD8..=0xF6 | 0xF8..=0x2FF | 0x370..=0x37D | 0x37F..=0x1FFF |
+            0x200C..=0x200D | 0x2070..=0x218F | 0x2C00..=0x2FEF | 0x3001..=0xD7FF |
+            0xF900..=0xFDCF | 0xFDF0..=0xFFFD | 0x10000..=0xEFFFF),
+    }
+}
+
+/// The `NameChar` production of XML 1.0.
+pub fn is_name_char(c: char) -> bool {
+    is_name_start_char(c) ||
+        match c {
+            '-' | '.' | '0'..='9' | '\u{B7}' => true,
+            _ => matches!(c as u32, 0x300..=0x36F | 0x203F..=0x2040),
+        }
+}
+
+/// The `Char` production of XML 1.0.
+pub fn is_xml_char(c: char) -> bool {
+    matches!(c as u32, 0x9 | 0xA | 0xD | 0x20..=0xD7FF | 0xE000..=0xFFFD | 0x10000..=0x10FFFF)
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+/// Parse the declarations of an internal subset. Malformed declarations are skipped and reported
+/// in `errors`. Parameter entity references between declarations are reported and ignored.
+pub fn parse_internal_subset(text: &str, errors: &mut Vec<Cow<'static, str>>) -> Vec<MarkupDecl> {
+    let mut parser = Parser {
+        text,
+        pos: 0,
+        errors,
+    };
+    let mut decls = vec![];
+    loop {
+        parser.skip_whitespace();
+        let rest = parser.rest();
+        if rest.is_empty() {
+            break;
+        }
+        let decl = if rest.starts_with("<!--") {
+            parser.skip_past("-->", "Unterminated comment in internal subset");
+            continue;
+        } else if rest.starts_with("<?") {
+            parser.skip_past(
+                "?>",
+                "Unterminated processing instruction in internal subset",
+            );
+            continue;
+        } else if rest.starts_with('%') {
+            parser.error(Borrowed("Parameter entity references are not supported"));
+            parser.skip_past(";", "Unterminated parameter entity reference");
+            continue;
+        } else if parser.eat("<!ENTITY") {
+            parser.entity_decl().map(MarkupDecl::Entity)
+        } else if parser.eat("<!ELEMENT") {
+            parser.element_decl().map(MarkupDecl::Element)
+        } else if parser.eat("<!ATTLIST") {
+            parser.attlist_decl().map(MarkupDecl::AttList)
+        } else if parser.eat("<!NOTATION") {
+            parser.notation_decl().map(MarkupDecl::Notation)
+        } else {
+            parser.error(Borrowed("Bad markup declaration in internal subset"));
+            None
+        };
+        match decl {
+            Some(decl) => decls.push(decl),
+            None => parser.skip_past(">", "Unterminated markup declaration"),
+        }
+    }
+    decls
+}
+
+struct Parser<'a, 'e> {
+    text: &'a str,
+    pos: usize,
+    errors: &'e mut Vec<Cow<'static, str>>,
+}
+
+impl<'a, 'e> Parser<'a, 'e> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn error(&mut self, msg: Cow<'static, str>) {
+        self.errors.push(msg);
+    }
+
+    fn eat(&mut self, pat: &str) -> bool {
+        if self.rest().starts_with(pat) {
+            self.pos += pat.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_past(&mut self, pat: &str, error: &'static str) {
+        match self.rest().find(pat) {
+            Some(i) => self.pos += i + pat.len(),
+            None => {
+                self.error(Borrowed(error));
+                self.pos = self.text.len();
+            },
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches(is_whitespace).len();
+        self.pos > start
+    }
+
+    fn require_whitespace(&mut self) -> Option<()> {
+        if self.skip_whitespace() {
+            Some(())
+        } else {
+            self.error(Borrowed("Missing whitespace in markup declaration"));
+            None
+        }
+    }
+
+    fn name(&mut self) -> Option<StrTendril> {
+        let rest = self.rest();
+        if !rest.starts_with(is_name_start_char) {
+            self.error(Borrowed("Missing name in markup declaration"));
+            return None;
+        }
+        let len = rest.len() - rest.trim_start_matches(is_name_char).len();
+        self.pos += len;
+        Some(StrTendril::from_slice(&rest[..len]))
+    }
+
+    /// A quoted literal, without its quotes.
+    fn literal(&mut self) -> Option<&'a str> {
+        let rest = self.rest();
+        let quote = match rest.chars().next() {
+            Some(c @ '"') | Some(c @ '\'') => c,
+            _ => {
+                self.error(Borrowed("Missing quoted literal in markup declaration"));
+                return None;
+            },
+        };
+        match rest[1..].find(quote) {
+            Some(end) => {
+                self.pos += end + 2;
+                Some(&rest[1..end + 1])
+            },
+            None => {
+                self.error(Borrowed("Unterminated literal in markup declaration"));
+                None
+            },
+        }
+    }
+
+    fn end_decl(&mut self) -> Option<()> {
+        self.skip_whitespace();
+        if self.eat(">") {
+            Some(())
+        } else {
+            self.error(Borrowed(
+                "Unexpected text at the end of a markup declaration",
+            ));
+            None
+        }
+    }
+
+    /// `SYSTEM "system"` or `PUBLIC "public" "system"`. The system literal is optional after a
+    /// public one when `public_only` is allowed, as in notation declarations.
+    fn external_id(
+        &mut self,
+        public_only: bool,
+    ) -> Option<(Option<StrTendril>, Option<StrTendril>)> {
+        if self.eat("SYSTEM") {
+            self.require_whitespace()?;
+            let system = self.literal()?;
+            Some((None, Some(StrTendril::from_slice(system))))
+        } else if self.eat("PUBLIC") {
+            self.require_whitespace()?;
+            let public = StrTendril::from_slice(self.literal()?);
+            let had_whitespace = self.skip_whitespace();
+            if public_only && !self.rest().starts_with(&['"', '\''][..]) {
+                return Some((Some(public), None));
+            }
+            if !had_whitespace {
+                self.error(Borrowed("Missing whitespace in markup declaration"));
+                return None;
+            }
+            let system = self.literal()?;
+            Some((Some(public), Some(StrTendril::from_slice(system))))
+        } else {
+            self.error(Borrowed("Expected SYSTEM or PUBLIC in markup declaration"));
+            None
+        }
+    }
+
+    fn entity_decl(&mut self) -> Option<EntityDecl> {
+        self.require_whitespace()?;
+        let parameter = self.eat("%");
+        if parameter {
+            self.require_whitespace()?;
+        }
+        let name = self.name()?;
+        self.require_whitespace()?;
+        let value = if self.rest().starts_with(&['"', '\''][..]) {
+            let literal = self.literal()?;
+            EntityValue::Internal(self.replacement_text(literal))
+        } else {
+            let (public_id, system_id) = self.external_id(false)?;
+            let had_whitespace = self.skip_whitespace();
+            let notation = if had_whitespace && self.eat("NDATA") {
+                if parameter {
+                    self.error(Borrowed("Parameter entities can't be unparsed"));
+                }
+                self.require_whitespace()?;
+                Some(self.name()?)
+            } else {
+                None
+            };
+            EntityValue::External {
+                public_id,
+                system_id: system_id.unwrap_or_default(),
+                notation,
+            }
+        };
+        self.end_decl()?;
+        Some(EntityDecl {
+            name,
+            parameter,
+            value,
+        })
+    }
+
+    /// Expand the character references of an entity value literal.
+    fn replacement_text(&mut self, literal: &str) -> StrTendril {
+        let mut text = StrTendril::new();
+        let mut rest = literal;
+        while let Some(i) = rest.find(&['&', '%'][..]) {
+            text.push_slice(&rest[..i]);
+            rest = &rest[i..];
+            if rest.starts_with('%') {
+                self.error(Borrowed("Parameter entity references are not supported"));
+            }
+            let end = match rest.find(';') {
+                Some(end) if rest.starts_with("&#") => end,
+                _ => {
+                    text.push_slice(&rest[..1]);
+                    rest = &rest[1..];
+                    continue;
+                },
+            };
+            match parse_char_ref(&rest[2..end]) {
+                Some(c) => text.push_char(c),
+                None => {
+                    self.error(Borrowed("Invalid character reference in entity value"));
+                    text.push_slice(&rest[..end + 1]);
+                },
+            }
+            rest = &rest[end + 1..];
+        }
+        text.push_slice(rest);
+        text
+    }
+
+    fn element_decl(&mut self) -> Option<ElementDecl> {
+        self.require_whitespace()?;
+        let name = self.name()?;
+        self.require_whitespace()?;
+        let rest = self.rest();
+        let len = match rest.find('>') {
+            Some(len) => len,
+            None => {
+                self.error(Borrowed("Unterminated markup declaration"));
+                return None;
+            },
+        };
+        let content_spec = rest[..len].trim_end_matches(is_whitespace);
+        if content_spec.is_empty() {
+            self.error(Borrowed(
+                "Missing content specification in element declaration",
+            ));
+            return None;
+        }
+        self.pos += len + 1;
+        Some(ElementDecl {
+            name,
+            content_spec: StrTendril::from_slice(content_spec),
+        })
+    }
+
+    fn attlist_decl(&mut self) -> Option<AttListDecl> {
+        self.require_whitespace()?;
+        let element = self.name()?;
+        let mut attrs = vec![];
+        loop {
+            let had_whitespace = self.skip_whitespace();
+            if self.eat(">") {
+                break;
+            }
+            if !had_whitespace {
+                self.error(Borrowed("Missing whitespace in markup declaration"));
+                return None;
+            }
+            attrs.push(self.att_def()?);
+        }
+        Some(AttListDecl { element, attrs })
+    }
+
+    fn att_def(&mut self) -> Option<AttDef> {
+        let name = self.name()?;
+        self.require_whitespace()?;
+        let start = self.pos;
+        if self.eat("NOTATION") {
+            self.require_whitespace()?;
+        }
+        if self.rest().starts_with('(') {
+            self.skip_past(")", "Unterminated enumeration in attribute definition");
+        } else {
+            self.name()?;
+        }
+        let att_type = StrTendril::from_slice(&self.text[start..self.pos]);
+        self.require_whitespace()?;
+        let default = if self.eat("#REQUIRED") {
+            AttDefault::Required
+        } else if self.eat("#IMPLIED") {
+            AttDefault::Implied
+        } else if self.eat("#FIXED") {
+            self.require_whitespace()?;
+            AttDefault::Fixed(StrTendril::from_slice(self.literal()?))
+        } else {
+            AttDefault::Value(StrTendril::from_slice(self.literal()?))
+        };
+        Some(AttDef {
+            name,
+            att_type,
+            default,
+        })
+    }
+
+    fn notation_decl(&mut self) -> Option<NotationDecl> {
+        self.require_whitespace()?;
+        let name = self.name()?;
+        self.require_whitespace()?;
+        let (public_id, system_id) = self.external_id(true)?;
+        self.end_decl()?;
+        Some(NotationDecl {
+            name,
+            public_id,
+            system_id,
+        })
+    }
+}
+
+/// The character of a numeric character reference, given the text between `&#` and `;`, or
+/// `None` if it isn't a `Char` that XML allows in a document.
+pub fn parse_char_ref(digits: &str) -> Option<char> {
+    let (digits, radix) = if digits.starts_with('x') {
+        (&digits[1..], 16)
+    } else {
+        (digits, 10)
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    match u32::from_str_radix(digits, radix).ok().and_then(from_u32) {
+        Some(c) if is_xml_char(c) => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_char_ref, parse_internal_subset};
+    use crate::tendril::SliceExt;
+    use crate::tokenizer::interface::*;
+
+    fn parse(text: &str) -> (Vec<MarkupDecl>, usize) {
+        let mut errors = vec![];
+        let decls = parse_internal_subset(text, &mut errors);
+        (decls, errors.len())
+    }
+
+    #[test]
+    fn declarations() {
+        let (decls, errors) = parse(
+            "<!ELEMENT doc (#PCDATA|b)* >
+             <!ATTLIST doc id ID #REQUIRED kind (a|b) 'a' v CDATA #FIXED \"1\" n NMTOKEN #IMPLIED>
+             <!-- <!ENTITY commented 'x'> -->
+             <?pi ]> ?>
+             <!ENTITY e 'a&#38;b&#x3C;&e2;'>
+             <!ENTITY % p \"<!ELEMENT x ANY>\">
+             <!ENTITY ext SYSTEM 'ext.xml'>
+             <!ENTITY img PUBLIC '-//img' 'img.png' NDATA png>
+             <!NOTATION png PUBLIC '-//png'>",
+        );
+        assert_eq!(errors, 0);
+        assert_eq!(
+            decls,
+            vec![
+                MarkupDecl::Element(ElementDecl {
+                    name: "doc".to_tendril(),
+                    content_spec: "(#PCDATA|b)*".to_tendril(),
+                }),
+                MarkupDecl::AttList(AttListDecl {
+                    element: "doc".to_tendril(),
+                    attrs: vec![
+                        AttDef {
+                            name: "id".to_tendril(),
+                            att_type: "ID".to_tendril(),
+                            default: AttDefault::Required,
+                        },
+                        AttDef {
+                            name: "kind".to_tendril(),
+                            att_type: "(a|b)".to_tendril(),
+                            default: AttDefault::Value("a".to_tendril()),
+                        },
+                        AttDef {
+                            name: "v".to_tendril(),
+                            att_type: "CDATA".to_tendril(),
+                            default: AttDefault::Fixed("1".to_tendril()),
+                        },
+                        AttDef {
+                            name: "n".to_tendril(),
+                            att_type: "NMTOKEN".to_tendril(),
+                            default: AttDefault::Implied,
+                        },
+                    ],
+                }),
+                MarkupDecl::Entity(EntityDecl {
+                    name: "e".to_tendril(),
+                    parameter: false,
+                    value: EntityValue::Internal("a&b<&e2;".to_tendril()),
+                }),
+                MarkupDecl::Entity(EntityDecl {
+                    name: "p".to_tendril(),
+                    parameter: true,
+                    value: EntityValue::Internal("<!ELEMENT x ANY>".to_tendril()),
+                }),
+                MarkupDecl::Entity(EntityDecl {
+                    name: "ext".to_tendril(),
+                    parameter: false,
+                    value: EntityValue::External {
+                        public_id: None,
+                        system_id: "ext.xml".to_tendril(),
+                        notation: None,
+                    },
+                }),
+                MarkupDecl::Entity(EntityDecl {
+                    name: "img".to_tendril(),
+                    parameter: false,
+                    value: EntityValue::External {
+                        public_id: Some("-//img".to_tendril()),
+                        system_id: "img.png".to_tendril(),
+                        notation: Some("png".to_tendril()),
+                    },
+                }),
+                MarkupDecl::Notation(NotationDecl {
+                    name: "png".to_tendril(),
+                    public_id: Some("-//png".to_tendril()),
+                    system_id: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_declarations_are_skipped() {
+        let (decls, errors) =
+            parse("<!ENTITY broken> <!BOGUS x> %pe; <!ENTITY ok 'fine'> <!ENTITY missing \"value>");
+        assert_eq!(errors, 4);
+        assert_eq!(
+            decls,
+            vec![MarkupDecl::Entity(EntityDecl {
+                name: "ok".to_tendril(),
+                parameter: false,
+                value: EntityValue::Internal("fine".to_tendril()),
+            })]
+        );
+    }
+
+    #[test]
+    fn names_and_char_refs_are_checked() {
+        let (decls, errors) =
+            parse("<!ENTITY 1st 'x'> <!ENTITY -e 'x'> <!ENTITY e 'a&#xFFFE;&#1;&#x9;b'>");
+        assert_eq!(errors, 4);
+        assert_eq!(
+            decls,
+            vec![MarkupDecl::Entity(EntityDecl {
+                name: "e".to_tendril(),
+                parameter: false,
+                value: EntityValue::Internal("a&#xFFFE;&#1;\tb".to_tendril()),
+            })]
+        );
+
+        assert_eq!(parse_char_ref("65"), Some('A'));
+        assert_eq!(parse_char_ref("x1F600"), Some('\u{1F600}'));
+        for digits in ["0", "8", "x1F", "xD800", "xFFFF", "x110000", "x", "12a"].iter() {
+            assert_eq!(parse_char_ref(digits), None, "{}", digits);
+        }
+    }
+}
@@ -14,9 +14,10 @@ use crate::{Attribute, QualName};
 
 pub use self::TagKind::{EmptyTag, EndTag, ShortTag, StartTag};
 pub use self::Token::{CharacterTokens, EOFToken, NullCharacterToken, ParseError};
-pub use self::Token::{CommentToken, DoctypeToken, PIToken, TagToken};
+pub use self::Token::{CDataToken, CommentToken, DoctypeToken, EntityRefToken, PIToken, TagToken};
 
 use super::states;
+use super::XmlTokenizerOpts;
 
 /// Tag kind denotes which kind of tag did we encounter.
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
@@ -62,8 +63,9 @@ impl Tag {
 
 /// A `DOCTYPE` token.
 /// Doctype token in XML5 is rather limited for reasons, such as:
-/// security and simplicity. XML5 only supports declaring DTD with
-/// name, public identifier and system identifier
+/// security and simplicity. XML5 supports declaring DTD with
+/// name, public identifier, system identifier and the declarations
+/// of an internal subset. The external subset is never loaded.
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Doctype {
     /// Name of DOCTYPE declared
@@ -72,6 +74,8 @@ pub struct Doctype {
     pub public_id: Option<StrTendril>,
     /// System identifier of this DOCTYPE.
     pub system_id: Option<StrTendril>,
+    /// Declarations of the internal subset, in document order.
+    pub internal_subset: Vec<MarkupDecl>,
 }
 
 impl Doctype {
@@ -81,10 +85,105 @@ impl Doctype {
             name: None,
             public_id: None,
             system_id: None,
+            internal_subset: vec![],
         }
     }
 }
 
+/// A markup declaration of a DOCTYPE's internal subset.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum MarkupDecl {
+    /// `<!ELEMENT name contentspec>`
+    Element(ElementDecl),
+    /// `<!ATTLIST element attdefs>`
+    AttList(AttListDecl),
+    /// `<!ENTITY name value>` or `<!ENTITY % name value>`
+    Entity(EntityDecl),
+    /// `<!NOTATION name id>`
+    Notation(NotationDecl),
+}
+
+/// An element type declaration.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ElementDecl {
+    /// Name of the element type.
+    pub name: StrTendril,
+    /// Content specification as written, e.g. `EMPTY` or `(#PCDATA|b)*`.
+    pub content_spec: StrTendril,
+}
+
+/// An attribute-list declaration.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct AttListDecl {
+    /// Name of the element type the attributes belong to.
+    pub element: StrTendril,
+    /// The attribute definitions.
+    pub attrs: Vec<AttDef>,
+}
+
+/// An attribute definition of an attribute-list declaration.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct AttDef {
+    /// Name of the attribute.
+    pub name: StrTendril,
+    /// Attribute type as written, e.g. `CDATA`, `ID` or `(a|b)`.
+    pub att_type: StrTendril,
+    /// Default declaration.
+    pub default: AttDefault,
+}
+
+/// The default declaration of an attribute definition.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum AttDefault {
+    /// `#REQUIRED`
+    Required,
+    /// `#IMPLIED`
+    Implied,
+    /// `#FIXED "value"`, with the value as written.
+    Fixed(StrTendril),
+    /// `"value"`, with the value as written.
+    Value(StrTendril),
+}
+
+/// An entity declaration.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct EntityDecl {
+    /// Name of the entity.
+    pub name: StrTendril,
+    /// Is this a parameter entity (`<!ENTITY % name ...>`)?
+    pub parameter: bool,
+    /// The entity's value.
+    pub value: EntityValue,
+}
+
+/// The value of an entity declaration.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum EntityValue {
+    /// An internal entity's replacement text. Character references are
+    /// expanded; entity references are kept as written.
+    Internal(StrTendril),
+    /// An external entity, which is never loaded.
+    External {
+        /// Public identifier, if any.
+        public_id: Option<StrTendril>,
+        /// System identifier.
+        system_id: StrTendril,
+        /// Notation of an unparsed entity (`NDATA name`), if any.
+        notation: Option<StrTendril>,
+    },
+}
+
+/// A notation declaration.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct NotationDecl {
+    /// Name of the notation.
+    pub name: StrTendril,
+    /// Public identifier, if any.
+    pub public_id: Option<StrTendril>,
+    /// System identifier, if any.
+    pub system_id: Option<StrTendril>,
+}
+
 /// A ProcessingInstruction token.
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Pi {
@@ -109,6 +208,12 @@ pub enum Token {
     CommentToken(StrTendril),
     /// Token that represents a series of characters.
     CharacterTokens(StrTendril),
+    /// The contents of a CDATA section. Only emitted if
+    /// `XmlTokenizerOpts::cdata_tokens` is set.
+    CDataToken(StrTendril),
+    /// Reference to a general entity declared in the internal subset,
+    /// e.g. `&name;`, in content.
+    EntityRefToken(StrTendril),
     /// End of File found.
     EOFToken,
     /// NullCharacter encountered.
@@ -131,4 +236,21 @@ pub trait TokenSink {
     fn query_state_change(&mut self) -> Option<states::XmlState> {
         None
     }
+
+    /// The tokenizer will call this for a reference to a general entity
+    /// declared in the internal subset that appears in an attribute value.
+    /// Returns the text to insert in its place, or `None` to keep the
+    /// reference as written. By default references are kept.
+    fn expand_entity_in_attribute(&mut self, _name: &str) -> Option<StrTendril> {
+        None
+    }
+
+    /// In strict mode, the tokenizer calls this before processing each
+    /// token with the line and column of the last character it consumed.
+    fn set_current_position(&mut self, _line: u64, _column: u64) {}
+
+    /// The tokenizer calls this once, when it is created, with its options.
+    /// A sink that tokenizes more text itself, as the tree builder does for
+    /// the replacement text of entities, can then do so in the same way.
+    fn set_tokenizer_opts(&mut self, _opts: &XmlTokenizerOpts) {}
 }
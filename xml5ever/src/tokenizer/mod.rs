@@ -8,13 +8,15 @@
 // except according to those terms.
 
 mod char_ref;
+mod dtd;
 mod interface;
 mod qname;
 pub mod states;
 
-pub use self::interface::{CharacterTokens, EOFToken, NullCharacterToken};
-pub use self::interface::{CommentToken, DoctypeToken, PIToken, TagToken};
-pub use self::interface::{Doctype, Pi};
+pub use self::interface::{CDataToken, CharacterTokens, EOFToken, NullCharacterToken};
+pub use self::interface::{CommentToken, DoctypeToken, EntityRefToken, PIToken, TagToken};
+pub use self::interface::{AttDef, AttDefault, AttListDecl, ElementDecl, EntityDecl};
+pub use self::interface::{Doctype, EntityValue, MarkupDecl, NotationDecl, Pi};
 pub use self::interface::{EmptyTag, EndTag, ShortTag, StartTag};
 pub use self::interface::{ParseError, Tag, TagKind, Token, TokenSink};
 pub use crate::{LocalName, Namespace, Prefix};
@@ -22,14 +24,17 @@ pub use crate::{LocalName, Namespace, Prefix};
 use crate::tendril::StrTendril;
 use crate::{buffer_queue, Attribute, QualName, SmallCharSet};
 use log::debug;
-use mac::{format_if, unwrap_or_return};
+use mac::{_tt_as_expr_hack, format_if, matches, unwrap_or_return};
 use markup5ever::{local_name, namespace_prefix, namespace_url, ns, small_char_set};
 use std::borrow::Cow::{self, Borrowed};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::mem::replace;
+use std::rc::Rc;
 
 use self::buffer_queue::{BufferQueue, FromSet, NotFromSet, SetResult};
 use self::char_ref::{CharRef, CharRefTokenizer};
+use self::dtd::SubsetScanner;
+pub(crate) use self::dtd::{is_name_char, is_name_start_char, is_xml_char, parse_char_ref};
 use self::qname::QualNameTokenizer;
 use self::states::XmlState;
 use self::states::{DoctypeKind, Public, System};
@@ -46,6 +51,15 @@ pub struct XmlTokenizerOpts {
     /// of the stream?  Default: true
     pub discard_bom: bool,
 
+    /// Stop at the first XML 1.0 well-formedness error instead of recovering
+    /// from it. The error is reported as `line:column: reason`, and no tokens
+    /// are emitted after it. Default: false
+    pub strict: bool,
+
+    /// Emit each CDATA section as a `CDataToken` instead of character
+    /// tokens? Default: false
+    pub cdata_tokens: bool,
+
     /// Keep a record of how long we spent in each state?  Printed
     /// when `end()` is called.  Default: false
     pub profile: bool,
@@ -55,6 +69,19 @@ pub struct XmlTokenizerOpts {
     pub initial_state: Option<states::XmlState>,
 }
 
+/// Formats a well-formedness error found in strict mode.
+pub(crate) fn positioned_error(line: u64, column: u64, reason: &str) -> Cow<'static, str> {
+    Cow::Owned(format!("{}:{}: {}", line, column, reason))
+}
+
+// Namespaces in XML 1.0 allow at most one colon in a name, separating two
+// non-empty parts.
+fn is_qname(name: &str) -> bool {
+    let mut parts = name.split(':');
+    let valid_part = |part: Option<&str>| part != Some("");
+    valid_part(parts.next()) && valid_part(parts.next()) && parts.next().is_none()
+}
+
 fn process_qname(tag_name: StrTendril) -> QualName {
     // If tag name can't possibly contain full namespace, skip qualified name
     // parsing altogether. For a tag to have namespace it must look like:
@@ -91,6 +118,8 @@ impl Default for XmlTokenizerOpts {
         XmlTokenizerOpts {
             exact_errors: false,
             discard_bom: true,
+            strict: false,
+            cdata_tokens: false,
             profile: false,
             initial_state: None,
         }
@@ -111,6 +140,25 @@ pub struct XmlTokenizer<Sink> {
     /// completely? This affects whether we will wait for lookahead or not.
     at_eof: bool,
 
+    /// Line and column of the last character consumed. Only tracked in
+    /// strict mode.
+    line: u64,
+    column: u64,
+
+    /// Have we stopped at a well-formedness error in strict mode?
+    halted: bool,
+
+    /// Number of consecutive `]` characters seen in character data, to
+    /// detect `]]>` in strict mode.
+    data_brackets: u8,
+
+    /// Has a quoted attribute value just ended, so that another attribute
+    /// needs whitespace before it?
+    attr_value_quoted: bool,
+
+    /// Has any token been emitted yet?
+    emitted_token: bool,
+
     /// Tokenizer for character references, if we're tokenizing
     /// one at the moment.
     char_ref_tokenizer: Option<Box<CharRefTokenizer>>,
@@ -149,9 +197,19 @@ pub struct XmlTokenizer<Sink> {
 
     current_doctype: Doctype,
 
+    /// Text of the internal subset of the current doctype.
+    subset_scanner: SubsetScanner,
+
+    /// General entities declared in the internal subset, except the predefined ones. References
+    /// to them are emitted as `EntityRefToken`s.
+    entity_names: Rc<HashSet<String>>,
+
     /// Current comment.
     current_comment: StrTendril,
 
+    /// Current CDATA section, if they are emitted as `CDataToken`s.
+    current_cdata: StrTendril,
+
     /// Current processing instruction target.
     current_pi_target: StrTendril,
 
@@ -167,11 +225,13 @@ pub struct XmlTokenizer<Sink> {
 
 impl<Sink: TokenSink> XmlTokenizer<Sink> {
     /// Create a new tokenizer which feeds tokens to a particular `TokenSink`.
-    pub fn new(sink: Sink, opts: XmlTokenizerOpts) -> XmlTokenizer<Sink> {
+    pub fn new(mut sink: Sink, opts: XmlTokenizerOpts) -> XmlTokenizer<Sink> {
         if opts.profile && cfg!(for_c) {
             panic!("Can't profile tokenizer when built as a C library");
         }
 
+        sink.set_tokenizer_opts(&opts);
+
         let state = *opts.initial_state.as_ref().unwrap_or(&states::Data);
         let discard_bom = opts.discard_bom;
         XmlTokenizer {
@@ -180,6 +240,12 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
             state,
             char_ref_tokenizer: None,
             at_eof: false,
+            line: 1,
+            column: 0,
+            halted: false,
+            data_brackets: 0,
+            attr_value_quoted: false,
+            emitted_token: false,
             current_char: '\0',
             reconsume: false,
             ignore_lf: false,
@@ -191,9 +257,12 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
             current_attr_name: StrTendril::new(),
             current_attr_value: StrTendril::new(),
             current_comment: StrTendril::new(),
+            current_cdata: StrTendril::new(),
             current_pi_data: StrTendril::new(),
             current_pi_target: StrTendril::new(),
             current_doctype: Doctype::new(),
+            subset_scanner: SubsetScanner::new(),
+            entity_names: Rc::new(HashSet::new()),
             state_profile: BTreeMap::new(),
             time_in_sink: 0,
         }
@@ -219,6 +288,13 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
     }
 
     fn process_token(&mut self, token: Token) {
+        if self.halted {
+            return;
+        }
+        if self.opts.strict {
+            self.sink.set_current_position(self.line, self.column);
+        }
+        self.emitted_token = true;
         if self.opts.profile {
             let (_, dt) = time!(self.sink.process_token(token));
             self.time_in_sink += dt;
@@ -242,6 +318,19 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
             c = '\n';
         }
 
+        if self.opts.strict {
+            if self.current_char == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+            if !is_xml_char(c) {
+                let msg = format!("Illegal character U+{:04X}", c as u32);
+                self.emit_error(Cow::Owned(msg));
+            }
+        }
+
         // Normalize \x00 into \uFFFD
         if c == '\x00' {
             c = '\u{FFFD}'
@@ -279,7 +368,7 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
         // This means that `FromSet` can contain characters not in the set!
         // It shouldn't matter because the fallback `FromSet` case should
         // always do the same thing as the `NotFromSet` case.
-        if self.opts.exact_errors || self.reconsume || self.ignore_lf {
+        if self.opts.exact_errors || self.opts.strict || self.reconsume || self.ignore_lf {
             return self.get_char(input).map(FromSet);
         }
 
@@ -310,7 +399,12 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
                 }
                 None
             },
-            Some(matched) => Some(matched),
+            Some(matched) => {
+                if matched && self.opts.strict {
+                    self.column += pat.len() as u64;
+                }
+                Some(matched)
+            },
         }
     }
 
@@ -372,7 +466,9 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
     }
 
     fn create_tag(&mut self, kind: TagKind, c: char) {
+        self.check_name_char(true, c);
         self.discard_tag();
+        self.attr_value_quoted = false;
         self.current_tag_name.push_char(c);
         self.current_tag_kind = kind;
     }
@@ -380,6 +476,7 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
     // This method creates a PI token and
     // sets its target to given char
     fn create_pi(&mut self, c: char) {
+        self.check_name_char(true, c);
         self.current_pi_target = StrTendril::new();
         self.current_pi_data = StrTendril::new();
         self.current_pi_target.push_char(c);
@@ -414,6 +511,12 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
 
     fn emit_current_tag(&mut self) {
         self.finish_attribute();
+        if self.opts.strict &&
+            self.current_tag_kind != ShortTag &&
+            !is_qname(&self.current_tag_name)
+        {
+            self.emit_error(Borrowed("Tag name is not a qualified name"));
+        }
 
         let qname = process_qname(replace(&mut self.current_tag_name, StrTendril::new()));
 
@@ -451,6 +554,13 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
 
     // Emits the current Processing Instruction
     fn emit_pi(&mut self) {
+        if self.opts.strict && self.current_pi_target.eq_ignore_ascii_case("xml") {
+            if &*self.current_pi_target != "xml" {
+                self.emit_error(Borrowed("Reserved processing instruction target"));
+            } else if self.emitted_token {
+                self.emit_error(Borrowed("XML declaration not at the start of the document"));
+            }
+        }
         let token = PIToken(Pi {
             target: replace(&mut self.current_pi_target, StrTendril::new()),
             data: replace(&mut self.current_pi_data, StrTendril::new()),
@@ -469,7 +579,88 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
     }
 
     fn emit_error(&mut self, error: Cow<'static, str>) {
-        self.process_token(ParseError(error));
+        if self.opts.strict {
+            let error = positioned_error(self.line, self.column, &error);
+            self.process_token(ParseError(error));
+            self.halted = true;
+        } else {
+            self.process_token(ParseError(error));
+        }
+    }
+
+    // Report an error that XML5 recovers from silently, but that breaks
+    // XML 1.0 well-formedness.
+    fn strict_error(&mut self, error: &'static str) {
+        if self.opts.strict {
+            self.emit_error(Borrowed(error));
+        }
+    }
+
+    fn check_name_char(&mut self, first: bool, c: char) {
+        if self.opts.strict {
+            let valid = if first {
+                is_name_start_char(c)
+            } else {
+                is_name_char(c)
+            };
+            if !valid {
+                let msg = format!("Illegal character {:?} in a name", c);
+                self.emit_error(Cow::Owned(msg));
+            }
+        }
+    }
+
+    fn push_tag(&mut self, c: char) {
+        self.check_name_char(false, c);
+        self.current_tag_name.push_char(c);
+    }
+
+    fn push_attr_name(&mut self, c: char) {
+        self.check_name_char(false, c);
+        self.current_attr_name.push_char(c);
+    }
+
+    fn push_pi_target(&mut self, c: char) {
+        self.check_name_char(false, c);
+        self.current_pi_target.push_char(c);
+    }
+
+    fn check_attr_space(&mut self) {
+        if self.attr_value_quoted {
+            self.attr_value_quoted = false;
+            self.strict_error("Missing whitespace between attributes");
+        }
+    }
+
+    // Character data may not contain `]]>`.
+    fn check_data_char(&mut self, c: char) {
+        if self.opts.strict {
+            match c {
+                ']' => self.data_brackets = self.data_brackets.saturating_add(1),
+                '>' if self.data_brackets >= 2 => {
+                    self.emit_error(Borrowed("']]>' in character data"));
+                },
+                _ => self.data_brackets = 0,
+            }
+        }
+    }
+
+    fn push_cdata(&mut self, c: char) {
+        if self.opts.cdata_tokens {
+            self.current_cdata.push_char(match c {
+                '\0' => '\u{FFFD}',
+                c => c,
+            });
+        } else {
+            self.emit_char(c);
+        }
+    }
+
+    fn emit_current_cdata(&mut self) {
+        if self.opts.cdata_tokens {
+            let cdata = replace(&mut self.current_cdata, StrTendril::new());
+            self.process_token(CDataToken(cdata));
+        }
     }
 
     fn emit_current_comment(&mut self) {
@@ -482,6 +673,23 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
         self.process_token(DoctypeToken(doctype));
     }
 
+    fn finish_internal_subset(&mut self) {
+        let text = self.subset_scanner.take_text();
+        let mut errors = vec![];
+        let decls = dtd::parse_internal_subset(&text, &mut errors);
+        for error in errors {
+            self.emit_error(error);
+        }
+        self.entity_names = Rc::new(entity_names(&decls));
+        self.current_doctype.internal_subset = decls;
+    }
+
+    /// Treat references to these general entities as declared. The tree builder uses this when
+    /// it tokenizes the replacement text of an entity.
+    pub(crate) fn set_entity_names(&mut self, names: Rc<HashSet<String>>) {
+        self.entity_names = names;
+    }
+
     fn doctype_id(&mut self, kind: DoctypeKind) -> &mut Option<StrTendril> {
         match kind {
             Public => &mut self.current_doctype.public_id,
@@ -511,6 +719,10 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
     }
 
     fn unconsume(&mut self, input: &mut BufferQueue, buf: StrTendril) {
+        if self.opts.strict {
+            // These characters will be counted again when they are reconsumed.
+            self.column = self.column.saturating_sub(buf.chars().count() as u64);
+        }
         input.push_front(buf);
     }
 }
@@ -519,29 +731,36 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
 macro_rules! shorthand (
     ( $me:ident : emit $c:expr                     ) => ( $me.emit_char($c);                                   );
     ( $me:ident : create_tag $kind:ident $c:expr   ) => ( $me.create_tag($kind, $c);                           );
-    ( $me:ident : push_tag $c:expr                 ) => ( $me.current_tag_name.push_char($c);                  );
+    ( $me:ident : push_tag $c:expr                 ) => ( $me.push_tag($c);                                    );
     ( $me:ident : discard_tag $input:expr          ) => ( $me.discard_tag($input);                             );
     ( $me:ident : discard_char                     ) => ( $me.discard_char();                                  );
     ( $me:ident : push_temp $c:expr                ) => ( $me.temp_buf.push_char($c);                          );
     ( $me:ident : emit_temp                        ) => ( $me.emit_temp_buf();                                 );
     ( $me:ident : clear_temp                       ) => ( $me.clear_temp_buf();                                );
     ( $me:ident : create_attr $c:expr              ) => ( $me.create_attribute($c);                            );
-    ( $me:ident : push_name $c:expr                ) => ( $me.current_attr_name.push_char($c);                 );
+    ( $me:ident : push_name $c:expr                ) => ( $me.push_attr_name($c);                              );
     ( $me:ident : push_value $c:expr               ) => ( $me.current_attr_value.push_char($c);                );
     ( $me:ident : append_value $c:expr             ) => ( $me.current_attr_value.push_tendril($c);             );
     ( $me:ident : push_comment $c:expr             ) => ( $me.current_comment.push_char($c);                   );
     ( $me:ident : append_comment $c:expr           ) => ( $me.current_comment.push_slice($c);                  );
     ( $me:ident : emit_comment                     ) => ( $me.emit_current_comment();                          );
+    ( $me:ident : push_cdata $c:expr               ) => ( $me.push_cdata($c);                                  );
+    ( $me:ident : emit_cdata                       ) => ( $me.emit_current_cdata();                            );
     ( $me:ident : clear_comment                    ) => ( $me.current_comment.clear();                         );
     ( $me:ident : create_doctype                   ) => ( $me.current_doctype = Doctype::new();                );
     ( $me:ident : push_doctype_name $c:expr        ) => ( option_push(&mut $me.current_doctype.name, $c);      );
     ( $me:ident : push_doctype_id $k:ident $c:expr ) => ( option_push($me.doctype_id($k), $c);                 );
     ( $me:ident : clear_doctype_id $k:ident        ) => ( $me.clear_doctype_id($k);                            );
     ( $me:ident : emit_doctype                     ) => ( $me.emit_current_doctype();                          );
+    ( $me:ident : finish_subset                    ) => ( $me.finish_internal_subset();                        );
     ( $me:ident : error                            ) => ( $me.bad_char_error();                                );
+    ( $me:ident : strict_error $msg:expr           ) => ( $me.strict_error($msg);                              );
+    ( $me:ident : check_data $c:expr               ) => ( $me.check_data_char($c);                             );
+    ( $me:ident : end_quoted_value                 ) => ( $me.attr_value_quoted = true;                        );
+    ( $me:ident : check_attr_space                 ) => ( $me.check_attr_space();                              );
     ( $me:ident : error_eof                        ) => ( $me.bad_eof_error();                                 );
     ( $me:ident : create_pi $c:expr                ) => ( $me.create_pi($c);                                   );
-    ( $me:ident : push_pi_target $c:expr           ) => ( $me.current_pi_target.push_char($c);                 );
+    ( $me:ident : push_pi_target $c:expr           ) => ( $me.push_pi_target($c);                              );
     ( $me:ident : push_pi_data $c:expr             ) => ( $me.current_pi_data.push_char($c);                   );
     ( $me:ident : set_empty_tag                    ) => ( $me.set_empty_tag();                                 );
 );
@@ -641,6 +860,9 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
     // (this just simplifies control flow vs. break / continue).
     #[allow(clippy::never_loop)]
     fn step(&mut self, input: &mut BufferQueue) -> bool {
+        if self.halted {
+            return false;
+        }
         if self.char_ref_tokenizer.is_some() {
             return self.step_char_ref_tokenizer(input);
         }
@@ -654,9 +876,9 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
             //§ data-state
             XmlState::Data => loop {
                 match pop_except_from!(self, input, small_char_set!('\r' '&' '<')) {
-                    FromSet('&') => go!(self: consume_char_ref),
-                    FromSet('<') => go!(self: to TagState),
-                    FromSet(c) => go!(self: emit c),
+                    FromSet('&') => go!(self: check_data '&'; consume_char_ref),
+                    FromSet('<') => go!(self: check_data '<'; to TagState),
+                    FromSet(c) => go!(self: check_data c; emit c),
                     NotFromSet(b) => self.emit_chars(b),
                 }
             },
@@ -814,16 +1036,22 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
                 match get_char!(self, input) {
                     '>' => go!(self: emit_comment; to Data),
                     '!' => go!(self: to CommentEndBang),
-                    '-' => go!(self: push_comment '-'),
-                    _ => go!(self: append_comment "--"; reconsume Comment),
+                    '-' => go!(self: strict_error "'--' in a comment"; push_comment '-'),
+                    _ => {
+                        go!(self: strict_error "'--' in a comment"; append_comment "--"; reconsume Comment)
+                    },
                 }
             },
             //§ comment-end-bang-state
             XmlState::CommentEndBang => loop {
                 match get_char!(self, input) {
-                    '-' => go!(self: append_comment "--!"; to CommentEndDash),
+                    '-' => {
+                        go!(self: strict_error "'--' in a comment"; append_comment "--!"; to CommentEndDash)
+                    },
                     '>' => go!(self: error; emit_comment; to Data),
-                    _ => go!(self: append_comment "--!"; reconsume Comment),
+                    _ => {
+                        go!(self: strict_error "'--' in a comment"; append_comment "--!"; reconsume Comment)
+                    },
                 }
             },
             //§ bogus-comment-state
@@ -837,22 +1065,24 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
             XmlState::Cdata => loop {
                 match get_char!(self, input) {
                     ']' => go!(self: to CdataBracket),
-                    cl => go!(self: emit cl),
+                    cl => go!(self: push_cdata cl),
                 }
             },
             //§ cdata-bracket-state
             XmlState::CdataBracket => loop {
                 match get_char!(self, input) {
                     ']' => go!(self: to CdataEnd),
-                    cl => go!(self: emit ']'; emit cl; to Cdata),
+                    cl => go!(self: push_cdata ']'; push_cdata cl; to Cdata),
                 }
             },
             //§ cdata-end-state
             XmlState::CdataEnd => loop {
                 match get_char!(self, input) {
-                    '>' => go!(self: to Data),
-                    ']' => go!(self: emit ']'),
-                    cl => go!(self: emit ']'; emit ']'; emit cl; to Cdata),
+                    '>' => go!(self: emit_cdata; to Data),
+                    ']' => go!(self: push_cdata ']'),
+                    cl => {
+                        go!(self: push_cdata ']'; push_cdata ']'; push_cdata cl; to Cdata)
+                    },
                 }
             },
             //§ tag-name-state
@@ -868,26 +1098,30 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
             XmlState::TagEmpty => loop {
                 match get_char!(self, input) {
                     '>' => go!(self: emit_empty_tag Data),
-                    _ => go!(self: reconsume TagAttrValueBefore),
+                    _ => {
+                        go!(self: strict_error "'/' not followed by '>' in a tag"; reconsume TagAttrValueBefore)
+                    },
                 }
             },
             //§ tag-attribute-name-before-state
             XmlState::TagAttrNameBefore => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | ' ' => (),
+                    '\t' | '\n' | ' ' => self.attr_value_quoted = false,
                     '>' => go!(self: emit_tag Data),
                     '/' => go!(self: set_empty_tag; to TagEmpty),
                     ':' => go!(self: error),
-                    cl => go!(self: create_attr cl; to TagAttrName),
+                    cl => go!(self: check_attr_space; create_attr cl; to TagAttrName),
                 }
             },
             //§ tag-attribute-name-state
             XmlState::TagAttrName => loop {
                 match get_char!(self, input) {
                     '=' => go!(self: to TagAttrValueBefore),
-                    '>' => go!(self: emit_tag Data),
+                    '>' => go!(self: strict_error "Attribute without a value"; emit_tag Data),
                     '\t' | '\n' | ' ' => go!(self: to TagAttrNameAfter),
-                    '/' => go!(self: set_empty_tag; to TagEmpty),
+                    '/' => {
+                        go!(self: strict_error "Attribute without a value"; set_empty_tag; to TagEmpty)
+                    },
                     cl => go!(self: push_name cl),
                 }
             },
@@ -896,9 +1130,13 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
                 match get_char!(self, input) {
                     '\t' | '\n' | ' ' => (),
                     '=' => go!(self: to TagAttrValueBefore),
-                    '>' => go!(self: emit_tag Data),
-                    '/' => go!(self: set_empty_tag; to TagEmpty),
-                    cl => go!(self: create_attr cl; to TagAttrName),
+                    '>' => go!(self: strict_error "Attribute without a value"; emit_tag Data),
+                    '/' => {
+                        go!(self: strict_error "Attribute without a value"; set_empty_tag; to TagEmpty)
+                    },
+                    cl => {
+                        go!(self: strict_error "Attribute without a value"; create_attr cl; to TagAttrName)
+                    },
                 }
             },
             //§ tag-attribute-value-before-state
@@ -907,32 +1145,45 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
                     '\t' | '\n' | ' ' => (),
                     '"' => go!(self: to TagAttrValue DoubleQuoted),
                     '\'' => go!(self: to TagAttrValue SingleQuoted),
-                    '&' => go!(self: reconsume TagAttrValue(Unquoted)),
-                    '>' => go!(self: emit_tag Data),
-                    cl => go!(self: push_value cl; to TagAttrValue(Unquoted)),
+                    '&' => {
+                        go!(self: strict_error "Unquoted attribute value"; reconsume TagAttrValue(Unquoted))
+                    },
+                    '>' => go!(self: strict_error "Missing attribute value"; emit_tag Data),
+                    cl => {
+                        go!(self: strict_error "Unquoted attribute value"; push_value cl; to TagAttrValue(Unquoted))
+                    },
                 }
             },
             //§ tag-attribute-value-double-quoted-state
             XmlState::TagAttrValue(DoubleQuoted) => loop {
-                match pop_except_from!(self, input, small_char_set!('\n' '"' '&')) {
-                    FromSet('"') => go!(self: to TagAttrNameBefore),
+                match pop_except_from!(self, input, small_char_set!('\t' '\r' '\n' '"' '&')) {
+                    FromSet('"') => go!(self: end_quoted_value; to TagAttrNameBefore),
                     FromSet('&') => go!(self: consume_char_ref '"' ),
+                    // Attribute-value normalization: literal whitespace becomes a space.
+                    FromSet('\t') | FromSet('\n') => go!(self: push_value ' '),
+                    FromSet('<') => {
+                        go!(self: strict_error "'<' in an attribute value"; push_value '<')
+                    },
                     FromSet(c) => go!(self: push_value c),
                     NotFromSet(ref b) => go!(self: append_value b),
                 }
             },
             //§ tag-attribute-value-single-quoted-state
             XmlState::TagAttrValue(SingleQuoted) => loop {
-                match pop_except_from!(self, input, small_char_set!('\n' '\'' '&')) {
-                    FromSet('\'') => go!(self: to TagAttrNameBefore),
+                match pop_except_from!(self, input, small_char_set!('\t' '\r' '\n' '\'' '&')) {
+                    FromSet('\'') => go!(self: end_quoted_value; to TagAttrNameBefore),
                     FromSet('&') => go!(self: consume_char_ref '\''),
+                    FromSet('\t') | FromSet('\n') => go!(self: push_value ' '),
+                    FromSet('<') => {
+                        go!(self: strict_error "'<' in an attribute value"; push_value '<')
+                    },
                     FromSet(c) => go!(self: push_value c),
                     NotFromSet(ref b) => go!(self: append_value b),
                 }
             },
             //§ tag-attribute-value-double-quoted-state
             XmlState::TagAttrValue(Unquoted) => loop {
-                match pop_except_from!(self, input, small_char_set!('\n' '\t' ' ' '&' '>')) {
+                match pop_except_from!(self, input, small_char_set!('\r' '\n' '\t' ' ' '&' '>')) {
                     FromSet('\t') | FromSet('\n') | FromSet(' ') => go!(self: to TagAttrNameBefore),
                     FromSet('&') => go!(self: consume_char_ref),
                     FromSet('>') => go!(self: emit_tag Data),
@@ -962,6 +1213,7 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
                 match get_char!(self, input) {
                     '\t' | '\n' | '\x0C' | ' ' => go!(self: to AfterDoctypeName),
                     '>' => go!(self: emit_doctype; to Data),
+                    '[' => go!(self: to DoctypeInternalSubset),
                     c => go!(self: push_doctype_name (c.to_ascii_lowercase());
                                   to DoctypeName),
                 }
@@ -976,6 +1228,7 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
                     match get_char!(self, input) {
                         '\t' | '\n' | '\x0C' | ' ' => (),
                         '>' => go!(self: emit_doctype; to Data),
+                        '[' => go!(self: to DoctypeInternalSubset),
                         _ => go!(self: error; to BogusDoctype),
                     }
                 }
@@ -1055,6 +1308,7 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
                 match get_char!(self, input) {
                     '\t' | '\n' | '\x0C' | ' ' => (),
                     '>' => go!(self: emit_doctype; to Data),
+                    '[' => go!(self: to DoctypeInternalSubset),
                     _ => go!(self: error; to BogusDoctype),
                 }
             },
@@ -1068,6 +1322,21 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
                     _ => go!(self: error; to BogusDoctype),
                 }
             },
+            //§ doctype-internal-subset-state
+            XmlState::DoctypeInternalSubset => loop {
+                let c = get_char!(self, input);
+                if self.subset_scanner.push(c) {
+                    go!(self: finish_subset; to AfterDoctypeInternalSubset);
+                }
+            },
+            //§ after-doctype-internal-subset-state
+            XmlState::AfterDoctypeInternalSubset => loop {
+                match get_char!(self, input) {
+                    '\t' | '\n' | '\x0C' | ' ' => (),
+                    '>' => go!(self: emit_doctype; to Data),
+                    _ => go!(self: error; to BogusDoctype),
+                }
+            },
             //§ bogus_doctype_state
             XmlState::BogusDoctype => loop {
                 match get_char!(self, input) {
@@ -1080,6 +1349,11 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
 
     /// Indicate that we have reached the end of the input.
     pub fn end(&mut self) {
+        if self.halted {
+            self.sink.end();
+            return;
+        }
+
         // Handle EOF in the char ref sub-tokenizer, if there is one.
         // Do this first because it might un-consume stuff.
         let mut input = BufferQueue::new();
@@ -1150,7 +1424,7 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
             XmlState::EndTagState => go!(self: error_eof; emit '<'; emit '/'; to Data),
             XmlState::TagEmpty => go!(self: error_eof; to TagAttrNameBefore),
             XmlState::Cdata | XmlState::CdataBracket | XmlState::CdataEnd => {
-                go!(self: error_eof; to Data)
+                go!(self: error_eof; emit_cdata; to Data)
             },
             XmlState::Pi => go!(self: error_eof; to BogusComment),
             XmlState::PiTargetAfter | XmlState::PiAfter => go!(self: reconsume PiData),
@@ -1173,8 +1447,10 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
             XmlState::AfterDoctypeIdentifier(_) |
             XmlState::DoctypeIdentifierSingleQuoted(_) |
             XmlState::DoctypeIdentifierDoubleQuoted(_) |
-            XmlState::BetweenDoctypePublicAndSystemIdentifiers => {
-                go!(self: error_eof; emit_doctype; to Data)
+            XmlState::BetweenDoctypePublicAndSystemIdentifiers |
+            XmlState::AfterDoctypeInternalSubset => go!(self: error_eof; emit_doctype; to Data),
+            XmlState::DoctypeInternalSubset => {
+                go!(self: error_eof; finish_subset; emit_doctype; to Data)
             },
             XmlState::BogusDoctype => go!(self: emit_doctype; to Data),
             XmlState::BogusComment => go!(self: emit_comment; to Data),
@@ -1185,9 +1461,30 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
         let CharRef {
             mut chars,
             mut num_chars,
+            entity,
         } = char_ref;
 
+        if let Some(name) = entity {
+            match self.state {
+                states::Data => self.process_token(EntityRefToken(name)),
+                states::TagAttrValue(_) => match self.sink.expand_entity_in_attribute(&name) {
+                    Some(text) => self.current_attr_value.push_tendril(&text),
+                    None => {
+                        self.current_attr_value.push_char('&');
+                        self.current_attr_value.push_tendril(&name);
+                        self.current_attr_value.push_char(';');
+                    },
+                },
+                _ => panic!(
+                    "state {:?} should not be reachable in process_char_ref",
+                    self.state
+                ),
+            }
+            return;
+        }
+
         if num_chars == 0 {
+            self.strict_error("'&' does not start a reference");
             chars[0] = '&';
             num_chars = 1;
         }
@@ -1235,11 +1532,21 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
         // FIXME: linear time search, do we care?
         let dup = {
             let name = &self.current_attr_name[..];
-            self.current_tag_attrs
-                .iter()
-                .any(|a| &*a.name.local == name)
+            self.current_tag_attrs.iter().any(|a| match a.name.prefix {
+                Some(ref prefix) => {
+                    name.len() == prefix.len() + 1 + a.name.local.len() &&
+                        name.starts_with(&**prefix) &&
+                        name[prefix.len()..].starts_with(':') &&
+                        name.ends_with(&*a.name.local)
+                },
+                None => &*a.name.local == name,
+            })
         };
 
+        if self.opts.strict && !is_qname(&self.current_attr_name) {
+            self.emit_error(Borrowed("Attribute name is not a qualified name"));
+        }
+
         if dup {
             self.emit_error(Borrowed("Duplicate attribute"));
             self.current_attr_name.clear();
@@ -1263,18 +1570,203 @@ impl<Sink: TokenSink> XmlTokenizer<Sink> {
 
     fn create_attribute(&mut self, c: char) {
         self.finish_attribute();
+        self.check_name_char(true, c);
 
         self.current_attr_name.push_char(c);
     }
 }
 
+/// The names of the general entities declared in an internal subset, except the predefined
+/// ones, which are always resolved as character references.
+pub(crate) fn entity_names(decls: &[MarkupDecl]) -> HashSet<String> {
+    decls
+        .iter()
+        .filter_map(|decl| match *decl {
+            MarkupDecl::Entity(EntityDecl {
+                ref name,
+                parameter: false,
+                ..
+            }) if !matches!(&**name, "amp" | "lt" | "gt" | "quot" | "apos") => {
+                Some(String::from(&**name))
+            },
+            _ => None,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
 
+    use mac::{_tt_as_expr_hack, matches};
+
     use super::process_qname;
-    use crate::tendril::SliceExt;
+    use super::{BufferQueue, Token, TokenSink, XmlTokenizer, XmlTokenizerOpts};
+    use super::{CDataToken, CharacterTokens, DoctypeToken, EntityRefToken, MarkupDecl, TagToken};
+    use crate::tendril::{SliceExt, StrTendril};
     use crate::{LocalName, Prefix};
 
+    struct Tokens {
+        tokens: Vec<Token>,
+        text: StrTendril,
+    }
+
+    impl Tokens {
+        fn flush(&mut self) {
+            if !self.text.is_empty() {
+                let text = std::mem::replace(&mut self.text, StrTendril::new());
+                self.tokens.push(CharacterTokens(text));
+            }
+        }
+    }
+
+    impl TokenSink for Tokens {
+        fn process_token(&mut self, token: Token) {
+            match token {
+                CharacterTokens(text) => self.text.push_tendril(&text),
+                Token::ParseError(_) | Token::EOFToken => (),
+                token => {
+                    self.flush();
+                    self.tokens.push(token);
+                },
+            }
+        }
+
+        fn expand_entity_in_attribute(&mut self, name: &str) -> Option<StrTendril> {
+            Some(format!("[{}]", name).to_tendril())
+        }
+    }
+
+    fn tokenize(input: &str) -> Vec<Token> {
+        tokenize_with(input, Default::default())
+    }
+
+    fn tokenize_with(input: &str, opts: XmlTokenizerOpts) -> Vec<Token> {
+        let mut tokenizer = XmlTokenizer::new(
+            Tokens {
+                tokens: vec![],
+                text: StrTendril::new(),
+            },
+            opts,
+        );
+        let mut buffer = BufferQueue::new();
+        // One character at a time, to exercise the incremental paths.
+        for c in input.chars() {
+            buffer.push_back(StrTendril::from_char(c));
+            tokenizer.feed(&mut buffer);
+        }
+        tokenizer.end();
+        tokenizer.sink.flush();
+        tokenizer.sink.tokens
+    }
+
+    #[test]
+    fn internal_subset() {
+        let tokens = tokenize(
+            "<!DOCTYPE doc SYSTEM 'doc.dtd' [
+               <!ENTITY a ']>'> <!-- ] --> <?pi ]?>
+               <!ENTITY notice 'n'> <!ENTITY lt '&#38;#60;'>
+             ]><doc x='&a;&lt;'>&a;&notice;&not;&lt;&undeclared;</doc>",
+        );
+        let doctype = match tokens[0] {
+            DoctypeToken(ref doctype) => doctype,
+            ref token => panic!("expected a doctype, got {:?}", token),
+        };
+        assert_eq!(doctype.system_id, Some("doc.dtd".to_tendril()));
+        assert_eq!(doctype.internal_subset.len(), 3);
+        assert!(doctype
+            .internal_subset
+            .iter()
+            .all(|decl| matches!(decl, MarkupDecl::Entity(_))));
+
+        match tokens[1] {
+            TagToken(ref tag) => assert_eq!(&*tag.attrs[0].value, "[a]<"),
+            ref token => panic!("expected a tag, got {:?}", token),
+        }
+        assert_eq!(
+            tokens[2..5],
+            [
+                EntityRefToken("a".to_tendril()),
+                EntityRefToken("notice".to_tendril()),
+                CharacterTokens("&not;<&undeclared;".to_tendril()),
+            ]
+        );
+    }
+
+    fn attributes(input: &str) -> Vec<(String, String)> {
+        match tokenize(input)[0] {
+            TagToken(ref tag) => tag
+                .attrs
+                .iter()
+                .map(|attr| {
+                    let name = match attr.name.prefix {
+                        Some(ref prefix) => format!("{}:{}", prefix, attr.name.local),
+                        None => attr.name.local.to_string(),
+                    };
+                    (name, attr.value.to_string())
+                })
+                .collect(),
+            ref token => panic!("expected a tag, got {:?}", token),
+        }
+    }
+
+    #[test]
+    fn attribute_value_normalization() {
+        assert_eq!(
+            attributes("<a x='1\t2\n3\r\n4\r5' y=\"6\t7\r\n8\" z=9\r\nw=1 />"),
+            [
+                ("x".to_owned(), "1 2 3 4 5".to_owned()),
+                ("y".to_owned(), "6 7 8".to_owned()),
+                ("z".to_owned(), "9".to_owned()),
+                ("w".to_owned(), "1".to_owned()),
+            ]
+        );
+        // Character references to whitespace are kept.
+        assert_eq!(
+            attributes("<a x='1&#9;2&#10;3'/>"),
+            [("x".to_owned(), "1\t2\n3".to_owned())]
+        );
+    }
+
+    #[test]
+    fn duplicate_attributes() {
+        // Only the first of two attributes with the same qualified name is kept.
+        assert_eq!(
+            attributes("<a p:x='1' x='2' q:x='3' p:x='4' x='5' p:xx='6' pp:x='7'/>"),
+            [
+                ("p:x".to_owned(), "1".to_owned()),
+                ("x".to_owned(), "2".to_owned()),
+                ("q:x".to_owned(), "3".to_owned()),
+                ("p:xx".to_owned(), "6".to_owned()),
+                ("pp:x".to_owned(), "7".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn cdata() {
+        let input = "<a>x<![CDATA[<b>&amp;]]]]><![CDATA[>]]><![CDATA[]]>y<![CDATA[z";
+        assert_eq!(
+            tokenize(input)[1..],
+            [CharacterTokens("x<b>&amp;]]>yz".to_tendril())]
+        );
+
+        let opts = XmlTokenizerOpts {
+            cdata_tokens: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            tokenize_with(input, opts)[1..],
+            [
+                CharacterTokens("x".to_tendril()),
+                CDataToken("<b>&amp;]]".to_tendril()),
+                CDataToken(">".to_tendril()),
+                CDataToken("".to_tendril()),
+                CharacterTokens("y".to_tendril()),
+                CDataToken("z".to_tendril()),
+            ]
+        );
+    }
+
     #[test]
     fn simple_namespace() {
         let qname = process_qname("prefix:local".to_tendril());
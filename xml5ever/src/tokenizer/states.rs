@@ -67,6 +67,8 @@ pub enum XmlState {
     DoctypeIdentifierSingleQuoted(DoctypeKind),
     AfterDoctypeIdentifier(DoctypeKind),
     BetweenDoctypePublicAndSystemIdentifiers,
+    DoctypeInternalSubset,
+    AfterDoctypeInternalSubset,
     BogusDoctype,
     BogusComment,
     Quiescent,
@@ -8,25 +8,30 @@
 // except according to those terms.
 
 mod types;
+pub(crate) mod xml_decl;
 
 use log::{debug, warn};
 use mac::{_tt_as_expr_hack, matches, unwrap_or_return};
 use markup5ever::{local_name, namespace_prefix, namespace_url, ns};
 use std::borrow::Cow;
 use std::borrow::Cow::Borrowed;
+use std::cmp;
 use std::collections::btree_map::Iter;
-use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt::{Debug, Error, Formatter};
 use std::mem;
+use std::rc::Rc;
 use std::result::Result;
 
 pub use self::interface::{NextParserState, NodeOrText, Tracer, TreeSink};
 use self::types::*;
 use crate::interface::{self, create_element, AppendNode, Attribute, QualName};
-use crate::interface::{AppendText, ExpandedName};
+use crate::interface::{AppendText, ExpandedName, XmlScope};
+use crate::buffer_queue::BufferQueue;
 use crate::tokenizer::states::Quiescent;
-use crate::tokenizer::{self, EndTag, StartTag, Tag, TokenSink};
-use crate::tokenizer::{Doctype, EmptyTag, Pi, ShortTag};
+use crate::tokenizer::{self, EndTag, StartTag, Tag, TokenSink, XmlTokenizer, XmlTokenizerOpts};
+use crate::tokenizer::{Doctype, EmptyTag, EntityDecl, EntityValue, MarkupDecl, Pi, ShortTag};
+use crate::uri::join_uri;
 use crate::{LocalName, Namespace, Prefix};
 
 use crate::tendril::{StrTendril, Tendril};
@@ -175,20 +180,64 @@ impl NamespaceMap {
     }
 }
 
+/// How the tree builder recovers from namespace errors: prefixes that aren't
+/// bound, invalid namespace declarations, and attributes that have the same
+/// name once their prefixes are bound.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NamespaceRecovery {
+    /// Report a parse error, put a name with an unbound prefix in no
+    /// namespace, and drop the later of two duplicate attributes.
+    NoNamespace,
+    /// Like `NoNamespace`, but keep an unbound prefix as part of the local
+    /// name, so that `p:x` becomes an unprefixed element named `p:x`.
+    Literal,
+    /// Stop parsing at the first namespace error, as strict mode does.
+    Fail,
+}
+
 /// Tree builder options, with an impl for Default.
 #[derive(Copy, Clone)]
-pub struct XmlTreeBuilderOpts {}
+pub struct XmlTreeBuilderOpts {
+    /// How deeply references to entities declared in the internal subset
+    /// may nest inside replacement text. Default: 16
+    pub max_entity_depth: usize,
+
+    /// The total size in bytes of the replacement text that entity
+    /// references may insert into a document. This bounds the work done
+    /// for documents like "billion laughs". Default: 1 MiB
+    pub max_entity_expansion: usize,
+
+    /// Stop at the first XML 1.0 well-formedness error, such as a mismatched
+    /// end tag or an unbound prefix, and report it as `line:column: reason`.
+    /// Positions come from a tokenizer that is also in strict mode.
+    /// Default: false
+    pub strict: bool,
+
+    /// Drop text nodes that are only whitespace, unless they are inside
+    /// an element with `xml:space="preserve"`. Default: false
+    pub strip_whitespace: bool,
+
+    /// How to recover from namespace errors.
+    /// Default: `NamespaceRecovery::NoNamespace`
+    pub namespace_recovery: NamespaceRecovery,
+}
 
 impl Default for XmlTreeBuilderOpts {
     fn default() -> XmlTreeBuilderOpts {
-        XmlTreeBuilderOpts {}
+        XmlTreeBuilderOpts {
+            max_entity_depth: 16,
+            max_entity_expansion: 1 << 20,
+            strict: false,
+            strip_whitespace: false,
+            namespace_recovery: NamespaceRecovery::NoNamespace,
+        }
     }
 }
 
 /// The XML tree builder.
 pub struct XmlTreeBuilder<Handle, Sink> {
     /// Configuration options for XmlTreeBuilder
-    _opts: XmlTreeBuilderOpts,
+    opts: XmlTreeBuilderOpts,
 
     /// Consumer of tree modifications.
     pub sink: Sink,
@@ -202,6 +251,13 @@ pub struct XmlTreeBuilder<Handle, Sink> {
     /// Stack of open elements, most recently added at end.
     open_elems: Vec<Handle>,
 
+    /// The `xml:` attribute values in scope for each open element.
+    xml_scopes: Vec<XmlScope>,
+
+    /// Text held back when stripping whitespace, until we know whether
+    /// it is all whitespace.
+    pending_text: StrTendril,
+
     /// Current element pointer.
     curr_elem: Option<Handle>,
 
@@ -216,6 +272,36 @@ pub struct XmlTreeBuilder<Handle, Sink> {
 
     /// Current tree builder phase.
     phase: XmlPhase,
+
+    /// General entities declared in the internal subset.
+    entities: HashMap<String, EntityValue>,
+
+    /// Names of the entities that the tokenizers of replacement texts treat
+    /// as declared.
+    entity_names: Rc<HashSet<String>>,
+
+    /// Names of the entities being expanded, outermost first.
+    entity_stack: Vec<String>,
+
+    /// Options for the tokenizers of replacement texts, from the tokenizer
+    /// of the document.
+    entity_tokenizer_opts: XmlTokenizerOpts,
+
+    /// Size of the replacement text expanded so far.
+    entity_expansion_size: usize,
+
+    /// Line and column of the current token, in strict mode.
+    position: (u64, u64),
+
+    /// Have we stopped at a well-formedness error in strict mode?
+    halted: bool,
+
+    /// Has nothing been processed yet, so that an XML declaration may follow?
+    at_document_start: bool,
+
+    /// Are we parsing a fragment? The first open element is then the root
+    /// that holds it, which end tags in the fragment can't close.
+    fragment: bool,
 }
 impl<Handle, Sink> XmlTreeBuilder<Handle, Sink>
 where
@@ -228,16 +314,96 @@ where
     pub fn new(mut sink: Sink, opts: XmlTreeBuilderOpts) -> XmlTreeBuilder<Handle, Sink> {
         let doc_handle = sink.get_document();
         XmlTreeBuilder {
-            _opts: opts,
+            opts,
             sink,
             doc_handle,
             next_tokenizer_state: None,
             open_elems: vec![],
+            xml_scopes: vec![],
+            pending_text: StrTendril::new(),
             curr_elem: None,
             namespace_stack: NamespaceMapStack::new(),
             current_namespace: NamespaceMap::empty(),
             present_attrs: HashSet::new(),
             phase: StartPhase,
+            entities: HashMap::new(),
+            entity_names: Rc::new(HashSet::new()),
+            entity_stack: vec![],
+            entity_tokenizer_opts: Default::default(),
+            entity_expansion_size: 0,
+            position: (0, 0),
+            halted: false,
+            at_document_start: true,
+            fragment: false,
+        }
+    }
+
+    /// Create a tree builder for parsing a fragment of a document, as the
+    /// content of an element with the given name and attributes.
+    ///
+    /// The fragment is parsed into a new root element with that name and
+    /// attributes. Namespace declarations among the attributes, and the
+    /// prefixes of the name and attributes, are in scope for the fragment,
+    /// as are those passed to `declare_context_namespaces`.
+    pub fn new_for_fragment(
+        sink: Sink,
+        context_name: QualName,
+        context_attrs: Vec<Attribute>,
+        opts: XmlTreeBuilderOpts,
+    ) -> XmlTreeBuilder<Handle, Sink> {
+        let mut tb = XmlTreeBuilder::new(sink, opts);
+        let mut declarations = NamespaceMap::empty();
+        let mut attrs = vec![];
+        for attr in context_attrs {
+            let prefix = match (&attr.name.prefix, &attr.name.local) {
+                (&Some(namespace_prefix!("xmlns")), local) => Some(Prefix::from(&**local)),
+                (&None, &local_name!("xmlns")) => None,
+                (&Some(_), _) => {
+                    declarations.insert(&attr.name);
+                    attrs.push(attr);
+                    continue;
+                },
+                (&None, _) => {
+                    attrs.push(attr);
+                    continue;
+                },
+            };
+            let namespace = Some(Namespace::from(&*attr.value)).filter(|ns| !ns.is_empty());
+            declarations.scope.insert(prefix, namespace);
+        }
+        if context_name.prefix.is_some() || context_name.ns != ns!() {
+            declarations.insert(&context_name);
+        }
+        if !declarations.scope.is_empty() {
+            let declared = declarations.get_scope_iter();
+            let declared = declared.map(|(p, ns)| (p.clone(), ns.clone())).collect();
+            tb.sink.declare_namespaces(declared);
+        }
+        tb.namespace_stack.push(declarations);
+
+        let (root, scope) = tb.append_tag_to_doc(Tag {
+            kind: StartTag,
+            name: context_name,
+            attrs,
+        });
+        tb.add_to_open_elems(root, scope);
+        tb.phase = MainPhase;
+        tb.at_document_start = false;
+        tb.fragment = true;
+        tb
+    }
+
+    /// Put namespaces in scope around the document, such as those of the
+    /// larger document that a fragment was taken from. `None` is the
+    /// default namespace. Declarations in the document take precedence.
+    pub fn declare_context_namespaces(
+        &mut self,
+        namespaces: &BTreeMap<Option<Prefix>, Namespace>,
+    ) {
+        for (prefix, namespace) in namespaces.iter() {
+            self.namespace_stack.0[0]
+                .scope
+                .insert(prefix.clone(), Some(namespace.clone()));
         }
     }
 
@@ -277,9 +443,39 @@ where
         );
     }
 
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        if self.opts.strict {
+            if self.halted {
+                return;
+            }
+            let (line, column) = self.position;
+            self.sink
+                .parse_error(tokenizer::positioned_error(line, column, &msg));
+            self.halted = true;
+        } else {
+            self.sink.parse_error(msg);
+        }
+    }
+
+    fn namespace_error(&mut self, msg: Cow<'static, str>) {
+        if self.halted {
+            return;
+        }
+        self.parse_error(msg);
+        if self.opts.namespace_recovery == NamespaceRecovery::Fail {
+            self.halted = true;
+        }
+    }
+
     fn declare_ns(&mut self, attr: &mut Attribute) {
+        if self.opts.strict &&
+            attr.name.prefix == Some(namespace_prefix!("xmlns")) &&
+            attr.value.is_empty()
+        {
+            self.parse_error(Borrowed("Prefixed namespace declaration with an empty value"));
+        }
         if let Err(msg) = self.current_namespace.insert_ns(&attr) {
-            self.sink.parse_error(msg);
+            self.namespace_error(msg);
         } else {
             attr.name.ns = ns!(xmlns);
         }
@@ -313,7 +509,12 @@ where
                 name.ns = ns_uri;
             },
             Err(msg) => {
-                self.sink.parse_error(msg);
+                self.namespace_error(msg);
+                if self.opts.namespace_recovery == NamespaceRecovery::Literal {
+                    if let Some(prefix) = name.prefix.take() {
+                        name.local = LocalName::from(format!("{}:{}", prefix, name.local));
+                    }
+                }
             },
         }
     }
@@ -345,6 +546,7 @@ where
 
     fn process_namespaces(&mut self, tag: &mut Tag) {
         let mut new_attr = vec![];
+        self.present_attrs.clear();
         // First we extract all namespace declarations
         for mut attr in tag.attrs.iter_mut().filter(|attr| {
             attr.name.prefix == Some(namespace_prefix!("xmlns")) ||
@@ -360,6 +562,8 @@ where
         }) {
             if self.bind_attr_qname(&mut attr.name) {
                 new_attr.push(attr.clone());
+            } else {
+                self.namespace_error(Borrowed("Duplicate attribute after namespace binding"));
             }
         }
         tag.attrs = new_attr;
@@ -369,6 +573,10 @@ where
 
         // Finally, we dump current namespace if its unneeded.
         let x = mem::replace(&mut self.current_namespace, NamespaceMap::empty());
+        if tag.kind != EndTag && !x.scope.is_empty() {
+            let declarations = x.get_scope_iter().map(|(p, ns)| (p.clone(), ns.clone()));
+            self.sink.declare_namespaces(declarations.collect());
+        }
 
         // Only start tag doesn't dump current namespace. However, <script /> is treated
         // differently than every other empty tag, so it needs to retain the current
@@ -379,6 +587,83 @@ where
         }
     }
 
+    fn declare_entities(&mut self, doctype: &Doctype) {
+        for decl in doctype.internal_subset.iter() {
+            if let MarkupDecl::Entity(EntityDecl {
+                ref name,
+                parameter: false,
+                ref value,
+            }) = *decl
+            {
+                // The first declaration of an entity is binding.
+                self.entities
+                    .entry(String::from(&**name))
+                    .or_insert_with(|| value.clone());
+            }
+        }
+        self.entity_names = Rc::new(tokenizer::entity_names(&doctype.internal_subset));
+    }
+
+    // Returns the replacement text of the entity `name`, or reports why it
+    // can't be expanded.
+    fn entity_replacement_text(&mut self, name: &str) -> Option<StrTendril> {
+        let text = match self.entities.get(name) {
+            Some(EntityValue::Internal(text)) => text.clone(),
+            Some(EntityValue::External {
+                notation: Some(_), ..
+            }) => {
+                self.parse_error(Borrowed("Reference to an unparsed entity"));
+                return None;
+            },
+            Some(EntityValue::External { .. }) => {
+                self.parse_error(Borrowed("External entities are not loaded"));
+                return None;
+            },
+            None => {
+                self.parse_error(Borrowed("Reference to an undeclared entity"));
+                return None;
+            },
+        };
+        if self.entity_stack.iter().any(|entity| entity == name) {
+            self.parse_error(Borrowed("Recursive entity reference"));
+            return None;
+        }
+        if self.entity_stack.len() >= self.opts.max_entity_depth {
+            self.parse_error(Borrowed("Entity references nested too deeply"));
+            return None;
+        }
+        // Empty entities count too, so that references to them aren't free.
+        self.entity_expansion_size = self
+            .entity_expansion_size
+            .saturating_add(cmp::max(text.len(), 1));
+        if self.entity_expansion_size > self.opts.max_entity_expansion {
+            self.parse_error(Borrowed("Entity expansion limit exceeded"));
+            return None;
+        }
+        Some(text)
+    }
+
+    // Parses the replacement text of an entity referenced in content in
+    // place of the reference.
+    fn expand_entity(&mut self, name: StrTendril) {
+        let text = match self.entity_replacement_text(&name) {
+            Some(text) => text,
+            None => return,
+        };
+        let names = self.entity_names.clone();
+        self.entity_stack.push(String::from(&*name));
+        {
+            let opts = self.entity_tokenizer_opts;
+            let mut tokenizer = XmlTokenizer::new(EntityExpansion(self), opts);
+            tokenizer.set_entity_names(names);
+            let mut input = BufferQueue::new();
+            input.push_back(text);
+            tokenizer.feed(&mut input);
+            tokenizer.end();
+        }
+        self.entity_stack.pop();
+    }
+
     fn process_to_completion(&mut self, mut token: Token) {
         // Queue of additional tokens yet to be processed.
         // This stays empty in the common case where we don't split whitespace.
@@ -408,9 +693,20 @@ where
 {
     fn process_token(&mut self, token: tokenizer::Token) {
         // Handle `ParseError` and `DoctypeToken`; convert everything else to the local `Token` type.
+        if self.halted {
+            return;
+        }
         let token = match token {
+            // The tokenizer has already positioned its errors.
             tokenizer::ParseError(e) => {
                 self.sink.parse_error(e);
+                self.halted = self.opts.strict;
+                return;
+            },
+
+            tokenizer::EntityRefToken(name) => {
+                self.at_document_start = false;
+                self.expand_entity(name);
                 return;
             },
 
@@ -421,20 +717,118 @@ where
             tokenizer::NullCharacterToken => NullCharacterToken,
             tokenizer::EOFToken => EOFToken,
             tokenizer::CharacterTokens(x) => CharacterTokens(x),
+            tokenizer::CDataToken(x) => CDataToken(x),
         };
 
         self.process_to_completion(token);
+        // Parse errors return above, so only content ends the document start.
+        self.at_document_start = false;
     }
 
     fn end(&mut self) {
+        self.xml_scopes.clear();
         for node in self.open_elems.drain(..).rev() {
             self.sink.pop(&node);
+            self.sink.element_closed(&node);
         }
     }
 
     fn query_state_change(&mut self) -> Option<tokenizer::states::XmlState> {
         self.next_tokenizer_state.take()
     }
+
+    fn set_current_position(&mut self, line: u64, column: u64) {
+        self.position = (line, column);
+    }
+
+    fn set_tokenizer_opts(&mut self, opts: &XmlTokenizerOpts) {
+        // Replacement text is tokenized from its start, so a BOM is kept
+        // and the initial state isn't overridden.
+        self.entity_tokenizer_opts = XmlTokenizerOpts {
+            discard_bom: false,
+            profile: false,
+            initial_state: None,
+            ..*opts
+        };
+    }
+
+    fn expand_entity_in_attribute(&mut self, name: &str) -> Option<StrTendril> {
+        let text = self.entity_replacement_text(name)?;
+        self.entity_stack.push(String::from(name));
+        let mut value = StrTendril::new();
+        let mut rest = &*text;
+        while let Some(i) = rest.find(|c| matches!(c, '&' | '<' | '\t' | '\n' | '\r')) {
+            value.push_slice(&rest[..i]);
+            let c = rest.as_bytes()[i];
+            rest = &rest[i + 1..];
+            match c {
+                b'&' => {
+                    let end = match rest.find(';') {
+                        Some(end) => end,
+                        None => {
+                            self.parse_error(Borrowed("Unterminated reference in entity value"));
+                            value.push_char('&');
+                            continue;
+                        },
+                    };
+                    let reference = &rest[..end];
+                    rest = &rest[end + 1..];
+                    let predefined = match reference {
+                        "amp" => Some('&'),
+                        "lt" => Some('<'),
+                        "gt" => Some('>'),
+                        "quot" => Some('"'),
+                        "apos" => Some('\''),
+                        _ if reference.starts_with('#') => {
+                            tokenizer::parse_char_ref(&reference[1..])
+                        },
+                        _ => None,
+                    };
+                    if let Some(c) = predefined {
+                        value.push_char(c);
+                    } else if let Some(text) = self.expand_entity_in_attribute(reference) {
+                        value.push_tendril(&text);
+                    }
+                },
+                b'<' => {
+                    self.parse_error(Borrowed("'<' in the replacement text of an attribute"));
+                    value.push_char('<');
+                },
+                // Attribute-value normalization.
+                _ => value.push_char(' '),
+            }
+        }
+        value.push_slice(rest);
+        self.entity_stack.pop();
+        Some(value)
+    }
+}
+
+// Passes the tokens of an entity's replacement text to the tree builder.
+struct EntityExpansion<'a, Handle, Sink>(&'a mut XmlTreeBuilder<Handle, Sink>);
+
+impl<'a, Handle, Sink> TokenSink for EntityExpansion<'a, Handle, Sink>
+where
+    Handle: Clone,
+    Sink: TreeSink<Handle = Handle>,
+{
+    fn process_token(&mut self, token: tokenizer::Token) {
+        match token {
+            tokenizer::EOFToken => (),
+            // Errors in the replacement text are reported at the reference.
+            // In strict mode they also carry their position in the text.
+            tokenizer::ParseError(e) => self.0.parse_error(e),
+            token => self.0.process_token(token),
+        }
+    }
+
+    fn query_state_change(&mut self) -> Option<tokenizer::states::XmlState> {
+        self.0.query_state_change()
+    }
+
+    fn expand_entity_in_attribute(&mut self, name: &str) -> Option<StrTendril> {
+        self.0.expand_entity_in_attribute(name)
+    }
 }
 
 fn current_node<Handle>(open_elems: &[Handle]) -> &Handle {
@@ -456,29 +850,62 @@ where
         self.sink.append(target, child);
     }
 
-    fn insert_tag(&mut self, tag: Tag) -> XmlProcessResult {
+    // The `xml:space`, `xml:lang` and `xml:base` values in scope for a new
+    // child of the current node with these attributes.
+    fn xml_scope(&self, attrs: &[Attribute]) -> XmlScope {
+        let mut scope = self.xml_scopes.last().cloned().unwrap_or_default();
+        for attr in attrs.iter().filter(|attr| attr.name.ns == ns!(xml)) {
+            match attr.name.local {
+                local_name!("space") => match &*attr.value {
+                    "preserve" => scope.preserve_space = true,
+                    "default" => scope.preserve_space = false,
+                    _ => (),
+                },
+                local_name!("lang") => scope.lang = Some(attr.value.clone()),
+                local_name!("base") => {
+                    scope.base = Some(match scope.base {
+                        Some(ref base) => StrTendril::from(join_uri(base, &attr.value)),
+                        None => attr.value.clone(),
+                    })
+                },
+                _ => (),
+            }
+        }
+        scope
+    }
+
+    fn create_elem(&mut self, tag: Tag) -> (Handle, XmlScope) {
+        let scope = self.xml_scope(&tag.attrs);
         let child = create_element(&mut self.sink, tag.name, tag.attrs);
+        self.sink.set_xml_scope(&child, &scope);
+        (child, scope)
+    }
+
+    fn insert_tag(&mut self, tag: Tag) -> XmlProcessResult {
+        let (child, scope) = self.create_elem(tag);
         self.insert_appropriately(AppendNode(child.clone()));
-        self.add_to_open_elems(child)
+        self.add_to_open_elems(child, scope)
     }
 
     fn append_tag(&mut self, tag: Tag) -> XmlProcessResult {
-        let child = create_element(&mut self.sink, tag.name, tag.attrs);
+        let (child, _) = self.create_elem(tag);
         self.insert_appropriately(AppendNode(child.clone()));
         self.sink.pop(&child);
+        self.sink.element_closed(&child);
         Done
     }
 
-    fn append_tag_to_doc(&mut self, tag: Tag) -> Handle {
-        let child = create_element(&mut self.sink, tag.name, tag.attrs);
+    fn append_tag_to_doc(&mut self, tag: Tag) -> (Handle, XmlScope) {
+        let (child, scope) = self.create_elem(tag);
 
         self.sink
             .append(&self.doc_handle, AppendNode(child.clone()));
-        child
+        (child, scope)
     }
 
-    fn add_to_open_elems(&mut self, el: Handle) -> XmlProcessResult {
+    fn add_to_open_elems(&mut self, el: Handle, scope: XmlScope) -> XmlProcessResult {
         self.open_elems.push(el);
+        self.xml_scopes.push(scope);
 
         Done
     }
@@ -503,6 +930,7 @@ where
                 None => Tendril::new(),
             }
         }
+        self.declare_entities(&doctype);
         self.sink.append_doctype_to_document(
             get_tendril(doctype.name),
             get_tendril(doctype.public_id),
@@ -525,12 +953,40 @@ where
     }
 
     fn append_text(&mut self, chars: StrTendril) -> XmlProcessResult {
-        self.insert_appropriately(AppendText(chars));
+        let preserve_space = self
+            .xml_scopes
+            .last()
+            .map_or(false, |scope| scope.preserve_space);
+        if self.opts.strip_whitespace && !preserve_space {
+            self.pending_text.push_tendril(&chars);
+        } else {
+            self.insert_appropriately(AppendText(chars));
+        }
+        Done
+    }
+
+    // Appends the text held back since the last markup, unless it is
+    // ignorable whitespace.
+    fn flush_pending_text(&mut self) {
+        let text = mem::take(&mut self.pending_text);
+        if any_not_whitespace(&text) {
+            self.insert_appropriately(AppendText(text));
+        }
+    }
+
+    fn append_cdata(&mut self, text: StrTendril) -> XmlProcessResult {
+        let target = current_node(&self.open_elems);
+        self.sink.append_cdata(target, text);
         Done
     }
 
+    // The open elements that end tags can close.
+    fn closable_elems(&self) -> &[Handle] {
+        &self.open_elems[self.fragment as usize..]
+    }
+
     fn tag_in_open_elems(&self, tag: &Tag) -> bool {
-        self.open_elems
+        self.closable_elems()
             .iter()
             .any(|a| self.sink.elem_name(a) == tag.name.expanded())
     }
@@ -564,9 +1020,12 @@ where
             &tag.name
         );
 
-        if *self.sink.elem_name(self.current_node()).local != tag.name.local {
-            self.sink
-                .parse_error(Borrowed("Current node doesn't match tag"));
+        let current_matches = self
+            .closable_elems()
+            .last()
+            .map_or(false, |node| *self.sink.elem_name(node).local == tag.name.local);
+        if !current_matches {
+            self.parse_error(Borrowed("Current node doesn't match tag"));
         }
 
         let is_closed = self.tag_in_open_elems(&tag);
@@ -585,8 +1044,10 @@ where
 
     fn pop(&mut self) -> Handle {
         self.namespace_stack.pop();
+        self.xml_scopes.pop();
         let node = self.open_elems.pop().expect("no current element");
         self.sink.pop(&node);
+        self.sink.element_closed(&node);
         node
     }
 
@@ -617,6 +1078,10 @@ where
     fn step(&mut self, mode: XmlPhase, token: Token) -> XmlProcessResult {
         self.debug_step(mode, &token);
 
+        if !self.pending_text.is_empty() && !matches!(token, CharacterTokens(_)) {
+            self.flush_pending_text();
+        }
+
         match mode {
             StartPhase => match token {
                 TagToken(Tag {
@@ -634,8 +1099,8 @@ where
                         tag
                     };
                     self.phase = MainPhase;
-                    let handle = self.append_tag_to_doc(tag);
-                    self.add_to_open_elems(handle)
+                    let (handle, scope) = self.append_tag_to_doc(tag);
+                    self.add_to_open_elems(handle, scope)
                 },
                 TagToken(Tag {
                     kind: EmptyTag,
@@ -652,16 +1117,23 @@ where
                         tag
                     };
                     self.phase = EndPhase;
-                    let handle = self.append_tag_to_doc(tag);
+                    let (handle, _) = self.append_tag_to_doc(tag);
                     self.sink.pop(&handle);
+                    self.sink.element_closed(&handle);
                     Done
                 },
                 CommentToken(comment) => self.append_comment_to_doc(comment),
+                PIToken(pi) if self.at_document_start && &*pi.target == "xml" => {
+                    match xml_decl::parse_xml_decl(&pi.data) {
+                        Ok(declaration) => self.sink.set_xml_declaration(declaration),
+                        Err(msg) => self.parse_error(msg),
+                    }
+                    self.append_pi_to_doc(pi)
+                },
                 PIToken(pi) => self.append_pi_to_doc(pi),
                 CharacterTokens(ref chars) if !any_not_whitespace(chars) => Done,
                 EOFToken => {
-                    self.sink
-                        .parse_error(Borrowed("Unexpected EOF in start phase"));
+                    self.parse_error(Borrowed("Unexpected EOF in start phase"));
                     Reprocess(EndPhase, EOFToken)
                 },
                 DoctypeToken(d) => {
@@ -669,13 +1141,13 @@ where
                     Done
                 },
                 _ => {
-                    self.sink
-                        .parse_error(Borrowed("Unexpected element in start phase"));
+                    self.parse_error(Borrowed("Unexpected element in start phase"));
                     Done
                 },
             },
             MainPhase => match token {
                 CharacterTokens(chs) => self.append_text(chs),
+                CDataToken(text) => self.append_cdata(text),
                 TagToken(Tag {
                     kind: StartTag,
                     name,
@@ -738,6 +1210,13 @@ where
                     retval
                 },
                 TagToken(Tag { kind: ShortTag, .. }) => {
+                    if self.opts.strict {
+                        self.parse_error(Borrowed("Short end tag"));
+                    }
+                    if self.closable_elems().is_empty() {
+                        self.parse_error(Borrowed("Short end tag closes the fragment's root"));
+                        return Done;
+                    }
                     self.pop();
                     if self.no_open_elems() {
                         self.phase = EndPhase;
@@ -746,10 +1225,14 @@ where
                 },
                 CommentToken(comment) => self.append_comment_to_tag(comment),
                 PIToken(pi) => self.append_pi_to_tag(pi),
-                EOFToken | NullCharacterToken => Reprocess(EndPhase, EOFToken),
+                EOFToken | NullCharacterToken => {
+                    if self.opts.strict && !self.closable_elems().is_empty() {
+                        self.parse_error(Borrowed("Unclosed element at end of input"));
+                    }
+                    Reprocess(EndPhase, EOFToken)
+                },
                 DoctypeToken(_) => {
-                    self.sink
-                        .parse_error(Borrowed("Unexpected element in main phase"));
+                    self.parse_error(Borrowed("Unexpected element in main phase"));
                     Done
                 },
             },
@@ -757,13 +1240,156 @@ where
                 CommentToken(comment) => self.append_comment_to_doc(comment),
                 PIToken(pi) => self.append_pi_to_doc(pi),
                 CharacterTokens(ref chars) if !any_not_whitespace(chars) => Done,
+                CharacterTokens(_) | CDataToken(_) => {
+                    self.parse_error(Borrowed("Text after the root element"));
+                    Done
+                },
                 EOFToken => self.stop_parsing(),
                 _ => {
-                    self.sink
-                        .parse_error(Borrowed("Unexpected element in end phase"));
+                    self.parse_error(Borrowed("Unexpected element in end phase"));
                     Done
                 },
             },
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+    use std::rc::Rc;
+
+    use markup5ever::{namespace_url, ns};
+
+    use super::TreeSink;
+    use crate::driver::parse_document;
+    use crate::interface::{Attribute, ElementFlags, ExpandedName, NodeOrText, QualName};
+    use crate::interface::{QuirksMode, XmlScope};
+    use crate::tendril::{StrTendril, TendrilSink};
+    use crate::LocalName;
+
+    // Records the scope of each element.
+    struct ScopeSink {
+        document: Rc<QualName>,
+        scopes: Vec<(String, XmlScope)>,
+    }
+
+    impl TreeSink for ScopeSink {
+        type Handle = Rc<QualName>;
+        type Output = Vec<(String, XmlScope)>;
+
+        fn finish(self) -> Vec<(String, XmlScope)> {
+            self.scopes
+        }
+
+        fn parse_error(&mut self, msg: Cow<'static, str>) {
+            panic!("{}", msg);
+        }
+
+        fn get_document(&mut self) -> Rc<QualName> {
+            self.document.clone()
+        }
+
+        fn elem_name<'a>(&'a self, target: &'a Rc<QualName>) -> ExpandedName<'a> {
+            target.expanded()
+        }
+
+        fn create_element(
+            &mut self,
+            name: QualName,
+            _: Vec<Attribute>,
+            _: ElementFlags,
+        ) -> Rc<QualName> {
+            Rc::new(name)
+        }
+
+        fn set_xml_scope(&mut self, target: &Rc<QualName>, scope: &XmlScope) {
+            self.scopes.push((target.local.to_string(), scope.clone()));
+        }
+
+        fn create_comment(&mut self, _: StrTendril) -> Rc<QualName> {
+            self.document.clone()
+        }
+
+        fn create_pi(&mut self, _: StrTendril, _: StrTendril) -> Rc<QualName> {
+            self.document.clone()
+        }
+
+        fn append(&mut self, _: &Rc<QualName>, _: NodeOrText<Rc<QualName>>) {}
+
+        fn append_based_on_parent_node(
+            &mut self,
+            _: &Rc<QualName>,
+            _: &Rc<QualName>,
+            _: NodeOrText<Rc<QualName>>,
+        ) {
+        }
+
+        fn append_doctype_to_document(&mut self, _: StrTendril, _: StrTendril, _: StrTendril) {}
+
+        fn get_template_contents(&mut self, target: &Rc<QualName>) -> Rc<QualName> {
+            target.clone()
+        }
+
+        fn same_node(&self, x: &Rc<QualName>, y: &Rc<QualName>) -> bool {
+            Rc::ptr_eq(x, y)
+        }
+
+        fn set_quirks_mode(&mut self, _: QuirksMode) {}
+
+        fn append_before_sibling(&mut self, _: &Rc<QualName>, _: NodeOrText<Rc<QualName>>) {}
+
+        fn add_attrs_if_missing(&mut self, _: &Rc<QualName>, _: Vec<Attribute>) {}
+
+        fn remove_from_parent(&mut self, _: &Rc<QualName>) {}
+
+        fn reparent_children(&mut self, _: &Rc<QualName>, _: &Rc<QualName>) {}
+    }
+
+    fn scope(preserve_space: bool, lang: Option<&str>, base: Option<&str>) -> XmlScope {
+        XmlScope {
+            preserve_space,
+            lang: lang.map(StrTendril::from),
+            base: base.map(StrTendril::from),
+        }
+    }
+
+    #[test]
+    fn xml_scope() {
+        let sink = ScopeSink {
+            document: Rc::new(QualName::new(None, ns!(), LocalName::from(""))),
+            scopes: vec![],
+        };
+        let scopes = parse_document(sink, Default::default()).from_utf8().one(
+            "<a xml:lang='en' xml:base='http://example.com/doc/'>\
+                 <b xml:space='preserve' xml:base='../img/'><c xml:lang=''/>\
+                 <d xml:base='/x?y#z' xml:space='default'/></b><e/></a>"
+                .as_bytes(),
+        );
+        assert_eq!(
+            scopes,
+            [
+                (
+                    "a".to_owned(),
+                    scope(false, Some("en"), Some("http://example.com/doc/"))
+                ),
+                (
+                    "b".to_owned(),
+                    scope(true, Some("en"), Some("http://example.com/img/"))
+                ),
+                (
+                    "c".to_owned(),
+                    scope(true, Some(""), Some("http://example.com/img/"))
+                ),
+                (
+                    "d".to_owned(),
+                    scope(false, Some("en"), Some("http://example.com/x?y#z"))
+                ),
+                (
+                    "e".to_owned(),
+                    scope(false, Some("en"), Some("http://example.com/doc/"))
+                ),
+            ]
+        );
+    }
+}
@@ -29,6 +29,7 @@ pub enum Token {
     DoctypeToken(Doctype),
     CommentToken(StrTendril),
     CharacterTokens(StrTendril),
+    CDataToken(StrTendril),
     PIToken(Pi),
     NullCharacterToken,
     EOFToken,
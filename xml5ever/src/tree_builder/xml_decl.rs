@@ -0,0 +1,132 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parsing of the XML declaration's pseudo-attributes.
+
+use mac::{_tt_as_expr_hack, matches};
+use std::borrow::Cow::{self, Borrowed};
+
+use crate::interface::XmlDeclaration;
+use crate::tendril::StrTendril;
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+// Splits off the next `name = "value"` pair.
+fn pseudo_attribute(rest: &str) -> Result<(&str, &str, &str), Cow<'static, str>> {
+    let name_len = rest
+        .find(|c: char| c == '=' || is_whitespace(c))
+        .unwrap_or(rest.len());
+    let (name, rest) = rest.split_at(name_len);
+    let rest = rest.trim_start_matches(is_whitespace);
+    if !rest.starts_with('=') {
+        return Err(Borrowed("Expected '=' in the XML declaration"));
+    }
+    let rest = rest[1..].trim_start_matches(is_whitespace);
+    let quote = match rest.chars().next() {
+        Some(quote @ '"') | Some(quote @ '\'') => quote,
+        _ => return Err(Borrowed("Unquoted value in the XML declaration")),
+    };
+    let rest = &rest[1..];
+    match rest.find(quote) {
+        Some(end) => Ok((name, &rest[..end], &rest[end + 1..])),
+        None => Err(Borrowed("Unterminated value in the XML declaration")),
+    }
+}
+
+fn is_version(value: &str) -> bool {
+    value.starts_with("1.") && value.len() > 2 && value[2..].bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_encoding_name(value: &str) -> bool {
+    let mut chars = value.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic()) &&
+        chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Parse the data of a `<?xml ...?>` processing instruction.
+pub(crate) fn parse_xml_decl(data: &str) -> Result<XmlDeclaration, Cow<'static, str>> {
+    let mut rest = data.trim_start_matches(is_whitespace);
+    let mut declaration = XmlDeclaration {
+        version: StrTendril::new(),
+        encoding: None,
+        standalone: None,
+    };
+    // The pseudo-attributes must appear in this order.
+    let mut expected = ["version", "encoding", "standalone"].iter().peekable();
+    while !rest.is_empty() {
+        let (name, value, after) = pseudo_attribute(rest)?;
+        while matches!(expected.peek(), Some(&&next) if next != name) {
+            expected.next();
+        }
+        match expected.next().cloned() {
+            Some("version") if is_version(value) => declaration.version = value.into(),
+            Some("encoding") if is_encoding_name(value) => {
+                declaration.encoding = Some(value.into())
+            },
+            Some("standalone") if value == "yes" || value == "no" => {
+                declaration.standalone = Some(value == "yes")
+            },
+            Some(_) => return Err(Borrowed("Invalid value in the XML declaration")),
+            None => return Err(Borrowed("Unexpected pseudo-attribute in the XML declaration")),
+        }
+        rest = after.trim_start_matches(is_whitespace);
+        if !rest.is_empty() && rest.len() == after.len() {
+            return Err(Borrowed("Missing whitespace in the XML declaration"));
+        }
+    }
+    if declaration.version.is_empty() {
+        return Err(Borrowed("Missing version in the XML declaration"));
+    }
+    Ok(declaration)
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_xml_decl;
+    use crate::interface::XmlDeclaration;
+
+    #[test]
+    fn xml_decl() {
+        assert_eq!(
+            parse_xml_decl("version='1.0' encoding = \"ISO-8859-1\"\tstandalone='yes' "),
+            Ok(XmlDeclaration {
+                version: "1.0".into(),
+                encoding: Some("ISO-8859-1".into()),
+                standalone: Some(true),
+            })
+        );
+        assert_eq!(
+            parse_xml_decl("version=\"1.1\""),
+            Ok(XmlDeclaration {
+                version: "1.1".into(),
+                encoding: None,
+                standalone: None,
+            })
+        );
+        for bad in [
+            "",
+            "encoding='UTF-8'",
+            "encoding='UTF-8' version='1.0'",
+            "version='1.0' standalone='yes' encoding='UTF-8'",
+            "version='1.0'encoding='UTF-8'",
+            "version='2.0'",
+            "version='1.0' standalone='maybe'",
+            "version='1.0' encoding='8bit'",
+            "version='1.0' other='x'",
+            "version=1.0",
+            "version='1.0",
+        ]
+        .iter()
+        {
+            assert!(parse_xml_decl(bad).is_err(), "{:?}", bad);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Just enough URI reference handling to resolve `xml:base` attributes.
+
+use mac::{_tt_as_expr_hack, matches};
+
+struct UriRef<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+fn split_off(s: &str, delimiter: char) -> (&str, Option<&str>) {
+    match s.find(delimiter) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    }
+}
+
+fn parse_uri(uri: &str) -> UriRef<'_> {
+    let (rest, fragment) = split_off(uri, '#');
+    let (mut rest, query) = split_off(rest, '?');
+    let mut scheme = None;
+    if let Some(i) = rest.find(':') {
+        let candidate = &rest[..i];
+        if candidate.starts_with(|c: char| c.is_ascii_alphabetic()) &&
+            candidate
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        {
+            scheme = Some(candidate);
+            rest = &rest[i + 1..];
+        }
+    }
+    let mut authority = None;
+    if rest.starts_with("//") {
+        let end = rest[2..].find('/').map_or(rest.len(), |i| i + 2);
+        authority = Some(&rest[2..end]);
+        rest = &rest[end..];
+    }
+    UriRef {
+        scheme,
+        authority,
+        path: rest,
+        query,
+        fragment,
+    }
+}
+
+/// RFC 3986's remove_dot_segments, modified as in Canonical XML 1.1 to keep the leading `..`
+/// segments of a relative path.
+fn remove_dot_segments(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let segments: Vec<&str> = path.split('/').skip(absolute as usize).collect();
+    let mut output: Vec<&str> = vec![];
+    for (i, segment) in segments.iter().enumerate() {
+        let last = i + 1 == segments.len();
+        match *segment {
+            "." => (),
+            ".." => match output.last() {
+                Some(&s) if s != ".." => {
+                    output.pop();
+                },
+                _ if !absolute => output.push(".."),
+                _ => (),
+            },
+            segment => output.push(segment),
+        }
+        if last && (*segment == "." || *segment == "..") {
+            output.push("");
+        }
+    }
+    let path = output.join("/");
+    if absolute {
+        format!("/{}", path)
+    } else {
+        path
+    }
+}
+
+/// Resolve `reference` against `base` as in RFC 3986 section 5.2, for joining `xml:base`
+/// values.
+pub(crate) fn join_uri(base: &str, reference: &str) -> String {
+    let base = parse_uri(base);
+    let reference = parse_uri(reference);
+    let (scheme, authority, path, query);
+    if reference.scheme.is_some() {
+        scheme = reference.scheme;
+        authority = reference.authority;
+        path = remove_dot_segments(reference.path);
+        query = reference.query;
+    } else {
+        scheme = base.scheme;
+        if reference.authority.is_some() {
+            authority = reference.authority;
+            path = remove_dot_segments(reference.path);
+            query = reference.query;
+        } else {
+            authority = base.authority;
+            if reference.path.is_empty() {
+                path = base.path.to_owned();
+                query = reference.query.or(base.query);
+            } else {
+                path = if reference.path.starts_with('/') {
+                    remove_dot_segments(reference.path)
+                } else if base.authority.is_some() && base.path.is_empty() {
+                    remove_dot_segments(&format!("/{}", reference.path))
+                } else {
+                    let directory = base.path.rfind('/').map_or("", |i| &base.path[..i + 1]);
+                    remove_dot_segments(&format!("{}{}", directory, reference.path))
+                };
+                query = reference.query;
+            }
+        }
+    }
+
+    let mut result = String::new();
+    if let Some(scheme) = scheme {
+        result.push_str(scheme);
+        result.push(':');
+    }
+    if let Some(authority) = authority {
+        result.push_str("//");
+        result.push_str(authority);
+    }
+    result.push_str(&path);
+    if let Some(query) = query {
+        result.push('?');
+        result.push_str(query);
+    }
+    if let Some(fragment) = reference.fragment {
+        result.push('#');
+        result.push_str(fragment);
+    }
+    result
+}